@@ -0,0 +1,25 @@
+//! Regenerate the C header of the `ffi` module, only with the `ffi` feature
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("set by cargo");
+        let mut config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("TRANSACTION_PROCESSOR_H".to_owned()),
+            cpp_compat: true,
+            ..<_>::default()
+        };
+        config.export.prefix = Some("Tp".to_owned());
+        // Variants like `TP_ACTION_TYPE_DEPOSIT`, since C enumerators share one namespace
+        config.enumeration.rename_variants = cbindgen::RenameRule::QualifiedScreamingSnakeCase;
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .generate()
+            .expect("the ffi module has a C interface")
+            .write_to_file(format!("{crate_dir}/include/transaction_processor.h"));
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
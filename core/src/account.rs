@@ -0,0 +1,199 @@
+//! Borrowed views of single accounts
+//!
+//! Unlike a summary, a view also exposes the open disputes and stored transactions
+//! of the account, and looking one up does not touch any other account.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::convert::Infallible;
+
+use crate::{
+    infallible, lifecycle::DisputeStage, AccountState, AccountStates, AccountStore, AccountSummary,
+    Balance, ClientId, TransactionId, TransactionKind,
+};
+
+/// Read-only view of one account, borrowed from [`AccountStates`]
+///
+/// Archived accounts are viewed as the archive keeps them, locked and without funds.
+#[derive(Debug, Clone)]
+pub struct AccountView<'a> {
+    client: ClientId,
+    account: Cow<'a, AccountState>,
+}
+
+impl<'a> AccountView<'a> {
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn locked(&self) -> bool {
+        self.account.locked
+    }
+
+    pub fn available(&self) -> &Balance {
+        &self.account.available
+    }
+
+    pub fn held(&self) -> &Balance {
+        &self.account.held
+    }
+
+    pub fn total(&self) -> Balance {
+        self.available() + self.held()
+    }
+
+    /// Funds charged back beyond what the account held, see
+    /// [`ChargebackPolicy`](crate::policy::ChargebackPolicy), and fees beyond its available funds
+    ///
+    /// They are owed by the client, or for a house account absorbed for other clients.
+    pub fn shortfall(&self) -> &Balance {
+        &self.account.shortfall
+    }
+
+    /// Fees charged to the account, see [`FeeSchedule`](crate::fees::FeeSchedule)
+    pub fn fees(&self) -> &Balance {
+        &self.account.fees
+    }
+
+    /// Number of deposits applied to the account, including those no longer kept
+    pub fn deposits(&self) -> u64 {
+        self.account.deposits
+    }
+
+    /// Number of withdrawals applied to the account, including those no longer kept
+    pub fn withdrawals(&self) -> u64 {
+        self.account.withdrawals
+    }
+
+    /// Total amount of the transactions charged back and not reversed
+    pub fn charged_back(&self) -> &Balance {
+        &self.account.charged_back
+    }
+
+    /// Transactions of the account under an open dispute, in no particular order
+    pub fn open_disputes(&self) -> impl Iterator<Item = TransactionId> + '_ {
+        self.account.disputes.iter().copied()
+    }
+
+    /// Number of deposits and withdrawals kept for later disputes,
+    /// or for an archived account those kept for its chargebacks
+    pub fn transaction_count(&self) -> usize {
+        self.account.transaction_amounts.len()
+    }
+
+    /// Deposits and withdrawals kept for later disputes, in no particular order
+    pub fn transactions(&self) -> impl Iterator<Item = (TransactionId, &TransactionKind)> + '_ {
+        self.account
+            .transaction_amounts
+            .iter()
+            .map(|(&transaction, kind)| (transaction, kind))
+    }
+
+    /// Transactions under dispute or charged back, with where each stands,
+    /// in no particular order
+    pub fn disputes(&self) -> impl Iterator<Item = (TransactionId, DisputeStage)> + '_ {
+        let open = self
+            .account
+            .disputes
+            .iter()
+            .map(|&transaction| (transaction, DisputeStage::Open));
+        let charged_back = self
+            .account
+            .chargebacks
+            .iter()
+            .map(|(&transaction, chargeback)| (transaction, chargeback.stage));
+        open.chain(charged_back)
+    }
+
+    pub fn summary(&self) -> AccountSummary {
+        AccountSummary::new(self.client, &self.account)
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// View of a single account, if the client has been seen
+    pub fn account(&self, client: ClientId) -> Option<AccountView<'_>> {
+        let account = infallible(self.accounts.get(client))
+            .or_else(|| self.archived.get(&client).map(Cow::Borrowed))?;
+        Some(AccountView { client, account })
+    }
+
+    /// Views of all accounts, ordered by client id
+    pub fn accounts(&self) -> impl Iterator<Item = AccountView<'_>> + '_ {
+        let mut clients: Vec<_> = self
+            .accounts
+            .clients()
+            .map(infallible)
+            .chain(self.archived.keys().copied())
+            .collect();
+        clients.sort_unstable();
+        clients
+            .into_iter()
+            .filter_map(|client| self.account(client))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    #[test]
+    fn inspect_one_account() {
+        let mut states = AccountStates::default();
+        for action in [
+            Action::Deposit {
+                client: ClientId(2),
+                transaction: TransactionId(1),
+                amount: "3".parse().unwrap(),
+            },
+            Action::Deposit {
+                client: ClientId(2),
+                transaction: TransactionId(2),
+                amount: "1".parse().unwrap(),
+            },
+            Action::Dispute {
+                client: ClientId(2),
+                transaction: TransactionId(1),
+            },
+            Action::Deposit {
+                client: ClientId(1),
+                transaction: TransactionId(3),
+                amount: "1".parse().unwrap(),
+            },
+            Action::Dispute {
+                client: ClientId(1),
+                transaction: TransactionId(3),
+            },
+            Action::Chargeback {
+                client: ClientId(1),
+                transaction: TransactionId(3),
+            },
+        ] {
+            states.process(action);
+        }
+        assert!(states.account(ClientId(3)).is_none());
+
+        let account = states.account(ClientId(2)).unwrap();
+        assert!(!account.locked());
+        assert_eq!(account.available().to_string(), "1.0000");
+        assert_eq!(account.held().to_string(), "3.0000");
+        assert_eq!(account.total().to_string(), "4.0000");
+        assert_eq!(
+            account.open_disputes().collect::<Vec<_>>(),
+            [TransactionId(1)]
+        );
+        assert_eq!(account.transaction_count(), 2);
+        assert_eq!(Some(account.summary()), states.account_summary(ClientId(2)));
+
+        states.archive_locked();
+        let archived = states.account(ClientId(1)).unwrap();
+        assert!(archived.locked());
+        assert!(archived.total().is_zero());
+        // Only the charged back deposit is kept, for a representment
+        assert_eq!(archived.transaction_count(), 1);
+        assert!(states
+            .accounts()
+            .map(|account| account.summary())
+            .eq(states.summary()));
+    }
+}
@@ -0,0 +1,185 @@
+//! Archival of settled locked accounts
+//!
+//! A locked account rejects every action but admin ones, so once it holds no funds
+//! and has no open dispute, most of its transaction history no longer matters.
+//! Archiving moves the account out of the [`AccountStore`] into an archive in memory,
+//! dropping the transactions but those charged back, which a representment and
+//! reversal can still refer to. The archive keeps the lock, the chargebacks, fees and
+//! counters, so archived accounts stay queryable through [`AccountStates::account`].
+//! An applied admin action restores the account to the store.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use crate::{infallible, AccountState, AccountStates, AccountStore, ClientId};
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Move every locked account without available or held funds out of the live state,
+    /// returning how many were archived
+    ///
+    /// Archived accounts still appear in summaries and keep rejecting actions as locked.
+    /// Archiving is cheap when nothing qualifies, so services can simply run it
+    /// on a schedule, for example after each [`AccountStates::close_period`].
+    pub fn archive_locked(&mut self) -> usize {
+        let settled: Vec<_> = self
+            .accounts
+            .iter()
+            .map(infallible)
+            .filter(|(_, account)| {
+                account.locked
+                    && account.available.is_zero()
+                    && account.held.is_zero()
+                    && account.shortfall.is_zero()
+                    && account.disputes.is_empty()
+            })
+            .map(|(client, _)| client)
+            .collect();
+        for &client in &settled {
+            if let Some(account) = infallible(self.accounts.remove(client)) {
+                // Archived transactions no longer count towards the transaction limit
+                if self.limits.transactions.is_some() {
+                    self.stored_transactions -= account.transaction_amounts.len();
+                }
+                self.archived.insert(client, account.archived());
+            }
+        }
+        settled.len()
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Whether the client's account has been archived
+    pub fn is_archived(&self, client: ClientId) -> bool {
+        self.archived.contains_key(&client)
+    }
+
+    /// Move an archived account back to the store, before an admin action is applied to it
+    pub(crate) fn restore(&mut self, client: ClientId) -> Result<(), S::Error> {
+        if let Some(account) = self.archived.remove(&client) {
+            if self.limits.transactions.is_some() {
+                self.stored_transactions += account.transaction_amounts.len();
+            }
+            self.accounts.upsert(client, account)?;
+        }
+        Ok(())
+    }
+
+    /// Move a restored account back to the archive, after the admin action was rejected
+    pub(crate) fn rearchive(&mut self, client: ClientId) -> Result<(), S::Error> {
+        if let Some(account) = self.accounts.remove(client)? {
+            if self.limits.transactions.is_some() {
+                self.stored_transactions -= account.transaction_amounts.len();
+            }
+            self.archived.insert(client, account);
+        }
+        Ok(())
+    }
+}
+
+impl AccountState {
+    /// What the archive keeps of a settled account
+    fn archived(mut self) -> Self {
+        let chargebacks = &self.chargebacks;
+        self.transaction_amounts
+            .retain(|transaction, _| chargebacks.contains_key(transaction));
+        Self {
+            transaction_amounts: self.transaction_amounts,
+            locked: true,
+            auto_lock: self.auto_lock,
+            chargebacks: self.chargebacks,
+            fees: self.fees,
+            deposits: self.deposits,
+            withdrawals: self.withdrawals,
+            charged_back: self.charged_back,
+            ..<_>::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, Outcome, Rejection, TransactionId};
+
+    #[test]
+    fn archive_settled_accounts() {
+        let mut states = AccountStates::default();
+        for client in [1, 2] {
+            states.process(Action::Deposit {
+                client: ClientId(client),
+                transaction: TransactionId(client.into()),
+                amount: "1".parse().unwrap(),
+            });
+        }
+        for action in [
+            Action::Dispute {
+                client: ClientId(1),
+                transaction: TransactionId(1),
+            },
+            Action::Chargeback {
+                client: ClientId(1),
+                transaction: TransactionId(1),
+            },
+        ] {
+            states.process(action);
+        }
+        let summaries = states.summary();
+        assert_eq!(states.archive_locked(), 1);
+        assert_eq!(states.archive_locked(), 0);
+        assert!(states.is_archived(ClientId(1)));
+        assert_eq!(states.summary(), summaries);
+        assert!(states.account_summary(ClientId(1)).unwrap().locked());
+
+        let deposit = Action::Deposit {
+            client: ClientId(1),
+            transaction: TransactionId(3),
+            amount: "1".parse().unwrap(),
+        };
+        assert_eq!(
+            states.explain(&deposit).rejection(),
+            Some(Rejection::AccountLocked)
+        );
+        assert_eq!(
+            states.process(deposit),
+            Outcome::Rejected(Rejection::AccountLocked)
+        );
+        assert!(!infallible(states.accounts.contains(ClientId(1))));
+        let archived = states.account(ClientId(1)).unwrap();
+        assert_eq!(
+            (archived.deposits(), archived.charged_back().to_string()),
+            (1, "1.0000".into())
+        );
+    }
+
+    #[test]
+    fn archived_transactions_leave_the_limit() {
+        let mut states = AccountStates::builder().max_transactions(1).build();
+        let client = ClientId(1);
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: TransactionId(1),
+                amount: "1".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId(1),
+            },
+            Action::Chargeback {
+                client,
+                transaction: TransactionId(1),
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert_eq!(states.archive_locked(), 1);
+        assert_eq!(
+            states.process(Action::Deposit {
+                client: ClientId(2),
+                transaction: TransactionId(2),
+                amount: "1".parse().unwrap(),
+            }),
+            Outcome::Applied
+        );
+    }
+}
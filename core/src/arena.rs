@@ -0,0 +1,105 @@
+//! Allocator of the transaction maps of an [`AccountState`](crate::AccountState)
+//!
+//! Accounts use the global allocator, unless their state was built with
+//! [`AccountStatesBuilder::arena`](crate::AccountStatesBuilder::arena), only available with
+//! the `arena` feature. The maps of every new account of such a state are then allocated
+//! from one bump arena: an allocation only moves a pointer, and the maps of an account
+//! lie next to each other. The arena gives its memory back only once the state and every
+//! account taken out of it are dropped, so the tables that maps outgrow stay allocated until then.
+
+#[cfg(feature = "arena")]
+use alloc::sync::Arc;
+use core::{alloc::Layout, ptr::NonNull};
+#[cfg(feature = "arena")]
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug},
+    hint,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use allocator_api2::alloc::{AllocError, Allocator, Global};
+#[cfg(feature = "arena")]
+use bumpalo::Bump;
+use hashbrown::{DefaultHashBuilder, HashMap, HashSet};
+
+/// Map of an account, from the allocator of its state
+pub(crate) type AccountMap<K, V> = HashMap<K, V, DefaultHashBuilder, AccountAlloc>;
+/// Set of an account, from the allocator of its state
+pub(crate) type AccountSet<T> = HashSet<T, DefaultHashBuilder, AccountAlloc>;
+
+/// Allocator of the maps of accounts
+#[derive(Debug, Clone, Default)]
+pub(crate) enum AccountAlloc {
+    #[default]
+    Global,
+    #[cfg(feature = "arena")]
+    Arena(Arc<Arena>),
+}
+
+#[cfg(feature = "arena")]
+impl AccountAlloc {
+    /// A new arena, shared by every map allocated from a clone of it
+    pub(crate) fn arena() -> Self {
+        Self::Arena(Arc::new(Arena {
+            locked: AtomicBool::new(false),
+            bump: UnsafeCell::new(Bump::new()),
+        }))
+    }
+}
+
+// Safety: memory is given back to the allocator it came from, arenas never give back single blocks
+unsafe impl Allocator for AccountAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match self {
+            Self::Global => Global.allocate(layout),
+            #[cfg(feature = "arena")]
+            Self::Arena(arena) => arena.allocate(layout),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        match self {
+            // Safety: `ptr` was allocated by `Global` with `layout`, as required of the caller
+            Self::Global => unsafe { Global.deallocate(ptr, layout) },
+            #[cfg(feature = "arena")]
+            Self::Arena(_) => {}
+        }
+    }
+}
+
+/// Bump arena that threads take turns to allocate from
+#[cfg(feature = "arena")]
+pub(crate) struct Arena {
+    locked: AtomicBool,
+    bump: UnsafeCell<Bump>,
+}
+
+// Safety: `bump` is only used while holding `locked`
+#[cfg(feature = "arena")]
+unsafe impl Sync for Arena {}
+
+#[cfg(feature = "arena")]
+impl Arena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Allocations are short and the arena belongs to a single state, so waits are rare
+        while (self.locked)
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        // Safety: holding `locked`, no other thread uses `bump`
+        let allocated = unsafe { &*self.bump.get() }.try_alloc_layout(layout);
+        self.locked.store(false, Ordering::Release);
+        let ptr = allocated.map_err(|_| AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+}
+
+#[cfg(feature = "arena")]
+impl Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena").finish_non_exhaustive()
+    }
+}
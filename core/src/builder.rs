@@ -0,0 +1,381 @@
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::{
+    fees::FeeSchedule,
+    policy::{
+        ChargebackPolicy, ClientPolicy, DisputePolicy, DisputeWindow, LockPolicy, Retention,
+        WithdrawalLimits,
+    },
+    AccountStates, AccountStore, Balance, ClientId, Limits, MemoryStore, MAX_CLIENTS,
+};
+
+/// Configuration of a new [`AccountStates`]
+///
+/// This is the supported way to construct anything but a default state;
+/// every option left unset keeps the behaviour of `AccountStates::default()`.
+#[derive(Debug, Clone, Default)]
+pub struct AccountStatesBuilder {
+    clients: usize,
+    txs_per_client: usize,
+    limits: Limits,
+    lock_policy: LockPolicy,
+    withdrawal_limits: WithdrawalLimits,
+    client_policies: HashMap<ClientId, ClientPolicy>,
+    fees: Option<FeeSchedule>,
+    interest_basis_points: i32,
+    dispute_policy: DisputePolicy,
+    chargeback_policy: ChargebackPolicy,
+    dispute_window: Option<DisputeWindow>,
+    retention: Option<Retention>,
+    chronological: bool,
+    transaction_index: bool,
+    #[cfg(feature = "arena")]
+    arena: bool,
+}
+
+impl AccountStatesBuilder {
+    /// Expect about `clients` distinct clients
+    pub fn clients(mut self, clients: usize) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Expect about `txs_per_client` transactions for each client
+    pub fn txs_per_client(mut self, txs_per_client: usize) -> Self {
+        self.txs_per_client = txs_per_client;
+        self
+    }
+
+    /// Reject actions that would add more than `max` distinct clients
+    pub fn max_clients(mut self, max: usize) -> Self {
+        self.limits.clients = Some(max);
+        self
+    }
+
+    /// Reject deposits and withdrawals that would retain more than `max` transactions,
+    /// counting every transaction that can still be disputed
+    pub fn max_transactions(mut self, max: usize) -> Self {
+        self.limits.transactions = Some(max);
+        self
+    }
+
+    /// Lock an account as soon as an applied action leaves its held funds
+    /// above `multiple` times its available funds
+    ///
+    /// The rule is reported by [`AccountStates::auto_lock`] and in the change stream.
+    pub fn lock_when_held_exceeds(mut self, multiple: u32) -> Self {
+        self.lock_policy.held_multiple = Some(multiple);
+        self
+    }
+
+    /// Keep an account locked from its `count`th chargeback on, counting reversed ones,
+    /// until it is unlocked by an [`Action::Unlock`](crate::Action::Unlock)
+    ///
+    /// Without this rule, reversing every chargeback of an account unlocks it.
+    /// The rule is reported by [`AccountStates::auto_lock`] and in the change stream.
+    pub fn lock_after_chargebacks(mut self, count: usize) -> Self {
+        self.lock_policy.chargebacks = Some(count);
+        self
+    }
+
+    /// Reject withdrawals of more than `max` at once
+    pub fn max_withdrawal(mut self, max: Balance) -> Self {
+        self.withdrawal_limits.max_single = Some(max);
+        self
+    }
+
+    /// Reject withdrawals that would take the withdrawals of a client on one UTC day beyond `max`
+    ///
+    /// Only withdrawals with a timestamp are counted and checked,
+    /// see [`AccountStates::process_at`].
+    pub fn max_daily_withdrawals(mut self, max: Balance) -> Self {
+        self.withdrawal_limits.max_daily = Some(max);
+        self
+    }
+
+    /// Reject withdrawals that would leave less than `min` available
+    pub fn min_balance(mut self, min: Balance) -> Self {
+        self.withdrawal_limits.min_balance = Some(min);
+        self
+    }
+
+    /// Apply `policy` to `client` instead of the global rules it overrides,
+    /// replacing any earlier policy of the client
+    pub fn client_policy(mut self, client: ClientId, policy: ClientPolicy) -> Self {
+        self.client_policies.insert(client, policy);
+        self
+    }
+
+    /// Charge the fees of `schedule` to every applied withdrawal and chargeback
+    ///
+    /// Charged fees are kept for the ledger until the next [`AccountStates::end_of_day`].
+    pub fn fees(mut self, schedule: FeeSchedule) -> Self {
+        self.fees = Some(schedule);
+        self
+    }
+
+    /// Post interest at `annual_basis_points` a year, negative to charge it,
+    /// at every [`AccountStates::end_of_day`]
+    pub fn interest(mut self, annual_basis_points: i32) -> Self {
+        self.interest_basis_points = annual_basis_points;
+        self
+    }
+
+    /// Select how disputes of withdrawals affect balances
+    pub fn dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.dispute_policy = policy;
+        self
+    }
+
+    /// Select how chargebacks of deposits whose funds were already withdrawn are booked
+    pub fn chargeback_policy(mut self, policy: ChargebackPolicy) -> Self {
+        self.chargeback_policy = policy;
+        self
+    }
+
+    /// Reject disputes of transactions older than `window`
+    pub fn dispute_window(mut self, window: DisputeWindow) -> Self {
+        self.dispute_window = Some(window);
+        self
+    }
+
+    /// Evict transactions beyond `retention` to cap the memory of long-lived accounts
+    pub fn retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Reject actions whose timestamp is before that of an earlier action,
+    /// see [`AccountStates::process_at`]
+    pub fn chronological(mut self) -> Self {
+        self.chronological = true;
+        self
+    }
+
+    /// Index the client of every stored transaction, so that disputes, resolutions,
+    /// chargebacks and returns against the transaction of another client
+    /// are rejected with [`Rejection::ClientMismatch`](crate::Rejection::ClientMismatch)
+    /// instead of as unknown or undisputed transactions
+    ///
+    /// The index costs an entry per stored transaction, shared by all clients.
+    pub fn transaction_index(mut self) -> Self {
+        self.transaction_index = true;
+        self
+    }
+
+    /// Allocate the transaction maps of the accounts from one arena of the state,
+    /// only available with the `arena` feature
+    ///
+    /// Suits giant batch runs: accounts are created faster and lie closer together,
+    /// but memory is only given back once the state and all accounts taken out of it
+    /// are dropped, including the tables that maps outgrew. Only the default [`MemoryStore`]
+    /// uses the arena.
+    #[cfg(feature = "arena")]
+    pub fn arena(mut self) -> Self {
+        self.arena = true;
+        self
+    }
+
+    /// Size for an input of about `rows` actions
+    /// whose distribution over clients is not known in advance
+    pub fn estimated_rows(self, rows: usize) -> Self {
+        let clients = rows.min(MAX_CLIENTS);
+        self.clients(clients).txs_per_client(rows / clients.max(1))
+    }
+
+    pub fn build(self) -> AccountStates {
+        let store = MemoryStore {
+            #[cfg(feature = "arena")]
+            alloc: if self.arena {
+                crate::arena::AccountAlloc::arena()
+            } else {
+                crate::arena::AccountAlloc::Global
+            },
+            ..MemoryStore::with_capacity(self.clients.min(MAX_CLIENTS), self.txs_per_client)
+        };
+        self.build_with(store)
+    }
+
+    /// Build states that keep their accounts in `store`
+    ///
+    /// The expected numbers of clients and transactions only size the default [`MemoryStore`],
+    /// other stores are used as they are.
+    pub fn build_with<S: AccountStore>(self, store: S) -> AccountStates<S> {
+        AccountStates {
+            accounts: store,
+            limits: self.limits,
+            lock_policy: self.lock_policy,
+            withdrawal_limits: self.withdrawal_limits,
+            client_policies: self.client_policies,
+            fees: self.fees,
+            pending_fees: Vec::new(),
+            interest_basis_points: self.interest_basis_points,
+            dispute_policy: self.dispute_policy,
+            chargeback_policy: self.chargeback_policy,
+            dispute_window: self.dispute_window,
+            retention: self.retention,
+            stored_transactions: 0,
+            period: <_>::default(),
+            archived: <_>::default(),
+            generation: 0,
+            chronological: self.chronological,
+            latest: None,
+            owners: self.transaction_index.then(HashMap::new),
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl AccountStates {
+    pub fn builder() -> AccountStatesBuilder {
+        <_>::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, ClientId, Outcome, Rejection, TransactionId};
+
+    #[cfg(feature = "arena")]
+    #[test]
+    fn match_global_allocation_with_arena() {
+        use crate::synthetic::{self, WorkloadConfig};
+
+        let config = WorkloadConfig {
+            rows: 20_000,
+            clients: 50,
+            ..Default::default()
+        };
+        // Retention and a dispute window exercise every map of the accounts
+        let builder = || {
+            AccountStates::builder()
+                .txs_per_client(4)
+                .retention(Retention::PerClient(100))
+                .dispute_window(DisputeWindow::Transactions(50))
+        };
+        let (mut global, mut arena) = (builder().build(), builder().arena().build());
+        for action in synthetic::generate(&config) {
+            assert_eq!(global.process(action.clone()), arena.process(action));
+        }
+        assert_eq!(arena, global);
+        assert_eq!(arena.clone().summary(), global.summary());
+    }
+
+    #[test]
+    fn build_with_capacity() {
+        let states = AccountStates::builder()
+            .clients(100)
+            .txs_per_client(10)
+            .build();
+        assert!(states.accounts.map.capacity() >= 100);
+        assert_eq!(states.accounts.txs_per_client, 10);
+
+        let states = AccountStates::builder().estimated_rows(1_000_000).build();
+        assert!(states.accounts.map.capacity() >= MAX_CLIENTS);
+        assert_eq!(states.accounts.txs_per_client, 1_000_000 / MAX_CLIENTS);
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let mut states = AccountStates::builder()
+            .max_clients(2)
+            .max_transactions(3)
+            .build();
+        let deposit = |client, transaction| Action::Deposit {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        assert_eq!(states.process(deposit(1, 1)), Outcome::Applied);
+        assert_eq!(states.process(deposit(2, 2)), Outcome::Applied);
+        assert_eq!(
+            states.process(deposit(3, 3)),
+            Outcome::Rejected(Rejection::ClientLimit)
+        );
+        assert_eq!(
+            states.explain(&deposit(3, 3)).to_string(),
+            "rejected: limit of distinct clients reached at 2"
+        );
+        assert_eq!(states.process(deposit(1, 3)), Outcome::Applied);
+        assert_eq!(
+            states.process(deposit(1, 4)),
+            Outcome::Rejected(Rejection::TransactionLimit)
+        );
+        for action in [
+            Action::Dispute {
+                client: ClientId(1),
+                transaction: TransactionId(1),
+            },
+            Action::Resolve {
+                client: ClientId(1),
+                transaction: TransactionId(1),
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert_eq!(states.process(deposit(2, 4)), Outcome::Applied);
+        assert_eq!(
+            states
+                .process_batch(&[deposit(2, 5)])
+                .unwrap_err()
+                .downcast::<Rejection>()
+                .unwrap(),
+            Rejection::TransactionLimit
+        );
+    }
+
+    #[test]
+    fn reject_disputes_of_other_clients() {
+        let deposit = |client, transaction| Action::Deposit {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        let dispute = |client, transaction| Action::Dispute {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+        };
+
+        let mut states = AccountStates::default();
+        states.process(deposit(1, 1));
+        assert_eq!(
+            states.process(dispute(2, 1)),
+            Outcome::Rejected(Rejection::UnknownTransaction)
+        );
+
+        let mut states = AccountStates::builder().transaction_index().build();
+        for action in [deposit(1, 1), deposit(2, 2), dispute(2, 2)] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert_eq!(
+            states.explain(&dispute(1, 2)).to_string(),
+            "rejected: transaction belongs to another client, client 2"
+        );
+        states
+            .process_batch(&[
+                dispute(1, 2),
+                Action::Chargeback {
+                    client: ClientId(1),
+                    transaction: TransactionId(2),
+                },
+            ])
+            .unwrap();
+        assert_eq!(states.period_totals().rejected, 2);
+        assert_eq!(
+            states.process(dispute(3, 2)),
+            Outcome::Rejected(Rejection::ClientMismatch)
+        );
+
+        states.process(Action::Return {
+            client: ClientId(1),
+            transaction: TransactionId(1),
+        });
+        assert_eq!(
+            states.process(dispute(2, 1)),
+            Outcome::Rejected(Rejection::UnknownTransaction)
+        );
+    }
+}
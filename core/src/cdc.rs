@@ -0,0 +1,158 @@
+//! Change-data-capture stream of account balance changes
+//!
+//! Every applied action that changes an account emits one [`BalanceChange`]
+//! per changed field, so downstream caches can follow the state without polling summaries.
+//! [`AccountStates::end_of_day_with_changes`] does the same for the interest of the day.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use alloc::{string::String, vec::Vec};
+use core::convert::Infallible;
+
+use crate::{
+    infallible,
+    period::{LedgerEntry, LedgerKind},
+    policy::AutoLock,
+    store_failure, AccountStates, AccountStore, Action, Balance, ClientId, Outcome, TransactionId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangedField {
+    Available,
+    Held,
+    Locked,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Amount(Balance),
+    Flag(bool),
+}
+
+#[derive(Clone, Serialize)]
+pub struct BalanceChange {
+    pub client: ClientId,
+    pub field: ChangedField,
+    pub old: FieldValue,
+    pub new: FieldValue,
+    /// The transaction of the action causing the change, `None` for interest
+    #[serde(rename = "tx", skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionId>,
+    /// The policy rule behind a lock that was not caused by a chargeback
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<AutoLock>,
+}
+
+/// Destination of a change stream
+pub trait ChangeSink {
+    fn emit(&mut self, change: &BalanceChange) -> Result<()>;
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Apply an action and report every field it changed to `sink`
+    ///
+    /// Fails if the action would exceed a configured limit, found the account inconsistent
+    /// or the store failed.
+    pub fn process_with_changes(
+        &mut self,
+        action: Action,
+        sink: &mut impl ChangeSink,
+    ) -> Result<()> {
+        let client = action.client();
+        let transaction = action.transaction();
+        let stored = self
+            .accounts
+            .get_partial(client, &[])
+            .map_err(|error| store_failure(&action, error))?;
+        let (available, held, locked) = match stored {
+            Some(account) => (
+                account.available.clone(),
+                account.held.clone(),
+                account.locked,
+            ),
+            None => (Balance::default(), Balance::default(), false),
+        };
+        match self.try_apply_at(&action, None) {
+            Ok(Outcome::Applied) => {}
+            Ok(Outcome::Rejected(rejection)) if rejection.is_limit() => bail!(rejection),
+            Ok(Outcome::Rejected(_)) => return Ok(()),
+            Ok(Outcome::Failed(error)) => bail!(error),
+            Err(error) => return Err(store_failure(&action, error)),
+        }
+        let account = self
+            .accounts
+            .get_partial(client, &[])
+            .map_err(|error| store_failure(&action, error))?
+            .expect("an applied action leaves an account");
+
+        let mut emit = |field, old, new, reason| {
+            sink.emit(&BalanceChange {
+                client,
+                field,
+                old,
+                new,
+                transaction: Some(transaction),
+                reason,
+            })
+        };
+        if account.available != available {
+            emit(
+                ChangedField::Available,
+                FieldValue::Amount(available),
+                FieldValue::Amount(account.available.clone()),
+                None,
+            )?;
+        }
+        if account.held != held {
+            emit(
+                ChangedField::Held,
+                FieldValue::Amount(held),
+                FieldValue::Amount(account.held.clone()),
+                None,
+            )?;
+        }
+        if account.locked != locked {
+            emit(
+                ChangedField::Locked,
+                FieldValue::Flag(locked),
+                FieldValue::Flag(account.locked),
+                account.auto_lock,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Close the day like [`AccountStates::end_of_day`], reporting the available funds
+    /// changed by interest to `sink`, without a transaction
+    pub fn end_of_day_with_changes(
+        &mut self,
+        date: impl Into<String>,
+        sink: &mut impl ChangeSink,
+    ) -> Result<Vec<LedgerEntry>> {
+        let ledger = self.end_of_day(date);
+        for entry in ledger.iter().filter(|entry| entry.kind != LedgerKind::Fee) {
+            let available = infallible(self.accounts.get_partial(entry.client, &[]))
+                .expect("interest is posted to an account")
+                .available
+                .clone();
+            let old = match entry.kind {
+                LedgerKind::NegativeInterest => &available + &entry.amount,
+                _ => (available.clone() - entry.amount.clone()).unwrap_or_default(),
+            };
+            sink.emit(&BalanceChange {
+                client: entry.client,
+                field: ChangedField::Available,
+                old: FieldValue::Amount(old),
+                new: FieldValue::Amount(available),
+                transaction: None,
+                reason: None,
+            })?;
+        }
+        Ok(ledger)
+    }
+}
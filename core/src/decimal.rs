@@ -0,0 +1,261 @@
+use alloc::vec::Vec;
+use core::{fmt::Display, str::FromStr};
+
+use num::{BigUint, ToPrimitive};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Ordering, equality and hashing follow the numeric value
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Balance(pub(crate) Repr);
+
+/// Amount in 1/10000 units
+///
+/// Values that fit in `u64` are always stored inline,
+/// and only larger values are promoted to a heap-allocated `BigUint`,
+/// so that the derived equality, ordering and hashing are also numeric.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum Repr {
+    Inline(u64),
+    Heap(BigUint),
+}
+
+impl Default for Repr {
+    fn default() -> Self {
+        Self::Inline(0)
+    }
+}
+
+impl From<u64> for Repr {
+    fn from(units: u64) -> Self {
+        Self::Inline(units)
+    }
+}
+
+impl From<BigUint> for Repr {
+    fn from(units: BigUint) -> Self {
+        match units.to_u64() {
+            Some(units) => Self::Inline(units),
+            None => Self::Heap(units),
+        }
+    }
+}
+
+impl Balance {
+    /// The amount in 1/10000 units as a `BigUint`
+    pub fn to_biguint(&self) -> BigUint {
+        match &self.0 {
+            Repr::Inline(units) => (*units).into(),
+            Repr::Heap(units) => units.clone(),
+        }
+    }
+
+    /// The amount in 1/10000 units as little-endian bytes, without trailing zero bytes
+    pub fn to_units_le_bytes(&self) -> Vec<u8> {
+        match &self.0 {
+            Repr::Inline(units) => {
+                let bytes = units.to_le_bytes();
+                let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+                bytes[..len].to_vec()
+            }
+            Repr::Heap(units) => units.to_bytes_le(),
+        }
+    }
+
+    /// Inverse of [`Balance::to_units_le_bytes`]
+    pub fn from_units_le_bytes(bytes: &[u8]) -> Self {
+        Self(if bytes.len() <= 8 {
+            let mut units = [0; 8];
+            units[..bytes.len()].copy_from_slice(bytes);
+            Repr::Inline(u64::from_le_bytes(units))
+        } else {
+            BigUint::from_bytes_le(bytes).into()
+        })
+    }
+}
+
+impl Display for Balance {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.0 {
+            Repr::Inline(units) => write!(f, "{}.{:>04}", units / 10000, units % 10000),
+            Repr::Heap(units) => write!(f, "{}.{:>04}", units / 10000u32, units % 10000u32),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DecimalError;
+
+/// Number of integral digits that can be scaled to 1/10000 units without overflowing `u64`
+const FAST_PATH_INTEGRAL_DIGITS: usize = 15;
+const SCALE: &[u64] = &[1, 10, 100, 1000, 10000];
+
+fn is_digits(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Caller must make sure `s` is all digits and short enough to fit in `u64`
+fn parse_u64(s: &str) -> u64 {
+    s.bytes().fold(0, |acc, b| acc * 10 + u64::from(b - b'0'))
+}
+
+impl FromStr for Balance {
+    type Err = DecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (integral, fractional_part) = s.split_once('.').unwrap_or((s, ""));
+        if integral.is_empty() || !is_digits(integral) || !is_digits(fractional_part) {
+            return Err(DecimalError);
+        }
+        let fractional_part = &fractional_part[..fractional_part.len().min(4)];
+        let fractional = parse_u64(fractional_part) * SCALE[4 - fractional_part.len()];
+        if integral.len() <= FAST_PATH_INTEGRAL_DIGITS {
+            Ok(Self(Repr::Inline(parse_u64(integral) * 10000 + fractional)))
+        } else {
+            let integral: BigUint = integral.parse().map_err(|_| DecimalError)?;
+            Ok(Self((integral * 10000u32 + fractional).into()))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Balance {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Balance;
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(formatter, "decimal number")
+            }
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.parse()
+                    .map_err(|_| E::custom("invalid decimal specification"))
+            }
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                core::str::from_utf8(v)
+                    .map_err(|_| E::custom("invalid decimal specification"))
+                    .and_then(|v| self.visit_str(v))
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+impl Serialize for Balance {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_correctly() {
+        assert_eq!(Balance(1u64.into()).to_string(), "0.0001");
+        assert_eq!(Balance(0u64.into()).to_string(), "0.0000");
+        assert_eq!(Balance(10000u64.into()).to_string(), "1.0000");
+        assert_eq!(Balance(100001u64.into()).to_string(), "10.0001");
+    }
+
+    #[test]
+    fn parse_correctly() {
+        assert!(Balance::from_str("not a number").is_err());
+        assert!(Balance::from_str("  1.100001.  ").is_err());
+        assert_eq!(
+            Balance::from_str("  1.100001  ").unwrap().0,
+            11000u64.into()
+        );
+        assert_eq!(Balance::from_str("  1.1  ").unwrap().0, 11000u64.into());
+        assert_eq!(Balance::from_str("  1. ").unwrap().0, 10000u64.into());
+        assert_eq!(Balance::from_str("  1 ").unwrap().0, 10000u64.into());
+        assert_eq!(Balance::from_str("  0 ").unwrap().0, 0u64.into());
+        assert_eq!(Balance::from_str("  10 ").unwrap().0, 100000u64.into());
+        assert!(Balance::from_str(" .5 ").is_err());
+        assert!(Balance::from_str("1.5a").is_err());
+        assert_eq!(
+            Balance::from_str("999999999999999.9999").unwrap().0,
+            9999999999999999999u64.into()
+        );
+        assert_eq!(
+            Balance::from_str("12345678901234567890.12345")
+                .unwrap()
+                .to_string(),
+            "12345678901234567890.1234"
+        );
+    }
+
+    #[test]
+    fn deserialize_from_bytes() {
+        use serde::de::value::{BorrowedBytesDeserializer, Error};
+        let balance =
+            Balance::deserialize(BorrowedBytesDeserializer::<Error>::new(b" 2.5 ")).unwrap();
+        assert_eq!(balance.0, 25000u64.into());
+        assert!(Balance::deserialize(BorrowedBytesDeserializer::<Error>::new(b"\xff")).is_err());
+    }
+
+    #[test]
+    fn promote_and_demote() {
+        let max = Balance(u64::MAX.into());
+        let one = Balance(1u64.into());
+        let promoted = &max + &one;
+        assert!(matches!(promoted.0, Repr::Heap(_)));
+        assert_eq!(promoted.to_string(), "1844674407370955.1616");
+        let demoted = (promoted - one).unwrap();
+        assert_eq!(demoted.0, Repr::Inline(u64::MAX));
+        assert!((max - Balance(Repr::Heap(BigUint::from(u64::MAX) + 1u8))).is_none());
+    }
+
+    #[test]
+    fn ordering_and_checked_arithmetic() {
+        let small = Balance::from_str("1.5").unwrap();
+        let large = Balance::from_str("2.25").unwrap();
+        let huge = Balance::from_str("12345678901234567890").unwrap();
+        assert!(small < large && large < huge);
+        assert_eq!(small.cmp(&small.clone()), std::cmp::Ordering::Equal);
+        assert_eq!(small.checked_add(&large).unwrap().to_string(), "3.7500");
+        assert_eq!(large.checked_sub(&small).unwrap().to_string(), "0.7500");
+        assert!(small.checked_sub(&large).is_none());
+        assert!(small.saturating_sub(&large).is_zero());
+        assert_eq!(small.abs_diff(&large), large.abs_diff(&small));
+        assert_eq!(small.abs_diff(&large).to_string(), "0.7500");
+        assert_eq!(
+            huge.abs_diff(&small).to_string(),
+            "12345678901234567888.5000"
+        );
+    }
+
+    #[test]
+    fn total_and_hash() {
+        let amounts: Vec<Balance> = ["1.5", "2.25", "0.0001"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let total: Balance = amounts.iter().sum();
+        assert_eq!(total.to_string(), "3.7501");
+        assert_eq!(amounts.iter().cloned().sum::<Balance>(), total);
+        assert_eq!(amounts.into_iter().collect::<Balance>(), total);
+        assert_eq!(
+            std::iter::empty::<Balance>().sum::<Balance>(),
+            Balance::default()
+        );
+
+        let distinct: std::collections::HashSet<Balance> = ["1.5", "1.50", "1.5000", "2"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(distinct.len(), 2);
+    }
+}
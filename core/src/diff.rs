@@ -0,0 +1,146 @@
+//! Per-client differences between two states
+//!
+//! Diffing the state before a batch with the state after it shows exactly what the
+//! batch changed: balances, lock flags, and the disputes it opened and closed.
+
+use alloc::{collections::BTreeSet, vec::Vec};
+use core::convert::Infallible;
+
+use crate::{
+    AccountState, AccountStates, AccountStore, AccountSummary, AccountView, ClientId, TransactionId,
+};
+
+/// Change of one client's account between two states
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub client: ClientId,
+    /// The account in the earlier state, empty and unlocked if it had none
+    pub before: AccountSummary,
+    /// The account in the later state, empty and unlocked if it has none
+    pub after: AccountSummary,
+    /// Disputes open in the later state only, in transaction order
+    pub opened_disputes: Vec<TransactionId>,
+    /// Disputes open in the earlier state only, in transaction order
+    pub closed_disputes: Vec<TransactionId>,
+}
+
+/// Every client whose account changed between two states, ordered by client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl StateDiff {
+    /// Whether no account changed
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// The changes from this state to `other`, as balances, lock flags and open disputes
+    ///
+    /// Archived accounts compare as locked and empty, like in their summaries.
+    pub fn diff<T: AccountStore<Error = Infallible>>(&self, other: &AccountStates<T>) -> StateDiff {
+        let clients: BTreeSet<_> = self
+            .accounts()
+            .chain(other.accounts())
+            .map(|account| account.client())
+            .collect();
+        let empty = AccountState::default();
+        let accounts = clients
+            .into_iter()
+            .filter_map(|client| {
+                let (before, after) = (self.account(client), other.account(client));
+                let summary = |account: &Option<AccountView<'_>>| match account {
+                    Some(account) => account.summary(),
+                    None => AccountSummary::new(client, &empty),
+                };
+                let disputes = |account: &Option<AccountView<'_>>| -> BTreeSet<_> {
+                    account
+                        .iter()
+                        .flat_map(|account| account.open_disputes())
+                        .collect()
+                };
+                let (open_before, open_after) = (disputes(&before), disputes(&after));
+                let diff = AccountDiff {
+                    client,
+                    before: summary(&before),
+                    after: summary(&after),
+                    opened_disputes: open_after.difference(&open_before).copied().collect(),
+                    closed_disputes: open_before.difference(&open_after).copied().collect(),
+                };
+                let changed = diff.before != diff.after
+                    || !diff.opened_disputes.is_empty()
+                    || !diff.closed_disputes.is_empty();
+                changed.then_some(diff)
+            })
+            .collect();
+        StateDiff { accounts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    #[test]
+    fn diff_batch() {
+        let client = ClientId(1);
+        let mut before = AccountStates::default();
+        for (transaction, amount) in [(1, "4"), (2, "1")] {
+            before.process(Action::Deposit {
+                client,
+                transaction: TransactionId(transaction),
+                amount: amount.parse().unwrap(),
+            });
+        }
+        before.process(Action::Dispute {
+            client,
+            transaction: TransactionId(1),
+        });
+        let mut after = before.clone();
+        assert!(before.diff(&after).is_empty());
+        for action in [
+            Action::Resolve {
+                client,
+                transaction: TransactionId(1),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId(2),
+            },
+            Action::Deposit {
+                client: ClientId(2),
+                transaction: TransactionId(3),
+                amount: "3".parse().unwrap(),
+            },
+        ] {
+            after.process(action);
+        }
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.accounts
+                .iter()
+                .map(|account| (
+                    u16::from(account.client),
+                    account.before.available().to_string(),
+                    account.after.available().to_string(),
+                    account.opened_disputes.clone(),
+                    account.closed_disputes.clone(),
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    1,
+                    "1.0000".into(),
+                    "4.0000".into(),
+                    [TransactionId(2)].into(),
+                    [TransactionId(1)].into(),
+                ),
+                (2, "0.0000".into(), "3.0000".into(), [].into(), [].into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! Structured explanations of what the engine would do with an action
+
+use core::{convert::Infallible, fmt::Display};
+
+use crate::{
+    infallible,
+    lifecycle::DisputeStage,
+    policy::{ChargebackPolicy, DisputePolicy},
+    AccountState, AccountStates, AccountStore, Action, Balance, ClientId, Rejection,
+    TransactionKind,
+};
+
+/// Why an action would be applied or rejected, with the state it was checked against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Explanation {
+    /// The action would be applied
+    Accepted,
+    /// The account was locked by an earlier chargeback
+    AccountLocked,
+    /// The client is suspended by its client policy
+    Suspended,
+    /// A deposit or withdrawal reuses the id of `existing`
+    DuplicateTransaction { existing: TransactionKind },
+    /// The available funds cannot cover `required`
+    InsufficientFunds {
+        available: Balance,
+        required: Balance,
+    },
+    /// No open transaction of the client has this id
+    UnknownTransaction,
+    /// The transaction belongs to `owner`
+    ClientMismatch { owner: ClientId },
+    /// `disputed` is already under dispute
+    AlreadyDisputed { disputed: TransactionKind },
+    /// `existing`, if any, is not under dispute
+    NotDisputed { existing: Option<TransactionKind> },
+    /// The action only applies to deposits, but the transaction is a withdrawal of `amount`
+    NotDeposit { amount: Balance },
+    /// Only a locked account can be unlocked
+    NotLocked,
+    /// Only a chargeback that was not represented yet can be represented
+    NotChargedBack,
+    /// Only a represented chargeback can be reversed
+    NotRepresented,
+    /// The disputed transaction is older than the configured dispute window
+    OutsideDisputeWindow,
+    /// The transaction was evicted by the configured retention policy
+    Evicted,
+    /// The state already holds the configured maximum of `max` clients
+    ClientLimit { max: usize },
+    /// The state already retains the configured maximum of `max` transactions
+    TransactionLimit { max: usize },
+    /// The withdrawal is larger than the configured maximum of `max`
+    OverWithdrawalLimit { max: Balance },
+    /// The withdrawal would leave less available than the configured minimum of `min`
+    BelowMinimumBalance { min: Balance },
+}
+
+impl Explanation {
+    /// The rejection `process` would report, or `None` if the action would be applied
+    pub fn rejection(&self) -> Option<Rejection> {
+        Some(match self {
+            Explanation::Accepted => return None,
+            Explanation::AccountLocked => Rejection::AccountLocked,
+            Explanation::Suspended => Rejection::Suspended,
+            Explanation::DuplicateTransaction { .. } => Rejection::DuplicateTransaction,
+            Explanation::InsufficientFunds { .. } => Rejection::InsufficientFunds,
+            Explanation::UnknownTransaction => Rejection::UnknownTransaction,
+            Explanation::ClientMismatch { .. } => Rejection::ClientMismatch,
+            Explanation::AlreadyDisputed { .. } => Rejection::AlreadyDisputed,
+            Explanation::NotDisputed { .. } => Rejection::NotDisputed,
+            Explanation::NotDeposit { .. } => Rejection::NotDeposit,
+            Explanation::NotLocked => Rejection::NotLocked,
+            Explanation::NotChargedBack => Rejection::NotChargedBack,
+            Explanation::NotRepresented => Rejection::NotRepresented,
+            Explanation::OutsideDisputeWindow => Rejection::OutsideDisputeWindow,
+            Explanation::Evicted => Rejection::Evicted,
+            Explanation::ClientLimit { .. } => Rejection::ClientLimit,
+            Explanation::TransactionLimit { .. } => Rejection::TransactionLimit,
+            Explanation::OverWithdrawalLimit { .. } => Rejection::OverWithdrawalLimit,
+            Explanation::BelowMinimumBalance { .. } => Rejection::BelowMinimumBalance,
+        })
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.rejection() {
+            None => return f.write_str("accepted"),
+            Some(rejection) => write!(f, "rejected: {rejection}")?,
+        }
+        match self {
+            Explanation::DuplicateTransaction { existing } => {
+                write!(f, " by an earlier {}", describe(existing))
+            }
+            Explanation::InsufficientFunds {
+                available,
+                required,
+            } => write!(f, ", {available} available but {required} required"),
+            Explanation::AlreadyDisputed { disputed } => {
+                write!(f, ", the disputed transaction is a {}", describe(disputed))
+            }
+            Explanation::NotDisputed {
+                existing: Some(existing),
+            } => write!(f, ", the transaction is a {}", describe(existing)),
+            Explanation::NotDeposit { amount } => write!(f, ", it is a withdrawal of {amount}"),
+            Explanation::ClientMismatch { owner } => write!(f, ", client {}", owner.0),
+            Explanation::ClientLimit { max } | Explanation::TransactionLimit { max } => {
+                write!(f, " at {max}")
+            }
+            Explanation::OverWithdrawalLimit { max } => write!(f, " of {max}"),
+            Explanation::BelowMinimumBalance { min } => write!(f, " of {min}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn describe(kind: &TransactionKind) -> impl Display + '_ {
+    struct Describe<'a>(&'a TransactionKind);
+    impl Display for Describe<'_> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            match self.0 {
+                TransactionKind::Deposit(amount) => write!(f, "deposit of {amount}"),
+                TransactionKind::Withdrawal(amount) => write!(f, "withdrawal of {amount}"),
+            }
+        }
+    }
+    Describe(kind)
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Explain what [`AccountStates::process`] would do with `action`, without applying it
+    pub fn explain(&self, action: &Action) -> Explanation {
+        match infallible(self.check_admission(action)) {
+            Some(Rejection::AccountLocked) => return Explanation::AccountLocked,
+            Some(Rejection::Suspended) => return Explanation::Suspended,
+            Some(Rejection::ClientMismatch) => {
+                return Explanation::ClientMismatch {
+                    owner: infallible(self.transaction_owner(action))
+                        .expect("a mismatch has an owner"),
+                }
+            }
+            Some(Rejection::ClientLimit) => {
+                return Explanation::ClientLimit {
+                    max: self.limits.clients.unwrap_or_default(),
+                }
+            }
+            Some(_) => {
+                return Explanation::TransactionLimit {
+                    max: self.limits.transactions.unwrap_or_default(),
+                }
+            }
+            None => {}
+        }
+        // Without a timestamp, only a window counted in transactions can apply
+        if infallible(self.outside_dispute_window(action, None)) {
+            return Explanation::OutsideDisputeWindow;
+        }
+        // Likewise the daily volume of withdrawals is not checked
+        let limits = self
+            .withdrawal_limits
+            .of_client(&self.client_policies, action.client());
+        match infallible(self.check_withdrawal(action, None)) {
+            Some(Rejection::OverWithdrawalLimit) => {
+                return Explanation::OverWithdrawalLimit {
+                    max: limits.max_single.clone().unwrap_or_default(),
+                }
+            }
+            Some(_) => {
+                return Explanation::BelowMinimumBalance {
+                    min: limits.min_balance.clone().unwrap_or_default(),
+                }
+            }
+            None => {}
+        }
+        if let (Some(_), Some(fees), Action::Withdrawal { amount, .. }) =
+            (infallible(self.check_fee(action)), &self.fees, action)
+        {
+            return Explanation::InsufficientFunds {
+                available: self
+                    .account(action.client())
+                    .map(|account| account.available().clone())
+                    .unwrap_or_default(),
+                required: amount + fees.withdrawal_fee(amount),
+            };
+        }
+        match infallible(self.accounts.get(action.client())) {
+            Some(account) => account.explain(action, self.dispute_policy, self.chargeback_policy),
+            // Only admin actions get past the admission of an archived account, which they restore
+            None if self.is_archived(action.client()) => self.archived[&action.client()].explain(
+                action,
+                self.dispute_policy,
+                self.chargeback_policy,
+            ),
+            None => {
+                AccountState::default().explain(action, self.dispute_policy, self.chargeback_policy)
+            }
+        }
+    }
+}
+
+impl AccountState {
+    /// Mirrors the checks of `apply`
+    fn explain(
+        &self,
+        action: &Action,
+        policy: DisputePolicy,
+        chargeback: ChargebackPolicy,
+    ) -> Explanation {
+        if self.locked && !action.is_admin() {
+            return Explanation::AccountLocked;
+        }
+        let transaction = action.transaction();
+        let existing = self.transaction_amounts.get(&transaction);
+        match (action, existing) {
+            (Action::Deposit { .. } | Action::Withdrawal { .. }, Some(existing)) => {
+                Explanation::DuplicateTransaction {
+                    existing: existing.clone(),
+                }
+            }
+            (
+                Action::Deposit { .. }
+                | Action::Withdrawal { .. }
+                | Action::Dispute { .. }
+                | Action::Return { .. }
+                | Action::Representment { .. }
+                | Action::ChargebackReversal { .. },
+                None,
+            ) if self.is_evicted(transaction) => Explanation::Evicted,
+            (Action::Deposit { .. }, None) => Explanation::Accepted,
+            (Action::Withdrawal { amount, .. }, None) => self.cover(amount),
+            (Action::Dispute { .. } | Action::Return { .. }, Some(kind))
+                if self.disputes.contains(&transaction) =>
+            {
+                Explanation::AlreadyDisputed {
+                    disputed: kind.clone(),
+                }
+            }
+            (Action::Dispute { .. }, Some(kind)) if self.chargebacks.contains_key(&transaction) => {
+                Explanation::AlreadyDisputed {
+                    disputed: kind.clone(),
+                }
+            }
+            (Action::Dispute { .. }, Some(TransactionKind::Deposit(_)))
+                if chargeback.seizes_available() =>
+            {
+                Explanation::Accepted
+            }
+            (Action::Dispute { .. }, Some(TransactionKind::Deposit(amount))) => self.cover(amount),
+            (Action::Dispute { .. }, Some(TransactionKind::Withdrawal(amount)))
+                if policy == DisputePolicy::DepositsOnly =>
+            {
+                Explanation::NotDeposit {
+                    amount: amount.clone(),
+                }
+            }
+            (Action::Dispute { .. }, Some(TransactionKind::Withdrawal(_))) => Explanation::Accepted,
+            (Action::Dispute { .. }, None) => Explanation::UnknownTransaction,
+            (Action::Return { .. }, Some(TransactionKind::Deposit(amount))) => self.cover(amount),
+            (Action::Return { .. }, Some(TransactionKind::Withdrawal(amount))) => {
+                Explanation::NotDeposit {
+                    amount: amount.clone(),
+                }
+            }
+            (Action::Return { .. }, None) => Explanation::UnknownTransaction,
+            (Action::Representment { .. } | Action::ChargebackReversal { .. }, None) => {
+                Explanation::UnknownTransaction
+            }
+            (Action::Representment { .. }, Some(_)) => match self.chargebacks.get(&transaction) {
+                Some(charged_back) if charged_back.stage == DisputeStage::ChargedBack => {
+                    Explanation::Accepted
+                }
+                _ => Explanation::NotChargedBack,
+            },
+            (Action::ChargebackReversal { .. }, Some(kind)) => {
+                match (self.chargebacks.get(&transaction), kind) {
+                    (Some(charged_back), TransactionKind::Withdrawal(_))
+                        if charged_back.stage == DisputeStage::Represented =>
+                    {
+                        self.cover(&charged_back.amount)
+                    }
+                    (Some(charged_back), TransactionKind::Deposit(_))
+                        if charged_back.stage == DisputeStage::Represented =>
+                    {
+                        Explanation::Accepted
+                    }
+                    _ => Explanation::NotRepresented,
+                }
+            }
+            (Action::Unlock { .. }, _) if self.locked => Explanation::Accepted,
+            (Action::Unlock { .. }, _) => Explanation::NotLocked,
+            (Action::CreditAdjustment { .. }, _) => Explanation::Accepted,
+            (Action::DebitAdjustment { amount, .. }, _) => self.cover(amount),
+            (Action::Resolve { .. } | Action::Chargeback { .. }, _) => {
+                if self.disputes.contains(&transaction) {
+                    Explanation::Accepted
+                } else {
+                    Explanation::NotDisputed {
+                        existing: existing.cloned(),
+                    }
+                }
+            }
+        }
+    }
+
+    fn cover(&self, required: &Balance) -> Explanation {
+        if self.available >= *required {
+            Explanation::Accepted
+        } else {
+            Explanation::InsufficientFunds {
+                available: self.available.clone(),
+                required: required.clone(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        synthetic::{generate, WorkloadConfig},
+        ClientId, Outcome, TransactionId,
+    };
+
+    #[test]
+    fn agree_with_process() {
+        let config = WorkloadConfig {
+            rows: 20_000,
+            clients: 20,
+            dispute_rate: 0.1,
+            chargeback_rate: 0.05,
+            ..<_>::default()
+        };
+        let mut states = AccountStates::default();
+        for action in generate(&config) {
+            let explanation = states.explain(&action);
+            let expected = match explanation.rejection() {
+                Some(rejection) => Outcome::Rejected(rejection),
+                None => Outcome::Applied,
+            };
+            assert_eq!(states.process(action), expected);
+        }
+    }
+
+    #[test]
+    fn explain_in_words() {
+        let mut states = AccountStates::default();
+        let client = ClientId(1);
+        states.process(Action::Deposit {
+            client,
+            transaction: TransactionId(1),
+            amount: "2".parse().unwrap(),
+        });
+        let withdrawal = |transaction| Action::Withdrawal {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "5".parse().unwrap(),
+        };
+        assert_eq!(
+            states.explain(&withdrawal(2)).to_string(),
+            "rejected: insufficient available funds, 2.0000 available but 5.0000 required"
+        );
+        assert_eq!(
+            states.explain(&withdrawal(1)).to_string(),
+            "rejected: transaction id is already used by an earlier deposit of 2.0000"
+        );
+        assert_eq!(
+            states
+                .explain(&Action::Resolve {
+                    client,
+                    transaction: TransactionId(1),
+                })
+                .to_string(),
+            "rejected: transaction is not disputed, the transaction is a deposit of 2.0000"
+        );
+        assert_eq!(
+            states
+                .explain(&Action::Dispute {
+                    client: ClientId(2),
+                    transaction: TransactionId(1),
+                })
+                .to_string(),
+            "rejected: unknown transaction"
+        );
+        let return_ = |transaction| Action::Return {
+            client,
+            transaction: TransactionId(transaction),
+        };
+        assert_eq!(states.explain(&return_(1)), Explanation::Accepted);
+        states.process(Action::Withdrawal {
+            client,
+            transaction: TransactionId(3),
+            amount: "1".parse().unwrap(),
+        });
+        assert_eq!(
+            states.explain(&return_(3)).to_string(),
+            "rejected: transaction is not a deposit, it is a withdrawal of 1.0000"
+        );
+        assert_eq!(
+            states.explain(&return_(1)).rejection(),
+            Some(Rejection::InsufficientFunds)
+        );
+    }
+}
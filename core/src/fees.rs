@@ -0,0 +1,244 @@
+//! Fees charged automatically while processing
+//!
+//! With a [`FeeSchedule`] configured through
+//! [`AccountStatesBuilder::fees`](crate::AccountStatesBuilder::fees), every applied
+//! withdrawal and chargeback is charged its fee, which is credited to the available
+//! funds of the fee account. A withdrawal must be covered together with its fee.
+//! A chargeback fee is taken from the available funds as far as they go, and the rest
+//! is booked as a shortfall of the account, like a chargeback beyond the held funds.
+//! [`AccountStates::extended_summary`] reports the fees charged to each account,
+//! along with counters of its activity, and [`AccountStates::end_of_day`] lists
+//! the fees charged during the day.
+
+use core::convert::Infallible;
+
+use serde::Serialize;
+
+use crate::{AccountState, AccountStates, AccountStore, Action, Balance, ClientId, Rejection};
+
+/// Fees of withdrawals and chargebacks, all zero by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FeeSchedule {
+    /// Account the fees are credited to
+    pub account: ClientId,
+    /// Flat fee of every withdrawal
+    pub withdrawal: Balance,
+    /// Fee of every withdrawal in basis points of its amount, rounded down to 1/10000
+    pub withdrawal_basis_points: u32,
+    /// Fee of every chargeback
+    pub chargeback: Balance,
+}
+
+impl FeeSchedule {
+    /// The fee of a withdrawal of `amount`
+    pub fn withdrawal_fee(&self, amount: &Balance) -> Balance {
+        let proportional = amount.to_biguint() * self.withdrawal_basis_points / 10_000u32;
+        &self.withdrawal + Balance(proportional.into())
+    }
+
+    /// The fee of an action, if it is charged one
+    fn fee(&self, action: &Action) -> Option<Balance> {
+        let fee = match action {
+            Action::Withdrawal { amount, .. } => self.withdrawal_fee(amount),
+            Action::Chargeback { .. } => self.chargeback.clone(),
+            _ => return None,
+        };
+        (!fee.is_zero()).then_some(fee)
+    }
+
+    /// Charge the fee of an applied action to the account,
+    /// returning the fee and the part of it that was collected
+    pub(crate) fn charge(
+        &self,
+        account: &mut AccountState,
+        action: &Action,
+    ) -> Option<(Balance, Balance)> {
+        let fee = self.fee(action)?;
+        let collected = if account.available >= fee {
+            fee.clone()
+        } else {
+            let uncovered = (fee.clone() - account.available.clone()).unwrap_or_default();
+            account.shortfall += uncovered;
+            account.available.clone()
+        };
+        account.available = (account.available.clone() - collected.clone()).unwrap_or_default();
+        account.fees += &fee;
+        Some((fee, collected))
+    }
+}
+
+/// Balances of an account together with its fees, shortfall and activity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtendedSummary {
+    pub client: ClientId,
+    pub locked: bool,
+    pub available: Balance,
+    pub held: Balance,
+    pub total: Balance,
+    /// Fees charged to the account, collected or not
+    pub fees: Balance,
+    /// Funds owed beyond the balances, see [`AccountView::shortfall`](crate::AccountView::shortfall)
+    pub shortfall: Balance,
+    /// Number of deposits applied to the account
+    pub deposits: u64,
+    /// Number of withdrawals applied to the account
+    pub withdrawals: u64,
+    /// Number of disputes still open
+    pub open_disputes: usize,
+    /// Total amount of the transactions charged back and not reversed
+    pub charged_back: Balance,
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// The fee schedule configured through the builder, if any
+    pub fn fee_schedule(&self) -> Option<&FeeSchedule> {
+        self.fees.as_ref()
+    }
+
+    /// The rejection of a withdrawal that can be covered, but not together with its fee
+    ///
+    /// Withdrawals rejected for any other reason are left to be rejected as such.
+    pub(crate) fn check_fee(&self, action: &Action) -> Result<Option<Rejection>, S::Error> {
+        let (
+            Some(fees),
+            Action::Withdrawal {
+                client,
+                transaction,
+                amount,
+            },
+        ) = (&self.fees, action)
+        else {
+            return Ok(None);
+        };
+        let Some(account) = self.accounts.get_partial(*client, &[*transaction])? else {
+            return Ok(None);
+        };
+        if account.locked
+            || account.transaction_amounts.contains_key(transaction)
+            || account.available < *amount
+        {
+            return Ok(None);
+        }
+        Ok((account.available < amount + fees.withdrawal_fee(amount))
+            .then_some(Rejection::InsufficientFunds))
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Summaries of all accounts with their fees, shortfalls and activity, ordered by client id
+    pub fn extended_summary(&self) -> impl Iterator<Item = ExtendedSummary> + '_ {
+        self.accounts().map(|account| {
+            let summary = account.summary();
+            ExtendedSummary {
+                client: summary.client(),
+                locked: summary.locked(),
+                available: summary.available().clone(),
+                held: summary.held().clone(),
+                total: summary.total().clone(),
+                fees: account.fees().clone(),
+                shortfall: account.shortfall().clone(),
+                deposits: account.deposits(),
+                withdrawals: account.withdrawals(),
+                open_disputes: account.open_disputes().count(),
+                charged_back: account.charged_back().clone(),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Outcome, TransactionId};
+
+    #[test]
+    fn charge_fees() {
+        let (client, system) = (ClientId(1), ClientId(0));
+        let mut states = AccountStates::builder()
+            .fees(FeeSchedule {
+                account: system,
+                withdrawal: "0.5".parse().unwrap(),
+                withdrawal_basis_points: 100,
+                chargeback: "15".parse().unwrap(),
+            })
+            .build();
+        let withdrawal = |transaction, amount: &str| Action::Withdrawal {
+            client,
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        for (action, outcome) in [
+            (
+                Action::Deposit {
+                    client,
+                    transaction: TransactionId(1),
+                    amount: "30".parse().unwrap(),
+                },
+                Outcome::Applied,
+            ),
+            (
+                Action::Deposit {
+                    client,
+                    transaction: TransactionId(2),
+                    amount: "10".parse().unwrap(),
+                },
+                Outcome::Applied,
+            ),
+            // A fee of 0.5 + 0.2
+            (withdrawal(3, "20"), Outcome::Applied),
+            // Covered, but not with its fee of 0.5 + 0.19
+            (
+                withdrawal(4, "19"),
+                Outcome::Rejected(Rejection::InsufficientFunds),
+            ),
+            (
+                Action::Dispute {
+                    client,
+                    transaction: TransactionId(2),
+                },
+                Outcome::Applied,
+            ),
+            (
+                Action::Chargeback {
+                    client,
+                    transaction: TransactionId(2),
+                },
+                Outcome::Applied,
+            ),
+        ] {
+            assert_eq!(states.process(action), outcome);
+        }
+        assert_eq!(
+            states.explain(&withdrawal(4, "19")).to_string(),
+            "rejected: account is locked"
+        );
+        let summaries: Vec<_> = states
+            .extended_summary()
+            .map(|summary| {
+                (
+                    u16::from(summary.client),
+                    summary.available.to_string(),
+                    summary.fees.to_string(),
+                    summary.shortfall.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summaries,
+            [
+                (0, "10.0000".into(), "0.0000".into(), "0.0000".into()),
+                (1, "0.0000".into(), "15.7000".into(), "5.7000".into()),
+            ]
+        );
+        let activity = states.extended_summary().nth(1).unwrap();
+        assert_eq!(
+            (
+                activity.deposits,
+                activity.withdrawals,
+                activity.open_disputes,
+                activity.charged_back.to_string()
+            ),
+            (2, 1, 0, "10.0000".into())
+        );
+    }
+}
@@ -0,0 +1,1660 @@
+//! Dispute-aware account state machine, free of any I/O
+//!
+//! This crate is the stable public API of `transaction-processor`: actions, balances,
+//! account states and their outcomes. It only needs `core` and `alloc`, so the same
+//! dispute logic can run on targets without `std`. CSV, file and CLI support live in
+//! the `transaction-processor` crate, which re-exports everything from here.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+};
+
+use hashbrown::{hash_map::Entry, HashMap};
+use serde::{Deserialize, Serialize};
+
+mod account;
+mod archive;
+mod arena;
+mod builder;
+pub mod cdc;
+mod decimal;
+pub mod diff;
+mod explain;
+pub mod fees;
+pub mod lifecycle;
+pub mod merge;
+pub mod money;
+pub mod observer;
+mod op_impls;
+pub mod period;
+pub mod policy;
+pub mod reconcile;
+pub mod seed;
+mod serde_impls;
+#[cfg(feature = "futures")]
+mod sink_impls;
+pub mod snapshot;
+pub mod store;
+pub mod synthetic;
+mod timestamp;
+mod view;
+pub use account::AccountView;
+use arena::{AccountAlloc, AccountMap, AccountSet};
+pub use builder::AccountStatesBuilder;
+pub use decimal::Balance;
+pub use explain::Explanation;
+use fees::FeeSchedule;
+use lifecycle::{ChargedBack, DisputeStage};
+pub use observer::EventObserver;
+use period::PeriodTotals;
+use policy::{
+    AutoLock, ChargebackPolicy, ClientPolicy, DisputePolicy, DisputeWindow, LockPolicy, Retention,
+    WithdrawalLimits,
+};
+use store::infallible;
+pub use store::{AccountStore, MemoryStore};
+pub use timestamp::Timestamp;
+pub use view::ReadView;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Hash)]
+#[serde(transparent)]
+pub struct ClientId(u16);
+
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct TransactionId(u32);
+
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        Self(id)
+    }
+}
+
+impl From<ClientId> for u16 {
+    fn from(id: ClientId) -> Self {
+        id.0
+    }
+}
+
+impl From<u32> for TransactionId {
+    fn from(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+impl From<TransactionId> for u32 {
+    fn from(id: TransactionId) -> Self {
+        id.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Action {
+    Deposit {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+        amount: Balance,
+    },
+    Withdrawal {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+        amount: Balance,
+    },
+    Dispute {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    Resolve {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    Chargeback {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    /// A settled deposit came back, for example a returned ACH payment,
+    /// which takes its amount out of the available funds without locking the account
+    Return {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    /// The merchant contested a chargeback
+    Representment {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    /// A represented chargeback was decided for the merchant, which gives back the funds
+    /// the chargeback moved and unlocks the account if nothing else keeps it locked
+    #[serde(rename = "chargeback_reversal")]
+    ChargebackReversal {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    /// Operations staff reopened a locked account
+    Unlock {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+    },
+    /// Operations staff added to the available funds to correct the balance
+    #[serde(rename = "credit_adjustment")]
+    CreditAdjustment {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+        amount: Balance,
+    },
+    /// Operations staff took from the available funds to correct the balance
+    #[serde(rename = "debit_adjustment")]
+    DebitAdjustment {
+        client: ClientId,
+        #[serde(rename = "tx")]
+        transaction: TransactionId,
+        amount: Balance,
+    },
+}
+
+impl Action {
+    /// The client the action is filed against
+    pub fn client(&self) -> ClientId {
+        match *self {
+            Action::Deposit { client, .. }
+            | Action::Withdrawal { client, .. }
+            | Action::Dispute { client, .. }
+            | Action::Resolve { client, .. }
+            | Action::Chargeback { client, .. }
+            | Action::Return { client, .. }
+            | Action::Representment { client, .. }
+            | Action::ChargebackReversal { client, .. }
+            | Action::Unlock { client, .. }
+            | Action::CreditAdjustment { client, .. }
+            | Action::DebitAdjustment { client, .. } => client,
+        }
+    }
+
+    /// The transaction the action creates or refers to
+    pub fn transaction(&self) -> TransactionId {
+        match *self {
+            Action::Deposit { transaction, .. }
+            | Action::Withdrawal { transaction, .. }
+            | Action::Dispute { transaction, .. }
+            | Action::Resolve { transaction, .. }
+            | Action::Chargeback { transaction, .. }
+            | Action::Return { transaction, .. }
+            | Action::Representment { transaction, .. }
+            | Action::ChargebackReversal { transaction, .. }
+            | Action::Unlock { transaction, .. }
+            | Action::CreditAdjustment { transaction, .. }
+            | Action::DebitAdjustment { transaction, .. } => transaction,
+        }
+    }
+
+    /// The amount of a deposit, withdrawal or adjustment
+    pub fn amount(&self) -> Option<&Balance> {
+        match self {
+            Action::Deposit { amount, .. }
+            | Action::Withdrawal { amount, .. }
+            | Action::CreditAdjustment { amount, .. }
+            | Action::DebitAdjustment { amount, .. } => Some(amount),
+            Action::Dispute { .. }
+            | Action::Resolve { .. }
+            | Action::Chargeback { .. }
+            | Action::Return { .. }
+            | Action::Representment { .. }
+            | Action::ChargebackReversal { .. }
+            | Action::Unlock { .. } => None,
+        }
+    }
+
+    /// Whether the action is a correction by operations staff or the outcome of a contested
+    /// chargeback, which locked and archived accounts still accept
+    ///
+    /// Adjustments are not stored as transactions, so they cannot be disputed,
+    /// and their ids are only for reference.
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            Action::Representment { .. }
+                | Action::ChargebackReversal { .. }
+                | Action::Unlock { .. }
+                | Action::CreditAdjustment { .. }
+                | Action::DebitAdjustment { .. }
+        )
+    }
+
+    /// The `type` of the action as spelled in input files
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Action::Deposit { .. } => "deposit",
+            Action::Withdrawal { .. } => "withdrawal",
+            Action::Dispute { .. } => "dispute",
+            Action::Resolve { .. } => "resolve",
+            Action::Chargeback { .. } => "chargeback",
+            Action::Return { .. } => "return",
+            Action::Representment { .. } => "representment",
+            Action::ChargebackReversal { .. } => "chargeback_reversal",
+            Action::Unlock { .. } => "unlock",
+            Action::CreditAdjustment { .. } => "credit_adjustment",
+            Action::DebitAdjustment { .. } => "debit_adjustment",
+        }
+    }
+}
+
+pub enum Transaction {
+    Deposit {
+        client: ClientId,
+        transaction: TransactionId,
+        amount: Balance,
+    },
+    Withdrawal {
+        client: ClientId,
+        transaction: TransactionId,
+        amount: Balance,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSummary {
+    client: ClientId,
+    locked: bool,
+    available: Balance,
+    held: Balance,
+    total: Balance,
+}
+
+impl AccountSummary {
+    fn new(client: ClientId, account: &AccountState) -> Self {
+        Self {
+            client,
+            locked: account.locked,
+            available: account.available.clone(),
+            held: account.held.clone(),
+            total: &account.available + &account.held,
+        }
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn available(&self) -> &Balance {
+        &self.available
+    }
+
+    pub fn held(&self) -> &Balance {
+        &self.held
+    }
+
+    pub fn total(&self) -> &Balance {
+        &self.total
+    }
+}
+
+/// What the engine did with an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Applied,
+    /// The action was ignored and left the account unchanged
+    Rejected(Rejection),
+    /// The account broke an invariant of the engine, so the action was left unapplied,
+    /// see [`AccountStates::verify`]
+    Failed(EngineError),
+}
+
+/// Why an action was ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rejection {
+    /// The account was locked by an earlier chargeback
+    AccountLocked,
+    /// A deposit or withdrawal reused the id of an earlier transaction of the client
+    DuplicateTransaction,
+    /// The available funds cannot cover a withdrawal or a disputed deposit
+    InsufficientFunds,
+    /// No open transaction of the client has this id
+    UnknownTransaction,
+    /// The transaction belongs to another client,
+    /// only detected by states built with [`AccountStatesBuilder::transaction_index`]
+    ClientMismatch,
+    /// The transaction is already under dispute
+    AlreadyDisputed,
+    /// The transaction is not under dispute, so it cannot be resolved or charged back
+    NotDisputed,
+    /// Only deposits can be returned, or disputed under [`DisputePolicy::DepositsOnly`]
+    NotDeposit,
+    /// Only a locked account can be unlocked
+    NotLocked,
+    /// The action is older than an earlier one, in a state that requires chronological order
+    OutOfOrder,
+    /// The disputed transaction is older than the configured dispute window
+    OutsideDisputeWindow,
+    /// The transaction was evicted by the configured retention policy,
+    /// so it can no longer be disputed or returned, nor its id reused
+    Evicted,
+    /// The action would add a client beyond the configured maximum
+    ClientLimit,
+    /// The action would store a transaction beyond the configured maximum
+    TransactionLimit,
+    /// The withdrawal is larger than the configured maximum of a single withdrawal
+    OverWithdrawalLimit,
+    /// The withdrawal would take the withdrawals of the day beyond the configured maximum
+    OverDailyLimit,
+    /// The withdrawal would leave less available than the configured minimum balance
+    BelowMinimumBalance,
+    /// Only a chargeback that was not represented yet can be represented
+    NotChargedBack,
+    /// Only a represented chargeback can be reversed
+    NotRepresented,
+    /// The client is suspended by its [`ClientPolicy`](policy::ClientPolicy),
+    /// which only lets admin actions through
+    Suspended,
+}
+
+impl Rejection {
+    /// Whether a configured limit was reached, which should fail the run
+    /// rather than skip the action
+    pub fn is_limit(&self) -> bool {
+        matches!(self, Rejection::ClientLimit | Rejection::TransactionLimit)
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Outcome::Applied => f.write_str("applied"),
+            Outcome::Rejected(rejection) => write!(f, "rejected: {rejection}"),
+            Outcome::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Rejection::AccountLocked => "account is locked",
+            Rejection::DuplicateTransaction => "transaction id is already used",
+            Rejection::InsufficientFunds => "insufficient available funds",
+            Rejection::UnknownTransaction => "unknown transaction",
+            Rejection::ClientMismatch => "transaction belongs to another client",
+            Rejection::AlreadyDisputed => "transaction is already disputed",
+            Rejection::NotDisputed => "transaction is not disputed",
+            Rejection::NotDeposit => "transaction is not a deposit",
+            Rejection::NotLocked => "account is not locked",
+            Rejection::OutOfOrder => "timestamp is before an earlier action",
+            Rejection::OutsideDisputeWindow => "transaction is too old to dispute",
+            Rejection::Evicted => "transaction is no longer retained",
+            Rejection::ClientLimit => "limit of distinct clients reached",
+            Rejection::TransactionLimit => "limit of stored transactions reached",
+            Rejection::OverWithdrawalLimit => "withdrawal exceeds the withdrawal limit",
+            Rejection::OverDailyLimit => "daily withdrawal limit reached",
+            Rejection::BelowMinimumBalance => "withdrawal would go below the minimum balance",
+            Rejection::NotChargedBack => "transaction is not charged back",
+            Rejection::NotRepresented => "chargeback is not represented",
+            Rejection::Suspended => "client is suspended",
+        })
+    }
+}
+
+impl core::error::Error for Rejection {}
+
+/// A broken invariant of the engine, which only a state not built by the engine itself can have,
+/// such as one seeded from edited files, or a failure of the store of the accounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EngineError {
+    /// The held funds of the client do not cover its open disputes
+    InsufficientHeld { client: ClientId },
+    /// A transaction is under dispute but no longer retained
+    DanglingDispute {
+        client: ClientId,
+        transaction: TransactionId,
+    },
+    /// The store failed to read or write an account while applying an action of the client,
+    /// see [`AccountStates::try_process`] for the cause
+    Store { client: ClientId },
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            EngineError::InsufficientHeld { client } => write!(
+                f,
+                "held funds of client {} do not cover its open disputes",
+                client.0
+            ),
+            EngineError::DanglingDispute {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "transaction {} of client {} is under dispute but not retained",
+                transaction.0, client.0
+            ),
+            EngineError::Store { client } => write!(
+                f,
+                "store of the accounts failed on an action of client {}",
+                client.0
+            ),
+        }
+    }
+}
+
+impl core::error::Error for EngineError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Deposit(Balance),
+    Withdrawal(Balance),
+}
+
+/// Balances and retained transactions of one client, as kept by an [`AccountStore`]
+///
+/// Only the engine changes an account; stores keep it as it is, serialized if need be.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountState {
+    transaction_amounts: AccountMap<TransactionId, TransactionKind>,
+    disputes: AccountSet<TransactionId>,
+    locked: bool,
+    available: Balance,
+    held: Balance,
+    /// The policy rule that locked the account, if it was not a chargeback
+    auto_lock: Option<AutoLock>,
+    /// Timestamps of the transactions that had one
+    timestamps: AccountMap<TransactionId, Timestamp>,
+    /// Number of deposits and withdrawals so far,
+    /// only counted with a dispute window or a retention policy
+    sequence: u64,
+    /// Value of `sequence` right after each transaction,
+    /// only kept with a dispute window or a retention policy
+    sequences: AccountMap<TransactionId, u64>,
+    /// Transactions not yet evicted with their `sequence`, oldest first,
+    /// only kept with a retention policy
+    retained: VecDeque<(u64, TransactionId)>,
+    /// Ids of the transactions evicted by the retention policy
+    evicted: AccountSet<TransactionId>,
+    /// Funds charged back beyond what the account held and uncollected fees, owed by the client,
+    /// or absorbed for other clients by the house account
+    #[serde(default)]
+    shortfall: Balance,
+    /// Part of each disputed deposit that was already withdrawn and is not held,
+    /// only with a [`ChargebackPolicy`] that seizes the available funds
+    #[serde(default)]
+    uncovered: AccountMap<TransactionId, Balance>,
+    /// UTC day of the latest timestamped withdrawal and the total withdrawn on that day,
+    /// only kept with a daily withdrawal limit
+    #[serde(default)]
+    daily_withdrawals: Option<(u64, Balance)>,
+    /// Charged back transactions, which representments and reversals refer to
+    #[serde(default)]
+    chargebacks: AccountMap<TransactionId, ChargedBack>,
+    /// Fees charged to the account, see [`FeeSchedule`]
+    #[serde(default)]
+    fees: Balance,
+    /// Number of applied deposits, evicted or not
+    #[serde(default)]
+    deposits: u64,
+    /// Number of applied withdrawals, evicted or not
+    #[serde(default)]
+    withdrawals: u64,
+    /// Amounts of the transactions charged back and not reversed
+    #[serde(default)]
+    charged_back: Balance,
+}
+
+/// Upper bound on the number of distinct clients, since client ids are `u16`
+const MAX_CLIENTS: usize = u16::MAX as usize + 1;
+
+impl AccountState {
+    /// An empty account with room for `txs` transactions, its maps allocated from `alloc`
+    fn new_in(txs: usize, alloc: &AccountAlloc) -> Self {
+        Self {
+            transaction_amounts: AccountMap::with_capacity_in(txs, alloc.clone()),
+            disputes: AccountSet::new_in(alloc.clone()),
+            timestamps: AccountMap::new_in(alloc.clone()),
+            sequences: AccountMap::new_in(alloc.clone()),
+            evicted: AccountSet::new_in(alloc.clone()),
+            uncovered: AccountMap::new_in(alloc.clone()),
+            chargebacks: AccountMap::new_in(alloc.clone()),
+            ..<_>::default()
+        }
+    }
+}
+
+impl AccountStates {
+    /// Pre-size the state for about `clients` distinct clients,
+    /// each with about `txs_per_client` transactions
+    pub fn with_capacity(clients: usize, txs_per_client: usize) -> Self {
+        Self::builder()
+            .clients(clients)
+            .txs_per_client(txs_per_client)
+            .build()
+    }
+
+    /// Pre-size the state for an input of about `rows` actions
+    /// whose distribution over clients is not known in advance
+    pub fn with_estimated_rows(rows: usize) -> Self {
+        Self::builder().estimated_rows(rows).build()
+    }
+
+    /// Seed a state from published summaries, keeping only the balances
+    ///
+    /// No transaction history is restored, so transactions from before the summaries
+    /// can no longer be disputed, and funds held by their open disputes stay held for good.
+    /// [`AccountStates::reconcile`] reports such funds as drift.
+    /// The `total` of each summary is ignored, and a later summary of the same client
+    /// replaces an earlier one.
+    pub fn from_summaries(summaries: &[AccountSummary]) -> Self {
+        let mut states = Self::with_capacity(summaries.len(), 0);
+        for summary in summaries {
+            states.accounts.map.insert(
+                summary.client,
+                AccountState {
+                    locked: summary.locked,
+                    available: summary.available.clone(),
+                    held: summary.held.clone(),
+                    ..<_>::default()
+                },
+            );
+        }
+        states
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// The store of the live accounts, without the archived ones
+    pub fn store(&self) -> &S {
+        &self.accounts
+    }
+
+    /// Summaries of all accounts, ordered by client id, unless the store fails to read them
+    pub fn try_summary(&self) -> Result<Vec<AccountSummary>, S::Error> {
+        let mut summaries = self
+            .accounts
+            .iter()
+            .map(|entry| entry.map(|(client, account)| AccountSummary::new(client, &account)))
+            .chain(
+                self.archived
+                    .iter()
+                    .map(|(&client, account)| Ok(AccountSummary::new(client, account))),
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        summaries.sort_unstable_by_key(|summary| summary.client);
+        Ok(summaries)
+    }
+
+    /// Apply an action against the client
+    ///
+    /// *Details*:
+    /// When a dispute is resolved, subsequent dispute filed will be ignored.
+    /// When a dispute is filed against a `Withdrawal` transaction,
+    /// some funds will be allocated to the `held` state,
+    /// and the reversal will move this portion of funds from `held` to `available`.
+    ///
+    /// An action that would exceed a limit configured with [`AccountStatesBuilder`]
+    /// is rejected with a rejection for which [`Rejection::is_limit`] holds.
+    ///
+    /// An action whose store fails is reported as [`EngineError::Store`].
+    pub fn process(&mut self, action: Action) -> Outcome {
+        self.apply(&action)
+    }
+
+    /// Apply an action like [`AccountStates::process`], but fail with the error of the store
+    /// instead of reporting [`EngineError::Store`]
+    ///
+    /// An action whose store failed may be applied in part, for example to the account
+    /// of the client but not to the fee account.
+    pub fn try_process(&mut self, action: Action) -> Result<Outcome, S::Error> {
+        self.try_apply_at(&action, None)
+    }
+
+    /// Apply actions as they are decoded, without collecting them first
+    ///
+    /// Fails at the first action that failed to decode, would exceed a configured limit
+    /// or found an account inconsistent.
+    pub fn process_stream(
+        &mut self,
+        actions: impl IntoIterator<Item = anyhow::Result<Action>>,
+    ) -> anyhow::Result<()> {
+        for action in actions {
+            let action = action?;
+            match self.try_apply_at(&action, None) {
+                Ok(Outcome::Rejected(rejection)) if rejection.is_limit() => {
+                    return Err(rejection.into())
+                }
+                Ok(Outcome::Failed(error)) => return Err(error.into()),
+                Ok(_) => {}
+                Err(error) => return Err(store_failure(&action, error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a batch of actions
+    ///
+    /// Consecutive actions against the same client share a single account lookup,
+    /// which pays off for inputs that are clustered by client.
+    /// Stops at the first action that would exceed a configured limit or found an account
+    /// inconsistent, failing with the [`Rejection`] or [`EngineError`].
+    /// States with limits, lock policies, dispute windows, retention policies, a transaction index,
+    /// observers or archived accounts check every action separately.
+    pub fn process_batch(&mut self, actions: &[Action]) -> anyhow::Result<()> {
+        if self.limits != Limits::default()
+            || !self.archived.is_empty()
+            || self.lock_policy.is_set()
+            || self.withdrawal_limits.is_set()
+            || !self.client_policies.is_empty()
+            || self.dispute_window.is_some()
+            || self.retention.is_some()
+            || self.owners.is_some()
+            || !self.observers.is_empty()
+            || matches!(self.chargeback_policy, ChargebackPolicy::BookToHouse(_))
+            || self.fees.is_some()
+        {
+            for action in actions {
+                match self.try_apply_at(action, None) {
+                    Ok(Outcome::Rejected(rejection)) if rejection.is_limit() => {
+                        return Err(rejection.into())
+                    }
+                    Ok(Outcome::Failed(error)) => return Err(error.into()),
+                    Ok(_) => {}
+                    Err(error) => return Err(store_failure(action, error)),
+                }
+            }
+            return Ok(());
+        }
+        let (dispute_policy, chargeback_policy) = (self.dispute_policy, self.chargeback_policy);
+        let (period, generation) = (&mut self.period, &mut self.generation);
+        let mut transactions = Vec::new();
+        for group in actions.chunk_by(|a, b| a.client() == b.client()) {
+            let client = group[0].client();
+            let stored = self
+                .accounts
+                .contains(client)
+                .map_err(|error| store_failure(&group[0], error))?;
+            // A new account shows up in the summaries even if every action is rejected
+            *generation += u64::from(!stored);
+            transactions.clear();
+            transactions.extend(group.iter().map(Action::transaction));
+            let failed = self
+                .accounts
+                .update_partial(client, &transactions, |account| {
+                    for action in group {
+                        let outcome = account.apply(action, dispute_policy, chargeback_policy);
+                        #[cfg(feature = "tracing")]
+                        if let Outcome::Rejected(rejection) = outcome {
+                            observer::trace_rejection(action, rejection);
+                        }
+                        period.record(action, outcome);
+                        *generation += u64::from(outcome == Outcome::Applied);
+                        if let Outcome::Failed(error) = outcome {
+                            return Some(error);
+                        }
+                    }
+                    None
+                });
+            if let Some(error) = failed.map_err(|error| store_failure(&group[0], error))? {
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, action: &Action) -> Outcome {
+        self.apply_at(action, None)
+    }
+
+    fn apply_at(&mut self, action: &Action, timestamp: Option<Timestamp>) -> Outcome {
+        self.try_apply_at(action, timestamp)
+            .unwrap_or(Outcome::Failed(EngineError::Store {
+                client: action.client(),
+            }))
+    }
+
+    /// Apply an action, recording it as failed if the store fails
+    fn try_apply_at(
+        &mut self,
+        action: &Action,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Outcome, S::Error> {
+        let result = self.apply_to_store(action, timestamp);
+        if result.is_err() {
+            let outcome = Outcome::Failed(EngineError::Store {
+                client: action.client(),
+            });
+            self.period.record(action, outcome);
+            self.notify(action, outcome, None);
+        }
+        result
+    }
+
+    fn apply_to_store(
+        &mut self,
+        action: &Action,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Outcome, S::Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "action",
+            client = action.client().0,
+            tx = action.transaction().0
+        )
+        .entered();
+        let rejection = self
+            .check_admission(action)
+            .transpose()
+            .or_else(|| self.check_timing(action, timestamp).transpose())
+            .or_else(|| self.check_withdrawal(action, timestamp).transpose())
+            .or_else(|| self.check_fee(action).transpose())
+            .transpose()?;
+        if let Some(rejection) = rejection {
+            let outcome = Outcome::Rejected(rejection);
+            self.period.record(action, outcome);
+            self.notify(action, outcome, None);
+            return Ok(outcome);
+        }
+        self.latest = self.latest.max(timestamp);
+        // Restored for the action, and archived again unless it is applied
+        let restored = action.is_admin() && self.is_archived(action.client());
+        if restored {
+            self.restore(action.client())?;
+        }
+        // Looked up before the chargeback drops it from the account
+        let house_shortfall = match (self.chargeback_policy, action) {
+            (
+                ChargebackPolicy::BookToHouse(house),
+                Action::Chargeback {
+                    client,
+                    transaction,
+                },
+            ) => self
+                .accounts
+                .get_partial(*client, &[])?
+                .and_then(|account| account.uncovered.get(transaction).cloned())
+                .map(|shortfall| (house, shortfall, true)),
+            (
+                ChargebackPolicy::BookToHouse(house),
+                Action::ChargebackReversal {
+                    client,
+                    transaction,
+                },
+            ) => self
+                .accounts
+                .get_partial(*client, &[])?
+                .and_then(|account| Some(account.chargebacks.get(transaction)?.shortfall.clone()))
+                .map(|shortfall| (house, shortfall, false)),
+            _ => None,
+        };
+        let (lock_policy, dispute_policy) = (self.lock_policy, self.dispute_policy);
+        let chargeback_policy = self.chargeback_policy;
+        let withdrawal_limits = self
+            .withdrawal_limits
+            .of_client(&self.client_policies, action.client());
+        let fees = self.fees.as_ref();
+        let (dispute_window, retention) = (self.dispute_window, self.retention);
+        // A new account shows up in the summaries even if the action is rejected
+        let created = !self.accounts.contains(action.client())?;
+        let apply = |account: &mut AccountState| {
+            let was_locked = account.locked;
+            let outcome = account.apply(action, dispute_policy, chargeback_policy);
+            // Lock policies stay out of the way of operations staff, who may unlock on purpose
+            if outcome == Outcome::Applied && !action.is_admin() {
+                lock_policy.enforce(account, action);
+            }
+            let locked = (account.locked && !was_locked).then_some(account.auto_lock);
+            if let (Outcome::Applied, Action::Withdrawal { amount, .. }) = (outcome, action) {
+                withdrawal_limits.record(account, amount, timestamp);
+            }
+            let fee = fees
+                .filter(|_| outcome == Outcome::Applied)
+                .and_then(|fees| fees.charge(account, action));
+            if let (
+                Outcome::Applied,
+                Action::Deposit { transaction, .. } | Action::Withdrawal { transaction, .. },
+            ) = (outcome, action)
+            {
+                if let Some(timestamp) = timestamp {
+                    account.timestamps.insert(*transaction, timestamp);
+                }
+                if matches!(dispute_window, Some(DisputeWindow::Transactions(_)))
+                    || retention.is_some()
+                {
+                    account.sequence += 1;
+                    account.sequences.insert(*transaction, account.sequence);
+                }
+                if let Some(retention) = retention {
+                    account.retained.push_back((account.sequence, *transaction));
+                    return (outcome, retention.enforce(account), locked, fee);
+                }
+            }
+            (outcome, Vec::new(), locked, fee)
+        };
+        let (outcome, evicted, locked, fee) = if retention.is_some() {
+            // Evictions drop transactions the action does not refer to
+            self.accounts.update(action.client(), apply)?
+        } else {
+            self.accounts
+                .update_partial(action.client(), &[action.transaction()], apply)?
+        };
+        if restored && outcome != Outcome::Applied {
+            self.rearchive(action.client())?;
+        }
+        if let (Some(fees), Some((charged, collected))) = (&self.fees, fee) {
+            self.accounts
+                .update_partial(fees.account, &[], |account| account.available += collected)?;
+            self.pending_fees
+                .push((action.client(), action.transaction(), charged));
+        }
+        if let (Outcome::Applied, Some((house, shortfall, booked))) = (outcome, house_shortfall) {
+            self.accounts.update_partial(house, &[], |account| {
+                account.shortfall = if booked {
+                    &account.shortfall + &shortfall
+                } else {
+                    (account.shortfall.clone() - shortfall).unwrap_or_default()
+                }
+            })?;
+        }
+        self.period.record(action, outcome);
+        self.generation += u64::from(outcome == Outcome::Applied || created);
+        if self.limits.transactions.is_some() && outcome == Outcome::Applied {
+            match action {
+                Action::Deposit { .. } | Action::Withdrawal { .. } => self.stored_transactions += 1,
+                Action::Resolve { .. } | Action::Return { .. } => self.stored_transactions -= 1,
+                Action::Dispute { .. }
+                | Action::Chargeback { .. }
+                | Action::Representment { .. }
+                | Action::ChargebackReversal { .. }
+                | Action::Unlock { .. }
+                | Action::CreditAdjustment { .. }
+                | Action::DebitAdjustment { .. } => {}
+            }
+            self.stored_transactions -= evicted.len();
+        }
+        if let (Outcome::Applied, Some(owners)) = (outcome, &mut self.owners) {
+            match *action {
+                Action::Deposit {
+                    client,
+                    transaction,
+                    ..
+                }
+                | Action::Withdrawal {
+                    client,
+                    transaction,
+                    ..
+                } => {
+                    owners.entry(transaction).or_insert(client);
+                }
+                Action::Resolve {
+                    client,
+                    transaction,
+                }
+                | Action::Return {
+                    client,
+                    transaction,
+                } if owners.get(&transaction) == Some(&client) => {
+                    owners.remove(&transaction);
+                }
+                _ => {}
+            }
+            for transaction in evicted {
+                if owners.get(&transaction) == Some(&action.client()) {
+                    owners.remove(&transaction);
+                }
+            }
+        }
+        self.notify(action, outcome, locked);
+        Ok(outcome)
+    }
+
+    /// The rejection of an action against an archived account
+    /// or one that would exceed a configured limit
+    fn check_admission(&self, action: &Action) -> Result<Option<Rejection>, S::Error> {
+        if !self.archived.is_empty()
+            && self.archived.contains_key(&action.client())
+            && !action.is_admin()
+        {
+            return Ok(Some(Rejection::AccountLocked));
+        }
+        if !self.client_policies.is_empty()
+            && self
+                .client_policies
+                .get(&action.client())
+                .is_some_and(|policy| policy.suspended)
+            && !action.is_admin()
+        {
+            return Ok(Some(Rejection::Suspended));
+        }
+        if self.transaction_owner(action)?.is_some() {
+            return Ok(Some(Rejection::ClientMismatch));
+        }
+        if let Some(max) = self.limits.clients {
+            if self.accounts.len() >= max && !self.accounts.contains(action.client())? {
+                return Ok(Some(Rejection::ClientLimit));
+            }
+        }
+        if let Some(max) = self.limits.transactions {
+            if matches!(action, Action::Deposit { .. } | Action::Withdrawal { .. })
+                && self.stored_transactions >= max
+            {
+                return Ok(Some(Rejection::TransactionLimit));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The other client owning the transaction a dispute, resolution, chargeback
+    /// or return refers to, if the client has no such transaction of its own
+    fn transaction_owner(&self, action: &Action) -> Result<Option<ClientId>, S::Error> {
+        let Some(owners) = self.owners.as_ref() else {
+            return Ok(None);
+        };
+        let (Action::Dispute {
+            client,
+            transaction,
+        }
+        | Action::Resolve {
+            client,
+            transaction,
+        }
+        | Action::Chargeback {
+            client,
+            transaction,
+        }
+        | Action::Return {
+            client,
+            transaction,
+        }) = *action
+        else {
+            return Ok(None);
+        };
+        let Some(&owner) = owners.get(&transaction) else {
+            return Ok(None);
+        };
+        let own = self
+            .accounts
+            .get_partial(client, &[transaction])?
+            .is_some_and(|account| account.transaction_amounts.contains_key(&transaction));
+        Ok((owner != client && !own).then_some(owner))
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Summaries of all accounts, ordered by client id
+    pub fn summary(&self) -> Vec<AccountSummary> {
+        infallible(self.try_summary())
+    }
+
+    /// Summaries of all accounts, ordered by client id, built one at a time
+    ///
+    /// Only the client ids are collected up front, so summaries can be written out
+    /// as they are produced instead of being held for every client at once.
+    pub fn summary_iter(&self) -> impl Iterator<Item = AccountSummary> + '_ {
+        self.accounts().map(|account| account.summary())
+    }
+
+    /// Summary of a single account, if the client has been seen
+    pub fn account_summary(&self, client: ClientId) -> Option<AccountSummary> {
+        self.account(client).map(|account| account.summary())
+    }
+}
+
+/// The error of an action whose store failed, caused by the failure of the store
+fn store_failure(
+    action: &Action,
+    error: impl core::error::Error + Send + Sync + 'static,
+) -> anyhow::Error {
+    anyhow::Error::new(error).context(EngineError::Store {
+        client: action.client(),
+    })
+}
+
+impl AccountState {
+    fn is_evicted(&self, transaction: TransactionId) -> bool {
+        !self.evicted.is_empty() && self.evicted.contains(&transaction)
+    }
+
+    /// The rejection of an action referring to a transaction the account does not retain
+    fn missing(&self, transaction: TransactionId) -> Rejection {
+        if self.is_evicted(transaction) {
+            Rejection::Evicted
+        } else {
+            Rejection::UnknownTransaction
+        }
+    }
+
+    /// The part of a disputed `amount` that is held, short of what was already withdrawn
+    fn held_for(&self, transaction: TransactionId, amount: &Balance) -> Balance {
+        match self.uncovered.get(&transaction) {
+            Some(uncovered) => (amount.clone() - uncovered.clone()).unwrap_or_default(),
+            None => amount.clone(),
+        }
+    }
+
+    fn apply(
+        &mut self,
+        action: &Action,
+        policy: DisputePolicy,
+        chargeback: ChargebackPolicy,
+    ) -> Outcome {
+        if self.locked && !action.is_admin() {
+            return Outcome::Rejected(Rejection::AccountLocked);
+        }
+        match *action {
+            Action::Deposit {
+                transaction,
+                ref amount,
+                ..
+            } => {
+                if self.is_evicted(transaction) {
+                    return Outcome::Rejected(Rejection::Evicted);
+                }
+                let Entry::Vacant(e) = self.transaction_amounts.entry(transaction) else {
+                    return Outcome::Rejected(Rejection::DuplicateTransaction);
+                };
+                e.insert(TransactionKind::Deposit(amount.clone()));
+                self.available += amount;
+                self.deposits += 1;
+            }
+            Action::Withdrawal {
+                transaction,
+                ref amount,
+                ..
+            } => {
+                if self.is_evicted(transaction) {
+                    return Outcome::Rejected(Rejection::Evicted);
+                }
+                let Entry::Vacant(e) = self.transaction_amounts.entry(transaction) else {
+                    return Outcome::Rejected(Rejection::DuplicateTransaction);
+                };
+                let Some(available) = self.available.clone() - amount.clone() else {
+                    return Outcome::Rejected(Rejection::InsufficientFunds);
+                };
+                self.available = available;
+                e.insert(TransactionKind::Withdrawal(amount.clone()));
+                self.withdrawals += 1;
+            }
+            Action::Dispute { transaction, .. } => {
+                if self.disputes.contains(&transaction)
+                    || self.chargebacks.contains_key(&transaction)
+                {
+                    return Outcome::Rejected(Rejection::AlreadyDisputed);
+                }
+                match self.transaction_amounts.get(&transaction) {
+                    Some(TransactionKind::Deposit(amount)) => {
+                        match self.available.clone() - amount.clone() {
+                            Some(available) => {
+                                self.available = available;
+                                self.held += amount.clone();
+                            }
+                            None if chargeback.seizes_available() => {
+                                let uncovered = (amount.clone() - self.available.clone())
+                                    .expect("less is available than the amount");
+                                self.held += core::mem::take(&mut self.available);
+                                self.uncovered.insert(transaction, uncovered);
+                            }
+                            None => return Outcome::Rejected(Rejection::InsufficientFunds),
+                        }
+                        self.disputes.insert(transaction);
+                    }
+                    Some(TransactionKind::Withdrawal(amount)) => match policy {
+                        DisputePolicy::HoldWithdrawals => {
+                            self.held += amount;
+                            self.disputes.insert(transaction);
+                        }
+                        DisputePolicy::DepositsOnly => {
+                            return Outcome::Rejected(Rejection::NotDeposit)
+                        }
+                        DisputePolicy::ReverseWithdrawals => {
+                            self.disputes.insert(transaction);
+                        }
+                    },
+                    None => return Outcome::Rejected(self.missing(transaction)),
+                }
+            }
+            Action::Resolve { transaction, .. } => {
+                if !self.disputes.contains(&transaction) {
+                    return Outcome::Rejected(Rejection::NotDisputed);
+                }
+                match self.transaction_amounts.get(&transaction) {
+                    Some(TransactionKind::Deposit(amount)) => {
+                        let amount = self.held_for(transaction, amount);
+                        if let Some(held) = self.held.clone() - amount.clone() {
+                            self.held = held;
+                            self.available += amount;
+                            self.transaction_amounts.remove(&transaction);
+                            self.disputes.remove(&transaction);
+                            self.uncovered.remove(&transaction);
+                        } else {
+                            return Outcome::Failed(EngineError::InsufficientHeld {
+                                client: action.client(),
+                            });
+                        }
+                    }
+                    Some(TransactionKind::Withdrawal(_)) if !policy.holds_withdrawals() => {
+                        self.transaction_amounts.remove(&transaction);
+                        self.disputes.remove(&transaction);
+                    }
+                    Some(TransactionKind::Withdrawal(amount)) => {
+                        if let Some(held) = self.held.clone() - amount.clone() {
+                            self.held = held;
+                            self.transaction_amounts.remove(&transaction);
+                            self.disputes.remove(&transaction);
+                        } else {
+                            return Outcome::Failed(EngineError::InsufficientHeld {
+                                client: action.client(),
+                            });
+                        }
+                    }
+                    None => return Outcome::Rejected(Rejection::UnknownTransaction),
+                }
+            }
+            Action::Chargeback { transaction, .. } => {
+                if !self.disputes.contains(&transaction) {
+                    return Outcome::Rejected(Rejection::NotDisputed);
+                }
+                match self.transaction_amounts.get(&transaction) {
+                    Some(TransactionKind::Deposit(amount)) => {
+                        let amount = self.held_for(transaction, amount);
+                        if let Some(held) = self.held.clone() - amount.clone() {
+                            self.held = held;
+                            self.disputes.remove(&transaction);
+                            self.locked = true;
+                            let shortfall = self.uncovered.remove(&transaction).unwrap_or_default();
+                            // The house account books the shortfall when it has one
+                            if chargeback == ChargebackPolicy::SeizeAvailable {
+                                self.shortfall += &shortfall;
+                            }
+                            self.chargebacks.insert(
+                                transaction,
+                                ChargedBack {
+                                    stage: DisputeStage::ChargedBack,
+                                    amount,
+                                    shortfall,
+                                },
+                            );
+                        } else {
+                            return Outcome::Failed(EngineError::InsufficientHeld {
+                                client: action.client(),
+                            });
+                        }
+                    }
+                    Some(TransactionKind::Withdrawal(amount)) if !policy.holds_withdrawals() => {
+                        self.available += amount;
+                        self.chargebacks
+                            .insert(transaction, ChargedBack::withdrawal(amount));
+                        self.disputes.remove(&transaction);
+                        self.locked = true;
+                    }
+                    Some(TransactionKind::Withdrawal(amount)) => {
+                        if let Some(held) = self.held.clone() - amount.clone() {
+                            self.held = held;
+                            self.available += amount.clone();
+                            self.chargebacks
+                                .insert(transaction, ChargedBack::withdrawal(amount));
+                            self.disputes.remove(&transaction);
+                            self.locked = true;
+                        } else {
+                            return Outcome::Failed(EngineError::InsufficientHeld {
+                                client: action.client(),
+                            });
+                        }
+                    }
+                    None => return Outcome::Rejected(Rejection::UnknownTransaction),
+                }
+            }
+            Action::Return { transaction, .. } => {
+                if self.disputes.contains(&transaction) {
+                    return Outcome::Rejected(Rejection::AlreadyDisputed);
+                }
+                match self.transaction_amounts.get(&transaction) {
+                    Some(TransactionKind::Deposit(amount)) => {
+                        let Some(available) = self.available.clone() - amount.clone() else {
+                            return Outcome::Rejected(Rejection::InsufficientFunds);
+                        };
+                        self.available = available;
+                        self.transaction_amounts.remove(&transaction);
+                    }
+                    Some(TransactionKind::Withdrawal(_)) => {
+                        return Outcome::Rejected(Rejection::NotDeposit)
+                    }
+                    None => return Outcome::Rejected(self.missing(transaction)),
+                }
+            }
+            Action::Representment { transaction, .. } => return self.represent(transaction),
+            Action::ChargebackReversal { transaction, .. } => {
+                return self.reverse_chargeback(transaction, chargeback)
+            }
+            Action::Unlock { .. } => {
+                if !self.locked {
+                    return Outcome::Rejected(Rejection::NotLocked);
+                }
+                self.locked = false;
+                self.auto_lock = None;
+            }
+            Action::CreditAdjustment { ref amount, .. } => self.available += amount,
+            Action::DebitAdjustment { ref amount, .. } => {
+                let Some(available) = self.available.clone() - amount.clone() else {
+                    return Outcome::Rejected(Rejection::InsufficientFunds);
+                };
+                self.available = available;
+            }
+        }
+        if let Action::Chargeback { transaction, .. } = *action {
+            if let Some(TransactionKind::Deposit(amount) | TransactionKind::Withdrawal(amount)) =
+                self.transaction_amounts.get(&transaction)
+            {
+                self.charged_back += amount;
+            }
+        }
+        if matches!(action, Action::Resolve { .. } | Action::Return { .. }) {
+            if !self.timestamps.is_empty() {
+                self.timestamps.remove(&action.transaction());
+            }
+            if !self.sequences.is_empty() {
+                self.sequences.remove(&action.transaction());
+            }
+        }
+        Outcome::Applied
+    }
+}
+
+/// Accounts of all clients with the configuration they are processed under
+///
+/// Accounts live in an [`AccountStore`], in memory unless built with
+/// [`AccountStatesBuilder::build_with`].
+#[derive(Clone)]
+pub struct AccountStates<S = MemoryStore> {
+    accounts: S,
+    limits: Limits,
+    lock_policy: LockPolicy,
+    withdrawal_limits: WithdrawalLimits,
+    /// Rules of single clients that replace the global ones
+    client_policies: HashMap<ClientId, ClientPolicy>,
+    fees: Option<FeeSchedule>,
+    /// Fees charged since the last end of day, see [`AccountStates::end_of_day`]
+    pending_fees: Vec<(ClientId, TransactionId, Balance)>,
+    interest_basis_points: i32,
+    dispute_policy: DisputePolicy,
+    chargeback_policy: ChargebackPolicy,
+    dispute_window: Option<DisputeWindow>,
+    retention: Option<Retention>,
+    /// Number of retained transactions, only tracked with a transaction limit
+    stored_transactions: usize,
+    period: PeriodTotals,
+    /// Settled locked accounts moved out of the store, see [`AccountStates::archive_locked`]
+    archived: HashMap<ClientId, AccountState>,
+    /// Number of applied actions, created accounts and interest postings,
+    /// see [`AccountStates::generation`]
+    generation: u64,
+    /// Whether actions older than `latest` are rejected
+    chronological: bool,
+    latest: Option<Timestamp>,
+    /// Client of every stored transaction, only kept with a transaction index
+    owners: Option<HashMap<TransactionId, ClientId>>,
+    /// See [`AccountStates::add_observer`]
+    observers: Vec<Arc<dyn EventObserver>>,
+}
+
+/// Caps protecting a state from runaway inputs, unlimited by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Limits {
+    clients: Option<usize>,
+    transactions: Option<usize>,
+}
+
+impl Default for AccountStates {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// States are equal when all accounts are, regardless of capacity hints
+impl<S: PartialEq> PartialEq for AccountStates<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.accounts == other.accounts && self.archived == other.archived
+    }
+}
+
+impl<S: Eq> Eq for AccountStates<S> {}
+
+/// Accounts are listed in client order so that output is stable
+impl<S: AccountStore<Error = Infallible>> Debug for AccountStates<S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let accounts: BTreeMap<_, _> = self.accounts.iter().map(infallible).collect();
+        f.debug_struct("AccountStates")
+            .field("accounts", &accounts)
+            .finish()
+    }
+}
+
+pub fn aggregate(stream: impl IntoIterator<Item = Action>) -> Vec<AccountSummary> {
+    let mut states = AccountStates::default();
+    for action in stream {
+        states.process(action);
+    }
+    states.summary()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serde_works() {
+        serde_json::from_str::<Action>(
+            r#"{
+            "type": "deposit",
+            "client": 1,
+            "tx": 1,
+            "amount": "1.0"
+        }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn summary_ordered_by_client() {
+        let mut states = AccountStates::with_capacity(4, 2);
+        for client in [3, 1, 2, 1] {
+            states.process(Action::Deposit {
+                client: ClientId(client),
+                transaction: TransactionId(client.into()),
+                amount: "1".parse().unwrap(),
+            });
+        }
+        let clients: Vec<_> = states.summary().iter().map(|s| s.client.0).collect();
+        assert_eq!(clients, [1, 2, 3]);
+        assert_eq!(
+            states.account_summary(ClientId(2)).unwrap().total(),
+            &"1".parse::<Balance>().unwrap()
+        );
+        assert!(states.account_summary(ClientId(4)).is_none());
+    }
+
+    #[test]
+    fn clone_and_compare_states() {
+        let mut states = AccountStates::default();
+        for (client, transaction) in [(2, 2), (1, 1), (1, 3)] {
+            states.process(Action::Deposit {
+                client: ClientId(client),
+                transaction: TransactionId(transaction),
+                amount: "2".parse().unwrap(),
+            });
+        }
+        states.process(Action::Dispute {
+            client: ClientId(1),
+            transaction: TransactionId(1),
+        });
+        let mut forked = states.clone();
+        assert_eq!(forked, states);
+        forked.process(Action::Deposit {
+            client: ClientId(2),
+            transaction: TransactionId(7),
+            amount: "1".parse().unwrap(),
+        });
+        assert_ne!(forked, states);
+        assert_eq!(
+            AccountStates::with_capacity(10, 10),
+            AccountStates::default()
+        );
+        assert!(format!("{states:?}").starts_with("AccountStates { accounts: {ClientId(1): "));
+    }
+
+    #[test]
+    fn report_outcomes() {
+        let mut states = AccountStates::default();
+        let client = ClientId(1);
+        let deposit = |transaction, amount: &str| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        assert_eq!(states.process(deposit(1, "2")), Outcome::Applied);
+        assert_eq!(
+            states.process(deposit(1, "2")),
+            Outcome::Rejected(Rejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            states.process(Action::Withdrawal {
+                client,
+                transaction: TransactionId(2),
+                amount: "3".parse().unwrap(),
+            }),
+            Outcome::Rejected(Rejection::InsufficientFunds)
+        );
+        let resolve = Action::Resolve {
+            client,
+            transaction: TransactionId(1),
+        };
+        assert_eq!(
+            states.process(resolve),
+            Outcome::Rejected(Rejection::NotDisputed)
+        );
+        let dispute = |transaction| Action::Dispute {
+            client,
+            transaction: TransactionId(transaction),
+        };
+        assert_eq!(
+            states.process(dispute(3)),
+            Outcome::Rejected(Rejection::UnknownTransaction)
+        );
+        assert_eq!(states.process(dispute(1)), Outcome::Applied);
+        assert_eq!(
+            states.process(dispute(1)),
+            Outcome::Rejected(Rejection::AlreadyDisputed)
+        );
+        assert_eq!(
+            states.process(Action::Chargeback {
+                client,
+                transaction: TransactionId(1),
+            }),
+            Outcome::Applied
+        );
+        let outcome = states.process(deposit(4, "1"));
+        assert_eq!(outcome, Outcome::Rejected(Rejection::AccountLocked));
+        assert_eq!(outcome.to_string(), "rejected: account is locked");
+    }
+
+    #[test]
+    fn stream_actions_and_summaries() {
+        let deposit = |client, transaction| {
+            Ok(Action::Deposit {
+                client: ClientId(client),
+                transaction: TransactionId(transaction),
+                amount: "1".parse().unwrap(),
+            })
+        };
+        let mut states = AccountStates::builder().max_clients(3).build();
+        states
+            .process_stream([deposit(3, 1), deposit(1, 2), deposit(2, 3)])
+            .unwrap();
+        assert!(states.summary_iter().eq(states.summary()));
+        assert!(states
+            .process_stream([
+                deposit(1, 4),
+                Err(anyhow::anyhow!("bad row")),
+                deposit(1, 5)
+            ])
+            .is_err());
+        assert_eq!(
+            states.account_summary(ClientId(1)).unwrap().total(),
+            &"2".parse::<Balance>().unwrap()
+        );
+        let error = states.process_stream([deposit(4, 6)]).unwrap_err();
+        assert_eq!(error.to_string(), "limit of distinct clients reached");
+    }
+
+    #[test]
+    fn return_deposits() {
+        let mut states = AccountStates::default();
+        let client = ClientId(1);
+        let deposit = |transaction, amount: &str| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        let return_ = |transaction| Action::Return {
+            client,
+            transaction: TransactionId(transaction),
+        };
+        states.process(deposit(1, "2"));
+        states.process(deposit(2, "3"));
+        states.process(Action::Withdrawal {
+            client,
+            transaction: TransactionId(3),
+            amount: "1".parse().unwrap(),
+        });
+        assert_eq!(
+            states.process(return_(3)),
+            Outcome::Rejected(Rejection::NotDeposit)
+        );
+        states.process(Action::Dispute {
+            client,
+            transaction: TransactionId(2),
+        });
+        assert_eq!(
+            states.process(return_(2)),
+            Outcome::Rejected(Rejection::AlreadyDisputed)
+        );
+        assert_eq!(
+            states.process(return_(1)),
+            Outcome::Rejected(Rejection::InsufficientFunds)
+        );
+        states.process(deposit(4, "1"));
+        assert_eq!(states.process(return_(1)), Outcome::Applied);
+        assert_eq!(
+            states.process(return_(1)),
+            Outcome::Rejected(Rejection::UnknownTransaction)
+        );
+        let summary = states.account_summary(client).unwrap();
+        assert!(!summary.locked());
+        assert_eq!(summary.available().to_string(), "0.0000");
+        assert_eq!(summary.held().to_string(), "3.0000");
+        assert_eq!(states.period_totals().returns, 1);
+    }
+
+    #[test]
+    fn admin_actions_reopen_and_correct() {
+        let mut states = AccountStates::default();
+        let client = ClientId(1);
+        let transaction = TransactionId(1);
+        let unlock = || Action::Unlock {
+            client,
+            transaction: TransactionId(10),
+        };
+        let debit = |amount: &str| Action::DebitAdjustment {
+            client,
+            transaction: TransactionId(11),
+            amount: amount.parse().unwrap(),
+        };
+        assert_eq!(
+            states.process(Action::Deposit {
+                client,
+                transaction,
+                amount: "2".parse().unwrap(),
+            }),
+            Outcome::Applied
+        );
+        assert_eq!(
+            states.process(unlock()),
+            Outcome::Rejected(Rejection::NotLocked)
+        );
+        states.process(Action::Dispute {
+            client,
+            transaction,
+        });
+        states.process(Action::Chargeback {
+            client,
+            transaction,
+        });
+        assert_eq!(
+            states.process(Action::CreditAdjustment {
+                client,
+                transaction: TransactionId(12),
+                amount: "3".parse().unwrap(),
+            }),
+            Outcome::Applied
+        );
+        assert_eq!(
+            states.explain(&debit("4")).rejection(),
+            Some(Rejection::InsufficientFunds)
+        );
+        assert_eq!(
+            states.process(debit("4")),
+            Outcome::Rejected(Rejection::InsufficientFunds)
+        );
+        assert_eq!(states.process(debit("3")), Outcome::Applied);
+        assert_eq!(states.archive_locked(), 1);
+        assert_eq!(
+            states.process(Action::ChargebackReversal {
+                client,
+                transaction,
+            }),
+            Outcome::Rejected(Rejection::NotRepresented)
+        );
+        assert!(states.is_archived(client));
+
+        assert_eq!(states.explain(&unlock()), Explanation::Accepted);
+        assert_eq!(states.process(unlock()), Outcome::Applied);
+        assert!(!states.is_archived(client));
+        assert_eq!(
+            states.process(Action::Deposit {
+                client,
+                transaction: TransactionId(2),
+                amount: "1".parse().unwrap(),
+            }),
+            Outcome::Applied
+        );
+        let summary = states.account_summary(client).unwrap();
+        assert!(!summary.locked());
+        assert_eq!(summary.total().to_string(), "1.0000");
+        assert_eq!(states.period_totals().admin, 3);
+    }
+
+    #[test]
+    fn seed_from_summaries() {
+        let mut states = AccountStates::default();
+        for (client, transaction) in [(1, 1), (2, 2), (2, 3)] {
+            states.process(Action::Deposit {
+                client: ClientId(client),
+                transaction: TransactionId(transaction),
+                amount: "2".parse().unwrap(),
+            });
+        }
+        states.process(Action::Dispute {
+            client: ClientId(2),
+            transaction: TransactionId(2),
+        });
+        let summaries = states.summary();
+        let mut seeded = AccountStates::from_summaries(&summaries);
+        assert_eq!(seeded.summary(), summaries);
+        assert_eq!(
+            seeded.process(Action::Resolve {
+                client: ClientId(2),
+                transaction: TransactionId(2),
+            }),
+            Outcome::Rejected(Rejection::NotDisputed)
+        );
+        assert_eq!(seeded.reconcile().len(), 1);
+    }
+}
@@ -0,0 +1,282 @@
+//! Disputes past their chargeback
+//!
+//! A dispute is opened by a dispute and closed by a resolution or a chargeback.
+//! A chargeback can still be contested: a representment by the merchant moves it
+//! to [`DisputeStage::Represented`], and a chargeback reversal, once the merchant won,
+//! gives back the funds the chargeback moved and lifts the lock it put on the account.
+//! Resolved transactions are forgotten, like transactions that were never disputed.
+
+use alloc::borrow::Cow;
+use core::convert::Infallible;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, policy::ChargebackPolicy, AccountState, AccountStates, AccountStore, Balance,
+    ClientId, Outcome, Rejection, TransactionId, TransactionKind,
+};
+
+/// Where a disputed transaction stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStage {
+    /// Under dispute, until resolved or charged back
+    Open,
+    /// Charged back, which the merchant can still contest with a representment
+    ChargedBack,
+    /// The merchant contested the chargeback, which stands unless it is reversed
+    Represented,
+    /// The chargeback was reversed in favour of the merchant, which is final
+    Reversed,
+}
+
+/// A chargeback of one transaction, kept so that it can be reversed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ChargedBack {
+    pub(crate) stage: DisputeStage,
+    /// Funds taken out of a deposit, or credited back for a withdrawal
+    pub(crate) amount: Balance,
+    /// Part of a deposit charged back beyond the held funds, see [`ChargebackPolicy`]
+    pub(crate) shortfall: Balance,
+}
+
+impl ChargedBack {
+    pub(crate) fn withdrawal(amount: &Balance) -> Self {
+        Self {
+            stage: DisputeStage::ChargedBack,
+            amount: amount.clone(),
+            shortfall: <_>::default(),
+        }
+    }
+
+    /// Whether the chargeback still keeps the account locked
+    pub(crate) fn stands(&self) -> bool {
+        self.stage != DisputeStage::Reversed
+    }
+}
+
+impl AccountState {
+    pub(crate) fn represent(&mut self, transaction: TransactionId) -> Outcome {
+        match self.chargebacks.get_mut(&transaction) {
+            Some(chargeback) if chargeback.stage == DisputeStage::ChargedBack => {
+                chargeback.stage = DisputeStage::Represented;
+                Outcome::Applied
+            }
+            Some(_) => Outcome::Rejected(Rejection::NotChargedBack),
+            None if self.transaction_amounts.contains_key(&transaction) => {
+                Outcome::Rejected(Rejection::NotChargedBack)
+            }
+            None => Outcome::Rejected(self.missing(transaction)),
+        }
+    }
+
+    /// Undo a represented chargeback, unlocking the account unless something else keeps it locked
+    pub(crate) fn reverse_chargeback(
+        &mut self,
+        transaction: TransactionId,
+        policy: ChargebackPolicy,
+    ) -> Outcome {
+        let chargeback = match self.chargebacks.get(&transaction) {
+            Some(chargeback) if chargeback.stage == DisputeStage::Represented => chargeback,
+            Some(_) => return Outcome::Rejected(Rejection::NotRepresented),
+            None if self.transaction_amounts.contains_key(&transaction) => {
+                return Outcome::Rejected(Rejection::NotRepresented)
+            }
+            None => return Outcome::Rejected(self.missing(transaction)),
+        };
+        let charged_back = match self.transaction_amounts.get(&transaction) {
+            Some(TransactionKind::Deposit(amount) | TransactionKind::Withdrawal(amount)) => {
+                amount.clone()
+            }
+            None => return Outcome::Rejected(self.missing(transaction)),
+        };
+        match self.transaction_amounts.get(&transaction) {
+            Some(TransactionKind::Deposit(_)) => {
+                self.available += &chargeback.amount;
+                if policy == ChargebackPolicy::SeizeAvailable {
+                    self.shortfall =
+                        (self.shortfall.clone() - chargeback.shortfall.clone()).unwrap_or_default();
+                }
+            }
+            Some(TransactionKind::Withdrawal(_)) => {
+                let Some(available) = self.available.clone() - chargeback.amount.clone() else {
+                    return Outcome::Rejected(Rejection::InsufficientFunds);
+                };
+                self.available = available;
+            }
+            None => return Outcome::Rejected(self.missing(transaction)),
+        }
+        if let Some(chargeback) = self.chargebacks.get_mut(&transaction) {
+            chargeback.stage = DisputeStage::Reversed;
+        }
+        self.charged_back = (self.charged_back.clone() - charged_back).unwrap_or_default();
+        if self.auto_lock.is_none() && !self.chargebacks.values().any(ChargedBack::stands) {
+            self.locked = false;
+        }
+        Outcome::Applied
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Where a transaction of the client stands in its dispute,
+    /// or `None` if it was never disputed, was resolved or is not retained
+    ///
+    /// Chargebacks of archived accounts are kept, so that they can still be reversed.
+    pub fn dispute_stage(
+        &self,
+        client: ClientId,
+        transaction: TransactionId,
+    ) -> Option<DisputeStage> {
+        let account = infallible(self.accounts.get_partial(client, &[transaction]))
+            .or_else(|| self.archived.get(&client).map(Cow::Borrowed))?;
+        if account.disputes.contains(&transaction) {
+            return Some(DisputeStage::Open);
+        }
+        Some(account.chargebacks.get(&transaction)?.stage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    #[test]
+    fn reverse_represented_chargebacks() {
+        let client = ClientId(1);
+        let (deposit, other) = (TransactionId(1), TransactionId(2));
+        let mut states = AccountStates::default();
+        let balances = |states: &AccountStates| {
+            let summary = states.account_summary(client).unwrap();
+            (
+                summary.available().to_string(),
+                summary.held().to_string(),
+                summary.locked(),
+            )
+        };
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: deposit,
+                amount: "10".parse().unwrap(),
+            },
+            Action::Deposit {
+                client,
+                transaction: other,
+                amount: "4".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: deposit,
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert_eq!(
+            states.dispute_stage(client, deposit),
+            Some(DisputeStage::Open)
+        );
+        let reversal = Action::ChargebackReversal {
+            client,
+            transaction: deposit,
+        };
+        assert_eq!(
+            states.process(reversal.clone()),
+            Outcome::Rejected(Rejection::NotRepresented)
+        );
+
+        let chargeback = Action::Chargeback {
+            client,
+            transaction: deposit,
+        };
+        assert_eq!(states.process(chargeback), Outcome::Applied);
+        assert_eq!(balances(&states), ("4.0000".into(), "0.0000".into(), true));
+        assert_eq!(
+            states.process(Action::Dispute {
+                client,
+                transaction: deposit,
+            }),
+            Outcome::Rejected(Rejection::AccountLocked)
+        );
+        assert_eq!(
+            states.explain(&reversal).rejection(),
+            Some(Rejection::NotRepresented)
+        );
+
+        let representment = Action::Representment {
+            client,
+            transaction: deposit,
+        };
+        assert_eq!(states.process(representment.clone()), Outcome::Applied);
+        assert_eq!(
+            states.dispute_stage(client, deposit),
+            Some(DisputeStage::Represented)
+        );
+        assert_eq!(
+            states.process(representment),
+            Outcome::Rejected(Rejection::NotChargedBack)
+        );
+        assert_eq!(states.explain(&reversal).rejection(), None);
+        assert_eq!(states.process(reversal.clone()), Outcome::Applied);
+        assert_eq!(
+            balances(&states),
+            ("14.0000".into(), "0.0000".into(), false)
+        );
+        assert_eq!(
+            states.dispute_stage(client, deposit),
+            Some(DisputeStage::Reversed)
+        );
+        assert_eq!(
+            states.process(reversal),
+            Outcome::Rejected(Rejection::NotRepresented)
+        );
+        assert_eq!(states.dispute_stage(client, other), None);
+        assert_eq!(states.reconcile(), []);
+    }
+
+    #[test]
+    fn reverse_archived_chargebacks() {
+        let (client, transaction) = (ClientId(1), TransactionId(1));
+        let mut states = AccountStates::default();
+        for action in [
+            Action::Deposit {
+                client,
+                transaction,
+                amount: "5".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction,
+            },
+            Action::Chargeback {
+                client,
+                transaction,
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert_eq!(states.archive_locked(), 1);
+        assert_eq!(
+            states.dispute_stage(client, transaction),
+            Some(DisputeStage::ChargedBack)
+        );
+        for action in [
+            Action::Representment {
+                client,
+                transaction,
+            },
+            Action::ChargebackReversal {
+                client,
+                transaction,
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        assert!(!states.is_archived(client));
+        let summary = states.account_summary(client).unwrap();
+        assert_eq!(
+            (summary.available().to_string(), summary.locked()),
+            ("5.0000".into(), false)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Merging of states processed separately, for sharded inputs
+//!
+//! Every action only touches the account of its client, so files sharded by client
+//! can be processed into separate states and merged afterwards. Files that are not
+//! sharded by client can be merged too: the accounts of a client found in both
+//! states are summed, as long as the two never stored the same transaction.
+
+use core::{convert::Infallible, fmt::Display};
+
+use crate::{
+    infallible, period::PeriodTotals, AccountState, AccountStates, AccountStore, ClientId,
+    TransactionId,
+};
+
+/// Why two states cannot be merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MergeConflict {
+    /// Both states stored, or evicted, the same transaction
+    DuplicateTransaction {
+        client: ClientId,
+        transaction: TransactionId,
+    },
+    /// Both states numbered the transactions of the client for a dispute window
+    /// or retention policy, and the two sequences cannot be interleaved
+    Interleaved { client: ClientId },
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            MergeConflict::DuplicateTransaction {
+                client,
+                transaction,
+            } => write!(
+                f,
+                "transaction {} of client {} is in both states",
+                transaction.0, client.0
+            ),
+            MergeConflict::Interleaved { client } => write!(
+                f,
+                "transactions of client {} are numbered in both states",
+                client.0
+            ),
+        }
+    }
+}
+
+impl core::error::Error for MergeConflict {}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Combine the accounts of two states, keeping the configuration and observers of `self`
+    ///
+    /// Accounts of clients found in only one state are taken as they are. Accounts found
+    /// in both are summed: balances, fees and counters add up, transactions, disputes
+    /// and chargebacks are joined, and the account is locked if either was.
+    /// An account archived in one state and live in the other is summed with the archived one
+    /// and stays live, but locked.
+    pub fn merge(mut self, other: Self) -> Result<Self, MergeConflict> {
+        if let (Some(owners), Some(other_owners)) = (&mut self.owners, other.owners) {
+            for (transaction, client) in other_owners {
+                if let Some(&owner) = owners.get(&transaction) {
+                    return Err(MergeConflict::DuplicateTransaction {
+                        client: owner,
+                        transaction,
+                    });
+                }
+                owners.insert(transaction, client);
+            }
+        }
+        for (client, account) in other.accounts.iter().map(infallible) {
+            infallible(self.restore(client));
+            let account = account.into_owned();
+            let merged = match infallible(self.accounts.remove(client)) {
+                Some(mut merged) => {
+                    merged.merge(client, account)?;
+                    merged
+                }
+                None => account,
+            };
+            infallible(self.accounts.upsert(client, merged));
+        }
+        for (client, account) in other.archived {
+            if let Some(mut merged) = infallible(self.accounts.remove(client)) {
+                if self.limits.transactions.is_some() {
+                    self.stored_transactions += account.transaction_amounts.len();
+                }
+                merged.merge(client, account)?;
+                infallible(self.accounts.upsert(client, merged));
+            } else if let Some(merged) = self.archived.get_mut(&client) {
+                merged.merge(client, account)?;
+            } else {
+                self.archived.insert(client, account);
+            }
+        }
+        self.pending_fees.extend(other.pending_fees);
+        self.stored_transactions += other.stored_transactions;
+        self.period.merge(other.period);
+        self.generation += other.generation;
+        self.latest = self.latest.max(other.latest);
+        Ok(self)
+    }
+}
+
+impl AccountState {
+    fn merge(&mut self, client: ClientId, other: AccountState) -> Result<(), MergeConflict> {
+        let duplicate =
+            other
+                .transaction_amounts
+                .keys()
+                .chain(&other.evicted)
+                .find(|transaction| {
+                    self.transaction_amounts.contains_key(*transaction)
+                        || self.evicted.contains(*transaction)
+                });
+        if let Some(&transaction) = duplicate {
+            return Err(MergeConflict::DuplicateTransaction {
+                client,
+                transaction,
+            });
+        }
+        if self.sequence > 0 && other.sequence > 0 {
+            return Err(MergeConflict::Interleaved { client });
+        }
+        if other.sequence > 0 {
+            self.sequence = other.sequence;
+            self.sequences = other.sequences;
+            self.retained = other.retained;
+        }
+        self.transaction_amounts.extend(other.transaction_amounts);
+        self.disputes.extend(other.disputes);
+        self.locked |= other.locked;
+        self.available += other.available;
+        self.held += other.held;
+        self.auto_lock = self.auto_lock.or(other.auto_lock);
+        self.timestamps.extend(other.timestamps);
+        self.evicted.extend(other.evicted);
+        self.shortfall += other.shortfall;
+        self.uncovered.extend(other.uncovered);
+        // Only the latest day counts towards the daily limit
+        self.daily_withdrawals = match (self.daily_withdrawals.take(), other.daily_withdrawals) {
+            (Some((day, total)), Some((other_day, other_total))) if day == other_day => {
+                Some((day, total + other_total))
+            }
+            (ours, theirs) => ours.max(theirs),
+        };
+        self.chargebacks.extend(other.chargebacks);
+        self.fees += other.fees;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.charged_back += other.charged_back;
+        Ok(())
+    }
+}
+
+impl PeriodTotals {
+    fn merge(&mut self, other: PeriodTotals) {
+        self.applied += other.applied;
+        self.rejected += other.rejected;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.chargebacks += other.chargebacks;
+        self.returns += other.returns;
+        self.admin += other.admin;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    #[test]
+    fn merge_partitions() {
+        let deposit = |client, transaction, amount: &str| Action::Deposit {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        let dispute = |client, transaction| Action::Dispute {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+        };
+        let first = [deposit(1, 1, "5"), deposit(2, 2, "3"), dispute(2, 2)];
+        let second = [
+            deposit(2, 3, "1"),
+            deposit(3, 4, "2"),
+            dispute(3, 4),
+            Action::Chargeback {
+                client: ClientId(3),
+                transaction: TransactionId(4),
+            },
+        ];
+        let states = |actions: &[Action]| {
+            let mut states = AccountStates::default();
+            for action in actions {
+                states.process(action.clone());
+            }
+            states
+        };
+        let merged = states(&first).merge(states(&second)).unwrap();
+        assert_eq!(merged, states(&[&first[..], &second[..]].concat()));
+        assert_eq!(merged.period_totals().applied, 7);
+
+        assert_eq!(
+            merged.merge(states(&[deposit(2, 2, "1")])),
+            Err(MergeConflict::DuplicateTransaction {
+                client: ClientId(2),
+                transaction: TransactionId(2),
+            })
+        );
+    }
+}
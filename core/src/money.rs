@@ -0,0 +1,148 @@
+//! Currency-tagged amounts that refuse to mix currencies
+
+use core::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Balance;
+
+/// ISO 4217 style three-letter currency code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency([u8; 3]);
+
+#[derive(Debug)]
+pub struct CurrencyError;
+
+impl FromStr for Currency {
+    type Err = CurrencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code: [u8; 3] = s.trim().as_bytes().try_into().map_err(|_| CurrencyError)?;
+        if code.iter().all(u8::is_ascii_uppercase) {
+            Ok(Self(code))
+        } else {
+            Err(CurrencyError)
+        }
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // only ASCII uppercase letters are ever stored
+        f.write_str(core::str::from_utf8(&self.0).map_err(|_| core::fmt::Error)?)
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = alloc::string::String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| de::Error::custom("invalid currency code"))
+    }
+}
+
+/// Two amounts in different currencies were combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrencyMismatch {
+    pub left: Currency,
+    pub right: Currency,
+}
+
+impl Display for CurrencyMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "cannot combine {} with {}", self.left, self.right)
+    }
+}
+
+impl core::error::Error for CurrencyMismatch {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Money {
+    pub currency: Currency,
+    pub amount: Balance,
+}
+
+impl Money {
+    pub fn new(currency: Currency, amount: Balance) -> Self {
+        Self { currency, amount }
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), CurrencyMismatch> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(CurrencyMismatch {
+                left: self.currency,
+                right: other.currency,
+            })
+        }
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, CurrencyMismatch> {
+        self.same_currency(other)?;
+        Ok(Money::new(self.currency, &self.amount + &other.amount))
+    }
+
+    /// Subtract `other`, or `Ok(None)` if it exceeds this amount
+    pub fn checked_sub(&self, other: &Money) -> Result<Option<Money>, CurrencyMismatch> {
+        self.same_currency(other)?;
+        Ok(self
+            .amount
+            .checked_sub(&other.amount)
+            .map(|amount| Money::new(self.currency, amount)))
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(currency: &str, amount: &str) -> Money {
+        Money::new(currency.parse().unwrap(), amount.parse().unwrap())
+    }
+
+    #[test]
+    fn refuse_mixed_currencies() {
+        assert_eq!(
+            money("EUR", "1.5")
+                .checked_add(&money("EUR", "2"))
+                .unwrap()
+                .to_string(),
+            "3.5000 EUR"
+        );
+        assert!(money("EUR", "1").checked_add(&money("USD", "1")).is_err());
+        assert!(money("EUR", "1").checked_sub(&money("USD", "1")).is_err());
+        assert!(money("EUR", "1")
+            .checked_sub(&money("EUR", "2"))
+            .unwrap()
+            .is_none());
+        assert!("eur".parse::<Currency>().is_err());
+        assert!("EURO".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&money("USD", "10.25")).unwrap();
+        assert_eq!(json, r#"{"currency":"USD","amount":"10.2500"}"#);
+        let parsed: Money = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.to_string(), "10.2500 USD");
+    }
+}
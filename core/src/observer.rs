@@ -0,0 +1,248 @@
+//! Hooks for embedders to react to account events
+//!
+//! Observers registered with [`AccountStates::add_observer`] are called right after
+//! each action is decided, for example to send notifications or count metrics,
+//! without wrapping every call to `process`.
+
+use alloc::sync::Arc;
+
+use crate::{
+    period::LedgerEntry, policy::AutoLock, AccountStates, AccountStore, Action, Balance, ClientId,
+    Outcome, Rejection, TransactionId,
+};
+
+/// Callbacks on account events, all of which do nothing by default
+///
+/// Observers are shared by clones of the states and across threads,
+/// so they take `&self` and keep any state of their own behind atomics or locks.
+pub trait EventObserver: Send + Sync {
+    /// Any action with its outcome, before the more specific callbacks
+    fn on_action(&self, _action: &Action, _outcome: Outcome) {}
+
+    fn on_deposit(&self, _client: ClientId, _transaction: TransactionId, _amount: &Balance) {}
+
+    fn on_withdrawal(&self, _client: ClientId, _transaction: TransactionId, _amount: &Balance) {}
+
+    fn on_withdrawal_rejected(
+        &self,
+        _client: ClientId,
+        _transaction: TransactionId,
+        _amount: &Balance,
+        _rejection: Rejection,
+    ) {
+    }
+
+    fn on_dispute_opened(&self, _client: ClientId, _transaction: TransactionId) {}
+
+    fn on_dispute_resolved(&self, _client: ClientId, _transaction: TransactionId) {}
+
+    fn on_chargeback(&self, _client: ClientId, _transaction: TransactionId) {}
+
+    /// A represented chargeback was reversed, which may also have unlocked the account
+    fn on_chargeback_reversed(&self, _client: ClientId, _transaction: TransactionId) {}
+
+    /// The account was locked by a chargeback, or by the lock policy `rule`
+    fn on_account_locked(&self, _client: ClientId, _rule: Option<AutoLock>) {}
+
+    fn on_account_unlocked(&self, _client: ClientId) {}
+
+    /// Interest, or negative interest, posted to the available funds by
+    /// [`AccountStates::end_of_day`]
+    fn on_interest(&self, _entry: &LedgerEntry) {}
+
+    /// Any rejected action, including the withdrawals also reported to `on_withdrawal_rejected`
+    fn on_rejected(&self, _action: &Action, _rejection: Rejection) {}
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Call `observer` on the events of every action processed from now on,
+    /// after any observers added earlier
+    ///
+    /// Keep a clone of the `Arc` to read back whatever the observer collects.
+    pub fn add_observer(&mut self, observer: Arc<dyn EventObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Report the events of a decided action to every observer
+    ///
+    /// `locked` is set if the action locked the account, to the policy rule behind the lock
+    /// unless it was a chargeback.
+    pub(crate) fn notify(
+        &self,
+        action: &Action,
+        outcome: Outcome,
+        locked: Option<Option<AutoLock>>,
+    ) {
+        let (client, transaction) = (action.client(), action.transaction());
+        #[cfg(feature = "tracing")]
+        if let Outcome::Rejected(rejection) = outcome {
+            trace_rejection(action, rejection);
+        }
+        for observer in &self.observers {
+            observer.on_action(action, outcome);
+            match (outcome, action) {
+                (Outcome::Applied, Action::Deposit { amount, .. }) => {
+                    observer.on_deposit(client, transaction, amount)
+                }
+                (Outcome::Applied, Action::Withdrawal { amount, .. }) => {
+                    observer.on_withdrawal(client, transaction, amount)
+                }
+                (Outcome::Applied, Action::Dispute { .. }) => {
+                    observer.on_dispute_opened(client, transaction)
+                }
+                (Outcome::Applied, Action::Resolve { .. }) => {
+                    observer.on_dispute_resolved(client, transaction)
+                }
+                (Outcome::Applied, Action::Chargeback { .. }) => {
+                    observer.on_chargeback(client, transaction)
+                }
+                (Outcome::Applied, Action::ChargebackReversal { .. }) => {
+                    observer.on_chargeback_reversed(client, transaction)
+                }
+                (Outcome::Applied, Action::Unlock { .. }) => observer.on_account_unlocked(client),
+                (Outcome::Rejected(rejection), Action::Withdrawal { amount, .. }) => {
+                    observer.on_withdrawal_rejected(client, transaction, amount, rejection);
+                    observer.on_rejected(action, rejection)
+                }
+                (Outcome::Rejected(rejection), _) => observer.on_rejected(action, rejection),
+                _ => {}
+            }
+            if let Some(rule) = locked {
+                observer.on_account_locked(client, rule);
+            }
+        }
+    }
+}
+
+/// Emit a structured event for a rejected action, only available with the `tracing` feature
+#[cfg(feature = "tracing")]
+pub(crate) fn trace_rejection(action: &Action, rejection: Rejection) {
+    tracing::info!(
+        client = action.client().0,
+        tx = action.transaction().0,
+        r#type = action.type_name(),
+        reason = %rejection,
+        "rejected action"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, vec::Vec};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventObserver for Recorder {
+        fn on_deposit(&self, client: ClientId, _: TransactionId, amount: &Balance) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("deposit {} {amount}", client.0));
+        }
+
+        fn on_withdrawal_rejected(
+            &self,
+            client: ClientId,
+            _: TransactionId,
+            _: &Balance,
+            rejection: Rejection,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("withdrawal of {} {rejection}", client.0));
+        }
+
+        fn on_chargeback(&self, client: ClientId, transaction: TransactionId) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("chargeback {} {}", client.0, transaction.0));
+        }
+
+        fn on_account_locked(&self, client: ClientId, rule: Option<AutoLock>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("locked {} {rule:?}", client.0));
+        }
+
+        fn on_rejected(&self, action: &Action, _: Rejection) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("rejected {}", action.type_name()));
+        }
+
+        fn on_interest(&self, entry: &LedgerEntry) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("interest {} {}", entry.client.0, entry.amount));
+        }
+    }
+
+    #[test]
+    fn observe_events() {
+        let recorder = Arc::new(Recorder::default());
+        let mut states = AccountStates::builder().lock_when_held_exceeds(1).build();
+        states.add_observer(recorder.clone());
+        let client = ClientId(1);
+        let actions = [
+            Action::Deposit {
+                client,
+                transaction: TransactionId(1),
+                amount: "1".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId(2),
+                amount: "2".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId(1),
+            },
+            Action::Unlock {
+                client,
+                transaction: TransactionId(3),
+            },
+            Action::Chargeback {
+                client,
+                transaction: TransactionId(1),
+            },
+        ];
+        for action in actions {
+            states.process(action);
+        }
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "deposit 1 1.0000",
+                "withdrawal of 1 insufficient available funds",
+                "rejected withdrawal",
+                "locked 1 Some(HeldExceedsAvailable { multiple: 1 })",
+                "chargeback 1 1",
+                "locked 1 None",
+            ]
+        );
+    }
+
+    #[test]
+    fn observe_interest() {
+        let recorder = Arc::new(Recorder::default());
+        let mut states = AccountStates::builder().interest(3650).build();
+        states.process(Action::Deposit {
+            client: ClientId(1),
+            transaction: TransactionId(1),
+            amount: "10".parse().unwrap(),
+        });
+        states.add_observer(recorder.clone());
+        states.end_of_day("2026-10-16");
+        assert_eq!(*recorder.0.lock().unwrap(), ["interest 1 0.0100"]);
+        assert_eq!(states.generation(), 2);
+    }
+}
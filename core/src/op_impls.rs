@@ -0,0 +1,146 @@
+use core::{
+    iter::Sum,
+    ops::{Add, AddAssign, Sub},
+};
+
+use num::{BigUint, CheckedSub};
+
+use crate::{decimal::Repr, Balance};
+
+impl Add<&'_ Repr> for &'_ Repr {
+    type Output = Repr;
+
+    fn add(self, rhs: &'_ Repr) -> Self::Output {
+        match (self, rhs) {
+            (Repr::Inline(a), Repr::Inline(b)) => match a.checked_add(*b) {
+                Some(sum) => Repr::Inline(sum),
+                None => Repr::Heap(BigUint::from(*a) + *b),
+            },
+            (Repr::Inline(a), Repr::Heap(b)) | (Repr::Heap(b), Repr::Inline(a)) => {
+                Repr::Heap(b + *a)
+            }
+            (Repr::Heap(a), Repr::Heap(b)) => Repr::Heap(a + b),
+        }
+    }
+}
+
+impl Repr {
+    pub(crate) fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        match (self, rhs) {
+            (Repr::Inline(a), Repr::Inline(b)) => u64::checked_sub(*a, *b).map(Repr::Inline),
+            // heap values are always larger than any inline value
+            (Repr::Inline(_), Repr::Heap(_)) => None,
+            (Repr::Heap(a), Repr::Inline(b)) => Some((a - *b).into()),
+            (Repr::Heap(a), Repr::Heap(b)) => CheckedSub::checked_sub(a, b).map(Repr::from),
+        }
+    }
+}
+
+impl Balance {
+    pub fn is_zero(&self) -> bool {
+        self.0 == Repr::Inline(0)
+    }
+
+    /// Add `rhs`, which never fails with the arbitrary-precision representation
+    pub fn checked_add(&self, rhs: &Balance) -> Option<Balance> {
+        Some(self + rhs)
+    }
+
+    /// Subtract `rhs`, or `None` if it exceeds this balance
+    pub fn checked_sub(&self, rhs: &Balance) -> Option<Balance> {
+        self.0.checked_sub(&rhs.0).map(Balance)
+    }
+
+    /// Subtract `rhs`, stopping at zero
+    pub fn saturating_sub(&self, rhs: &Balance) -> Balance {
+        self.checked_sub(rhs).unwrap_or_default()
+    }
+
+    /// The absolute difference between the two balances
+    pub fn abs_diff(&self, rhs: &Balance) -> Balance {
+        if self >= rhs {
+            self.saturating_sub(rhs)
+        } else {
+            rhs.saturating_sub(self)
+        }
+    }
+}
+
+impl Add<Balance> for Balance {
+    type Output = Self;
+
+    fn add(self, rhs: Balance) -> Self::Output {
+        Self(&self.0 + &rhs.0)
+    }
+}
+
+impl Add<&'_ Balance> for &'_ Balance {
+    type Output = Balance;
+
+    fn add(self, rhs: &'_ Balance) -> Self::Output {
+        Balance(&self.0 + &rhs.0)
+    }
+}
+
+impl Add<Balance> for &'_ Balance {
+    type Output = Balance;
+
+    fn add(self, rhs: Balance) -> Self::Output {
+        Balance(&self.0 + &rhs.0)
+    }
+}
+
+impl AddAssign<Balance> for Balance {
+    fn add_assign(&mut self, rhs: Balance) {
+        self.0 = &self.0 + &rhs.0;
+    }
+}
+
+impl AddAssign<&'_ Balance> for Balance {
+    fn add_assign(&mut self, rhs: &Balance) {
+        self.0 = &self.0 + &rhs.0;
+    }
+}
+
+impl Sub<Balance> for Balance {
+    type Output = Option<Self>;
+
+    fn sub(self, rhs: Balance) -> Self::Output {
+        self.0.checked_sub(&rhs.0).map(Self)
+    }
+}
+
+impl Sub<&'_ Balance> for Balance {
+    type Output = Option<Self>;
+
+    fn sub(self, rhs: &Balance) -> Self::Output {
+        self.0.checked_sub(&rhs.0).map(Self)
+    }
+}
+
+impl Sum<Balance> for Balance {
+    fn sum<I: Iterator<Item = Balance>>(iter: I) -> Self {
+        iter.fold(Balance::default(), |total, amount| total + amount)
+    }
+}
+
+impl<'a> Sum<&'a Balance> for Balance {
+    fn sum<I: Iterator<Item = &'a Balance>>(iter: I) -> Self {
+        iter.fold(Balance::default(), |mut total, amount| {
+            total += amount;
+            total
+        })
+    }
+}
+
+impl FromIterator<Balance> for Balance {
+    fn from_iter<I: IntoIterator<Item = Balance>>(iter: I) -> Self {
+        iter.into_iter().sum()
+    }
+}
+
+impl<'a> FromIterator<&'a Balance> for Balance {
+    fn from_iter<I: IntoIterator<Item = &'a Balance>>(iter: I) -> Self {
+        iter.into_iter().sum()
+    }
+}
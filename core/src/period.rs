@@ -0,0 +1,284 @@
+//! End-of-day cutover of account states
+//!
+//! [`AccountStates::close_period`] freezes the summaries and counters of a period,
+//! and [`AccountStates::end_of_day`] posts the interest of the day and hands out
+//! the ledger of fees and interest, from which statements can be put together.
+
+use alloc::{string::String, vec::Vec};
+use core::convert::Infallible;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, AccountStates, AccountStore, AccountSummary, Action, Balance, ClientId, Outcome,
+    TransactionId,
+};
+
+/// Counters of the actions processed since the last period close
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodTotals {
+    pub applied: usize,
+    pub rejected: usize,
+    /// Sum of applied deposits
+    pub deposits: Balance,
+    /// Sum of applied withdrawals
+    pub withdrawals: Balance,
+    pub chargebacks: usize,
+    /// Number of applied deposit returns
+    pub returns: usize,
+    /// Number of applied unlocks and balance adjustments
+    pub admin: usize,
+}
+
+impl PeriodTotals {
+    pub(crate) fn record(&mut self, action: &Action, outcome: Outcome) {
+        if outcome != Outcome::Applied {
+            self.rejected += 1;
+            return;
+        }
+        self.applied += 1;
+        match action {
+            Action::Deposit { amount, .. } => self.deposits += amount,
+            Action::Withdrawal { amount, .. } => self.withdrawals += amount,
+            Action::Chargeback { .. } => self.chargebacks += 1,
+            Action::Return { .. } => self.returns += 1,
+            Action::Unlock { .. }
+            | Action::CreditAdjustment { .. }
+            | Action::DebitAdjustment { .. } => self.admin += 1,
+            Action::Dispute { .. }
+            | Action::Resolve { .. }
+            | Action::Representment { .. }
+            | Action::ChargebackReversal { .. } => {}
+        }
+    }
+}
+
+/// Frozen result of a closed period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodReport {
+    pub label: String,
+    /// Summaries of all accounts at the close, ordered by client id
+    pub summaries: Vec<AccountSummary>,
+    pub totals: PeriodTotals,
+}
+
+/// What a ledger entry posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerKind {
+    /// A fee taken from the account, see [`FeeSchedule`](crate::fees::FeeSchedule)
+    Fee,
+    /// Interest credited to the available funds
+    Interest,
+    /// Negative interest taken from the available funds
+    NegativeInterest,
+}
+
+/// A fee or interest posted to an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LedgerEntry {
+    /// The day passed to [`AccountStates::end_of_day`]
+    pub date: String,
+    pub client: ClientId,
+    /// The withdrawal or chargeback a fee was charged for, `None` for interest
+    pub tx: Option<TransactionId>,
+    pub kind: LedgerKind,
+    pub amount: Balance,
+}
+
+const DAYS_PER_YEAR: u32 = 365;
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Close the day `date`, such as `2026-10-16`, returning the ledger of the day
+    ///
+    /// The fees charged since the previous end of day come first, in the order
+    /// they were charged. Then, with an interest rate configured through
+    /// [`AccountStatesBuilder::interest`](crate::AccountStatesBuilder::interest),
+    /// a day of interest at a 365th of the annual rate is posted to the available funds
+    /// of every open account, rounded down to 1/10000, and listed by client.
+    /// Negative interest takes at most the available funds.
+    ///
+    /// Every interest posting counts towards [`AccountStates::generation`] and is reported
+    /// to the observers through [`EventObserver::on_interest`](crate::EventObserver::on_interest).
+    pub fn end_of_day(&mut self, date: impl Into<String>) -> Vec<LedgerEntry> {
+        let date = date.into();
+        let mut ledger: Vec<_> = self
+            .pending_fees
+            .drain(..)
+            .map(|(client, transaction, amount)| LedgerEntry {
+                date: date.clone(),
+                client,
+                tx: Some(transaction),
+                kind: LedgerKind::Fee,
+                amount,
+            })
+            .collect();
+        if self.interest_basis_points == 0 {
+            return ledger;
+        }
+        let (rate, kind) = match self.interest_basis_points {
+            rate if rate > 0 => (rate.unsigned_abs(), LedgerKind::Interest),
+            rate => (rate.unsigned_abs(), LedgerKind::NegativeInterest),
+        };
+        let fees = ledger.len();
+        let mut clients: Vec<_> = self.accounts.clients().map(infallible).collect();
+        clients.sort_unstable();
+        for client in clients {
+            let amount = infallible(self.accounts.update_partial(client, &[], |account| {
+                if account.locked {
+                    return None;
+                }
+                let amount = Balance(
+                    (account.available.to_biguint() * rate / (10_000 * DAYS_PER_YEAR)).into(),
+                );
+                if amount.is_zero() {
+                    return None;
+                }
+                account.available = match kind {
+                    LedgerKind::NegativeInterest => {
+                        (account.available.clone() - amount.clone()).unwrap_or_default()
+                    }
+                    _ => &account.available + &amount,
+                };
+                Some(amount)
+            }));
+            if let Some(amount) = amount {
+                ledger.push(LedgerEntry {
+                    date: date.clone(),
+                    client,
+                    tx: None,
+                    kind,
+                    amount,
+                });
+            }
+        }
+        let interest = &ledger[fees..];
+        self.generation += interest.len() as u64;
+        for observer in &self.observers {
+            for entry in interest {
+                observer.on_interest(entry);
+            }
+        }
+        ledger
+    }
+
+    /// Close the current period under `label`
+    ///
+    /// The report freezes the account summaries as of now together with the period counters,
+    /// which then start over. Account balances and transaction history carry over unchanged.
+    pub fn close_period(&mut self, label: impl Into<String>) -> PeriodReport {
+        PeriodReport {
+            label: label.into(),
+            summaries: self.summary(),
+            totals: core::mem::take(&mut self.period),
+        }
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Counters of the current period
+    pub fn period_totals(&self) -> &PeriodTotals {
+        &self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fees::FeeSchedule, TransactionId};
+
+    #[test]
+    fn close_and_reset() {
+        let mut states = AccountStates::default();
+        let deposit = |transaction| Action::Deposit {
+            client: ClientId(1),
+            transaction: TransactionId(transaction),
+            amount: "2".parse().unwrap(),
+        };
+        states.process(deposit(1));
+        states.process(deposit(1));
+        states.process_batch(&[deposit(2), deposit(3)]).unwrap();
+        let report = states.close_period("2026-10-15");
+        assert_eq!(report.label, "2026-10-15");
+        assert_eq!(report.summaries, states.summary());
+        assert_eq!(
+            report.totals,
+            PeriodTotals {
+                applied: 3,
+                rejected: 1,
+                deposits: "6".parse().unwrap(),
+                ..<_>::default()
+            }
+        );
+        assert_eq!(states.period_totals(), &PeriodTotals::default());
+
+        states.process(Action::Withdrawal {
+            client: ClientId(1),
+            transaction: TransactionId(4),
+            amount: "1".parse().unwrap(),
+        });
+        let report = states.close_period("2026-10-16");
+        assert_eq!(report.totals.withdrawals, "1".parse().unwrap());
+        assert_eq!(report.totals.deposits, Balance::default());
+    }
+
+    #[test]
+    fn post_interest_and_fees() {
+        let mut states = AccountStates::builder()
+            .fees(FeeSchedule {
+                account: ClientId(0),
+                withdrawal: "1".parse().unwrap(),
+                ..<_>::default()
+            })
+            .interest(365)
+            .build();
+        for action in [
+            Action::Deposit {
+                client: ClientId(1),
+                transaction: TransactionId(1),
+                amount: "1001".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client: ClientId(1),
+                transaction: TransactionId(2),
+                amount: "500".parse().unwrap(),
+            },
+        ] {
+            assert_eq!(states.process(action), Outcome::Applied);
+        }
+        let entry = |client, tx: Option<u32>, kind, amount: &str| LedgerEntry {
+            date: "2026-10-16".into(),
+            client: ClientId(client),
+            tx: tx.map(TransactionId),
+            kind,
+            amount: amount.parse().unwrap(),
+        };
+        assert_eq!(
+            states.end_of_day("2026-10-16"),
+            [
+                entry(1, Some(2), LedgerKind::Fee, "1"),
+                entry(0, None, LedgerKind::Interest, "0.0001"),
+                entry(1, None, LedgerKind::Interest, "0.05"),
+            ]
+        );
+        assert_eq!(
+            states.account_summary(ClientId(1)).unwrap().available(),
+            &"500.05".parse().unwrap()
+        );
+
+        let mut states = AccountStates::builder().interest(-3650).build();
+        states.process(Action::Deposit {
+            client: ClientId(1),
+            transaction: TransactionId(1),
+            amount: "100".parse().unwrap(),
+        });
+        assert_eq!(
+            states.end_of_day("2026-10-16")[0].kind,
+            LedgerKind::NegativeInterest
+        );
+        assert_eq!(
+            states.account_summary(ClientId(1)).unwrap().available(),
+            &"99.9".parse().unwrap()
+        );
+    }
+}
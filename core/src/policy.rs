@@ -0,0 +1,802 @@
+//! Configurable rules of the engine
+//!
+//! A chargeback always locks its account. Lock policies configured with
+//! [`AccountStatesBuilder`](crate::AccountStatesBuilder) additionally lock an account
+//! right after an applied action leaves it over a threshold, and record which rule fired.
+//! The dispute policy selects how disputes of withdrawals affect balances,
+//! the chargeback policy what becomes of deposits whose funds were already withdrawn,
+//! and the dispute window how old a disputed transaction may be.
+//! A retention policy caps how many transactions each account keeps for later disputes.
+//! Withdrawal limits reject withdrawals that are too large, that would exceed a daily volume,
+//! or that would leave too little available. A client policy suspends a single client,
+//! or gives it withdrawal limits of its own.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::convert::Infallible;
+
+use hashbrown::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, lifecycle::ChargedBack, AccountState, AccountStates, AccountStore, Action, Balance,
+    ClientId, Rejection, Timestamp, TransactionId, TransactionKind,
+};
+
+/// The rule that locked an account automatically
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum AutoLock {
+    /// The held funds exceeded `multiple` times the available funds
+    HeldExceedsAvailable { multiple: u32 },
+    /// The account had its `count`th chargeback
+    Chargebacks { count: usize },
+}
+
+/// How disputes of withdrawals affect balances
+///
+/// Disputes of deposits always move the deposited amount from available to held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputePolicy {
+    /// A disputed withdrawal adds its amount to the held funds,
+    /// which a resolution releases and a chargeback moves to the available funds
+    #[default]
+    HoldWithdrawals,
+    /// Withdrawals cannot be disputed, only deposits
+    ///
+    /// Disputes of withdrawals seeded with [`AccountStates::seed_disputes`]
+    /// are still held as with [`DisputePolicy::HoldWithdrawals`].
+    DepositsOnly,
+    /// A disputed withdrawal leaves the balances alone
+    /// until a chargeback credits its amount back to the available funds
+    ReverseWithdrawals,
+}
+
+impl DisputePolicy {
+    /// Whether open disputes of withdrawals are part of the held funds
+    pub(crate) fn holds_withdrawals(self) -> bool {
+        self != Self::ReverseWithdrawals
+    }
+
+    /// The part of the held funds due to an open dispute of `kind`
+    pub(crate) fn held_by(self, kind: &TransactionKind) -> Option<&Balance> {
+        match kind {
+            TransactionKind::Deposit(amount) => Some(amount),
+            TransactionKind::Withdrawal(amount) => self.holds_withdrawals().then_some(amount),
+        }
+    }
+}
+
+/// How disputes and chargebacks of deposits whose funds were partly withdrawn are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargebackPolicy {
+    /// A deposit can only be disputed while its whole amount is available,
+    /// so that its chargeback always seizes all of it
+    #[default]
+    RequireFunds,
+    /// The dispute of a deposit holds whatever is still available of it,
+    /// and its chargeback seizes that, locks the account and books the rest
+    /// as a shortfall owed by the client, see [`AccountView::shortfall`](crate::AccountView::shortfall)
+    SeizeAvailable,
+    /// As [`ChargebackPolicy::SeizeAvailable`], but the shortfall is absorbed by
+    /// the house account of this client instead of owed by the charged back client
+    BookToHouse(ClientId),
+}
+
+impl ChargebackPolicy {
+    /// Whether a deposit can be disputed beyond the available funds
+    pub(crate) fn seizes_available(self) -> bool {
+        self != Self::RequireFunds
+    }
+}
+
+/// How old a transaction may be and still be disputed, unlimited by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeWindow {
+    /// A dispute must be filed within this many seconds of the transaction
+    ///
+    /// Only disputes with a timestamp of transactions with a timestamp are checked,
+    /// see [`AccountStates::process_at`].
+    Seconds(u64),
+    /// A dispute must be filed before the client made more than this many
+    /// later deposits and withdrawals
+    Transactions(u64),
+}
+
+impl DisputeWindow {
+    pub const fn days(days: u64) -> Self {
+        Self::Seconds(days * 24 * 60 * 60)
+    }
+}
+
+/// How many deposits and withdrawals each account retains for later disputes, all by default
+///
+/// Older transactions are evicted, oldest first, and disputes or returns of them are rejected
+/// with [`Rejection::Evicted`](crate::Rejection::Evicted). Transactions under dispute
+/// are never evicted, since the held funds depend on them. Only the ids of evicted
+/// transactions are remembered, to tell them apart from unknown ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retention {
+    /// Keep at most this many transactions of each client, not counting those under dispute
+    PerClient(usize),
+    /// Evict a transaction once the client made more than this many
+    /// later deposits and withdrawals
+    Transactions(u64),
+}
+
+impl Retention {
+    /// Evict the transactions of the account beyond the retention, returning their ids
+    pub(crate) fn enforce(self, account: &mut AccountState) -> Vec<TransactionId> {
+        let mut evicted = Vec::new();
+        while let Some(&(sequence, transaction)) = account.retained.front() {
+            let expired = match self {
+                Retention::PerClient(max) => account.retained.len() > max,
+                Retention::Transactions(count) => account.sequence - sequence > count,
+            };
+            if !expired {
+                break;
+            }
+            account.retained.pop_front();
+            // Resolved or returned since, or still needed by an open dispute or chargeback
+            if account.sequences.get(&transaction) != Some(&sequence)
+                || account.disputes.contains(&transaction)
+                || account
+                    .chargebacks
+                    .get(&transaction)
+                    .is_some_and(ChargedBack::stands)
+            {
+                continue;
+            }
+            account.transaction_amounts.remove(&transaction);
+            account.chargebacks.remove(&transaction);
+            account.sequences.remove(&transaction);
+            account.timestamps.remove(&transaction);
+            account.evicted.insert(transaction);
+            evicted.push(transaction);
+        }
+        evicted
+    }
+}
+
+/// Thresholds of automatic locks, none by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct LockPolicy {
+    pub(crate) held_multiple: Option<u32>,
+    pub(crate) chargebacks: Option<usize>,
+}
+
+impl LockPolicy {
+    pub(crate) fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Lock the account if `action` took it over a threshold
+    ///
+    /// Chargebacks lock their account anyway, the chargeback rule only records itself
+    /// as the reason, so that reversing the chargebacks does not unlock the account.
+    pub(crate) fn enforce(&self, account: &mut AccountState, action: &Action) {
+        if let (Some(count), Action::Chargeback { .. }) = (self.chargebacks, action) {
+            // Reversed and represented chargebacks count too
+            if account.auto_lock.is_none() && account.chargebacks.len() >= count {
+                account.locked = true;
+                account.auto_lock = Some(AutoLock::Chargebacks { count });
+            }
+        }
+        if account.locked {
+            return;
+        }
+        if let Some(multiple) = self.held_multiple {
+            if account.held.to_biguint() > account.available.to_biguint() * multiple {
+                account.locked = true;
+                account.auto_lock = Some(AutoLock::HeldExceedsAvailable { multiple });
+            }
+        }
+    }
+}
+
+/// Rules of one client that replace the global ones, see
+/// [`AccountStatesBuilder::client_policy`](crate::AccountStatesBuilder::client_policy)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientPolicy {
+    /// Reject every action of the client but admin ones with [`Rejection::Suspended`]
+    pub suspended: bool,
+    /// Maximum of a single withdrawal instead of the global one
+    pub max_withdrawal: Option<Balance>,
+    /// Maximum of the withdrawals of a day instead of the global one
+    pub max_daily_withdrawals: Option<Balance>,
+    /// Minimum balance instead of the global one
+    pub min_balance: Option<Balance>,
+}
+
+/// Caps on the withdrawals of every client, none by default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct WithdrawalLimits {
+    pub(crate) max_single: Option<Balance>,
+    /// Only withdrawals with a timestamp are counted, by UTC day
+    pub(crate) max_daily: Option<Balance>,
+    pub(crate) min_balance: Option<Balance>,
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+impl WithdrawalLimits {
+    pub(crate) fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// The limits of `client`, with those its policy sets in place of the global ones
+    pub(crate) fn of_client(
+        &self,
+        policies: &HashMap<ClientId, ClientPolicy>,
+        client: ClientId,
+    ) -> Cow<'_, Self> {
+        let Some(policy) = policies.get(&client) else {
+            return Cow::Borrowed(self);
+        };
+        let or = |own: &Option<Balance>, global: &Option<Balance>| own.clone().or(global.clone());
+        Cow::Owned(Self {
+            max_single: or(&policy.max_withdrawal, &self.max_single),
+            max_daily: or(&policy.max_daily_withdrawals, &self.max_daily),
+            min_balance: or(&policy.min_balance, &self.min_balance),
+        })
+    }
+
+    /// The rejection of a withdrawal of `amount` from the account that breaches a limit
+    fn check(
+        &self,
+        account: &AccountState,
+        amount: &Balance,
+        timestamp: Option<Timestamp>,
+    ) -> Option<Rejection> {
+        if self.max_single.as_ref().is_some_and(|max| amount > max) {
+            return Some(Rejection::OverWithdrawalLimit);
+        }
+        if let (Some(max), Some(timestamp)) = (&self.max_daily, timestamp) {
+            let day = u64::from(timestamp) / SECONDS_PER_DAY;
+            let withdrawn = match &account.daily_withdrawals {
+                Some((last, withdrawn)) if *last == day => withdrawn + amount,
+                _ => amount.clone(),
+            };
+            if &withdrawn > max {
+                return Some(Rejection::OverDailyLimit);
+            }
+        }
+        if let Some(min) = &self.min_balance {
+            // Withdrawals beyond the available funds are left to fail as such
+            if (account.available.clone() - amount.clone()).is_some_and(|left| &left < min) {
+                return Some(Rejection::BelowMinimumBalance);
+            }
+        }
+        None
+    }
+
+    /// Count an applied withdrawal towards the daily volume of the account
+    pub(crate) fn record(
+        &self,
+        account: &mut AccountState,
+        amount: &Balance,
+        timestamp: Option<Timestamp>,
+    ) {
+        let (Some(_), Some(timestamp)) = (&self.max_daily, timestamp) else {
+            return;
+        };
+        let day = u64::from(timestamp) / SECONDS_PER_DAY;
+        match &mut account.daily_withdrawals {
+            Some((last, withdrawn)) if *last == day => *withdrawn += amount,
+            // Withdrawals timestamped before the current day no longer count
+            Some((last, _)) if *last > day => {}
+            daily => *daily = Some((day, amount.clone())),
+        }
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// The chargeback policy configured through the builder
+    pub fn chargeback_policy(&self) -> ChargebackPolicy {
+        self.chargeback_policy
+    }
+
+    /// The rejection of a withdrawal over a withdrawal limit
+    ///
+    /// Withdrawals from locked accounts are left to be rejected as such.
+    pub(crate) fn check_withdrawal(
+        &self,
+        action: &Action,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Option<Rejection>, S::Error> {
+        let Action::Withdrawal { client, amount, .. } = action else {
+            return Ok(None);
+        };
+        let limits = self
+            .withdrawal_limits
+            .of_client(&self.client_policies, *client);
+        if !limits.is_set() {
+            return Ok(None);
+        }
+        let account = self.accounts.get_partial(*client, &[])?.unwrap_or_default();
+        if account.locked {
+            return Ok(None);
+        }
+        Ok(limits.check(&account, amount, timestamp))
+    }
+
+    /// Whether `action` disputes a transaction older than the dispute window
+    pub(crate) fn outside_dispute_window(
+        &self,
+        action: &Action,
+        timestamp: Option<Timestamp>,
+    ) -> Result<bool, S::Error> {
+        let (
+            Some(window),
+            Action::Dispute {
+                client,
+                transaction,
+            },
+        ) = (self.dispute_window, action)
+        else {
+            return Ok(false);
+        };
+        let Some(account) = self.accounts.get_partial(*client, &[*transaction])? else {
+            return Ok(false);
+        };
+        Ok(match window {
+            DisputeWindow::Seconds(seconds) => {
+                match (timestamp, account.timestamps.get(transaction)) {
+                    (Some(now), Some(&then)) => {
+                        u64::from(now).saturating_sub(then.into()) > seconds
+                    }
+                    _ => false,
+                }
+            }
+            DisputeWindow::Transactions(count) => account
+                .sequences
+                .get(transaction)
+                .is_some_and(|&sequence| account.sequence - sequence > count),
+        })
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// The rule that locked the client's account, if it was locked automatically
+    pub fn auto_lock(&self, client: ClientId) -> Option<AutoLock> {
+        infallible(self.accounts.get_partial(client, &[]))?.auto_lock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Outcome, Rejection, TransactionId};
+
+    #[test]
+    fn dispute_withdrawals_by_policy() {
+        let client = ClientId(1);
+        let dispute = || Action::Dispute {
+            client,
+            transaction: TransactionId(2),
+        };
+        let chargeback = || Action::Chargeback {
+            client,
+            transaction: TransactionId(2),
+        };
+        let balances = |states: &AccountStates| {
+            let summary = states.account_summary(client).unwrap();
+            (summary.available().to_string(), summary.held().to_string())
+        };
+        for (policy, disputed, charged_back) in [
+            (
+                DisputePolicy::HoldWithdrawals,
+                Some(("6.0000", "4.0000")),
+                ("10.0000", "0.0000"),
+            ),
+            (DisputePolicy::DepositsOnly, None, ("6.0000", "0.0000")),
+            (
+                DisputePolicy::ReverseWithdrawals,
+                Some(("6.0000", "0.0000")),
+                ("10.0000", "0.0000"),
+            ),
+        ] {
+            let mut states = AccountStates::builder().dispute_policy(policy).build();
+            states.process(Action::Deposit {
+                client,
+                transaction: TransactionId(1),
+                amount: "10".parse().unwrap(),
+            });
+            states.process(Action::Withdrawal {
+                client,
+                transaction: TransactionId(2),
+                amount: "4".parse().unwrap(),
+            });
+            let rejection = disputed.is_none().then_some(Rejection::NotDeposit);
+            assert_eq!(states.explain(&dispute()).rejection(), rejection);
+            if let Some((available, held)) = disputed {
+                assert_eq!(states.process(dispute()), Outcome::Applied);
+                assert_eq!(balances(&states), (available.into(), held.into()));
+                assert_eq!(states.reconcile(), []);
+                assert_eq!(states.process(chargeback()), Outcome::Applied);
+            } else {
+                assert_eq!(
+                    states.process(dispute()),
+                    Outcome::Rejected(Rejection::NotDeposit)
+                );
+            }
+            let (available, held) = charged_back;
+            assert_eq!(balances(&states), (available.into(), held.into()));
+        }
+    }
+
+    #[test]
+    fn reject_disputes_outside_window() {
+        let client = ClientId(1);
+        let deposit = |transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        let dispute = |transaction| Action::Dispute {
+            client,
+            transaction: TransactionId(transaction),
+        };
+
+        let mut states = AccountStates::builder()
+            .dispute_window(DisputeWindow::Transactions(1))
+            .build();
+        for transaction in 1..=3 {
+            states.process(deposit(transaction));
+        }
+        assert_eq!(
+            states.explain(&dispute(1)).rejection(),
+            Some(Rejection::OutsideDisputeWindow)
+        );
+        assert_eq!(
+            states.process(dispute(1)),
+            Outcome::Rejected(Rejection::OutsideDisputeWindow)
+        );
+        assert_eq!(states.process(dispute(2)), Outcome::Applied);
+
+        let day = 24 * 60 * 60;
+        let mut states = AccountStates::builder()
+            .dispute_window(DisputeWindow::days(90))
+            .build();
+        states.process_at(deposit(1), Some(0.into()));
+        states.process_at(deposit(2), Some((10 * day).into()));
+        states.process(deposit(3));
+        let now = Some((91 * day).into());
+        assert_eq!(
+            states.process_at(dispute(1), now),
+            Outcome::Rejected(Rejection::OutsideDisputeWindow)
+        );
+        assert_eq!(states.process(dispute(1)), Outcome::Applied);
+        assert_eq!(states.process_at(dispute(2), now), Outcome::Applied);
+        assert_eq!(states.process_at(dispute(3), now), Outcome::Applied);
+    }
+
+    #[test]
+    fn evict_beyond_retention() {
+        let client = ClientId(1);
+        let deposit = |transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        let dispute = |transaction| Action::Dispute {
+            client,
+            transaction: TransactionId(transaction),
+        };
+
+        let mut states = AccountStates::builder()
+            .retention(Retention::PerClient(2))
+            .max_transactions(4)
+            .build();
+        states.process(deposit(1));
+        assert_eq!(states.process(dispute(1)), Outcome::Applied);
+        for transaction in 2..=5 {
+            assert_eq!(states.process(deposit(transaction)), Outcome::Applied);
+        }
+        assert_eq!(states.account(client).unwrap().transaction_count(), 3);
+        assert_eq!(
+            states.explain(&dispute(2)).rejection(),
+            Some(Rejection::Evicted)
+        );
+        assert_eq!(
+            states.process(dispute(3)),
+            Outcome::Rejected(Rejection::Evicted)
+        );
+        assert_eq!(
+            states.process(deposit(2)),
+            Outcome::Rejected(Rejection::Evicted)
+        );
+        assert_eq!(
+            states.process(dispute(6)),
+            Outcome::Rejected(Rejection::UnknownTransaction)
+        );
+        assert_eq!(states.process(dispute(5)), Outcome::Applied);
+        assert_eq!(states.reconcile(), []);
+
+        let mut states = AccountStates::builder()
+            .retention(Retention::Transactions(1))
+            .build();
+        for transaction in 1..=3 {
+            states.process(deposit(transaction));
+        }
+        assert_eq!(
+            states.process(dispute(1)),
+            Outcome::Rejected(Rejection::Evicted)
+        );
+        assert_eq!(states.process(dispute(2)), Outcome::Applied);
+    }
+
+    #[test]
+    fn lock_when_held_exceeds_available() {
+        let mut states = AccountStates::builder().lock_when_held_exceeds(3).build();
+        let client = ClientId(1);
+        for (transaction, amount) in [(1, "1"), (2, "2"), (3, "7")] {
+            states.process(Action::Deposit {
+                client,
+                transaction: TransactionId(transaction),
+                amount: amount.parse().unwrap(),
+            });
+        }
+        let dispute = |transaction| Action::Dispute {
+            client,
+            transaction: TransactionId(transaction),
+        };
+        states.process(dispute(3));
+        assert_eq!(states.auto_lock(client), None);
+        assert_eq!(states.process(dispute(2)), Outcome::Applied);
+        assert_eq!(
+            states.auto_lock(client),
+            Some(AutoLock::HeldExceedsAvailable { multiple: 3 })
+        );
+        assert_eq!(
+            states.process(dispute(1)),
+            Outcome::Rejected(Rejection::AccountLocked)
+        );
+        assert!(states.account_summary(client).unwrap().locked());
+    }
+
+    #[test]
+    fn lock_after_chargebacks() {
+        let mut states = AccountStates::builder().lock_after_chargebacks(2).build();
+        let client = ClientId(1);
+        let charge_back = |states: &mut AccountStates, transaction| {
+            let transaction = TransactionId(transaction);
+            for action in [
+                Action::Deposit {
+                    client,
+                    transaction,
+                    amount: "1".parse().unwrap(),
+                },
+                Action::Dispute {
+                    client,
+                    transaction,
+                },
+                Action::Chargeback {
+                    client,
+                    transaction,
+                },
+            ] {
+                states.process(action);
+            }
+        };
+        let reverse = |states: &mut AccountStates, transaction| {
+            let transaction = TransactionId(transaction);
+            states.process(Action::Representment {
+                client,
+                transaction,
+            });
+            states.process(Action::ChargebackReversal {
+                client,
+                transaction,
+            })
+        };
+        charge_back(&mut states, 1);
+        assert_eq!(states.auto_lock(client), None);
+        assert_eq!(reverse(&mut states, 1), Outcome::Applied);
+        assert!(!states.account_summary(client).unwrap().locked());
+
+        charge_back(&mut states, 2);
+        assert_eq!(
+            states.auto_lock(client),
+            Some(AutoLock::Chargebacks { count: 2 })
+        );
+        assert_eq!(reverse(&mut states, 2), Outcome::Applied);
+        assert!(states.account_summary(client).unwrap().locked());
+        states.process(Action::Unlock {
+            client,
+            transaction: TransactionId(3),
+        });
+        assert_eq!(states.auto_lock(client), None);
+        assert!(!states.account_summary(client).unwrap().locked());
+    }
+
+    #[test]
+    fn charge_back_withdrawn_deposits() {
+        let (client, house) = (ClientId(1), ClientId(9));
+        let actions = [
+            Action::Deposit {
+                client,
+                transaction: TransactionId(1),
+                amount: "10".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId(2),
+                amount: "7".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId(1),
+            },
+        ];
+        let chargeback = Action::Chargeback {
+            client,
+            transaction: TransactionId(1),
+        };
+        let shortfall = |states: &AccountStates, client| {
+            states
+                .account(client)
+                .map(|account| account.shortfall().to_string())
+        };
+
+        let mut states = AccountStates::default();
+        for action in actions.clone() {
+            states.process(action);
+        }
+        assert_eq!(
+            states.process(chargeback.clone()),
+            Outcome::Rejected(Rejection::NotDisputed)
+        );
+
+        for (policy, client_shortfall, house_shortfall) in [
+            (ChargebackPolicy::SeizeAvailable, "7.0000", None),
+            (
+                ChargebackPolicy::BookToHouse(house),
+                "0.0000",
+                Some("7.0000".to_owned()),
+            ),
+        ] {
+            let mut states = AccountStates::builder().chargeback_policy(policy).build();
+            for action in actions.clone() {
+                assert_eq!(states.process(action), Outcome::Applied);
+            }
+            let summary = states.account_summary(client).unwrap();
+            assert_eq!(summary.available().to_string(), "0.0000");
+            assert_eq!(summary.held().to_string(), "3.0000");
+            assert_eq!(states.reconcile(), []);
+
+            let mut resolved = states.clone();
+            resolved.process(Action::Resolve {
+                client,
+                transaction: TransactionId(1),
+            });
+            let summary = resolved.account_summary(client).unwrap();
+            assert_eq!(summary.available().to_string(), "3.0000");
+
+            assert_eq!(states.process(chargeback.clone()), Outcome::Applied);
+            let summary = states.account_summary(client).unwrap();
+            assert!(summary.locked());
+            assert_eq!(summary.total().to_string(), "0.0000");
+            assert_eq!(shortfall(&states, client), Some(client_shortfall.into()));
+            assert_eq!(shortfall(&states, house), house_shortfall);
+            assert_eq!(states.reconcile(), []);
+        }
+    }
+
+    #[test]
+    fn enforce_withdrawal_limits() {
+        let client = ClientId(1);
+        let mut states = AccountStates::builder()
+            .max_withdrawal("50".parse().unwrap())
+            .max_daily_withdrawals("80".parse().unwrap())
+            .min_balance("10".parse().unwrap())
+            .build();
+        states.process(Action::Deposit {
+            client,
+            transaction: TransactionId(1),
+            amount: "200".parse().unwrap(),
+        });
+        let withdrawal = |transaction, amount: &str| Action::Withdrawal {
+            client,
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        assert_eq!(
+            states.explain(&withdrawal(2, "60")).to_string(),
+            "rejected: withdrawal exceeds the withdrawal limit of 50.0000"
+        );
+        let day = |days: u64| Some(Timestamp::from(days * SECONDS_PER_DAY + 3600));
+        for (transaction, amount, timestamp, outcome) in [
+            (2, "60", day(1), Err(Rejection::OverWithdrawalLimit)),
+            (3, "50", day(1), Ok(())),
+            (4, "40", day(1), Err(Rejection::OverDailyLimit)),
+            // Withdrawals without a timestamp are not counted
+            (5, "40", None, Ok(())),
+            (6, "30", day(1), Ok(())),
+            (7, "50", day(2), Ok(())),
+            (8, "25", day(2), Err(Rejection::BelowMinimumBalance)),
+            (9, "35", None, Err(Rejection::InsufficientFunds)),
+        ] {
+            assert_eq!(
+                states.process_at(withdrawal(transaction, amount), timestamp),
+                outcome.map_or_else(Outcome::Rejected, |()| Outcome::Applied),
+                "withdrawal {transaction}"
+            );
+        }
+        assert_eq!(
+            states
+                .account_summary(client)
+                .unwrap()
+                .available()
+                .to_string(),
+            "30.0000"
+        );
+    }
+
+    #[test]
+    fn apply_client_policies() {
+        let (vip, suspended, other) = (ClientId(1), ClientId(2), ClientId(3));
+        let mut states = AccountStates::builder()
+            .max_withdrawal("10".parse().unwrap())
+            .client_policy(
+                vip,
+                ClientPolicy {
+                    max_withdrawal: Some("100".parse().unwrap()),
+                    ..<_>::default()
+                },
+            )
+            .client_policy(
+                suspended,
+                ClientPolicy {
+                    suspended: true,
+                    ..<_>::default()
+                },
+            )
+            .build();
+        let deposit = |client, transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "50".parse().unwrap(),
+        };
+        let withdrawal = |client, transaction| Action::Withdrawal {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "20".parse().unwrap(),
+        };
+        for (action, outcome) in [
+            (deposit(vip, 1), Outcome::Applied),
+            (withdrawal(vip, 2), Outcome::Applied),
+            (deposit(other, 3), Outcome::Applied),
+            (
+                withdrawal(other, 4),
+                Outcome::Rejected(Rejection::OverWithdrawalLimit),
+            ),
+            (
+                deposit(suspended, 5),
+                Outcome::Rejected(Rejection::Suspended),
+            ),
+            (
+                Action::CreditAdjustment {
+                    client: suspended,
+                    transaction: TransactionId(6),
+                    amount: "1".parse().unwrap(),
+                },
+                Outcome::Applied,
+            ),
+        ] {
+            assert_eq!(states.process(action), outcome);
+        }
+        assert_eq!(
+            states.explain(&deposit(suspended, 7)).to_string(),
+            "rejected: client is suspended"
+        );
+        assert_eq!(
+            states.explain(&withdrawal(other, 8)).to_string(),
+            "rejected: withdrawal exceeds the withdrawal limit of 10.0000"
+        );
+    }
+}
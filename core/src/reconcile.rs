@@ -0,0 +1,196 @@
+//! Self-check of incrementally maintained balances
+//!
+//! The held funds of an account are fully determined by its open disputes
+//! and the dispute policy,
+//! so they can be recomputed from the retained transactions and compared
+//! with the running total. Available funds cannot be checked the same way,
+//! since resolved transactions are dropped from the history.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use crate::{
+    infallible, AccountStates, AccountStore, Balance, ClientId, EngineError, TransactionId,
+};
+
+/// A mismatch between an account and its retained transaction history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// The held funds differ from the sum of the disputed transactions
+    Held {
+        client: ClientId,
+        recorded: Balance,
+        recomputed: Balance,
+    },
+    /// A transaction is under dispute but no longer retained
+    DanglingDispute {
+        client: ClientId,
+        transaction: TransactionId,
+    },
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Recompute what can be recomputed from the retained transactions and report every drift,
+    /// ordered by client
+    pub fn reconcile(&self) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+        for (client, account) in self.accounts.iter().map(infallible) {
+            let mut recomputed = Balance::default();
+            for transaction in &account.disputes {
+                match account.transaction_amounts.get(transaction) {
+                    Some(kind) => {
+                        if let Some(amount) = self.dispute_policy.held_by(kind) {
+                            recomputed += account.held_for(*transaction, amount)
+                        }
+                    }
+                    None => drifts.push(Drift::DanglingDispute {
+                        client,
+                        transaction: *transaction,
+                    }),
+                }
+            }
+            if recomputed != account.held {
+                drifts.push(Drift::Held {
+                    client,
+                    recorded: account.held.clone(),
+                    recomputed,
+                });
+            }
+        }
+        drifts.sort_by_key(|drift| match *drift {
+            Drift::Held { client, .. } => (client, None),
+            Drift::DanglingDispute {
+                client,
+                transaction,
+            } => (client, Some(transaction)),
+        });
+        drifts
+    }
+
+    /// Check the invariants that processing relies on, failing with the first broken one
+    ///
+    /// Unlike [`AccountStates::reconcile`], this accepts held funds beyond the open disputes,
+    /// as left by [`AccountStates::from_summaries`]. Only a state that fails this check
+    /// can make [`AccountStates::process`] return [`Outcome::Failed`](crate::Outcome::Failed).
+    pub fn verify(&self) -> Result<(), EngineError> {
+        for drift in self.reconcile() {
+            match drift {
+                Drift::Held {
+                    client,
+                    recorded,
+                    recomputed,
+                } if recorded < recomputed => return Err(EngineError::InsufficientHeld { client }),
+                Drift::Held { .. } => {}
+                Drift::DanglingDispute {
+                    client,
+                    transaction,
+                } => {
+                    return Err(EngineError::DanglingDispute {
+                        client,
+                        transaction,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        synthetic::{generate, WorkloadConfig},
+        Action, Outcome,
+    };
+
+    #[test]
+    fn report_drifts() {
+        let config = WorkloadConfig {
+            rows: 10_000,
+            clients: 10,
+            dispute_rate: 0.2,
+            ..<_>::default()
+        };
+        let mut states = AccountStates::default();
+        for action in generate(&config) {
+            states.process(action);
+        }
+        assert_eq!(states.reconcile(), []);
+
+        let client = ClientId(20);
+        states.process(Action::Deposit {
+            client,
+            transaction: TransactionId(1),
+            amount: "2".parse().unwrap(),
+        });
+        states.process(Action::Dispute {
+            client,
+            transaction: TransactionId(1),
+        });
+        let account = states.accounts.map.get_mut(&client).unwrap();
+        account.held = "1".parse().unwrap();
+        account.disputes.insert(TransactionId(2));
+        assert_eq!(
+            states.reconcile(),
+            [
+                Drift::Held {
+                    client,
+                    recorded: "1".parse().unwrap(),
+                    recomputed: "2".parse().unwrap(),
+                },
+                Drift::DanglingDispute {
+                    client,
+                    transaction: TransactionId(2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fail_instead_of_panicking() {
+        let client = ClientId(1);
+        let mut seeded = AccountStates::default();
+        seeded.process(Action::Deposit {
+            client,
+            transaction: TransactionId(9),
+            amount: "5".parse().unwrap(),
+        });
+        seeded.process(Action::Dispute {
+            client,
+            transaction: TransactionId(9),
+        });
+        // The held funds outlive the dispute, which is not carried over
+        let mut states = AccountStates::from_summaries(&seeded.summary());
+        assert_eq!(states.verify(), Ok(()));
+
+        states.process(Action::Deposit {
+            client,
+            transaction: TransactionId(1),
+            amount: "2".parse().unwrap(),
+        });
+        states.process(Action::Dispute {
+            client,
+            transaction: TransactionId(1),
+        });
+        let account = states.accounts.map.get_mut(&client).unwrap();
+        account.held = "1".parse().unwrap();
+        let error = EngineError::InsufficientHeld { client };
+        assert_eq!(states.verify(), Err(error));
+        let before = states.clone();
+        assert_eq!(
+            states.process(Action::Chargeback {
+                client,
+                transaction: TransactionId(1),
+            }),
+            Outcome::Failed(error)
+        );
+        assert_eq!(states, before);
+        assert!(states
+            .process_batch(&[Action::Resolve {
+                client,
+                transaction: TransactionId(1),
+            }])
+            .is_err());
+    }
+}
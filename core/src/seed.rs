@@ -0,0 +1,194 @@
+//! Carrying over in-flight disputes into a state seeded from summaries
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, AccountStates, AccountStore, Balance, ClientId, TransactionId, TransactionKind,
+};
+
+/// A transaction under dispute in another system
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    #[serde(rename = "tx")]
+    pub transaction: TransactionId,
+    pub amount: Balance,
+    pub kind: DisputedKind,
+}
+
+/// Kind of a disputed transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputedKind {
+    Deposit,
+    Withdrawal,
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Record disputes that are already reflected in the held funds of seeded accounts,
+    /// so that they can still be resolved or charged back
+    ///
+    /// Balances are left untouched. Seeded transactions count towards the transaction limit,
+    /// which they must not exceed. Every dispute must belong to an existing account,
+    /// must not reuse a retained transaction id, and the disputes of an account must not
+    /// add up to more than its held funds, counting withdrawals only if the
+    /// [`DisputePolicy`](crate::policy::DisputePolicy) holds them. Use [`AccountStates::reconcile`] afterwards
+    /// to check that the held funds are fully accounted for.
+    pub fn seed_disputes(&mut self, disputes: impl IntoIterator<Item = OpenDispute>) -> Result<()> {
+        for dispute in disputes {
+            let client = u16::from(dispute.client);
+            let tx = u32::from(dispute.transaction);
+            ensure!(
+                infallible(self.accounts.contains(dispute.client)),
+                "dispute of transaction {tx} for unknown client {client}"
+            );
+            if let Some(max) = self.limits.transactions {
+                ensure!(
+                    self.stored_transactions < max,
+                    "dispute of transaction {tx} exceeds the limit of {max} stored transactions"
+                );
+            }
+            let policy = self.dispute_policy;
+            infallible(self.accounts.update(dispute.client, |account| {
+                ensure!(
+                    !account
+                        .transaction_amounts
+                        .contains_key(&dispute.transaction),
+                    "transaction {tx} of client {client} is already recorded"
+                );
+                let disputed: Balance = account
+                    .disputes
+                    .iter()
+                    .filter_map(|transaction| account.transaction_amounts.get(transaction))
+                    .filter_map(|kind| policy.held_by(kind))
+                    .sum();
+                let kind = match dispute.kind {
+                    DisputedKind::Deposit => TransactionKind::Deposit(dispute.amount),
+                    DisputedKind::Withdrawal => TransactionKind::Withdrawal(dispute.amount),
+                };
+                ensure!(
+                    &disputed + policy.held_by(&kind).cloned().unwrap_or_default() <= account.held,
+                    "disputes of client {client} exceed its held funds of {}",
+                    account.held
+                );
+                account
+                    .transaction_amounts
+                    .insert(dispute.transaction, kind);
+                account.disputes.insert(dispute.transaction);
+                Ok(())
+            }))?;
+            if self.limits.transactions.is_some() {
+                self.stored_transactions += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every dispute still open, ordered by client and transaction,
+    /// in the form accepted by [`AccountStates::seed_disputes`]
+    pub fn open_disputes(&self) -> Vec<OpenDispute> {
+        let mut disputes = Vec::new();
+        for (client, account) in self.accounts.iter().map(infallible) {
+            for &transaction in &account.disputes {
+                let (amount, kind) = match account.transaction_amounts.get(&transaction) {
+                    Some(TransactionKind::Deposit(amount)) => (amount, DisputedKind::Deposit),
+                    Some(TransactionKind::Withdrawal(amount)) => (amount, DisputedKind::Withdrawal),
+                    None => continue,
+                };
+                disputes.push(OpenDispute {
+                    client,
+                    transaction,
+                    amount: amount.clone(),
+                    kind,
+                });
+            }
+        }
+        disputes.sort_unstable_by_key(|dispute| (dispute.client, dispute.transaction));
+        disputes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, Outcome};
+
+    #[test]
+    fn resolve_seeded_dispute() {
+        let mut source = AccountStates::default();
+        let client = ClientId(1);
+        source.process(Action::Deposit {
+            client,
+            transaction: TransactionId(1),
+            amount: "3".parse().unwrap(),
+        });
+        source.process(Action::Dispute {
+            client,
+            transaction: TransactionId(1),
+        });
+        let mut states = AccountStates::from_summaries(&source.summary());
+        let dispute = |client, transaction, amount: &str| OpenDispute {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: amount.parse().unwrap(),
+            kind: DisputedKind::Deposit,
+        };
+        assert!(states.seed_disputes([dispute(2, 1, "3")]).is_err());
+        assert!(states.seed_disputes([dispute(1, 1, "4")]).is_err());
+        states.seed_disputes([dispute(1, 1, "3")]).unwrap();
+        assert!(states.seed_disputes([dispute(1, 1, "3")]).is_err());
+        assert_eq!(states.reconcile(), []);
+        assert_eq!(states.open_disputes(), source.open_disputes());
+        assert_eq!(states.open_disputes(), [dispute(1, 1, "3")]);
+        assert_eq!(
+            states.process(Action::Resolve {
+                client,
+                transaction: TransactionId(1),
+            }),
+            Outcome::Applied
+        );
+        assert_eq!(
+            states.account_summary(client).unwrap().available(),
+            &"3".parse::<Balance>().unwrap()
+        );
+    }
+
+    #[test]
+    fn count_seeded_transactions() {
+        let client = ClientId(1);
+        let mut states = AccountStates::builder()
+            .max_transactions(2)
+            .dispute_policy(crate::policy::DisputePolicy::ReverseWithdrawals)
+            .build();
+        let deposit = |transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "5".parse().unwrap(),
+        };
+        let withdrawal = |transaction| OpenDispute {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+            kind: DisputedKind::Withdrawal,
+        };
+        assert_eq!(states.process(deposit(1)), Outcome::Applied);
+        states.seed_disputes([withdrawal(2)]).unwrap();
+        assert!(states.seed_disputes([withdrawal(3)]).is_err());
+        assert_eq!(
+            states.process(deposit(4)),
+            Outcome::Rejected(crate::Rejection::TransactionLimit)
+        );
+        assert_eq!(
+            states.process(Action::Resolve {
+                client,
+                transaction: TransactionId(2),
+            }),
+            Outcome::Applied
+        );
+        assert_eq!(states.process(deposit(4)), Outcome::Applied);
+    }
+}
@@ -9,7 +9,7 @@ impl<'de> Deserialize<'de> for ClientId {
         struct Visitor;
         impl<'de> de::Visitor<'de> for Visitor {
             type Value = u16;
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
                 write!(formatter, "unsigned 16-bit integer")
             }
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -20,6 +20,28 @@ impl<'de> Deserialize<'de> for ClientId {
                     .parse()
                     .map_err(|_| E::custom("invalid u16 number"))
             }
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                core::str::from_utf8(v)
+                    .map_err(|_| E::custom("invalid u16 number"))
+                    .and_then(|v| self.visit_str(v))
+            }
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.try_into()
+                    .map_err(|_| E::custom("u16 number out of range"))
+            }
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.try_into()
+                    .map_err(|_| E::custom("u16 number out of range"))
+            }
             fn visit_u16<E>(self, v: u16) -> Result<Self::Value, E>
             where
                 E: de::Error,
@@ -46,7 +68,7 @@ impl<'de> Deserialize<'de> for TransactionId {
         impl<'de> de::Visitor<'de> for Visitor {
             type Value = u32;
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
                 write!(formatter, "unsigned 32-bit integer")
             }
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -57,6 +79,28 @@ impl<'de> Deserialize<'de> for TransactionId {
                     .parse()
                     .map_err(|_| E::custom("invalid u32 number"))
             }
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                core::str::from_utf8(v)
+                    .map_err(|_| E::custom("invalid u32 number"))
+                    .and_then(|v| self.visit_str(v))
+            }
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.try_into()
+                    .map_err(|_| E::custom("u32 number out of range"))
+            }
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.try_into()
+                    .map_err(|_| E::custom("u32 number out of range"))
+            }
             fn visit_u32<E>(self, v: u32) -> Result<Self::Value, E>
             where
                 E: de::Error,
@@ -0,0 +1,55 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_sink::Sink;
+
+use crate::{AccountStates, Action};
+
+/// Actions are applied synchronously in `start_send`, so the sink is always ready
+/// and never buffers anything that would need flushing.
+impl Sink<Action> for AccountStates {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Action) -> Result<(), Self::Error> {
+        self.get_mut().process(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, stream, StreamExt};
+
+    use super::*;
+    use crate::{Balance, ClientId, TransactionId};
+
+    #[test]
+    fn forward_into_states() {
+        let actions = (1..=3).map(|tx| {
+            Ok(Action::Deposit {
+                client: ClientId(1),
+                transaction: TransactionId(tx),
+                amount: "1.5".parse::<Balance>().unwrap(),
+            })
+        });
+        let mut states = AccountStates::default();
+        block_on(stream::iter(actions).forward(&mut states)).unwrap();
+        let summaries = states.summary();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].available().to_string(), "4.5000");
+    }
+}
@@ -0,0 +1,128 @@
+//! Saved copies of the accounts of a state, for browsing them later
+//!
+//! A [`Snapshot`] keeps every account with its stored transactions, disputes and
+//! chargebacks, the archived accounts and the counters of the current period.
+//! It is serializable, so it can be written at the end of a run and loaded elsewhere,
+//! for example by a read-only server. The configuration of the state is not kept:
+//! [`AccountStates::from_snapshot`] restores the accounts under the default one.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, period::PeriodTotals, AccountState, AccountStates, AccountStore, ClientId,
+    Timestamp,
+};
+
+/// Accounts and period counters of a state at one point, see the [module docs](self)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Live accounts, ordered by client id
+    accounts: Vec<(ClientId, AccountState)>,
+    /// Archived accounts, ordered by client id
+    archived: Vec<(ClientId, AccountState)>,
+    period: PeriodTotals,
+    generation: u64,
+    latest: Option<Timestamp>,
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Copy every account and the counters of the current period
+    pub fn snapshot(&self) -> Snapshot {
+        let mut accounts: Vec<_> = self
+            .accounts
+            .iter()
+            .map(infallible)
+            .map(|(client, account)| (client, account.into_owned()))
+            .collect();
+        accounts.sort_unstable_by_key(|&(client, _)| client);
+        let mut archived: Vec<_> = self
+            .archived
+            .iter()
+            .map(|(&client, account)| (client, account.clone()))
+            .collect();
+        archived.sort_unstable_by_key(|&(client, _)| client);
+        Snapshot {
+            accounts,
+            archived,
+            period: self.period.clone(),
+            generation: self.generation,
+            latest: self.latest,
+        }
+    }
+}
+
+impl AccountStates {
+    /// The accounts and period counters of `snapshot` under the default configuration
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let mut states = Self::with_capacity(snapshot.accounts.len(), 0);
+        for (client, account) in snapshot.accounts {
+            states.accounts.map.insert(client, account);
+        }
+        states.archived = snapshot.archived.into_iter().collect();
+        states.period = snapshot.period;
+        states.generation = snapshot.generation;
+        states.latest = snapshot.latest;
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lifecycle::DisputeStage, Action, TransactionId};
+
+    #[test]
+    fn restore_snapshot() {
+        let mut states = AccountStates::default();
+        let client = ClientId(1);
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: TransactionId(1),
+                amount: "3".parse().unwrap(),
+            },
+            Action::Deposit {
+                client,
+                transaction: TransactionId(2),
+                amount: "1".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId(1),
+            },
+            Action::Deposit {
+                client: ClientId(2),
+                transaction: TransactionId(3),
+                amount: "2".parse().unwrap(),
+            },
+            Action::Dispute {
+                client: ClientId(2),
+                transaction: TransactionId(3),
+            },
+            Action::Chargeback {
+                client: ClientId(2),
+                transaction: TransactionId(3),
+            },
+        ] {
+            states.process(action);
+        }
+        states.archive_locked();
+        let json = serde_json::to_string(&states.snapshot()).unwrap();
+        let restored = AccountStates::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.summary(), states.summary());
+        assert_eq!(restored.period_totals(), states.period_totals());
+        assert_eq!(restored.generation(), states.generation());
+        assert_eq!(
+            restored.dispute_stage(client, TransactionId(1)),
+            Some(DisputeStage::Open)
+        );
+        assert_eq!(
+            restored.dispute_stage(ClientId(2), TransactionId(3)),
+            Some(DisputeStage::ChargedBack)
+        );
+        assert_eq!(restored.account(client).unwrap().transaction_count(), 2);
+    }
+}
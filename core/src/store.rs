@@ -0,0 +1,440 @@
+//! Pluggable storage of accounts
+//!
+//! [`AccountStates`](crate::AccountStates) keeps its accounts in an [`AccountStore`],
+//! by default the in-memory [`MemoryStore`]. Other stores, for example on top of
+//! a database, only need to get, upsert, remove and iterate accounts.
+//! [`AccountState`] is serializable, so a store can persist it in any serde format.
+//!
+//! Stores that can fail, such as databases, report their failures through
+//! [`AccountStore::Error`]. Actions then fail with [`EngineError::Store`](crate::EngineError::Store),
+//! and only processing is available on them: queries such as
+//! [`AccountStates::summary`](crate::AccountStates::summary) need a store that cannot fail,
+//! while [`AccountStates::try_summary`](crate::AccountStates::try_summary) works on any.
+//!
+//! A store that keeps the transactions of an account apart from the account itself
+//! can load only those an action refers to, see [`AccountStore::update_partial`].
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::convert::Infallible;
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::AccountAlloc, AccountState, ClientId, Timestamp, TransactionId, TransactionKind,
+};
+
+/// Storage of the accounts of an [`AccountStates`](crate::AccountStates), keyed by client
+pub trait AccountStore {
+    /// Failure to read or write an account, [`Infallible`] for stores in memory
+    type Error: core::error::Error + Send + Sync + 'static;
+
+    /// The account of `client`, if stored
+    fn get(&self, client: ClientId) -> Result<Option<Cow<'_, AccountState>>, Self::Error>;
+
+    /// Store the account of `client`, replacing any earlier one
+    fn upsert(&mut self, client: ClientId, account: AccountState) -> Result<(), Self::Error>;
+
+    fn remove(&mut self, client: ClientId) -> Result<Option<AccountState>, Self::Error>;
+
+    /// All stored accounts, in no particular order
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = Result<(ClientId, Cow<'_, AccountState>), Self::Error>> + '_;
+
+    /// Number of stored accounts
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, client: ClientId) -> Result<bool, Self::Error> {
+        Ok(self.get(client)?.is_some())
+    }
+
+    /// Clients of all stored accounts, in no particular order
+    fn clients(&self) -> impl Iterator<Item = Result<ClientId, Self::Error>> + '_ {
+        self.iter().map(|entry| entry.map(|(client, _)| client))
+    }
+
+    /// Run `f` on the account of `client`, created empty if it is not stored yet
+    ///
+    /// The default loads the account and upserts it afterwards, stores that hold
+    /// their accounts in memory should update them in place instead.
+    fn update<R>(
+        &mut self,
+        client: ClientId,
+        f: impl FnOnce(&mut AccountState) -> R,
+    ) -> Result<R, Self::Error> {
+        let mut account = self.get(client)?.map(Cow::into_owned).unwrap_or_default();
+        let result = f(&mut account);
+        self.upsert(client, account)?;
+        Ok(result)
+    }
+
+    /// The account of `client`, if stored, with at least the entries of `transactions`
+    ///
+    /// The entries of other transactions, see [`TransactionEntry`], may be left out.
+    /// The engine looks accounts up through this method before applying an action.
+    /// The default loads the whole account.
+    fn get_partial(
+        &self,
+        client: ClientId,
+        transactions: &[TransactionId],
+    ) -> Result<Option<Cow<'_, AccountState>>, Self::Error> {
+        let _ = transactions;
+        self.get(client)
+    }
+
+    /// Run `f` on the account of `client` as loaded by [`AccountStore::get_partial`],
+    /// created empty if it is not stored yet
+    ///
+    /// `f` only reads the entries of `transactions`, and replaces the entries of any
+    /// other transaction it changes as a whole, so a store only needs to write back
+    /// the entries that differ from the loaded ones. The engine applies every action
+    /// through this method, except with a retention policy, which evicts transactions
+    /// the action does not refer to. The default updates the whole account.
+    fn update_partial<R>(
+        &mut self,
+        client: ClientId,
+        transactions: &[TransactionId],
+        f: impl FnOnce(&mut AccountState) -> R,
+    ) -> Result<R, Self::Error> {
+        let _ = transactions;
+        self.update(client, f)
+    }
+}
+
+/// The value of an operation on a store that cannot fail
+pub(crate) fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(never) => match never {},
+    }
+}
+
+/// What an account keeps of one of its transactions, for stores that keep
+/// every transaction apart from the rest of its account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<TransactionKind>,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    disputed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    evicted: bool,
+}
+
+impl TransactionEntry {
+    /// Whether the account keeps nothing of the transaction
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl AccountState {
+    /// The entry of `transaction`, empty if the account keeps nothing of it
+    pub fn transaction_entry(&self, transaction: TransactionId) -> TransactionEntry {
+        TransactionEntry {
+            kind: self.transaction_amounts.get(&transaction).cloned(),
+            disputed: self.disputes.contains(&transaction),
+            timestamp: self.timestamps.get(&transaction).copied(),
+            sequence: self.sequences.get(&transaction).copied(),
+            evicted: self.evicted.contains(&transaction),
+        }
+    }
+
+    /// Replace the entry of `transaction`, removing it if `entry` is empty
+    pub fn set_transaction_entry(&mut self, transaction: TransactionId, entry: TransactionEntry) {
+        match entry.kind {
+            Some(kind) => self.transaction_amounts.insert(transaction, kind),
+            None => self.transaction_amounts.remove(&transaction),
+        };
+        if entry.disputed {
+            self.disputes.insert(transaction);
+        } else {
+            self.disputes.remove(&transaction);
+        }
+        match entry.timestamp {
+            Some(timestamp) => self.timestamps.insert(transaction, timestamp),
+            None => self.timestamps.remove(&transaction),
+        };
+        match entry.sequence {
+            Some(sequence) => self.sequences.insert(transaction, sequence),
+            None => self.sequences.remove(&transaction),
+        };
+        if entry.evicted {
+            self.evicted.insert(transaction);
+        } else {
+            self.evicted.remove(&transaction);
+        }
+    }
+
+    /// Move the entries of all transactions out of the account, leaving the rest of it
+    pub fn take_transaction_entries(&mut self) -> Vec<(TransactionId, TransactionEntry)> {
+        let mut transactions: Vec<_> = self
+            .transaction_amounts
+            .keys()
+            .chain(&self.disputes)
+            .chain(self.timestamps.keys())
+            .chain(self.sequences.keys())
+            .chain(&self.evicted)
+            .copied()
+            .collect();
+        transactions.sort_unstable();
+        transactions.dedup();
+        let entries = transactions
+            .into_iter()
+            .map(|transaction| (transaction, self.transaction_entry(transaction)))
+            .collect();
+        self.transaction_amounts.clear();
+        self.disputes.clear();
+        self.timestamps.clear();
+        self.sequences.clear();
+        self.evicted.clear();
+        entries
+    }
+}
+
+/// Accounts in a hash map in memory, the default store
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    pub(crate) map: HashMap<ClientId, AccountState>,
+    /// Expected number of transactions of each client, to size new accounts
+    pub(crate) txs_per_client: usize,
+    /// Allocator of the maps of new accounts
+    pub(crate) alloc: AccountAlloc,
+}
+
+impl MemoryStore {
+    /// Pre-size the store for about `clients` distinct clients,
+    /// each with about `txs_per_client` transactions
+    pub fn with_capacity(clients: usize, txs_per_client: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(clients),
+            txs_per_client,
+            alloc: AccountAlloc::Global,
+        }
+    }
+}
+
+/// Stores are equal when all accounts are, regardless of capacity hints
+impl PartialEq for MemoryStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl Eq for MemoryStore {}
+
+impl AccountStore for MemoryStore {
+    type Error = Infallible;
+
+    fn get(&self, client: ClientId) -> Result<Option<Cow<'_, AccountState>>, Infallible> {
+        Ok(self.map.get(&client).map(Cow::Borrowed))
+    }
+
+    fn upsert(&mut self, client: ClientId, account: AccountState) -> Result<(), Infallible> {
+        self.map.insert(client, account);
+        Ok(())
+    }
+
+    fn remove(&mut self, client: ClientId) -> Result<Option<AccountState>, Infallible> {
+        Ok(self.map.remove(&client))
+    }
+
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = Result<(ClientId, Cow<'_, AccountState>), Infallible>> + '_ {
+        self.map
+            .iter()
+            .map(|(&client, account)| Ok((client, Cow::Borrowed(account))))
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn contains(&self, client: ClientId) -> Result<bool, Infallible> {
+        Ok(self.map.contains_key(&client))
+    }
+
+    fn clients(&self) -> impl Iterator<Item = Result<ClientId, Infallible>> + '_ {
+        self.map.keys().copied().map(Ok)
+    }
+
+    fn update<R>(
+        &mut self,
+        client: ClientId,
+        f: impl FnOnce(&mut AccountState) -> R,
+    ) -> Result<R, Infallible> {
+        let (txs_per_client, alloc) = (self.txs_per_client, &self.alloc);
+        Ok(f(self.map.entry(client).or_insert_with(|| {
+            AccountState::new_in(txs_per_client, alloc)
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use core::fmt::Display;
+
+    use super::*;
+    use crate::{AccountStates, Action, EngineError, Outcome};
+
+    /// A store that only keeps serialized accounts, like one backed by a database
+    #[derive(Default)]
+    struct SerializedStore(BTreeMap<ClientId, alloc::string::String>);
+
+    impl AccountStore for SerializedStore {
+        type Error = Infallible;
+
+        fn get(&self, client: ClientId) -> Result<Option<Cow<'_, AccountState>>, Infallible> {
+            let account = self.0.get(&client);
+            Ok(account.map(|account| Cow::Owned(serde_json::from_str(account).unwrap())))
+        }
+
+        fn upsert(&mut self, client: ClientId, account: AccountState) -> Result<(), Infallible> {
+            self.0
+                .insert(client, serde_json::to_string(&account).unwrap());
+            Ok(())
+        }
+
+        fn remove(&mut self, client: ClientId) -> Result<Option<AccountState>, Infallible> {
+            let account = self.get(client)?.map(Cow::into_owned);
+            self.0.remove(&client);
+            Ok(account)
+        }
+
+        fn iter(
+            &self,
+        ) -> impl Iterator<Item = Result<(ClientId, Cow<'_, AccountState>), Infallible>> + '_
+        {
+            self.0
+                .keys()
+                .map(|&client| Ok((client, infallible(self.get(client)).unwrap())))
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl Display for Unavailable {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str("store is unavailable")
+        }
+    }
+
+    impl core::error::Error for Unavailable {}
+
+    /// A store in memory that fails once `unavailable` is set
+    #[derive(Default)]
+    struct FlakyStore {
+        accounts: MemoryStore,
+        unavailable: bool,
+    }
+
+    impl FlakyStore {
+        fn check(&self) -> Result<(), Unavailable> {
+            match self.unavailable {
+                true => Err(Unavailable),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl AccountStore for FlakyStore {
+        type Error = Unavailable;
+
+        fn get(&self, client: ClientId) -> Result<Option<Cow<'_, AccountState>>, Unavailable> {
+            self.check()?;
+            Ok(infallible(self.accounts.get(client)))
+        }
+
+        fn upsert(&mut self, client: ClientId, account: AccountState) -> Result<(), Unavailable> {
+            self.check()?;
+            infallible(self.accounts.upsert(client, account));
+            Ok(())
+        }
+
+        fn remove(&mut self, client: ClientId) -> Result<Option<AccountState>, Unavailable> {
+            self.check()?;
+            Ok(infallible(self.accounts.remove(client)))
+        }
+
+        fn iter(
+            &self,
+        ) -> impl Iterator<Item = Result<(ClientId, Cow<'_, AccountState>), Unavailable>> + '_
+        {
+            let failure = self.check().err().map(Err);
+            failure
+                .into_iter()
+                .chain(self.accounts.iter().map(|entry| Ok(infallible(entry))))
+        }
+
+        fn len(&self) -> usize {
+            self.accounts.len()
+        }
+    }
+
+    #[test]
+    fn match_memory_store() {
+        let config = crate::synthetic::WorkloadConfig {
+            rows: 5_000,
+            clients: 20,
+            dispute_rate: 0.1,
+            ..<_>::default()
+        };
+        let mut memory = AccountStates::default();
+        let mut serialized = AccountStates::builder().build_with(SerializedStore::default());
+        for action in crate::synthetic::generate(&config) {
+            let expected = memory.process(action.clone());
+            assert_eq!(serialized.process(action), expected);
+        }
+        assert_eq!(serialized.summary(), memory.summary());
+        assert_eq!(serialized.archive_locked(), memory.archive_locked());
+        assert_eq!(serialized.reconcile(), memory.reconcile());
+    }
+
+    #[test]
+    fn report_store_failures() {
+        let client = ClientId(1);
+        let deposit = |transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        let mut states = AccountStates::builder().build_with(FlakyStore::default());
+        assert_eq!(states.process(deposit(1)), Outcome::Applied);
+
+        states.accounts.unavailable = true;
+        assert_eq!(
+            states.process(deposit(2)),
+            Outcome::Failed(EngineError::Store { client })
+        );
+        assert!(states.try_process(deposit(2)).is_err());
+        assert!(states.try_summary().is_err());
+        let error = states.process_batch(&[deposit(2)]).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<EngineError>(),
+            Some(&EngineError::Store { client })
+        );
+        assert_eq!(error.root_cause().to_string(), "store is unavailable");
+        assert_eq!(states.period_totals().applied, 1);
+
+        states.accounts.unavailable = false;
+        assert_eq!(states.process(deposit(2)), Outcome::Applied);
+        let summary = states.try_summary().unwrap();
+        assert_eq!(summary[0].total().to_string(), "2.0000");
+    }
+}
@@ -0,0 +1,139 @@
+//! Deterministic synthetic workloads for benchmarking and load testing
+
+use alloc::{vec, vec::Vec};
+
+use crate::{decimal::Repr, Action, Balance, ClientId, TransactionId};
+
+/// Shape of a synthetic workload
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    pub rows: usize,
+    pub clients: u16,
+    /// Probability that a row disputes one of the client's earlier deposits
+    pub dispute_rate: f64,
+    /// Probability that a closed dispute ends in a chargeback rather than a resolution
+    pub chargeback_rate: f64,
+    /// Share of deposits among the remaining rows, which are withdrawals
+    pub deposit_share: f64,
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            rows: 1_000_000,
+            clients: 1000,
+            dispute_rate: 0.01,
+            chargeback_rate: 0.2,
+            deposit_share: 0.6,
+            seed: 0,
+        }
+    }
+}
+
+/// SplitMix64, which is tiny and stable across releases so that seeds stay reproducible
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Generate a workload of `config.rows` actions
+///
+/// Most rows are deposits and withdrawals of up to 1000 units;
+/// disputes target the client's latest deposit, and open disputes are later
+/// resolved or charged back, so that every kind of action is exercised.
+pub fn generate(config: &WorkloadConfig) -> impl Iterator<Item = Action> {
+    let mut rng = SplitMix64(config.seed);
+    let clients = config.clients.max(1);
+    let dispute_rate = config.dispute_rate;
+    let chargeback_rate = config.chargeback_rate;
+    let deposit_share = config.deposit_share;
+    let mut last_deposits: Vec<Option<TransactionId>> = vec![None; clients as usize];
+    let mut open_disputes: Vec<(ClientId, TransactionId)> = vec![];
+    let mut next_transaction = 0u32;
+    (0..config.rows).map(move |_| {
+        let roll = rng.next_f64();
+        let index = rng.below(clients.into()) as usize;
+        let client = ClientId(index as u16 + 1);
+        if roll < dispute_rate {
+            if let Some(transaction) = last_deposits[index].take() {
+                open_disputes.push((client, transaction));
+                return Action::Dispute {
+                    client,
+                    transaction,
+                };
+            }
+        } else if roll < 2. * dispute_rate && !open_disputes.is_empty() {
+            let which = rng.below(open_disputes.len() as u64) as usize;
+            let (client, transaction) = open_disputes.swap_remove(which);
+            return if rng.next_f64() < chargeback_rate {
+                Action::Chargeback {
+                    client,
+                    transaction,
+                }
+            } else {
+                Action::Resolve {
+                    client,
+                    transaction,
+                }
+            };
+        }
+        next_transaction = next_transaction.wrapping_add(1);
+        let transaction = TransactionId(next_transaction);
+        let amount = Balance(Repr::Inline(rng.below(1000_0000) + 1));
+        if rng.next_f64() < deposit_share {
+            last_deposits[index] = Some(transaction);
+            Action::Deposit {
+                client,
+                transaction,
+                amount,
+            }
+        } else {
+            Action::Withdrawal {
+                client,
+                transaction,
+                amount,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountStates;
+
+    #[test]
+    fn deterministic_for_seed() {
+        let config = WorkloadConfig {
+            rows: 10_000,
+            clients: 50,
+            dispute_rate: 0.05,
+            ..<_>::default()
+        };
+        assert_eq!(generate(&config).count(), 10_000);
+        let mut first = AccountStates::default();
+        let mut second = AccountStates::default();
+        for (a, b) in generate(&config).zip(generate(&config)) {
+            first.process(a);
+            second.process(b);
+        }
+        assert_eq!(first, second);
+        assert!(generate(&config).any(|action| matches!(action, Action::Chargeback { .. })));
+    }
+}
@@ -0,0 +1,128 @@
+//! Timestamps of actions and chronological validation
+//!
+//! Timestamps are optional and travel next to an action rather than inside it,
+//! like the category column of CSV inputs. The timestamps of deposits and withdrawals
+//! are kept for as long as the transaction can be disputed.
+
+use core::convert::Infallible;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    infallible, AccountStates, AccountStore, Action, ClientId, Outcome, Rejection, TransactionId,
+};
+
+/// Seconds since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl From<u64> for Timestamp {
+    fn from(seconds: u64) -> Self {
+        Self(seconds)
+    }
+}
+
+impl From<Timestamp> for u64 {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.0
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Apply an action that happened at `timestamp`, if known
+    ///
+    /// States built with [`AccountStatesBuilder::chronological`](crate::AccountStatesBuilder::chronological)
+    /// reject an action older than the latest one seen with [`Rejection::OutOfOrder`].
+    /// Actions without a timestamp are never out of order.
+    pub fn process_at(&mut self, action: Action, timestamp: Option<Timestamp>) -> Outcome {
+        self.apply_at(&action, timestamp)
+    }
+
+    /// The rejection of an action out of chronological order or outside the dispute window
+    pub(crate) fn check_timing(
+        &self,
+        action: &Action,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Option<Rejection>, S::Error> {
+        let late = |timestamp| self.latest.is_some_and(|latest| timestamp < latest);
+        Ok(if self.chronological && timestamp.is_some_and(late) {
+            Some(Rejection::OutOfOrder)
+        } else if self.outside_dispute_window(action, timestamp)? {
+            Some(Rejection::OutsideDisputeWindow)
+        } else {
+            None
+        })
+    }
+
+    /// The latest timestamp of all actions processed so far
+    pub fn latest_timestamp(&self) -> Option<Timestamp> {
+        self.latest
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// When a deposit or withdrawal that can still be disputed happened, if it had a timestamp
+    pub fn transaction_timestamp(
+        &self,
+        client: ClientId,
+        transaction: TransactionId,
+    ) -> Option<Timestamp> {
+        infallible(self.accounts.get_partial(client, &[transaction]))?
+            .timestamps
+            .get(&transaction)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_out_of_order_actions() {
+        let client = ClientId(1);
+        let deposit = |transaction| Action::Deposit {
+            client,
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        let mut states = AccountStates::builder().chronological().build();
+        assert_eq!(
+            states.process_at(deposit(1), Some(Timestamp(10))),
+            Outcome::Applied
+        );
+        assert_eq!(states.process_at(deposit(2), None), Outcome::Applied);
+        assert_eq!(
+            states.process_at(deposit(3), Some(Timestamp(9))),
+            Outcome::Rejected(Rejection::OutOfOrder)
+        );
+        assert_eq!(
+            states.process_at(deposit(3), Some(Timestamp(10))),
+            Outcome::Applied
+        );
+        assert_eq!(
+            states.transaction_timestamp(client, TransactionId(1)),
+            Some(Timestamp(10))
+        );
+        assert_eq!(states.transaction_timestamp(client, TransactionId(2)), None);
+
+        states.process(Action::Dispute {
+            client,
+            transaction: TransactionId(1),
+        });
+        states.process(Action::Resolve {
+            client,
+            transaction: TransactionId(1),
+        });
+        assert_eq!(states.transaction_timestamp(client, TransactionId(1)), None);
+
+        let mut lenient = AccountStates::default();
+        lenient.process_at(deposit(1), Some(Timestamp(10)));
+        assert_eq!(
+            lenient.process_at(deposit(2), Some(Timestamp(9))),
+            Outcome::Applied
+        );
+        assert_eq!(lenient.latest_timestamp(), Some(Timestamp(10)));
+    }
+}
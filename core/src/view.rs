@@ -0,0 +1,170 @@
+//! Immutable snapshots of account states for concurrent readers
+//!
+//! A writer applying actions needs `&mut AccountStates`, so readers sharing the state
+//! behind a lock would contend with it on every request. Instead the writer can
+//! publish a [`ReadView`] every so often, and readers serve from their own clone of it
+//! while the writer carries on. [`ReadView::refresh`] brings a published view up to date
+//! with only the accounts a batch touched.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::convert::Infallible;
+
+use crate::{policy::ChargebackPolicy, AccountStates, AccountStore, AccountSummary, ClientId};
+
+/// Frozen copy of all account summaries, stamped with the generation it was taken at
+///
+/// Clones share the same copy, so handing a view to another thread is cheap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadView {
+    generation: u64,
+    accounts: Arc<BTreeMap<ClientId, AccountSummary>>,
+}
+
+impl ReadView {
+    /// The generation of the state when the view was taken
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Summary of a single account, if the client had been seen
+    pub fn account(&self, client: ClientId) -> Option<&AccountSummary> {
+        self.accounts.get(&client)
+    }
+
+    /// Summaries of all accounts, ordered by client id
+    pub fn summaries(&self) -> impl Iterator<Item = &AccountSummary> {
+        self.accounts.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Bring the view up to date with `states` after a batch of actions of `clients`,
+    /// refreshing only their summaries and those of the fee and house accounts
+    ///
+    /// The summaries are updated in place, unless another clone of the view is still
+    /// alive and keeps its own copy. Nothing is done if the generation has not moved.
+    pub fn refresh<S: AccountStore<Error = Infallible>>(
+        &mut self,
+        states: &AccountStates<S>,
+        clients: impl IntoIterator<Item = ClientId>,
+    ) {
+        if self.generation == states.generation {
+            return;
+        }
+        let fees = states.fee_schedule().map(|fees| fees.account);
+        let house = match states.chargeback_policy() {
+            ChargebackPolicy::BookToHouse(house) => Some(house),
+            _ => None,
+        };
+        let accounts = Arc::make_mut(&mut self.accounts);
+        for client in clients.into_iter().chain(fees).chain(house) {
+            match states.account_summary(client) {
+                Some(summary) => accounts.insert(client, summary),
+                None => accounts.remove(&client),
+            };
+        }
+        self.generation = states.generation;
+    }
+}
+
+impl<S: AccountStore> AccountStates<S> {
+    /// Number of actions applied, accounts created and interest postings made so far,
+    /// which grows whenever a summary may have changed
+    ///
+    /// A view whose generation equals the current one is still up to date.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl<S: AccountStore<Error = Infallible>> AccountStates<S> {
+    /// Take an immutable snapshot of all accounts
+    ///
+    /// Taking a view copies every summary, so writers should publish one per batch
+    /// rather than per action, and may skip it when [`AccountStates::generation`]
+    /// has not moved since the last one.
+    pub fn read_view(&self) -> ReadView {
+        ReadView {
+            generation: self.generation,
+            accounts: Arc::new(
+                self.summary()
+                    .into_iter()
+                    .map(|summary| (summary.client, summary))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, Outcome, Rejection, TransactionId};
+
+    #[test]
+    fn view_is_frozen() {
+        let mut states = AccountStates::default();
+        let deposit = |client, transaction| Action::Deposit {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        states.process(deposit(1, 1));
+        let view = states.read_view();
+        let shared = view.clone();
+        assert_eq!(view.generation(), 1);
+
+        states.process(deposit(2, 2));
+        states.process(deposit(1, 1));
+        assert_eq!(states.generation(), 2);
+        assert_eq!(shared.len(), 1);
+        assert_eq!(
+            shared.account(ClientId(1)),
+            states.account_summary(ClientId(1)).as_ref()
+        );
+        assert!(shared.account(ClientId(2)).is_none());
+
+        let view = states.read_view();
+        assert_eq!(view.generation(), 2);
+        assert!(view.summaries().eq(states.summary().iter()));
+    }
+
+    #[test]
+    fn refresh_touched_accounts() {
+        let mut states = AccountStates::default();
+        let deposit = |client, transaction| Action::Deposit {
+            client: ClientId(client),
+            transaction: TransactionId(transaction),
+            amount: "1".parse().unwrap(),
+        };
+        states.process(deposit(1, 1));
+        let mut view = states.read_view();
+        let shared = view.clone();
+        for action in [deposit(2, 2), deposit(1, 3)] {
+            states.process(action);
+        }
+        view.refresh(&states, [ClientId(2), ClientId(1)]);
+        assert_eq!(view, states.read_view());
+        assert_eq!(shared.len(), 1);
+
+        // Rejected, but still creates the account
+        let withdrawal = Action::Withdrawal {
+            client: ClientId(3),
+            transaction: TransactionId(4),
+            amount: "1".parse().unwrap(),
+        };
+        assert_eq!(
+            states.process(withdrawal),
+            Outcome::Rejected(Rejection::InsufficientFunds)
+        );
+        view.refresh(&states, [ClientId(3)]);
+        assert_eq!(view, states.read_view());
+        assert!(view.account(ClientId(3)).is_some());
+    }
+}
@@ -0,0 +1,116 @@
+#ifndef TRANSACTION_PROCESSOR_H
+#define TRANSACTION_PROCESSOR_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * What became of a submitted action
+ */
+typedef enum TpSubmission {
+  TP_SUBMISSION_APPLIED,
+  /**
+   * Refused by the rules, for example for insufficient funds, without changing any balance
+   */
+  TP_SUBMISSION_REJECTED,
+  /**
+   * The account is inconsistent, see `EngineError`
+   */
+  TP_SUBMISSION_FAILED,
+  /**
+   * A null pointer, or an amount that is missing or not a decimal
+   */
+  TP_SUBMISSION_INVALID,
+} TpSubmission;
+
+/**
+ * The `type` of an action
+ */
+typedef enum TpActionType {
+  TP_ACTION_TYPE_DEPOSIT,
+  TP_ACTION_TYPE_WITHDRAWAL,
+  TP_ACTION_TYPE_DISPUTE,
+  TP_ACTION_TYPE_RESOLVE,
+  TP_ACTION_TYPE_CHARGEBACK,
+  TP_ACTION_TYPE_RETURN,
+  TP_ACTION_TYPE_UNLOCK,
+  TP_ACTION_TYPE_CREDIT_ADJUSTMENT,
+  TP_ACTION_TYPE_DEBIT_ADJUSTMENT,
+  TP_ACTION_TYPE_REPRESENTMENT,
+  TP_ACTION_TYPE_CHARGEBACK_REVERSAL,
+} TpActionType;
+
+/**
+ * An opaque engine, created by `tp_engine_new` and destroyed by `tp_engine_free`
+ */
+typedef struct TpEngine TpEngine;
+
+/**
+ * An action as in a row of a CSV input
+ */
+typedef struct TpActionRecord {
+  enum TpActionType kind;
+  uint16_t client;
+  uint32_t tx;
+  /**
+   * Decimal amount as a NUL-terminated string like `"1.5"`, required by deposits,
+   * withdrawals and adjustments and ignored by the other actions
+   */
+  const char *amount;
+} TpActionRecord;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create an empty engine
+ */
+struct TpEngine *tp_engine_new(void);
+
+/**
+ * Destroy an engine, doing nothing if `engine` is null
+ *
+ * # Safety
+ *
+ * `engine` must be null or come from `tp_engine_new`, and must not be used afterwards.
+ */
+void tp_engine_free(struct TpEngine *engine);
+
+/**
+ * Apply an action
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live engine, `action` null or a valid record
+ * whose `amount` is null or a NUL-terminated string.
+ */
+enum TpSubmission tp_engine_submit(struct TpEngine *engine, const struct TpActionRecord *action);
+
+/**
+ * The summaries of all accounts as CSV, in the format of the command line output
+ *
+ * Returns null if `engine` is null. Free the string with `tp_string_free`.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live engine.
+ */
+char *tp_engine_summary_csv(const struct TpEngine *engine);
+
+/**
+ * Free a string returned by the engine, doing nothing if `string` is null
+ *
+ * # Safety
+ *
+ * `string` must be null or come from `tp_engine_summary_csv`, and must not be used afterwards.
+ */
+void tp_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TRANSACTION_PROCESSOR_H */
@@ -0,0 +1,115 @@
+//! Pseudonymous client ids for outputs shared outside the company
+//!
+//! Client ids are replaced by a truncated SHA-256 of a secret salt and the id.
+//! With only 65536 possible ids, the pseudonyms are only as safe as the salt,
+//! which must therefore be kept secret and long enough not to be guessed.
+
+use std::{fs, io::Write, path::Path};
+
+use anyhow::{ensure, Result};
+use csv::WriterBuilder;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{AccountSummary, Balance, ClientId};
+
+/// Bytes of the digest kept in a pseudonym
+const PSEUDONYM_BYTES: usize = 8;
+
+/// Salted hashing of client ids
+#[derive(Clone)]
+pub struct Anonymizer {
+    salt: Vec<u8>,
+}
+
+impl Anonymizer {
+    pub fn new(salt: impl Into<Vec<u8>>) -> Result<Self> {
+        let salt = salt.into();
+        ensure!(!salt.is_empty(), "anonymization salt must not be empty");
+        Ok(Self { salt })
+    }
+
+    /// Read the salt from a file, ignoring a trailing newline
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let mut salt = fs::read(path)?;
+        while salt.last().is_some_and(|b| b.is_ascii_whitespace()) {
+            salt.pop();
+        }
+        Self::new(salt)
+    }
+
+    /// The pseudonym of a client, as lowercase hex
+    pub fn pseudonym(&self, client: ClientId) -> String {
+        let digest = Sha256::new()
+            .chain_update(&self.salt)
+            .chain_update(u16::from(client).to_be_bytes())
+            .finalize();
+        digest[..PSEUDONYM_BYTES]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct AnonymizedSummary<'a> {
+    client: String,
+    locked: bool,
+    available: &'a Balance,
+    held: &'a Balance,
+    total: &'a Balance,
+}
+
+/// Like [`crate::write_summary_io_csv`], with client ids replaced by their pseudonyms
+pub fn write_anonymized_summary_io_csv<'a>(
+    summaries: impl IntoIterator<Item = &'a AccountSummary>,
+    anonymizer: &Anonymizer,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for summary in summaries {
+        writer.serialize(AnonymizedSummary {
+            client: anonymizer.pseudonym(summary.client()),
+            locked: summary.locked(),
+            available: summary.available(),
+            held: summary.held(),
+            total: summary.total(),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_summary_io_csv;
+
+    #[test]
+    fn replace_client_ids() {
+        let anonymizer = Anonymizer::new("pepper").unwrap();
+        let other = Anonymizer::new("salt").unwrap();
+        let client = ClientId::from(1);
+        assert_eq!(anonymizer.pseudonym(client).len(), 2 * PSEUDONYM_BYTES);
+        assert_eq!(anonymizer.pseudonym(client), anonymizer.pseudonym(client));
+        assert_ne!(
+            anonymizer.pseudonym(client),
+            anonymizer.pseudonym(ClientId::from(2))
+        );
+        assert_ne!(anonymizer.pseudonym(client), other.pseudonym(client));
+        assert!(Anonymizer::new("").is_err());
+
+        let summaries =
+            read_summary_io_csv("client,locked,available,held,total\n1,false,1,0,1\n".as_bytes())
+                .unwrap();
+        let mut output = vec![];
+        write_anonymized_summary_io_csv(&summaries, &anonymizer, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "client,locked,available,held,total\n{},false,1.0000,0.0000,1.0000\n",
+                anonymizer.pseudonym(client)
+            )
+        );
+    }
+}
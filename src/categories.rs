@@ -0,0 +1,147 @@
+//! Per-client totals of deposits and withdrawals by category
+//!
+//! Inputs may carry an optional `category` column on deposits and withdrawals.
+//! Only applied actions with a non-empty category are counted.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use anyhow::Result;
+use csv::{Reader, WriterBuilder};
+use serde::Serialize;
+
+use crate::{
+    csv_io::for_each_csv_action_with_column, AccountStates, Action, Balance, ClientId, Outcome,
+    TransactionKind,
+};
+
+/// Totals of one client in one category
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CategoryTotals {
+    pub deposits: Balance,
+    pub withdrawals: Balance,
+}
+
+/// Category totals of every client, ordered by client and category
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CategoryRollup {
+    totals: BTreeMap<(ClientId, String), CategoryTotals>,
+}
+
+#[derive(Serialize)]
+struct CategoryRecord<'a> {
+    client: ClientId,
+    category: &'a str,
+    deposits: &'a Balance,
+    withdrawals: &'a Balance,
+}
+
+impl CategoryRollup {
+    /// Totals of `client` in `category`, if any action was counted
+    pub fn get(&self, client: ClientId, category: &str) -> Option<&CategoryTotals> {
+        self.totals.get(&(client, category.to_owned()))
+    }
+
+    fn record(&mut self, client: ClientId, category: &str, transaction: TransactionKind) {
+        let totals = self
+            .totals
+            .entry((client, category.to_owned()))
+            .or_default();
+        match transaction {
+            TransactionKind::Deposit(amount) => totals.deposits += amount,
+            TransactionKind::Withdrawal(amount) => totals.withdrawals += amount,
+        }
+    }
+
+    /// Write the totals as CSV with the columns `client`, `category`, `deposits` and `withdrawals`
+    pub fn write_io_csv(&self, writer: impl Write) -> Result<()> {
+        let mut writer = WriterBuilder::new().from_writer(writer);
+        for ((client, category), totals) in &self.totals {
+            writer.serialize(CategoryRecord {
+                client: *client,
+                category,
+                deposits: &totals.deposits,
+                withdrawals: &totals.withdrawals,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Apply all actions from a CSV reader, rolling up their amounts by category
+pub fn process_csv_with_categories<R: Read>(
+    states: &mut AccountStates,
+    reader: Reader<R>,
+) -> Result<CategoryRollup> {
+    let mut rollup = CategoryRollup::default();
+    for_each_csv_action_with_column(reader, "category", |action, category| {
+        let category = category.map(String::from_utf8_lossy);
+        let category = category.as_deref().map(str::trim).unwrap_or_default();
+        let counted = match &action {
+            _ if category.is_empty() => None,
+            Action::Deposit { amount, .. } => Some(TransactionKind::Deposit(amount.clone())),
+            Action::Withdrawal { amount, .. } => Some(TransactionKind::Withdrawal(amount.clone())),
+            Action::Dispute { .. }
+            | Action::Resolve { .. }
+            | Action::Chargeback { .. }
+            | Action::Return { .. }
+            | Action::Representment { .. }
+            | Action::ChargebackReversal { .. }
+            | Action::Unlock { .. }
+            | Action::CreditAdjustment { .. }
+            | Action::DebitAdjustment { .. } => None,
+        };
+        let client = action.client();
+        match (states.process(action), counted) {
+            (Outcome::Rejected(rejection), _) if rejection.is_limit() => {
+                return Err(rejection.into())
+            }
+            (Outcome::Failed(error), _) => return Err(error.into()),
+            (Outcome::Applied, Some(transaction)) => rollup.record(client, category, transaction),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(rollup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount, category
+deposit, 1, 1, 10.0, payroll
+deposit, 1, 2, 5.0, refund
+withdrawal, 1, 3, 2.5, groceries
+withdrawal, 1, 4, 1.5, groceries
+withdrawal, 1, 5, 100.0, groceries
+deposit, 2, 6, 3.0,
+deposit, 2, 7, 4.0, payroll
+dispute, 1, 2,, refund
+"#;
+
+    #[test]
+    fn roll_up_by_category() {
+        let mut states = AccountStates::default();
+        let rollup = process_csv_with_categories(
+            &mut states,
+            Reader::from_reader(TRANSACTION_CSV.as_bytes()),
+        )
+        .unwrap();
+        let mut output = vec![];
+        rollup.write_io_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,category,deposits,withdrawals
+1,groceries,0.0000,4.0000
+1,payroll,10.0000,0.0000
+1,refund,5.0000,0.0000
+2,payroll,4.0000,0.0000
+"
+        );
+        assert_eq!(states.summary().len(), 2);
+    }
+}
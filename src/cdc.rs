@@ -0,0 +1,208 @@
+//! Change-data-capture stream of account balance changes
+//!
+//! The change types are defined by the core crate and re-exported here,
+//! together with a JSON Lines sink and CSV input that captures changes.
+
+use std::io::{Read, Write};
+#[cfg(feature = "chaos")]
+use std::sync::Arc;
+
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder};
+use serde::Serialize;
+pub use transaction_processor_core::cdc::*;
+
+#[cfg(feature = "chaos")]
+use crate::chaos::Faults;
+use crate::{
+    anonymize::Anonymizer, for_each_csv_action, policy::AutoLock, AccountStates, AccountSummary,
+    TransactionId,
+};
+
+/// Change sink writing one JSON object per line
+pub struct JsonlChangeSink<W> {
+    writer: W,
+    anonymizer: Option<Anonymizer>,
+    #[cfg(feature = "chaos")]
+    faults: Option<Arc<Faults>>,
+}
+
+impl<W: Write> JsonlChangeSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            anonymizer: None,
+            #[cfg(feature = "chaos")]
+            faults: None,
+        }
+    }
+
+    /// Replace client ids by their pseudonyms
+    pub fn anonymized(self, anonymizer: Anonymizer) -> Self {
+        Self {
+            anonymizer: Some(anonymizer),
+            ..self
+        }
+    }
+
+    /// Delay every change as set by `faults`
+    #[cfg(feature = "chaos")]
+    pub fn with_faults(self, faults: Arc<Faults>) -> Self {
+        Self {
+            faults: Some(faults),
+            ..self
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[derive(Serialize)]
+struct AnonymizedChange<'a> {
+    client: String,
+    field: ChangedField,
+    old: &'a FieldValue,
+    new: &'a FieldValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx: Option<TransactionId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<AutoLock>,
+}
+
+impl<W: Write> ChangeSink for JsonlChangeSink<W> {
+    fn emit(&mut self, change: &BalanceChange) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        if let Some(faults) = &self.faults {
+            faults.delay_sink();
+        }
+        match &self.anonymizer {
+            None => serde_json::to_writer(&mut self.writer, change)?,
+            Some(anonymizer) => serde_json::to_writer(
+                &mut self.writer,
+                &AnonymizedChange {
+                    client: anonymizer.pseudonym(change.client),
+                    field: change.field,
+                    old: &change.old,
+                    new: &change.new,
+                    tx: change.transaction,
+                    reason: change.reason,
+                },
+            )?,
+        }
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// CSV input with change capture for [`AccountStates`]
+pub trait ProcessCsvWithChanges {
+    /// Apply all actions from a CSV reader, reporting every change to `sink`
+    fn process_csv_with_changes<R: Read>(
+        &mut self,
+        reader: Reader<R>,
+        sink: &mut impl ChangeSink,
+    ) -> Result<()>;
+}
+
+impl ProcessCsvWithChanges for AccountStates {
+    fn process_csv_with_changes<R: Read>(
+        &mut self,
+        reader: Reader<R>,
+        sink: &mut impl ChangeSink,
+    ) -> Result<()> {
+        for_each_csv_action(reader, |action| self.process_with_changes(action, sink))
+    }
+}
+
+/// Compute account summary from IO CSV source, streaming every change to `sink`
+pub fn summaries_from_io_csv_with_changes(
+    reader: impl Read,
+    sink: &mut impl ChangeSink,
+) -> Result<Vec<AccountSummary>> {
+    let mut states = AccountStates::default();
+    states.process_csv_with_changes(ReaderBuilder::new().from_reader(reader), sink)?;
+    Ok(states.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+withdrawal, 1, 2, 5.0
+dispute, 1, 1,
+chargeback, 1, 1,
+"#;
+
+    #[test]
+    fn emit_changes_as_jsonl() {
+        let mut sink = JsonlChangeSink::new(vec![]);
+        summaries_from_io_csv_with_changes(TRANSACTION_CSV.as_bytes(), &mut sink).unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            r#"{"client":1,"field":"available","old":"0.0000","new":"1.0000","tx":1}
+{"client":1,"field":"available","old":"1.0000","new":"0.0000","tx":1}
+{"client":1,"field":"held","old":"0.0000","new":"1.0000","tx":1}
+{"client":1,"field":"held","old":"1.0000","new":"0.0000","tx":1}
+{"client":1,"field":"locked","old":false,"new":true,"tx":1}
+"#
+        );
+    }
+
+    #[test]
+    fn record_auto_lock_reason() {
+        let mut states = AccountStates::builder().lock_when_held_exceeds(1).build();
+        let mut sink = JsonlChangeSink::new(vec![]);
+        states
+            .process_csv_with_changes(
+                ReaderBuilder::new().from_reader(
+                    "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1,\n".as_bytes(),
+                ),
+                &mut sink,
+            )
+            .unwrap();
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert!(output.ends_with(
+            r#"{"client":1,"field":"locked","old":false,"new":true,"tx":1,"reason":{"rule":"held_exceeds_available","multiple":1}}
+"#
+        ));
+    }
+
+    #[test]
+    fn emit_interest_changes() {
+        let mut states = AccountStates::builder().interest(3650).build();
+        let mut sink = JsonlChangeSink::new(vec![]);
+        states
+            .process_csv_with_changes(
+                ReaderBuilder::new()
+                    .from_reader("type, client, tx, amount\ndeposit, 1, 1, 10\n".as_bytes()),
+                &mut sink,
+            )
+            .unwrap();
+        let generation = states.generation();
+        states
+            .end_of_day_with_changes("2026-10-16", &mut sink)
+            .unwrap();
+        assert_eq!(states.generation(), generation + 1);
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert!(output.ends_with(
+            r#"{"client":1,"field":"available","old":"10.0000","new":"10.0100"}
+"#
+        ));
+    }
+
+    #[test]
+    fn anonymize_changes() {
+        let anonymizer = Anonymizer::new("pepper").unwrap();
+        let pseudonym = anonymizer.pseudonym(1.into());
+        let mut sink = JsonlChangeSink::new(vec![]).anonymized(anonymizer);
+        summaries_from_io_csv_with_changes(TRANSACTION_CSV.as_bytes(), &mut sink).unwrap();
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert!(output.starts_with(&format!(
+            r#"{{"client":"{pseudonym}","field":"available","old":"0.0000","new":"1.0000","tx":1}}"#
+        )));
+    }
+}
@@ -0,0 +1,220 @@
+//! Fault injection for rehearsing failures of a durable service, only available with the `chaos` feature
+//!
+//! A [`Faults`] is handed to the parts of the service it should break:
+//! - [`Faults::snapshot_writer`] wraps the writer of a snapshot, failing every write
+//!   with [`Faults::fail_snapshot_write`]
+//! - a [`WriteAheadLog`](crate::wal::WriteAheadLog) or a
+//!   [`JsonlChangeSink`](crate::cdc::JsonlChangeSink) given the faults with `with_faults`
+//!   waits [`Faults::sink_delay`] before every entry or change it writes
+//! - a write-ahead log given the faults fails the action chosen by [`Faults::drop_action`]
+//!   with an error, so that [`ProcessLogged::process_logged`](crate::wal::ProcessLogged)
+//!   neither logs nor applies it
+//!
+//! Faults can also be parsed from a comma separated list, for setting them from a
+//! configuration or the environment: `fail-snapshot-write,sink-delay-ms=250,drop-action=5`.
+
+use std::{
+    io::{self, Write},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+/// Faults to inject, none by default
+#[derive(Debug, Default)]
+pub struct Faults {
+    fail_snapshot_write: bool,
+    sink_delay: Option<Duration>,
+    drop_action: Option<u64>,
+    /// Actions counted towards `drop_action` so far
+    actions: AtomicU64,
+}
+
+impl Faults {
+    /// Fail every write to a [`Faults::snapshot_writer`]
+    pub fn fail_snapshot_write(mut self) -> Self {
+        self.fail_snapshot_write = true;
+        self
+    }
+
+    /// Wait `delay` before every entry or change written to a sink
+    pub fn sink_delay(mut self, delay: Duration) -> Self {
+        self.sink_delay = Some(delay);
+        self
+    }
+
+    /// Fail the `nth` action appended to a write-ahead log, counting from 1 across all logs
+    /// given these faults
+    pub fn drop_action(mut self, nth: u64) -> Self {
+        self.drop_action = Some(nth);
+        self
+    }
+
+    /// `writer`, failing every write if snapshot writes should fail
+    pub fn snapshot_writer<W: Write>(&self, writer: W) -> SnapshotWriter<W> {
+        SnapshotWriter {
+            writer,
+            fail: self.fail_snapshot_write,
+        }
+    }
+
+    /// Wait as long as a sink should before writing
+    pub(crate) fn delay_sink(&self) {
+        if let Some(delay) = self.sink_delay {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Count an action, failing if it is the one to drop
+    pub(crate) fn count_action(&self) -> Result<()> {
+        let nth = self.actions.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.drop_action == Some(nth) {
+            bail!("injected fault: dropped action {nth}");
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Faults {
+    type Err = Error;
+
+    fn from_str(list: &str) -> Result<Self> {
+        let mut faults = Self::default();
+        for fault in list
+            .split(',')
+            .map(str::trim)
+            .filter(|fault| !fault.is_empty())
+        {
+            faults = match fault.split_once('=') {
+                None if fault == "fail-snapshot-write" => faults.fail_snapshot_write(),
+                Some(("sink-delay-ms", millis)) => faults.sink_delay(Duration::from_millis(
+                    millis
+                        .parse()
+                        .with_context(|| format!("invalid delay {millis:?}"))?,
+                )),
+                Some(("drop-action", nth)) => faults.drop_action(
+                    nth.parse()
+                        .with_context(|| format!("invalid action {nth:?}"))?,
+                ),
+                _ => return Err(anyhow!("unknown fault {fault:?}")),
+            };
+        }
+        Ok(faults)
+    }
+}
+
+/// Writer of a snapshot that fails every write if asked to, see [`Faults::snapshot_writer`]
+#[derive(Debug)]
+pub struct SnapshotWriter<W> {
+    writer: W,
+    fail: bool,
+}
+
+impl<W> SnapshotWriter<W> {
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Write for SnapshotWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.fail {
+            return Err(io::Error::other("injected fault: snapshot write"));
+        }
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Instant};
+
+    use super::*;
+    use crate::{
+        cdc::{JsonlChangeSink, ProcessCsvWithChanges},
+        wal::{ProcessLogged, WriteAheadLog},
+        write_snapshot_json, AccountStates, Action, TransactionId,
+    };
+
+    fn deposit(transaction: u32) -> Action {
+        Action::Deposit {
+            client: 1.into(),
+            transaction: TransactionId::from(transaction),
+            amount: "1".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn parse_faults() {
+        let faults: Faults = "fail-snapshot-write, sink-delay-ms=250,drop-action=5"
+            .parse()
+            .unwrap();
+        assert!(faults.fail_snapshot_write);
+        assert_eq!(faults.sink_delay, Some(Duration::from_millis(250)));
+        assert_eq!(faults.drop_action, Some(5));
+        assert!("".parse::<Faults>().is_ok());
+        assert!("drop-action=x".parse::<Faults>().is_err());
+        assert!("fail-everything".parse::<Faults>().is_err());
+    }
+
+    #[test]
+    fn drop_the_nth_logged_action() {
+        let path = std::env::temp_dir().join(format!("chaos-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let faults = Arc::new(Faults::default().drop_action(2));
+        let mut log = WriteAheadLog::open(&path).unwrap().with_faults(faults);
+        let mut states = AccountStates::default();
+        let logged: Vec<_> = (1..=3)
+            .map(|tx| states.process_logged(deposit(tx), None, &mut log).is_ok())
+            .collect();
+        assert_eq!(logged, [true, false, true]);
+        drop(log);
+        assert_eq!(
+            states.account(1.into()).unwrap().total().to_string(),
+            "2.0000"
+        );
+        assert_eq!(
+            AccountStates::recover(AccountStates::default(), &path).unwrap(),
+            states
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn delay_the_change_sink() {
+        let faults = Arc::new(Faults::default().sink_delay(Duration::from_millis(20)));
+        let mut sink = JsonlChangeSink::new(vec![]).with_faults(faults);
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\n";
+        let start = Instant::now();
+        let mut states = AccountStates::default();
+        states
+            .process_csv_with_changes(csv::Reader::from_reader(input.as_bytes()), &mut sink)
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(sink.into_inner().iter().filter(|&&b| b == b'\n').count(), 2);
+    }
+
+    #[test]
+    fn fail_snapshot_writes() {
+        let mut states = AccountStates::default();
+        states.process(deposit(1));
+        let error = write_snapshot_json(
+            &states,
+            Faults::default()
+                .fail_snapshot_write()
+                .snapshot_writer(vec![]),
+        )
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("injected fault: snapshot write"));
+        let mut written = Faults::default().snapshot_writer(vec![]);
+        write_snapshot_json(&states, &mut written).unwrap();
+        assert!(!written.into_inner().is_empty());
+    }
+}
@@ -0,0 +1,353 @@
+//! Compact columnar encoding of an action history for repeated replays
+//!
+//! Actions are grouped by client, keeping their relative order within each client.
+//! With the default configuration of [`AccountStates`], this yields exactly the same
+//! account states as the original order, because every action only touches the state
+//! of its own client. Rules that look across clients or at the global order, like a
+//! transaction index, a house account, limits on clients or transactions, dispute windows
+//! counted in transactions or chronological mode, can give different results on replay.
+//! Replaying then needs a single account lookup per client.
+//!
+//! The binary layout is the magic bytes, the row count as `u64`,
+//! then the kind, client and transaction columns as little-endian integers,
+//! and finally the byte length of the amount column as `u64` followed by the amounts.
+//! Each amount is a length byte followed by its value in 1/10000 units as little-endian bytes.
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, ensure, Result};
+use csv::Reader;
+
+use crate::{
+    for_each_csv_action, AccountStates, AccountSummary, Action, Balance, ClientId, TransactionId,
+};
+
+const MAGIC: &[u8; 8] = b"TXPCOL01";
+
+const DEPOSIT: u8 = 0;
+const WITHDRAWAL: u8 = 1;
+const DISPUTE: u8 = 2;
+const RESOLVE: u8 = 3;
+const CHARGEBACK: u8 = 4;
+const RETURN: u8 = 5;
+const UNLOCK: u8 = 6;
+const CREDIT_ADJUSTMENT: u8 = 7;
+const DEBIT_ADJUSTMENT: u8 = 8;
+const REPRESENTMENT: u8 = 9;
+const CHARGEBACK_REVERSAL: u8 = 10;
+
+/// Action history stored column by column, grouped by client
+#[derive(Default)]
+pub struct ColumnarActions {
+    kinds: Vec<u8>,
+    clients: Vec<u16>,
+    transactions: Vec<u32>,
+    amounts: Vec<u8>,
+}
+
+impl ColumnarActions {
+    pub fn from_actions(actions: impl IntoIterator<Item = Action>) -> Self {
+        let mut actions: Vec<_> = actions.into_iter().collect();
+        actions.sort_by_key(Action::client);
+        let mut columns = Self::default();
+        for action in actions {
+            columns.push(action);
+        }
+        columns
+    }
+
+    pub fn from_csv<R: Read>(reader: Reader<R>) -> Result<Self> {
+        let mut actions = vec![];
+        for_each_csv_action(reader, |action| {
+            actions.push(action);
+            Ok(())
+        })?;
+        Ok(Self::from_actions(actions))
+    }
+
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    fn push(&mut self, action: Action) {
+        let (kind, client, transaction, amount) = match action {
+            Action::Deposit {
+                client,
+                transaction,
+                amount,
+            } => (DEPOSIT, client, transaction, Some(amount)),
+            Action::Withdrawal {
+                client,
+                transaction,
+                amount,
+            } => (WITHDRAWAL, client, transaction, Some(amount)),
+            Action::Dispute {
+                client,
+                transaction,
+            } => (DISPUTE, client, transaction, None),
+            Action::Resolve {
+                client,
+                transaction,
+            } => (RESOLVE, client, transaction, None),
+            Action::Chargeback {
+                client,
+                transaction,
+            } => (CHARGEBACK, client, transaction, None),
+            Action::Return {
+                client,
+                transaction,
+            } => (RETURN, client, transaction, None),
+            Action::Unlock {
+                client,
+                transaction,
+            } => (UNLOCK, client, transaction, None),
+            Action::CreditAdjustment {
+                client,
+                transaction,
+                amount,
+            } => (CREDIT_ADJUSTMENT, client, transaction, Some(amount)),
+            Action::DebitAdjustment {
+                client,
+                transaction,
+                amount,
+            } => (DEBIT_ADJUSTMENT, client, transaction, Some(amount)),
+            Action::Representment {
+                client,
+                transaction,
+            } => (REPRESENTMENT, client, transaction, None),
+            Action::ChargebackReversal {
+                client,
+                transaction,
+            } => (CHARGEBACK_REVERSAL, client, transaction, None),
+        };
+        self.kinds.push(kind);
+        self.clients.push(client.into());
+        self.transactions.push(transaction.into());
+        if let Some(amount) = amount {
+            let bytes = amount.to_units_le_bytes();
+            self.amounts
+                .push(u8::try_from(bytes.len()).expect("amount should be shorter than 256 bytes"));
+            self.amounts.extend(bytes);
+        }
+    }
+
+    /// Decode the actions back in their stored order
+    pub fn actions(&self) -> impl Iterator<Item = Result<Action>> + '_ {
+        let mut amounts = &self.amounts[..];
+        self.kinds
+            .iter()
+            .zip(&self.clients)
+            .zip(&self.transactions)
+            .map(move |((&kind, &client), &transaction)| {
+                let client = ClientId::from(client);
+                let transaction = TransactionId::from(transaction);
+                Ok(match kind {
+                    DEPOSIT => Action::Deposit {
+                        client,
+                        transaction,
+                        amount: take_amount(&mut amounts)?,
+                    },
+                    WITHDRAWAL => Action::Withdrawal {
+                        client,
+                        transaction,
+                        amount: take_amount(&mut amounts)?,
+                    },
+                    DISPUTE => Action::Dispute {
+                        client,
+                        transaction,
+                    },
+                    RESOLVE => Action::Resolve {
+                        client,
+                        transaction,
+                    },
+                    CHARGEBACK => Action::Chargeback {
+                        client,
+                        transaction,
+                    },
+                    RETURN => Action::Return {
+                        client,
+                        transaction,
+                    },
+                    UNLOCK => Action::Unlock {
+                        client,
+                        transaction,
+                    },
+                    CREDIT_ADJUSTMENT => Action::CreditAdjustment {
+                        client,
+                        transaction,
+                        amount: take_amount(&mut amounts)?,
+                    },
+                    DEBIT_ADJUSTMENT => Action::DebitAdjustment {
+                        client,
+                        transaction,
+                        amount: take_amount(&mut amounts)?,
+                    },
+                    REPRESENTMENT => Action::Representment {
+                        client,
+                        transaction,
+                    },
+                    CHARGEBACK_REVERSAL => Action::ChargebackReversal {
+                        client,
+                        transaction,
+                    },
+                    kind => bail!("unknown action kind {kind}"),
+                })
+            })
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.kinds)?;
+        for client in &self.clients {
+            writer.write_all(&client.to_le_bytes())?;
+        }
+        for transaction in &self.transactions {
+            writer.write_all(&transaction.to_le_bytes())?;
+        }
+        writer.write_all(&(self.amounts.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.amounts)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        ensure!(&magic == MAGIC, "not a columnar action file");
+        let rows = read_len(&mut reader)?;
+        let (Some(clients_len), Some(transactions_len)) =
+            (rows.checked_mul(2), rows.checked_mul(4))
+        else {
+            bail!("row count {rows} is too large");
+        };
+        let kinds = read_bytes(&mut reader, rows)?;
+        let clients = read_bytes(&mut reader, clients_len)?
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let transactions = read_bytes(&mut reader, transactions_len)?
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let amounts_len = read_len(&mut reader)?;
+        let amounts = read_bytes(&mut reader, amounts_len)?;
+        Ok(Self {
+            kinds,
+            clients,
+            transactions,
+            amounts,
+        })
+    }
+}
+
+fn read_len(reader: &mut impl Read) -> Result<usize> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    Ok(u64::from_le_bytes(len).try_into()?)
+}
+
+fn read_bytes(reader: &mut impl Read, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    ensure!(bytes.len() == len, "truncated columnar action file");
+    Ok(bytes)
+}
+
+fn take_amount(amounts: &mut &[u8]) -> Result<Balance> {
+    let (&len, rest) = amounts
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("missing amount"))?;
+    let len = len as usize;
+    ensure!(rest.len() >= len, "truncated amount");
+    let (bytes, rest) = rest.split_at(len);
+    *amounts = rest;
+    Ok(Balance::from_units_le_bytes(bytes))
+}
+
+/// Columnar input for [`AccountStates`]
+pub trait Replay {
+    /// Apply a compiled action history, looking up each client's account only once
+    fn replay(&mut self, columns: &ColumnarActions) -> Result<()>;
+}
+
+impl Replay for AccountStates {
+    fn replay(&mut self, columns: &ColumnarActions) -> Result<()> {
+        let mut actions = columns.actions();
+        let mut batch = vec![];
+        for run in columns.clients.chunk_by(|a, b| a == b) {
+            batch.clear();
+            for action in actions.by_ref().take(run.len()) {
+                batch.push(action?);
+            }
+            self.process_batch(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compute account summary from a compiled columnar action file
+pub fn summaries_from_columnar(reader: impl Read) -> Result<Vec<AccountSummary>> {
+    let mut states = AccountStates::default();
+    states.replay(&ColumnarActions::read_from(reader)?)?;
+    Ok(states.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{summaries_from_io_csv, write_summary_io_csv};
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 12345678901234567890.5
+dispute, 1, 1,
+deposit, 1, 3, 2.0
+withdrawal, 2, 5, 3.0
+resolve, 1, 1,
+withdrawal, 1, 4, 1.5
+dispute, 2, 2,
+chargeback, 2, 2,
+"#;
+
+    #[test]
+    fn replay_matches_csv() {
+        let columns =
+            ColumnarActions::from_csv(Reader::from_reader(TRANSACTION_CSV.as_bytes())).unwrap();
+        assert_eq!(columns.len(), 9);
+        let mut compiled = vec![];
+        columns.write_to(&mut compiled).unwrap();
+
+        let mut replayed = vec![];
+        write_summary_io_csv(
+            summaries_from_columnar(&compiled[..]).unwrap(),
+            &mut replayed,
+        )
+        .unwrap();
+        let mut expected = vec![];
+        write_summary_io_csv(
+            summaries_from_io_csv(TRANSACTION_CSV.as_bytes()).unwrap(),
+            &mut expected,
+        )
+        .unwrap();
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn reject_truncated_file() {
+        let columns =
+            ColumnarActions::from_csv(Reader::from_reader(TRANSACTION_CSV.as_bytes())).unwrap();
+        let mut compiled = vec![];
+        columns.write_to(&mut compiled).unwrap();
+        compiled.pop();
+        assert!(ColumnarActions::read_from(&compiled[..]).is_err());
+        assert!(ColumnarActions::read_from(&b"garbage!"[..]).is_err());
+        let overflowing = [&MAGIC[..], &(u64::MAX / 2).to_le_bytes()].concat();
+        assert!(ColumnarActions::read_from(&overflowing[..])
+            .is_err_and(|e| e.to_string().contains("too large")));
+    }
+}
@@ -0,0 +1,153 @@
+//! Per-client differences between an expected and an actual summary file
+//!
+//! Nightly verification runs process the same input twice, or compare against a
+//! reference implementation, and need to know exactly which accounts disagree.
+//! [`write_state_diff_io_csv`] writes the finer [`StateDiff`] of two states,
+//! which also tells which disputes were opened and closed.
+
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use crate::{diff::StateDiff, trend::signed_change, AccountSummary, Balance, ClientId};
+
+/// Disagreement of the expected and actual summary of one client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SummaryDifference {
+    pub client: ClientId,
+    /// Actual minus expected available funds, with an explicit sign
+    pub available_change: String,
+    /// Actual minus expected held funds, with an explicit sign
+    pub held_change: String,
+    /// Empty if the client is missing from the expected summary
+    pub expected_locked: Option<bool>,
+    /// Empty if the client is missing from the actual summary
+    pub actual_locked: Option<bool>,
+}
+
+/// Every client whose balances or lock flag differ, ordered by client
+///
+/// A client missing from one summary counts as having zero balances there.
+pub fn compare(expected: &[AccountSummary], actual: &[AccountSummary]) -> Vec<SummaryDifference> {
+    let mut pairs: BTreeMap<ClientId, (Option<&AccountSummary>, Option<&AccountSummary>)> =
+        BTreeMap::new();
+    for summary in expected {
+        pairs.entry(summary.client()).or_default().0 = Some(summary);
+    }
+    for summary in actual {
+        pairs.entry(summary.client()).or_default().1 = Some(summary);
+    }
+    let zero = Balance::default();
+    let balances = |summary: Option<&AccountSummary>| match summary {
+        Some(summary) => (summary.available().clone(), summary.held().clone()),
+        None => (zero.clone(), zero.clone()),
+    };
+    pairs
+        .into_iter()
+        .filter_map(|(client, (expected, actual))| {
+            let (expected_available, expected_held) = balances(expected);
+            let (actual_available, actual_held) = balances(actual);
+            let (expected_locked, actual_locked) = (
+                expected.map(AccountSummary::locked),
+                actual.map(AccountSummary::locked),
+            );
+            // The total is derived from the balances, so it cannot disagree on its own
+            if (&expected_available, &expected_held, expected_locked)
+                == (&actual_available, &actual_held, actual_locked)
+            {
+                return None;
+            }
+            Some(SummaryDifference {
+                client,
+                available_change: signed_change(&expected_available, &actual_available),
+                held_change: signed_change(&expected_held, &actual_held),
+                expected_locked,
+                actual_locked,
+            })
+        })
+        .collect()
+}
+
+pub fn write_differences_io_csv(
+    differences: &[SummaryDifference],
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for difference in differences {
+        writer.serialize(difference)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct AccountDiffRow {
+    client: ClientId,
+    available_change: String,
+    held_change: String,
+    locked_before: bool,
+    locked_after: bool,
+    /// Transaction ids separated by spaces
+    opened_disputes: String,
+    closed_disputes: String,
+}
+
+/// Write a diff with the columns `client`, `available_change`, `held_change`,
+/// `locked_before`, `locked_after`, `opened_disputes` and `closed_disputes`,
+/// the last two listing transaction ids separated by spaces
+pub fn write_state_diff_io_csv(diff: &StateDiff, writer: impl Write) -> Result<()> {
+    let ids = |transactions: &[_]| {
+        transactions
+            .iter()
+            .map(|&transaction| u32::from(transaction).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for account in &diff.accounts {
+        writer.serialize(AccountDiffRow {
+            client: account.client,
+            available_change: signed_change(account.before.available(), account.after.available()),
+            held_change: signed_change(account.before.held(), account.after.held()),
+            locked_before: account.before.locked(),
+            locked_after: account.after.locked(),
+            opened_disputes: ids(&account.opened_disputes),
+            closed_disputes: ids(&account.closed_disputes),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_summary_io_csv;
+
+    #[test]
+    fn report_differences() {
+        let expected = read_summary_io_csv(
+            "client,locked,available,held,total\n1,false,5,0,5\n2,false,1,1,2\n3,false,1,0,1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let actual = read_summary_io_csv(
+            "client,locked,available,held,total\n1,false,5,0,5\n2,true,1.5,0,1.5\n4,false,0,2,2\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(compare(&expected, &expected), []);
+        let mut output = vec![];
+        write_differences_io_csv(&compare(&expected, &actual), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available_change,held_change,expected_locked,actual_locked
+2,+0.5000,-1.0000,false,true
+3,-1.0000,+0.0000,false,
+4,+0.0000,+2.0000,,false
+"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Recording the shape of real runs for realistic synthetic benchmarks
+//!
+//! A corpus is a JSON Lines file with one [`CorpusEntry`] per recorded run.
+//! Entries only hold counts and timings, never client ids, transaction ids or amounts,
+//! so a corpus can be shared with whoever tunes performance.
+
+use std::{
+    io::{BufRead, Read, Write},
+    time::Instant,
+};
+
+use anyhow::Result;
+use csv::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    for_each_csv_action, synthetic::WorkloadConfig, AccountStates, AccountSummary, Action, Outcome,
+};
+
+/// Anonymized shape and timing of one run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    pub rows: usize,
+    /// Number of distinct clients
+    pub clients: usize,
+    pub deposits: usize,
+    pub withdrawals: usize,
+    pub disputes: usize,
+    pub resolves: usize,
+    pub chargebacks: usize,
+    pub returns: usize,
+    /// Unlocks and balance adjustments, missing from entries recorded before they existed
+    #[serde(default)]
+    pub admin: usize,
+    /// Representments and chargeback reversals, missing from entries recorded before they existed
+    #[serde(default)]
+    pub representments: usize,
+    pub rejected: usize,
+    /// Wall time spent decoding and applying the input
+    pub elapsed_ms: u64,
+}
+
+impl CorpusEntry {
+    fn count(&mut self, action: &Action) {
+        self.rows += 1;
+        *match action {
+            Action::Deposit { .. } => &mut self.deposits,
+            Action::Withdrawal { .. } => &mut self.withdrawals,
+            Action::Dispute { .. } => &mut self.disputes,
+            Action::Resolve { .. } => &mut self.resolves,
+            Action::Chargeback { .. } => &mut self.chargebacks,
+            Action::Return { .. } => &mut self.returns,
+            Action::Representment { .. } | Action::ChargebackReversal { .. } => {
+                &mut self.representments
+            }
+            Action::Unlock { .. }
+            | Action::CreditAdjustment { .. }
+            | Action::DebitAdjustment { .. } => &mut self.admin,
+        } += 1;
+    }
+
+    /// A synthetic workload with the same row count, clients and mix of actions
+    ///
+    /// Returns, representments and admin actions are not generated,
+    /// so their rows count as deposits and withdrawals.
+    pub fn workload(&self, seed: u64) -> WorkloadConfig {
+        let ratio = |part: usize, whole: usize| {
+            if whole == 0 {
+                0.
+            } else {
+                part as f64 / whole as f64
+            }
+        };
+        WorkloadConfig {
+            rows: self.rows,
+            clients: self.clients.clamp(1, u16::MAX.into()) as u16,
+            dispute_rate: ratio(self.disputes, self.rows),
+            chargeback_rate: ratio(self.chargebacks, self.chargebacks + self.resolves),
+            deposit_share: ratio(self.deposits, self.deposits + self.withdrawals),
+            seed,
+        }
+    }
+}
+
+/// Apply all actions from a CSV reader like [`crate::ProcessCsv::process_csv`],
+/// recording the shape and timing of the run
+pub fn record_csv<R: Read>(reader: Reader<R>) -> Result<(Vec<AccountSummary>, CorpusEntry)> {
+    let start = Instant::now();
+    let mut states = AccountStates::default();
+    let mut entry = CorpusEntry::default();
+    for_each_csv_action(reader, |action| {
+        entry.count(&action);
+        match states.process(action) {
+            Outcome::Rejected(rejection) if rejection.is_limit() => return Err(rejection.into()),
+            Outcome::Rejected(_) => entry.rejected += 1,
+            Outcome::Failed(error) => return Err(error.into()),
+            Outcome::Applied => {}
+        }
+        Ok(())
+    })?;
+    let summaries = states.summary();
+    entry.elapsed_ms = start.elapsed().as_millis().try_into().unwrap_or(u64::MAX);
+    entry.clients = summaries.len();
+    Ok((summaries, entry))
+}
+
+/// Append an entry as one line of a corpus
+pub fn append_corpus_entry(entry: &CorpusEntry, mut writer: impl Write) -> Result<()> {
+    serde_json::to_writer(&mut writer, entry)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read every entry of a corpus, skipping blank lines
+pub fn read_corpus(reader: impl BufRead) -> Result<Vec<CorpusEntry>> {
+    let mut entries = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 2, 3, 2.0
+withdrawal, 1, 4, 5.0
+dispute, 2, 2,
+resolve, 2, 2,
+dispute, 2, 3,
+chargeback, 2, 3,
+"#;
+
+    #[test]
+    fn record_and_replay_shape() {
+        let (summaries, entry) =
+            record_csv(Reader::from_reader(TRANSACTION_CSV.as_bytes())).unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(
+            entry,
+            CorpusEntry {
+                rows: 8,
+                clients: 2,
+                deposits: 3,
+                withdrawals: 1,
+                disputes: 2,
+                resolves: 1,
+                chargebacks: 1,
+                rejected: 1,
+                elapsed_ms: entry.elapsed_ms,
+                ..<_>::default()
+            }
+        );
+
+        let mut corpus = vec![];
+        append_corpus_entry(&entry, &mut corpus).unwrap();
+        append_corpus_entry(&entry, &mut corpus).unwrap();
+        let text = String::from_utf8(corpus.clone()).unwrap();
+        assert!(!text.contains("client\":1") && text.lines().count() == 2);
+        let entries = read_corpus(&corpus[..]).unwrap();
+        assert_eq!(entries, [entry.clone(), entry.clone()]);
+
+        let workload = entry.workload(7);
+        assert_eq!((workload.rows, workload.clients), (8, 2));
+        assert_eq!(workload.dispute_rate, 0.25);
+        assert_eq!(workload.chargeback_rate, 0.5);
+        assert_eq!(workload.deposit_share, 0.75);
+    }
+}
@@ -0,0 +1,949 @@
+//! CSV input and output of account states, only available with the `std` feature
+
+use std::{
+    borrow::Borrow,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use csv::{ByteRecord, ErrorKind, Position, Reader, ReaderBuilder, Trim, Writer, WriterBuilder};
+use serde::{
+    de::{self, value::BorrowedBytesDeserializer},
+    Deserialize,
+};
+
+use crate::{
+    fees::ExtendedSummary, seed::OpenDispute, AccountStates, AccountSummary, Action, Balance,
+    ClientId, Outcome, Rejection, Timestamp, TransactionId,
+};
+
+/// Rough length in bytes of an input CSV row, used to estimate row counts from file sizes
+const ESTIMATED_ROW_BYTES: u64 = 20;
+
+/// Compute account summary from a CSV reader
+pub fn summaries_from_csv<R: Read>(reader: Reader<R>) -> Result<Vec<AccountSummary>> {
+    let mut states = AccountStates::default();
+    states.process_csv(reader)?;
+    Ok(states.summary())
+}
+
+/// Compute account summary from a CSV reader, skipping rows that fail to decode
+pub fn summaries_from_csv_lenient<R: Read>(
+    reader: Reader<R>,
+) -> Result<(Vec<AccountSummary>, Vec<RowError>)> {
+    let mut states = AccountStates::default();
+    let errors = states.process_csv_lenient(reader)?;
+    Ok((states.summary(), errors))
+}
+
+/// Compute account summary from a CSV reader in chronological mode,
+/// skipping and returning the rows that are out of order
+///
+/// See [`AccountStatesBuilder::chronological`](crate::AccountStatesBuilder::chronological).
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn summaries_from_csv_chronological<R: Read>(
+    reader: Reader<R>,
+) -> Result<(Vec<AccountSummary>, Vec<RowError>)> {
+    let mut states = AccountStates::builder().chronological().build();
+    let mut errors = vec![];
+    for_each_timed_record(reader, Schema::Lenient, |action, timestamp, record| {
+        match states.process_at(action, timestamp) {
+            Outcome::Rejected(rejection) if rejection.is_limit() => return Err(rejection.into()),
+            Outcome::Failed(error) => return Err(error.into()),
+            Outcome::Rejected(rejection @ Rejection::OutOfOrder) => {
+                let latest = states.latest_timestamp().map_or(0, u64::from);
+                errors.push(RowError::new(
+                    record.position().map(Position::line),
+                    record,
+                    RowErrorKind::OutOfOrder,
+                    format_args!("{rejection}, the latest is {latest}"),
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok((states.summary(), errors))
+}
+
+/// Columns of inputs without a header row, in order
+const POSITIONAL_HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Columns read by some inputs, besides those of [`POSITIONAL_HEADERS`]
+const OPTIONAL_HEADERS: [&str; 2] = ["timestamp", "category"];
+
+/// How strictly the columns and fields of a CSV input are checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Schema {
+    /// Ignore unknown columns, and the amounts of actions that take none like disputes
+    #[default]
+    Lenient,
+    /// Fail on unknown columns, on a missing `type`, `client` or `tx` column,
+    /// on an empty amount and on an amount given to an action that takes none
+    ///
+    /// Known columns are `type`, `client`, `tx`, `amount`, `timestamp` and `category`.
+    Strict,
+}
+
+impl FromStr for Schema {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            _ => bail!("unknown schema {s:?}, expected lenient or strict"),
+        }
+    }
+}
+
+/// Dialect and column names of CSV inputs, for TSV files, legacy and bank exports
+///
+/// The options are carried by the [`Reader`] built by [`CsvOptions::reader`],
+/// which every function taking a CSV reader accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field delimiter, `,` by default
+    pub delimiter: u8,
+    /// Quote character, `"` by default, or `None` to read quotes as part of the fields
+    pub quote: Option<u8>,
+    /// Whether the first row names the columns, `true` by default
+    ///
+    /// Rows of inputs without headers are read as `type`, `client`, `tx` and `amount`,
+    /// in that order.
+    pub headers: bool,
+    /// Input headers to read as other columns, like `("txn_type", "type")`
+    ///
+    /// Headers are compared once trimmed. Columns without an alias keep their header.
+    pub aliases: Vec<(String, String)>,
+    /// Checks of the columns and fields, lenient by default
+    ///
+    /// Only applied by functions taking the options, like [`summaries_from_file_with_options`],
+    /// or the schema, like [`ProcessCsv::process_csv_with_schema`].
+    pub schema: Schema,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: Some(b'"'),
+            headers: true,
+            aliases: vec![],
+            schema: Schema::Lenient,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Read the input header `header` as the column `column`
+    pub fn alias(mut self, header: impl Into<String>, column: impl Into<String>) -> Self {
+        self.aliases.push((header.into(), column.into()));
+        self
+    }
+
+    /// A CSV reader of `reader` in this dialect
+    ///
+    /// With aliases, the header row is read and renamed up front, which fails on I/O errors.
+    pub fn reader<R: Read>(&self, reader: R) -> Result<Reader<R>> {
+        let mut builder = ReaderBuilder::new();
+        builder.delimiter(self.delimiter).has_headers(self.headers);
+        match self.quote {
+            Some(quote) => builder.quote(quote),
+            None => builder.quoting(false),
+        };
+        let mut reader = builder.from_reader(reader);
+        if self.headers && !self.aliases.is_empty() {
+            let headers = reader
+                .byte_headers()?
+                .iter()
+                .map(|raw| {
+                    let header = std::str::from_utf8(raw)?.trim();
+                    Ok(
+                        match self.aliases.iter().find(|(alias, _)| alias == header) {
+                            Some((_, column)) => column.as_bytes(),
+                            None => raw,
+                        }
+                        .to_vec(),
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            reader.set_byte_headers(headers.into());
+        }
+        Ok(reader)
+    }
+}
+
+/// CSV input for [`AccountStates`]
+pub trait ProcessCsv {
+    /// Apply all actions from a CSV reader
+    ///
+    /// Actions are applied with the timestamp of the optional `timestamp` column,
+    /// in seconds since the Unix epoch, see [`AccountStates::process_at`].
+    /// Fails at the first action that would exceed a configured limit.
+    fn process_csv<R: Read>(&mut self, reader: Reader<R>) -> Result<()>;
+
+    /// Like [`ProcessCsv::process_csv`], checking the columns and fields as set by `schema`
+    ///
+    /// `process_csv` is the same with [`Schema::Lenient`].
+    fn process_csv_with_schema<R: Read>(&mut self, reader: Reader<R>, schema: Schema)
+        -> Result<()>;
+
+    /// Apply all actions from a CSV reader, skipping and returning the rows that fail to decode
+    ///
+    /// Still fails on I/O errors and at the first action that would exceed a configured limit.
+    fn process_csv_lenient<R: Read>(&mut self, reader: Reader<R>) -> Result<Vec<RowError>>;
+
+    /// Seed a state from the summary CSV of an earlier run, to continue from its closing balances
+    ///
+    /// See [`AccountStates::from_summaries`] for what is carried over.
+    /// Fails if a row does not parse or its `total` is not the sum of its `available`
+    /// and `held` funds, which only an edited file has.
+    fn from_summary_csv(reader: impl Read) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+impl ProcessCsv for AccountStates {
+    fn process_csv<R: Read>(&mut self, reader: Reader<R>) -> Result<()> {
+        self.process_csv_with_schema(reader, Schema::Lenient)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "process_csv", skip_all)
+    )]
+    fn process_csv_with_schema<R: Read>(
+        &mut self,
+        reader: Reader<R>,
+        schema: Schema,
+    ) -> Result<()> {
+        for_each_timed_record(reader, schema, |action, timestamp, _| {
+            process_checked(self, action, timestamp)
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn process_csv_lenient<R: Read>(&mut self, reader: Reader<R>) -> Result<Vec<RowError>> {
+        let mut errors = vec![];
+        for_each_csv_action_lenient(
+            reader,
+            |action| process_checked(self, action, None),
+            |error| errors.push(error),
+        )?;
+        Ok(errors)
+    }
+
+    fn from_summary_csv(reader: impl Read) -> Result<Self> {
+        let summaries = read_summary_io_csv(reader)?;
+        for summary in &summaries {
+            ensure!(
+                summary.available() + summary.held() == *summary.total(),
+                "total of client {} is not the sum of its available and held funds",
+                u16::from(summary.client())
+            );
+        }
+        Ok(Self::from_summaries(&summaries))
+    }
+}
+
+/// Apply an action, failing only if it would exceed a configured limit
+fn process_checked(
+    states: &mut AccountStates,
+    action: Action,
+    timestamp: Option<Timestamp>,
+) -> Result<()> {
+    match states.process_at(action, timestamp) {
+        Outcome::Rejected(rejection) if rejection.is_limit() => Err(rejection.into()),
+        Outcome::Failed(error) => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// A CSV row that was skipped, because it failed to decode in lenient mode
+/// or was out of order in chronological mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// Line of the row in the input, where the header is line 1
+    pub line: u64,
+    /// Fields of the row as read
+    pub record: Vec<String>,
+    pub kind: RowErrorKind,
+    pub error: String,
+}
+
+impl RowError {
+    fn new(
+        line: Option<u64>,
+        record: &ByteRecord,
+        kind: RowErrorKind,
+        error: impl Display,
+    ) -> Self {
+        Self {
+            line: line.unwrap_or_default(),
+            record: record
+                .iter()
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect(),
+            kind,
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Why a row was skipped, without the details of the row, so that rows can be grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RowErrorKind {
+    /// The field of the column is missing, or the column is
+    Missing(&'static str),
+    /// The field of the column does not decode
+    Invalid(&'static str),
+    /// The row has a different number of fields than the others
+    FieldCount,
+    /// The row is before an earlier row in chronological mode
+    OutOfOrder,
+}
+
+impl Display for RowErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowErrorKind::Missing(column) => write!(f, "missing {column} in column '{column}'"),
+            RowErrorKind::Invalid(column) => write!(f, "invalid {column} in column '{column}'"),
+            RowErrorKind::FieldCount => f.write_str("wrong number of fields"),
+            RowErrorKind::OutOfOrder => f.write_str("out of order"),
+        }
+    }
+}
+
+/// Number of skipped rows of every kind, most frequent first
+pub fn count_row_errors<'a>(
+    errors: impl IntoIterator<Item = &'a RowError>,
+) -> Vec<(RowErrorKind, u64)> {
+    let mut counts = std::collections::BTreeMap::new();
+    for error in errors {
+        *counts.entry(error.kind).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|&(kind, count)| (std::cmp::Reverse(count), kind));
+    counts
+}
+
+/// Table of [`count_row_errors`], one line per kind like `1,204 rows: invalid amount in column 'amount'`
+pub fn format_row_error_counts<'a>(errors: impl IntoIterator<Item = &'a RowError>) -> String {
+    count_row_errors(errors)
+        .into_iter()
+        .map(|(kind, count)| {
+            let digits = count.to_string();
+            let mut grouped = String::new();
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (digits.len() - i) % 3 == 0 {
+                    grouped.push(',');
+                }
+                grouped.push(digit);
+            }
+            let rows = if count == 1 { "row" } else { "rows" };
+            format!("{grouped} {rows}: {kind}\n")
+        })
+        .collect()
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: {} in {:?}",
+            self.line,
+            self.error,
+            self.record.join(",")
+        )
+    }
+}
+
+/// Decode every action from a CSV reader in order
+///
+/// Headers are trimmed and mapped to column positions once up front, and every field
+/// is then parsed in place from the raw record bytes, so decoding a row allocates nothing
+/// but the rare amounts too large to be stored inline.
+pub fn for_each_csv_action<R: Read>(
+    reader: Reader<R>,
+    mut f: impl FnMut(Action) -> Result<()>,
+) -> Result<()> {
+    for_each_csv_action_with_column(reader, "", |action, _| f(action))
+}
+
+/// Like [`for_each_csv_action`], also passing the raw value of the optional `column`
+pub(crate) fn for_each_csv_action_with_column<R: Read>(
+    mut reader: Reader<R>,
+    column: &str,
+    f: impl FnMut(Action, Option<&[u8]>) -> Result<()>,
+) -> Result<()> {
+    let headers = read_headers(&mut reader)?;
+    let column = headers.iter().position(|header| header == column);
+    for_each_record_action(reader, &Columns::new(&headers, Schema::Lenient)?, column, f)
+}
+
+/// The trimmed headers of `reader`, or the positional columns if it has no header row
+fn read_headers<R: Read>(reader: &mut Reader<R>) -> Result<Vec<String>> {
+    if reader.has_headers() {
+        trim_headers(reader.byte_headers()?)
+    } else {
+        Ok(POSITIONAL_HEADERS.map(str::to_owned).into())
+    }
+}
+
+fn trim_headers(raw: &ByteRecord) -> Result<Vec<String>> {
+    raw.iter()
+        .map(|header| Ok(std::str::from_utf8(header)?.trim().to_owned()))
+        .collect()
+}
+
+/// Positions of the action fields in the rows of a CSV input, resolved once from its headers
+///
+/// With [`Schema::Lenient`], a missing column is only an error for the rows that need it,
+/// like a missing field.
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    kind: Option<usize>,
+    client: Option<usize>,
+    tx: Option<usize>,
+    amount: Option<usize>,
+    strict: bool,
+}
+
+impl Columns {
+    fn new(headers: &[String], schema: Schema) -> Result<Self> {
+        let position = |name| headers.iter().position(|header| header == name);
+        let columns = Self {
+            kind: position("type"),
+            client: position("client"),
+            tx: position("tx"),
+            amount: position("amount"),
+            strict: schema == Schema::Strict,
+        };
+        if columns.strict {
+            if let Some(unknown) = headers.iter().find(|header| {
+                !POSITIONAL_HEADERS.contains(&header.as_str())
+                    && !OPTIONAL_HEADERS.contains(&header.as_str())
+            }) {
+                bail!("unknown column {unknown:?}");
+            }
+            for (column, name) in [
+                (columns.kind, "type"),
+                (columns.client, "client"),
+                (columns.tx, "tx"),
+            ] {
+                ensure!(column.is_some(), "missing column {name:?}");
+            }
+        }
+        Ok(columns)
+    }
+}
+
+fn for_each_record_action<R: Read>(
+    mut reader: Reader<R>,
+    columns: &Columns,
+    column: Option<usize>,
+    mut f: impl FnMut(Action, Option<&[u8]>) -> Result<()>,
+) -> Result<()> {
+    let mut record = ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        let action = decode_action(columns, &record)?;
+        f(action, column.and_then(|column| record.get(column)))?
+    }
+    Ok(())
+}
+
+/// Decode every action with its optional timestamp and raw row
+fn for_each_timed_record<R: Read>(
+    mut reader: Reader<R>,
+    schema: Schema,
+    mut f: impl FnMut(Action, Option<Timestamp>, &ByteRecord) -> Result<()>,
+) -> Result<()> {
+    let headers = read_headers(&mut reader)?;
+    let column = headers.iter().position(|header| header == "timestamp");
+    let columns = Columns::new(&headers, schema)?;
+    let mut record = ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        let action = decode_action(&columns, &record)?;
+        let timestamp = match column.and_then(|column| record.get(column)) {
+            Some(field) => parse_timestamp(field)?,
+            None => None,
+        };
+        f(action, timestamp, &record)?
+    }
+    Ok(())
+}
+
+fn parse_timestamp(field: &[u8]) -> Result<Option<Timestamp>> {
+    let field = std::str::from_utf8(field)?.trim();
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let seconds: u64 = field
+        .parse()
+        .with_context(|| format!("invalid timestamp {field:?}"))?;
+    Ok(Some(seconds.into()))
+}
+
+/// The `type` of every action, as expected by [`decode_action`]
+const ACTION_TYPES: &[&str] = &[
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "return",
+    "representment",
+    "chargeback_reversal",
+    "unlock",
+    "credit_adjustment",
+    "debit_adjustment",
+];
+
+/// Decode the action of a row, with the same errors as deserializing the row into an [`Action`]
+fn decode_action(columns: &Columns, record: &ByteRecord) -> Result<Action, de::value::Error> {
+    decode_action_kind(columns, record).map_err(|(_, error)| error)
+}
+
+/// Like [`decode_action`], also telling which column the error is about
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+fn decode_action_kind(
+    columns: &Columns,
+    record: &ByteRecord,
+) -> Result<Action, (RowErrorKind, de::value::Error)> {
+    let field = |column: Option<usize>, name| {
+        column
+            .and_then(|column| record.get(column))
+            .ok_or_else(|| (RowErrorKind::Missing(name), de::Error::missing_field(name)))
+    };
+    let parse = |column, name| Ok(BorrowedBytesDeserializer::new(field(column, name)?));
+    let invalid = |name| move |error| (RowErrorKind::Invalid(name), error);
+    let kind = field(columns.kind, "type")?;
+    let client =
+        ClientId::deserialize(parse(columns.client, "client")?).map_err(invalid("client"))?;
+    let transaction =
+        TransactionId::deserialize(parse(columns.tx, "tx")?).map_err(invalid("tx"))?;
+    let amount = || {
+        let amount = field(columns.amount, "amount")?;
+        if columns.strict && amount.trim_ascii().is_empty() {
+            return Err((
+                RowErrorKind::Missing("amount"),
+                de::Error::missing_field("amount"),
+            ));
+        }
+        Balance::deserialize(BorrowedBytesDeserializer::new(amount)).map_err(invalid("amount"))
+    };
+    let action = match kind {
+        b"deposit" => Action::Deposit {
+            client,
+            transaction,
+            amount: amount()?,
+        },
+        b"withdrawal" => Action::Withdrawal {
+            client,
+            transaction,
+            amount: amount()?,
+        },
+        b"credit_adjustment" => Action::CreditAdjustment {
+            client,
+            transaction,
+            amount: amount()?,
+        },
+        b"debit_adjustment" => Action::DebitAdjustment {
+            client,
+            transaction,
+            amount: amount()?,
+        },
+        b"dispute" => Action::Dispute {
+            client,
+            transaction,
+        },
+        b"resolve" => Action::Resolve {
+            client,
+            transaction,
+        },
+        b"chargeback" => Action::Chargeback {
+            client,
+            transaction,
+        },
+        b"return" => Action::Return {
+            client,
+            transaction,
+        },
+        b"representment" => Action::Representment {
+            client,
+            transaction,
+        },
+        b"chargeback_reversal" => Action::ChargebackReversal {
+            client,
+            transaction,
+        },
+        b"unlock" => Action::Unlock {
+            client,
+            transaction,
+        },
+        kind => {
+            return Err(invalid("type")(de::Error::unknown_variant(
+                &String::from_utf8_lossy(kind),
+                ACTION_TYPES,
+            )))
+        }
+    };
+    if columns.strict && action.amount().is_none() {
+        let amount = columns.amount.and_then(|column| record.get(column));
+        if amount.is_some_and(|amount| !amount.trim_ascii().is_empty()) {
+            return Err(invalid("amount")(de::Error::custom(format_args!(
+                "unexpected amount for a {}",
+                action.type_name()
+            ))));
+        }
+    }
+    Ok(action)
+}
+
+/// Like [`for_each_csv_action`], passing every row that fails to decode
+/// or has the wrong number of fields to `on_error` instead of failing
+pub fn for_each_csv_action_lenient<R: Read>(
+    mut reader: Reader<R>,
+    mut f: impl FnMut(Action) -> Result<()>,
+    mut on_error: impl FnMut(RowError),
+) -> Result<()> {
+    let columns = Columns::new(&read_headers(&mut reader)?, Schema::Lenient)?;
+    let mut record = ByteRecord::new();
+    loop {
+        match reader.read_byte_record(&mut record) {
+            Ok(false) => return Ok(()),
+            Ok(true) => match decode_action_kind(&columns, &record) {
+                Ok(action) => f(action)?,
+                Err((kind, e)) => on_error(RowError::new(
+                    record.position().map(Position::line),
+                    &record,
+                    kind,
+                    e,
+                )),
+            },
+            Err(e) if matches!(e.kind(), ErrorKind::UnequalLengths { .. }) => {
+                on_error(RowError::new(
+                    e.position().map(Position::line),
+                    &record,
+                    RowErrorKind::FieldCount,
+                    &e,
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Column layout shared by a sequence of CSV inputs with identical headers
+///
+/// The first input resolves the column names, and later inputs only check
+/// that their raw header row is byte-for-byte the same instead of trimming
+/// and mapping it again, which matters when processing many small files.
+#[derive(Debug, Default)]
+pub struct HeaderCache {
+    layout: Option<(ByteRecord, Columns)>,
+}
+
+impl HeaderCache {
+    /// Like [`for_each_csv_action`], failing if the headers differ from the first input
+    ///
+    /// Inputs without a header row all share the positional columns.
+    pub fn for_each_csv_action<R: Read>(
+        &mut self,
+        mut reader: Reader<R>,
+        mut f: impl FnMut(Action) -> Result<()>,
+    ) -> Result<()> {
+        if !reader.has_headers() {
+            let columns = Columns::new(&read_headers(&mut reader)?, Schema::Lenient)?;
+            return for_each_record_action(reader, &columns, None, |action, _| f(action));
+        }
+        let raw = reader.byte_headers()?;
+        let columns = match &self.layout {
+            Some((cached, columns)) if cached == raw => columns,
+            Some((cached, _)) => bail!(
+                "headers {:?} differ from the first input {:?}",
+                join_headers(raw),
+                join_headers(cached)
+            ),
+            None => {
+                let columns = Columns::new(&trim_headers(raw)?, Schema::Lenient)?;
+                &self.layout.insert((raw.clone(), columns)).1
+            }
+        };
+        for_each_record_action(reader, columns, None, |action, _| f(action))
+    }
+
+    /// Like [`ProcessCsv::process_csv`], failing if the headers differ from the first input
+    pub fn process_csv<R: Read>(
+        &mut self,
+        states: &mut AccountStates,
+        reader: Reader<R>,
+    ) -> Result<()> {
+        self.for_each_csv_action(reader, |action| match states.process(action) {
+            Outcome::Rejected(rejection) if rejection.is_limit() => Err(rejection.into()),
+            Outcome::Failed(error) => Err(error.into()),
+            _ => Ok(()),
+        })
+    }
+}
+
+fn join_headers(raw: &ByteRecord) -> String {
+    raw.iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Compute account summary from CSV files processed in order, which must share their headers
+pub fn summaries_from_files(files: impl IntoIterator<Item = File>) -> Result<Vec<AccountSummary>> {
+    let mut states = AccountStates::default();
+    let mut cache = HeaderCache::default();
+    for file in files {
+        cache.process_csv(
+            &mut states,
+            ReaderBuilder::new().from_reader(BufReader::new(file)),
+        )?;
+    }
+    Ok(states.summary())
+}
+
+/// Compute account summary from IO CSV source
+pub fn summaries_from_io_csv(reader: impl Read) -> Result<Vec<AccountSummary>> {
+    summaries_from_csv(ReaderBuilder::new().from_reader(reader))
+}
+
+/// Compute account summary from a CSV file, pre-sizing the state from the file length
+///
+/// With the `io-uring` feature on Linux the file is read through [`crate::uring::UringReader`].
+pub fn summaries_from_file(file: File) -> Result<Vec<AccountSummary>> {
+    summaries_from_file_with_options(file, &CsvOptions::default())
+}
+
+/// Like [`summaries_from_file`], for a CSV file in the dialect of `options`
+pub fn summaries_from_file_with_options(
+    file: File,
+    options: &CsvOptions,
+) -> Result<Vec<AccountSummary>> {
+    let rows = file.metadata()?.len() / ESTIMATED_ROW_BYTES;
+    let mut states = AccountStates::with_estimated_rows(rows.try_into().unwrap_or(usize::MAX));
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    let file = crate::uring::UringReader::new(file)?;
+    states.process_csv_with_schema(options.reader(file)?, options.schema)?;
+    Ok(states.summary())
+}
+
+/// Read summaries as written by [`write_summary_io_csv`], for example to seed a state
+/// with [`AccountStates::from_summaries`]
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn read_summary_io_csv(reader: impl Read) -> Result<Vec<AccountSummary>> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    Ok(reader.deserialize().collect::<Result<_, _>>()?)
+}
+
+/// Read open disputes with the columns `tx`, `client`, `amount` and `kind`,
+/// to be carried over with [`AccountStates::seed_disputes`]
+pub fn read_open_disputes_io_csv(reader: impl Read) -> Result<Vec<OpenDispute>> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    Ok(reader.deserialize().collect::<Result<_, _>>()?)
+}
+
+/// Write summaries, either borrowed or as produced by [`AccountStates::summary_iter`]
+pub fn write_summary_csv<W: Write>(
+    summaries: impl IntoIterator<Item = impl Borrow<AccountSummary>>,
+    mut writer: Writer<W>,
+) -> Result<()> {
+    for record in summaries {
+        writer.serialize(record.borrow())?
+    }
+    Ok(())
+}
+
+pub fn write_summary_io_csv(
+    summaries: impl IntoIterator<Item = impl Borrow<AccountSummary>>,
+    writer: impl Write,
+) -> Result<()> {
+    write_summary_csv(summaries, WriterBuilder::new().from_writer(writer))
+}
+
+/// Write summaries with the fees, shortfall and activity counters of every account,
+/// as produced by [`AccountStates::extended_summary`]
+pub fn write_extended_summary_io_csv(
+    summaries: impl IntoIterator<Item = ExtendedSummary>,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for record in summaries {
+        writer.serialize(record)?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::value::{BorrowedStrDeserializer, MapDeserializer};
+
+    use super::*;
+
+    #[test]
+    fn decode_like_deserialize() {
+        let input = "type, client, tx, amount, category
+deposit, 1, 1, 1.5, card
+withdrawal, 1, 2, , card
+dispute, 1, 1, 3, card
+credit_adjustment, 1, 3, 1e2,
+transfer, 1, 4, 1,
+chargeback, 70000, 1, ,
+resolve, 1, x, ,
+";
+        let mut reader = ReaderBuilder::new().from_reader(input.as_bytes());
+        let headers = trim_headers(reader.byte_headers().unwrap()).unwrap();
+        let columns = Columns::new(&headers, Schema::Lenient).unwrap();
+        for record in reader.byte_records() {
+            let record = record.unwrap();
+            let deserialized = Action::deserialize(MapDeserializer::<_, de::value::Error>::new(
+                headers.iter().zip(&record).map(|(k, v)| {
+                    (
+                        BorrowedStrDeserializer::new(k.as_str()),
+                        BorrowedBytesDeserializer::new(v),
+                    )
+                }),
+            ));
+            assert_eq!(
+                decode_action(&columns, &record).map_err(|e| e.to_string()),
+                deserialized.map_err(|e| e.to_string()),
+                "{record:?}"
+            );
+        }
+        let record = ByteRecord::from(vec!["deposit", "1"]);
+        assert_eq!(
+            decode_action(
+                &Columns::new(&["type".into(), "client".into()], Schema::Lenient).unwrap(),
+                &record
+            )
+            .unwrap_err()
+            .to_string(),
+            "missing field `tx`"
+        );
+    }
+    #[test]
+    fn count_skipped_rows() {
+        let mut input = String::from("type,client,tx,amount\n");
+        for tx in 0..1204 {
+            input += &format!("deposit,1,{tx},x\n");
+        }
+        input += "deposit,70000,1,1\ntransfer,1,1,1\ntransfer,1,1,1\ndeposit,1\nwithdrawal,1,2,1\n";
+        let reader = ReaderBuilder::new().from_reader(input.as_bytes());
+        let (summaries, errors) = summaries_from_csv_lenient(reader).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            count_row_errors(&errors),
+            [
+                (RowErrorKind::Invalid("amount"), 1204),
+                (RowErrorKind::Invalid("type"), 2),
+                (RowErrorKind::Invalid("client"), 1),
+                (RowErrorKind::FieldCount, 1),
+            ]
+        );
+        assert_eq!(
+            format_row_error_counts(&errors),
+            "1,204 rows: invalid amount in column 'amount'
+2 rows: invalid type in column 'type'
+1 row: invalid client in column 'client'
+1 row: wrong number of fields
+"
+        );
+    }
+
+    #[test]
+    fn read_dialects() {
+        let summaries = |options: CsvOptions, input: &str| {
+            summaries_from_csv(options.reader(input.as_bytes()).unwrap()).unwrap()
+        };
+        let expected = summaries(
+            CsvOptions::default(),
+            "type,client,tx,amount\ndeposit,1,1,2.5\nwithdrawal,1,2,1\ndispute,1,1,\n",
+        );
+        let tsv = CsvOptions {
+            delimiter: b'\t',
+            ..<_>::default()
+        };
+        assert_eq!(
+            summaries(
+                tsv,
+                "type\tclient\ttx\tamount\ndeposit\t1\t1\t\"2.5\"\nwithdrawal\t1\t2\t1\ndispute\t1\t1\t\n"
+            ),
+            expected
+        );
+        let legacy = CsvOptions {
+            delimiter: b';',
+            quote: None,
+            headers: false,
+            ..<_>::default()
+        };
+        assert_eq!(
+            summaries(
+                legacy.clone(),
+                "deposit;1;1;2.5\nwithdrawal;1;2;1\ndispute;1;1;\n"
+            ),
+            expected
+        );
+        let bank = CsvOptions::default()
+            .alias("txn_type", "type")
+            .alias("customer_id", "client")
+            .alias("txn_id", "tx")
+            .alias("value", "amount");
+        assert_eq!(
+            summaries(
+                bank,
+                "txn_type, customer_id, txn_id, value\ndeposit,1,1,2.5\nwithdrawal,1,2,1\ndispute,1,1,\n"
+            ),
+            expected
+        );
+        assert!(
+            summaries_from_csv(legacy.reader("deposit;1;1;\"2.5\"\n".as_bytes()).unwrap()).is_err()
+        );
+    }
+    #[test]
+    fn check_strict_schema() {
+        let process = |schema, input: &str| {
+            AccountStates::default()
+                .process_csv_with_schema(ReaderBuilder::new().from_reader(input.as_bytes()), schema)
+                .map_err(|e| e.to_string())
+        };
+        for (input, error) in [
+            (
+                "type,client,tx,amount,note\ndeposit,1,1,1,\n",
+                "unknown column \"note\"",
+            ),
+            ("type,tx,amount\ndeposit,1,1\n", "missing column \"client\""),
+            (
+                "type,client,tx,amount\ndeposit,1,1,\n",
+                "missing field `amount`",
+            ),
+            (
+                "type,client,tx,amount\ndeposit,1,1,1\ndispute,1,1,1\n",
+                "unexpected amount for a dispute",
+            ),
+        ] {
+            assert_eq!(process(Schema::Strict, input), Err(error.to_owned()));
+        }
+        assert_eq!(
+            process(
+                Schema::Lenient,
+                "type,client,tx,amount,note\ndeposit,1,1,1,x\ndispute,1,1,1,\n"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            process(
+                Schema::Strict,
+                "type,client,tx,amount,timestamp\ndeposit,1,1,1,10\ndispute,1,1, ,\n"
+            ),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Live terminal dashboard of a run for operators, only available with the `tui` feature
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use csv::Reader;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    widgets::{Block, List, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    for_each_csv_action, AccountStates, AccountSummary, Action, ClientId, Outcome, Rejection,
+};
+
+/// Number of lock events kept for display
+const RECENT_LOCKS: usize = 10;
+/// Number of accounts shown by held funds
+const TOP_HELD: usize = 10;
+/// Minimum time between two redraws
+const REFRESH: Duration = Duration::from_millis(200);
+/// Number of rows processed between two checks of the clock
+const ROWS_PER_CHECK: u64 = 4096;
+
+/// Counters of a run, fed with every action and its outcome
+#[derive(Debug, Default)]
+pub struct RunStats {
+    rows: u64,
+    applied: u64,
+    rejects: BTreeMap<Rejection, u64>,
+    /// Row number and client of the latest locks, newest last
+    recent_locks: VecDeque<(u64, ClientId)>,
+}
+
+impl RunStats {
+    /// Apply an action to `states` and count its outcome
+    pub fn process(&mut self, states: &mut AccountStates, action: Action) -> Outcome {
+        let client = action.client();
+        let chargeback = matches!(action, Action::Chargeback { .. });
+        let outcome = states.process(action);
+        self.rows += 1;
+        match outcome {
+            Outcome::Applied => {
+                self.applied += 1;
+                // An applied action that finds the account auto-locked must have locked it,
+                // since a locked account rejects everything
+                if chargeback || states.auto_lock(client).is_some() {
+                    if self.recent_locks.len() == RECENT_LOCKS {
+                        self.recent_locks.pop_front();
+                    }
+                    self.recent_locks.push_back((self.rows, client));
+                }
+            }
+            Outcome::Rejected(rejection) => *self.rejects.entry(rejection).or_default() += 1,
+            Outcome::Failed(_) => {}
+        }
+        outcome
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn applied(&self) -> u64 {
+        self.applied
+    }
+
+    /// Number of rejected actions by reason, most frequent first
+    pub fn rejects(&self) -> Vec<(Rejection, u64)> {
+        let mut rejects: Vec<_> = self.rejects.iter().map(|(&r, &n)| (r, n)).collect();
+        rejects.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        rejects
+    }
+
+    /// Row number and client of the latest locked accounts, newest first
+    pub fn recent_locks(&self) -> impl Iterator<Item = (u64, ClientId)> + '_ {
+        self.recent_locks.iter().rev().copied()
+    }
+}
+
+/// Accounts with the most held funds, largest first
+pub fn top_held(states: &AccountStates, n: usize) -> Vec<AccountSummary> {
+    let mut summaries: Vec<_> = states
+        .summary()
+        .into_iter()
+        .filter(|summary| !summary.held().is_zero())
+        .collect();
+    summaries.sort_by(|a, b| b.held().cmp(a.held()));
+    summaries.truncate(n);
+    summaries
+}
+
+/// Render the dashboard into one frame
+pub fn draw(
+    frame: &mut Frame,
+    stats: &RunStats,
+    states: &AccountStates,
+    elapsed: Duration,
+    finished: bool,
+) {
+    let [header, middle, locks] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(TOP_HELD as u16 + 3),
+        Constraint::Length(RECENT_LOCKS as u16 + 2),
+    ])
+    .areas(frame.area());
+    let [rejects, held] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(middle);
+
+    let status = if finished {
+        "finished, press q to quit"
+    } else {
+        "running, press q to abort"
+    };
+    frame.render_widget(
+        Paragraph::new(format!(
+            "rows: {}  applied: {}  rows/sec: {:.0}  elapsed: {:.1}s  {status}",
+            stats.rows(),
+            stats.applied(),
+            stats.rows() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            elapsed.as_secs_f64(),
+        ))
+        .block(Block::bordered().title("Throughput")),
+        header,
+    );
+    frame.render_widget(
+        Table::new(
+            stats
+                .rejects()
+                .into_iter()
+                .map(|(rejection, count)| Row::new([rejection.to_string(), count.to_string()])),
+            [Constraint::Fill(1), Constraint::Length(12)],
+        )
+        .header(Row::new(["reason", "count"]))
+        .block(Block::bordered().title("Rejects")),
+        rejects,
+    );
+    frame.render_widget(
+        Table::new(
+            top_held(states, TOP_HELD).into_iter().map(|summary| {
+                Row::new([
+                    u16::from(summary.client()).to_string(),
+                    summary.held().to_string(),
+                    summary.available().to_string(),
+                ])
+            }),
+            [
+                Constraint::Length(8),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(Row::new(["client", "held", "available"]))
+        .block(Block::bordered().title("Top accounts by held funds")),
+        held,
+    );
+    frame.render_widget(
+        List::new(
+            stats
+                .recent_locks()
+                .map(|(row, client)| format!("row {row}: client {} locked", u16::from(client))),
+        )
+        .block(Block::bordered().title("Recent locks")),
+        locks,
+    );
+}
+
+/// Whether the event is the operator pressing `q` or Esc
+fn is_quit(event: &Event) -> bool {
+    matches!(event, Event::Key(key)
+        if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+}
+
+/// Whether the operator asked to quit since the last check, without blocking
+fn quit_requested() -> Result<bool> {
+    while event::poll(Duration::ZERO)? {
+        if is_quit(&event::read()?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Process a CSV input while showing the dashboard on `terminal`
+///
+/// The final state stays on screen until the operator quits,
+/// and pressing `q` during the run aborts it with an error.
+pub fn run<R: Read>(
+    terminal: &mut DefaultTerminal,
+    reader: Reader<R>,
+) -> Result<Vec<AccountSummary>> {
+    let start = Instant::now();
+    let mut states = AccountStates::default();
+    let mut stats = RunStats::default();
+    let mut last_draw = None::<Instant>;
+    for_each_csv_action(reader, |action| {
+        if let Outcome::Failed(error) = stats.process(&mut states, action) {
+            bail!(error)
+        }
+        if stats.rows() % ROWS_PER_CHECK == 1
+            && last_draw.is_none_or(|last_draw| last_draw.elapsed() >= REFRESH)
+        {
+            terminal.draw(|frame| draw(frame, &stats, &states, start.elapsed(), false))?;
+            last_draw = Some(Instant::now());
+            if quit_requested()? {
+                bail!("aborted by the operator after {} rows", stats.rows())
+            }
+        }
+        Ok(())
+    })?;
+    let elapsed = start.elapsed();
+    loop {
+        terminal.draw(|frame| draw(frame, &stats, &states, elapsed, true))?;
+        if is_quit(&event::read()?) {
+            return Ok(states.summary());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+withdrawal, 1, 3, 5.0
+dispute, 2, 2,
+dispute, 1, 1,
+chargeback, 1, 1,
+deposit, 1, 4, 1.0
+resolve, 2, 9,
+"#;
+
+    fn run_stats() -> (RunStats, AccountStates) {
+        let mut states = AccountStates::default();
+        let mut stats = RunStats::default();
+        for_each_csv_action(Reader::from_reader(TRANSACTION_CSV.as_bytes()), |action| {
+            stats.process(&mut states, action);
+            Ok(())
+        })
+        .unwrap();
+        (stats, states)
+    }
+
+    #[test]
+    fn count_rejects_and_locks() {
+        let (stats, states) = run_stats();
+        assert_eq!(stats.rows(), 8);
+        assert_eq!(stats.applied(), 5);
+        assert_eq!(
+            stats.rejects(),
+            [
+                (Rejection::AccountLocked, 1),
+                (Rejection::InsufficientFunds, 1),
+                (Rejection::NotDisputed, 1),
+            ]
+        );
+        assert_eq!(
+            stats.recent_locks().collect::<Vec<_>>(),
+            [(6, ClientId::from(1))]
+        );
+        let top = top_held(&states, TOP_HELD);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].client(), ClientId::from(2));
+    }
+
+    #[test]
+    fn render_all_panels() {
+        let (stats, states) = run_stats();
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, &stats, &states, Duration::from_secs(1), true))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("rows: 8  applied: 5  rows/sec: 8"));
+        assert!(screen.contains("insufficient available funds"));
+        assert!(screen.contains("2.0000"));
+        assert!(screen.contains("row 6: client 1 locked"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Export of the disputes still open at the end of a run
+//!
+//! The export can be carried over into the next run with
+//! [`read_open_disputes_io_csv`](crate::read_open_disputes_io_csv), which ignores
+//! the extra `opened_at` column.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use anyhow::Result;
+use csv::{Reader, WriterBuilder};
+use serde::Serialize;
+
+use crate::{
+    for_each_csv_action, seed::DisputedKind, AccountStates, Action, Balance, ClientId, Outcome,
+    TransactionId,
+};
+
+/// Input rows at which disputes were filed, counted from 1 after the header
+#[derive(Debug, Clone, Default)]
+pub struct DisputeRows {
+    opened: HashMap<(ClientId, TransactionId), u64>,
+}
+
+impl DisputeRows {
+    /// The row of the applied dispute that opened the client's dispute of `transaction`
+    pub fn opened_at(&self, client: ClientId, transaction: TransactionId) -> Option<u64> {
+        self.opened.get(&(client, transaction)).copied()
+    }
+}
+
+/// Apply all actions from a CSV reader, remembering the row of every open dispute
+///
+/// Fails at the first action that would exceed a configured limit.
+pub fn process_csv_tracking_disputes<R: Read>(
+    states: &mut AccountStates,
+    reader: Reader<R>,
+) -> Result<DisputeRows> {
+    let mut rows = DisputeRows::default();
+    let mut row = 0;
+    for_each_csv_action(reader, |action| {
+        row += 1;
+        let key = (action.client(), action.transaction());
+        // Whether an applied action opens or closes a dispute
+        let opens = match action {
+            Action::Dispute { .. } => Some(true),
+            Action::Resolve { .. } | Action::Chargeback { .. } => Some(false),
+            _ => None,
+        };
+        match (states.process(action), opens) {
+            (Outcome::Rejected(rejection), _) if rejection.is_limit() => {
+                return Err(rejection.into())
+            }
+            (Outcome::Failed(error), _) => return Err(error.into()),
+            (Outcome::Applied, Some(true)) => {
+                rows.opened.insert(key, row);
+            }
+            (Outcome::Applied, Some(false)) => {
+                rows.opened.remove(&key);
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+#[derive(Serialize)]
+struct OpenDisputeRecord<'a> {
+    client: ClientId,
+    tx: TransactionId,
+    amount: &'a Balance,
+    kind: DisputedKind,
+    opened_at: Option<u64>,
+}
+
+/// Write the open disputes of `states` as CSV with the columns
+/// `client`, `tx`, `amount`, `kind` and `opened_at`
+///
+/// `opened_at` is left empty for disputes that were not filed during the tracked run,
+/// such as seeded ones.
+pub fn write_open_disputes_io_csv(
+    states: &AccountStates,
+    rows: &DisputeRows,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for dispute in states.open_disputes() {
+        writer.serialize(OpenDisputeRecord {
+            client: dispute.client,
+            tx: dispute.transaction,
+            amount: &dispute.amount,
+            kind: dispute.kind,
+            opened_at: rows.opened_at(dispute.client, dispute.transaction),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_open_disputes_io_csv;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 2.0
+withdrawal, 2, 3, 1.0
+deposit, 2, 4, 4.0
+withdrawal, 2, 5, 1.5
+dispute, 1, 2,
+dispute, 1, 1,
+resolve, 1, 1,
+dispute, 2, 5,
+dispute, 2, 5,
+"#;
+
+    #[test]
+    fn export_open_disputes() {
+        let mut states = AccountStates::default();
+        let rows = process_csv_tracking_disputes(
+            &mut states,
+            Reader::from_reader(TRANSACTION_CSV.as_bytes()),
+        )
+        .unwrap();
+        let mut output = vec![];
+        write_open_disputes_io_csv(&states, &rows, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "client,tx,amount,kind,opened_at\n1,2,2.0000,deposit,6\n2,5,1.5000,withdrawal,9\n"
+        );
+
+        let mut seeded = AccountStates::from_summaries(&states.summary());
+        seeded
+            .seed_disputes(read_open_disputes_io_csv(&output[..]).unwrap())
+            .unwrap();
+        assert_eq!(seeded.open_disputes(), states.open_disputes());
+        assert_eq!(seeded.reconcile(), []);
+    }
+}
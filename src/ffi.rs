@@ -0,0 +1,237 @@
+//! C interface for embedding the engine in C and C++ programs,
+//! only available with the `ffi` feature
+//!
+//! Building with the feature regenerates `include/transaction_processor.h`.
+//! Link the `staticlib` or `cdylib` built by
+//! `cargo rustc --lib --release --features ffi --crate-type staticlib`.
+//!
+//! Engines are not thread safe: calls on one engine must not overlap.
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+use crate::{write_summary_io_csv, AccountStates, Action, Balance, ClientId, Outcome};
+
+/// An opaque engine, created by `tp_engine_new` and destroyed by `tp_engine_free`
+pub struct Engine(AccountStates);
+
+/// The `type` of an action
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Return,
+    Unlock,
+    CreditAdjustment,
+    DebitAdjustment,
+    Representment,
+    ChargebackReversal,
+}
+
+/// An action as in a row of a CSV input
+#[repr(C)]
+pub struct ActionRecord {
+    pub kind: ActionType,
+    pub client: u16,
+    pub tx: u32,
+    /// Decimal amount as a NUL-terminated string like `"1.5"`, required by deposits,
+    /// withdrawals and adjustments and ignored by the other actions
+    pub amount: *const c_char,
+}
+
+/// What became of a submitted action
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submission {
+    Applied,
+    /// Refused by the rules, for example for insufficient funds, without changing any balance
+    Rejected,
+    /// The account is inconsistent, see `EngineError`
+    Failed,
+    /// A null pointer, or an amount that is missing or not a decimal
+    Invalid,
+}
+
+/// Create an empty engine
+#[no_mangle]
+pub extern "C" fn tp_engine_new() -> *mut Engine {
+    Box::into_raw(Box::new(Engine(AccountStates::default())))
+}
+
+/// Destroy an engine, doing nothing if `engine` is null
+///
+/// # Safety
+///
+/// `engine` must be null or come from `tp_engine_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tp_engine_free(engine: *mut Engine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Apply an action
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine, `action` null or a valid record
+/// whose `amount` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tp_engine_submit(
+    engine: *mut Engine,
+    action: *const ActionRecord,
+) -> Submission {
+    let (Some(engine), Some(action)) = (engine.as_mut(), action.as_ref()) else {
+        return Submission::Invalid;
+    };
+    let Some(action) = action.to_action() else {
+        return Submission::Invalid;
+    };
+    match engine.0.process(action) {
+        Outcome::Applied => Submission::Applied,
+        Outcome::Rejected(_) => Submission::Rejected,
+        Outcome::Failed(_) => Submission::Failed,
+    }
+}
+
+/// The summaries of all accounts as CSV, in the format of the command line output
+///
+/// Returns null if `engine` is null. Free the string with `tp_string_free`.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tp_engine_summary_csv(engine: *const Engine) -> *mut c_char {
+    let Some(engine) = engine.as_ref() else {
+        return ptr::null_mut();
+    };
+    let mut output = vec![];
+    write_summary_io_csv(engine.0.summary(), &mut output).expect("writing to memory does not fail");
+    CString::new(output)
+        .expect("summaries have no NUL bytes")
+        .into_raw()
+}
+
+/// Free a string returned by the engine, doing nothing if `string` is null
+///
+/// # Safety
+///
+/// `string` must be null or come from `tp_engine_summary_csv`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tp_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+impl ActionRecord {
+    /// # Safety
+    ///
+    /// `amount` must be null or a NUL-terminated string.
+    unsafe fn to_action(&self) -> Option<Action> {
+        let (client, transaction) = (ClientId::from(self.client), self.tx.into());
+        let amount = || -> Option<Balance> {
+            if self.amount.is_null() {
+                return None;
+            }
+            CStr::from_ptr(self.amount).to_str().ok()?.parse().ok()
+        };
+        Some(match self.kind {
+            ActionType::Deposit => Action::Deposit {
+                client,
+                transaction,
+                amount: amount()?,
+            },
+            ActionType::Withdrawal => Action::Withdrawal {
+                client,
+                transaction,
+                amount: amount()?,
+            },
+            ActionType::CreditAdjustment => Action::CreditAdjustment {
+                client,
+                transaction,
+                amount: amount()?,
+            },
+            ActionType::DebitAdjustment => Action::DebitAdjustment {
+                client,
+                transaction,
+                amount: amount()?,
+            },
+            ActionType::Dispute => Action::Dispute {
+                client,
+                transaction,
+            },
+            ActionType::Resolve => Action::Resolve {
+                client,
+                transaction,
+            },
+            ActionType::Chargeback => Action::Chargeback {
+                client,
+                transaction,
+            },
+            ActionType::Return => Action::Return {
+                client,
+                transaction,
+            },
+            ActionType::Representment => Action::Representment {
+                client,
+                transaction,
+            },
+            ActionType::ChargebackReversal => Action::ChargebackReversal {
+                client,
+                transaction,
+            },
+            ActionType::Unlock => Action::Unlock {
+                client,
+                transaction,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submit_through_ffi() {
+        unsafe {
+            let engine = tp_engine_new();
+            let record = |kind, tx, amount: &CStr| ActionRecord {
+                kind,
+                client: 1,
+                tx,
+                amount: amount.as_ptr(),
+            };
+            for (action, expected) in [
+                (record(ActionType::Deposit, 1, c"1.5"), Submission::Applied),
+                (
+                    record(ActionType::Withdrawal, 2, c"2"),
+                    Submission::Rejected,
+                ),
+                (record(ActionType::Deposit, 3, c"x"), Submission::Invalid),
+                (record(ActionType::Dispute, 1, c""), Submission::Applied),
+            ] {
+                assert_eq!(tp_engine_submit(engine, &action), expected);
+            }
+            let csv = tp_engine_summary_csv(engine);
+            assert_eq!(
+                CStr::from_ptr(csv).to_str().unwrap(),
+                "client,locked,available,held,total\n1,false,0.0000,1.5000,1.5000\n"
+            );
+            tp_string_free(csv);
+            tp_engine_free(engine);
+            assert_eq!(
+                tp_engine_submit(ptr::null_mut(), ptr::null()),
+                Submission::Invalid
+            );
+        }
+    }
+}
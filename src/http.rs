@@ -0,0 +1,595 @@
+//! Small HTTP service over one shared [`AccountStates`], only available with the `http` feature
+//!
+//! - `POST /actions` applies the JSON Lines actions of the body, in the format of [`crate::jsonl`],
+//!   and answers with the outcome of each one, or 413 for a body over [`MAX_BODY_BYTES`]
+//! - `GET /accounts` answers with the summaries of all accounts
+//! - `GET /accounts/{client}` answers with the summary of one account
+//! - `GET /accounts/{client}/transactions` answers with the deposits and withdrawals
+//!   the account keeps for later disputes, ordered by transaction id
+//! - `GET /accounts/{client}/disputes` answers with the disputed and charged back
+//!   transactions of the account and where each stands, ordered by transaction id
+//! - `GET /totals` answers with the counters of the current period
+//! - `GET /metrics` answers with [`crate::metrics`] in the Prometheus text format,
+//!   only with the `metrics` feature
+//!
+//! A [`Service::read_only`] only answers the `GET` requests, for browsing a state loaded
+//! from a [`Snapshot`](crate::snapshot::Snapshot) without any way to change it.
+//!
+//! Requests are answered by several worker threads. Summaries are served from a [`ReadView`]
+//! that is refreshed after every applied body with the accounts it touched, so they only
+//! wait for the writer while it refreshes those, not while it applies the actions.
+//! Transactions, disputes and totals are read from the state itself and wait for the writer.
+//!
+//! The [`Ingestion`] of the service counts the actions of `POST /actions` as they are decoded
+//! and as they are processed, for the [`crate::watchdog`] to notice a writer that stopped.
+
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    jsonl::for_each_jsonl_action,
+    lifecycle::DisputeStage,
+    sync::{Mutex, MutexGuard, RwLock},
+    AccountStates, AccountView, Action, Balance, ClientId, Outcome, ReadView, TransactionId,
+    TransactionKind,
+};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(feature = "watchdog")]
+use crate::watchdog::Watchdog;
+
+/// Largest accepted body of `POST /actions`
+pub const MAX_BODY_BYTES: u64 = 16 << 20;
+
+/// Account states shared by all requests
+pub struct Service {
+    states: Mutex<AccountStates>,
+    view: RwLock<ReadView>,
+    read_only: bool,
+    ingestion: Ingestion,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
+}
+
+/// Counts of the actions of `POST /actions`, from any thread
+#[derive(Debug, Default)]
+pub struct Ingestion {
+    received: AtomicU64,
+    processed: AtomicU64,
+}
+
+impl Ingestion {
+    /// Actions decoded from bodies so far
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Acquire)
+    }
+
+    /// Actions applied or rejected so far
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Acquire)
+    }
+
+    /// Actions decoded but not processed yet, waiting for the writer
+    pub fn backlog(&self) -> u64 {
+        // Loaded first, as actions are only processed after being received
+        let processed = self.processed();
+        self.received() - processed
+    }
+}
+
+#[derive(Serialize)]
+struct TransactionEntry {
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    amount: Balance,
+}
+
+#[derive(Serialize)]
+struct DisputeEntry {
+    tx: TransactionId,
+    stage: DisputeStage,
+}
+
+#[derive(Serialize)]
+struct ActionOutcome {
+    client: ClientId,
+    tx: TransactionId,
+    applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejection: Option<String>,
+}
+
+impl Service {
+    pub fn new(states: AccountStates) -> Self {
+        #[cfg(feature = "metrics")]
+        let (states, metrics) = {
+            let (mut states, metrics) = (states, Arc::new(Metrics::default()));
+            states.add_observer(metrics.clone());
+            (states, metrics)
+        };
+        let view = RwLock::new(states.read_view());
+        Self {
+            states: Mutex::new(states),
+            view,
+            read_only: false,
+            ingestion: Ingestion::default(),
+            #[cfg(feature = "metrics")]
+            metrics,
+        }
+    }
+
+    /// A service answering `POST /actions` with 405, so that `states` never change
+    pub fn read_only(states: AccountStates) -> Self {
+        Self {
+            read_only: true,
+            ..Self::new(states)
+        }
+    }
+
+    /// Counts of the actions received and processed so far
+    pub fn ingestion(&self) -> &Ingestion {
+        &self.ingestion
+    }
+
+    /// The view that reads are currently served from
+    pub fn view(&self) -> ReadView {
+        self.view.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Answer a request with a status code and a JSON body
+    ///
+    /// A body with a malformed line is rejected as a whole, without applying any of it.
+    pub fn respond(&self, method: &Method, path: &str, body: impl Read) -> (u16, String) {
+        let path = path.split_once('?').map_or(path, |(path, _query)| path);
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        match (method, &segments[..]) {
+            (Method::Post, ["actions"]) if self.read_only => {
+                error(405, "the service is read-only".to_owned())
+            }
+            (Method::Post, ["actions"]) => {
+                let mut bytes = vec![];
+                if let Err(e) = body.take(MAX_BODY_BYTES + 1).read_to_end(&mut bytes) {
+                    return error(400, format!("cannot read body: {e}"));
+                }
+                if bytes.len() as u64 > MAX_BODY_BYTES {
+                    return error(413, format!("body exceeds {MAX_BODY_BYTES} bytes"));
+                }
+                let mut actions = vec![];
+                let decoded = for_each_jsonl_action(&bytes[..], |action| {
+                    actions.push(action);
+                    Ok(())
+                });
+                match decoded {
+                    Ok(()) => json(200, &self.apply(actions)),
+                    Err(e) => error(400, format!("{e:#}")),
+                }
+            }
+            (Method::Get, ["accounts"]) => json(200, &self.view().summaries().collect::<Vec<_>>()),
+            (Method::Get, ["accounts", client]) => match client.parse::<u16>() {
+                Ok(client) => match self.view().account(client.into()) {
+                    Some(summary) => json(200, summary),
+                    None => error(404, format!("unknown client {client}")),
+                },
+                Err(_) => error(400, format!("invalid client {client:?}")),
+            },
+            (Method::Get, ["accounts", client, "transactions"]) => {
+                self.with_account(client, |account| {
+                    let mut transactions: Vec<_> = account
+                        .transactions()
+                        .map(|(tx, kind)| {
+                            let (kind, amount) = match kind {
+                                TransactionKind::Deposit(amount) => ("deposit", amount),
+                                TransactionKind::Withdrawal(amount) => ("withdrawal", amount),
+                            };
+                            TransactionEntry {
+                                tx,
+                                kind,
+                                amount: amount.clone(),
+                            }
+                        })
+                        .collect();
+                    transactions.sort_unstable_by_key(|entry| entry.tx);
+                    json(200, &transactions)
+                })
+            }
+            (Method::Get, ["accounts", client, "disputes"]) => {
+                self.with_account(client, |account| {
+                    let mut disputes: Vec<_> = account
+                        .disputes()
+                        .map(|(tx, stage)| DisputeEntry { tx, stage })
+                        .collect();
+                    disputes.sort_unstable_by_key(|entry| entry.tx);
+                    json(200, &disputes)
+                })
+            }
+            (Method::Get, ["totals"]) => json(200, self.lock_states().period_totals()),
+            #[cfg(feature = "metrics")]
+            (Method::Get, ["metrics"]) => {
+                let mut body = vec![];
+                match self
+                    .metrics
+                    .write_prometheus(self.view().summaries(), &mut body)
+                {
+                    Ok(()) => (200, String::from_utf8_lossy(&body).into_owned()),
+                    Err(e) => error(500, format!("{e:#}")),
+                }
+            }
+            (
+                _,
+                ["actions"]
+                | ["accounts"]
+                | ["accounts", _]
+                | ["accounts", _, "transactions" | "disputes"]
+                | ["totals"],
+            ) => error(405, format!("method {method} not allowed")),
+            _ => error(404, format!("no route for {path}")),
+        }
+    }
+
+    fn lock_states(&self) -> MutexGuard<'_, AccountStates> {
+        self.states.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Answer with `f` of the view of `client`, or with 404 or 400 if there is none
+    fn with_account(
+        &self,
+        client: &str,
+        f: impl FnOnce(AccountView<'_>) -> (u16, String),
+    ) -> (u16, String) {
+        let Ok(client) = client.parse::<u16>() else {
+            return error(400, format!("invalid client {client:?}"));
+        };
+        match self.lock_states().account(client.into()) {
+            Some(account) => f(account),
+            None => error(404, format!("unknown client {client}")),
+        }
+    }
+
+    fn apply(&self, actions: Vec<Action>) -> Vec<ActionOutcome> {
+        self.ingestion
+            .received
+            .fetch_add(actions.len() as u64, Ordering::AcqRel);
+        let mut states = self.lock_states();
+        let clients: BTreeSet<_> = actions.iter().map(Action::client).collect();
+        let outcomes = actions
+            .into_iter()
+            .map(|action| {
+                let (client, tx) = (action.client(), action.transaction());
+                let rejection = match states.process(action) {
+                    Outcome::Applied => None,
+                    Outcome::Rejected(rejection) => Some(rejection.to_string()),
+                    Outcome::Failed(error) => Some(error.to_string()),
+                };
+                self.ingestion.processed.fetch_add(1, Ordering::AcqRel);
+                ActionOutcome {
+                    client,
+                    tx,
+                    applied: rejection.is_none(),
+                    rejection,
+                }
+            })
+            .collect();
+        self.view
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .refresh(&states, clients);
+        outcomes
+    }
+
+    /// Answer every request arriving at `server` on `workers` threads, at least one
+    ///
+    /// Returns once every worker stopped, with the error of the first worker that failed.
+    pub fn serve(&self, server: &Server, workers: usize) -> Result<()> {
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..workers.max(1))
+                .map(|_| scope.spawn(|| self.serve_worker(server)))
+                .collect();
+            workers.into_iter().try_for_each(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+        })
+    }
+
+    fn serve_worker(&self, server: &Server) -> Result<()> {
+        for mut request in server.incoming_requests() {
+            let (method, url) = (request.method().clone(), request.url().to_owned());
+            let (status, body) = self.respond(&method, &url, request.as_reader());
+            let content_type = if status == 200 && url.starts_with("/metrics") {
+                "text/plain; version=0.0.4"
+            } else {
+                "application/json"
+            };
+            reply(request, status, content_type, body)?;
+        }
+        Ok(())
+    }
+}
+
+fn reply(request: Request, status: u16, content_type: &str, body: String) -> Result<()> {
+    let content_type =
+        Header::from_bytes("Content-Type", content_type).map_err(|()| anyhow!("invalid header"))?;
+    request.respond(
+        Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type),
+    )?;
+    Ok(())
+}
+
+fn json(status: u16, body: &impl Serialize) -> (u16, String) {
+    match serde_json::to_string(body) {
+        Ok(body) => (status, body),
+        Err(e) => error(500, e.to_string()),
+    }
+}
+
+fn error(status: u16, message: String) -> (u16, String) {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
+
+impl Default for Service {
+    fn default() -> Self {
+        Self::new(AccountStates::default())
+    }
+}
+
+/// Serve `service` on `addr` until the process is stopped,
+/// with a worker thread per available core
+pub fn serve(addr: &str, service: Service) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("cannot listen on {addr}: {e}"))?;
+    service.serve(&server, workers())
+}
+
+/// Serve `service` on `addr` like [`serve`], with `watchdog` watching its ingestion
+///
+/// Failures to post an alert to the webhook are passed to `report`.
+#[cfg(feature = "watchdog")]
+pub fn serve_watched(
+    addr: &str,
+    service: Service,
+    watchdog: Watchdog,
+    report: impl FnMut(anyhow::Error) + Send,
+) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("cannot listen on {addr}: {e}"))?;
+    let (stop, stopped) = std::sync::mpsc::channel::<()>();
+    std::thread::scope(|scope| {
+        let service = &service;
+        scope.spawn(move || watchdog.watch(&service.ingestion, &service.metrics, stopped, report));
+        let served = service.serve(&server, workers());
+        drop(stop);
+        served
+    })
+}
+
+fn workers() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_and_read_accounts() {
+        let service = Service::default();
+        let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}
+{"type": "withdrawal", "client": 1, "tx": 2, "amount": "5.0"}
+"#;
+        let (status, outcomes) = service.respond(&Method::Post, "/actions", body.as_bytes());
+        assert_eq!(status, 200);
+        assert_eq!(
+            outcomes,
+            r#"[{"client":1,"tx":1,"applied":true},{"client":1,"tx":2,"applied":false,"rejection":"insufficient available funds"}]"#
+        );
+
+        assert_eq!(
+            service.respond(&Method::Get, "/accounts/1", &[][..]),
+            (
+                200,
+                r#"{"client":1,"locked":false,"available":"2.0000","held":"0.0000","total":"2.0000"}"#
+                    .to_owned()
+            )
+        );
+        let (status, accounts) = service.respond(&Method::Get, "/accounts?all", &[][..]);
+        assert_eq!(status, 200);
+        assert!(accounts.starts_with(r#"[{"client":1,"#));
+        assert_eq!(service.respond(&Method::Get, "/accounts/2", &[][..]).0, 404);
+        assert_eq!(service.respond(&Method::Get, "/accounts/x", &[][..]).0, 400);
+        assert_eq!(
+            service.respond(&Method::Delete, "/accounts", &[][..]).0,
+            405
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn serve_metrics() {
+        let service = Service::default();
+        let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}
+{"type": "dispute", "client": 1, "tx": 1}
+"#;
+        service.respond(&Method::Post, "/actions", body.as_bytes());
+        let (status, metrics) = service.respond(&Method::Get, "/metrics", &[][..]);
+        assert_eq!(status, 200);
+        assert!(metrics.contains("transactions_actions_total{type=\"dispute\"} 1\n"));
+        assert!(metrics.contains("transactions_held_funds 2.0000\n"));
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn alert_on_stalled_ingestion() {
+        use std::time::Duration;
+
+        let service = Service::default();
+        let hook = Server::http("127.0.0.1:0").unwrap();
+        let watchdog = Watchdog {
+            stall: Duration::from_millis(50),
+            webhook: Some(format!("http://{}/alerts", hook.server_addr())),
+        };
+        let alert = || {
+            let mut request = hook.recv_timeout(Duration::from_secs(10)).unwrap().unwrap();
+            let mut alert = String::new();
+            request.as_reader().read_to_string(&mut alert).unwrap();
+            request.respond(Response::empty(200)).unwrap();
+            alert
+        };
+        let (stop, stopped) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            let states = service.lock_states();
+            let (service, watchdog) = (&service, &watchdog);
+            scope.spawn(move || {
+                let report = |e: anyhow::Error| panic!("{e:#}");
+                watchdog.watch(&service.ingestion, &service.metrics, stopped, report)
+            });
+            scope.spawn(|| {
+                let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}"#;
+                service.respond(&Method::Post, "/actions", body.as_bytes())
+            });
+            assert!(alert().starts_with(r#"{"state":"stalled","backlog":1,"#));
+            let (_, metrics) = service.respond(&Method::Get, "/metrics", &[][..]);
+            assert!(metrics.contains("transactions_ingestion_stalled 1\n"));
+            drop(states);
+            assert!(alert().starts_with(r#"{"state":"recovered","backlog":0,"#));
+            drop(stop);
+        });
+        let (_, metrics) = service.respond(&Method::Get, "/metrics", &[][..]);
+        assert!(metrics.contains("transactions_ingestion_stalled 0\n"));
+        assert!(metrics.contains("transactions_ingestion_stalls_total 1\n"));
+    }
+
+    #[test]
+    fn reject_malformed_body_as_a_whole() {
+        let service = Service::default();
+        let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}
+{"type": "bogus"}
+"#;
+        let (status, error) = service.respond(&Method::Post, "/actions", body.as_bytes());
+        assert_eq!(status, 400);
+        assert!(error.contains("line 2"));
+        assert!(service.view().is_empty());
+    }
+
+    #[test]
+    fn reject_oversized_body() {
+        let service = Service::default();
+        let line = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}
+"#;
+        let body = line.repeat(MAX_BODY_BYTES as usize / line.len() + 1);
+        let (status, _) = service.respond(&Method::Post, "/actions", body.as_bytes());
+        assert_eq!(status, 413);
+        assert!(service.view().is_empty());
+    }
+
+    #[test]
+    fn read_accounts_created_by_rejected_actions() {
+        let service = Service::default();
+        let body = r#"{"type": "withdrawal", "client": 7, "tx": 1, "amount": "1.0"}"#;
+        let (status, outcomes) = service.respond(&Method::Post, "/actions", body.as_bytes());
+        assert_eq!(status, 200);
+        assert!(outcomes.contains(r#""applied":false"#));
+        assert_eq!(
+            service.respond(&Method::Get, "/accounts/7", &[][..]),
+            (
+                200,
+                r#"{"client":7,"locked":false,"available":"0.0000","held":"0.0000","total":"0.0000"}"#
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn serve_read_only() {
+        let mut states = AccountStates::default();
+        for (transaction, amount) in [(1, "2"), (3, "1")] {
+            states.process(Action::Deposit {
+                client: 1.into(),
+                transaction: transaction.into(),
+                amount: amount.parse().unwrap(),
+            });
+        }
+        states.process(Action::Dispute {
+            client: 1.into(),
+            transaction: 3.into(),
+        });
+        let mut snapshot = vec![];
+        crate::write_snapshot_json(&states, &mut snapshot).unwrap();
+        let service = Service::read_only(crate::read_snapshot_json(&snapshot[..]).unwrap());
+        let body = r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": "1.0"}"#;
+        assert_eq!(
+            service
+                .respond(&Method::Post, "/actions", body.as_bytes())
+                .0,
+            405
+        );
+        let get = |path| service.respond(&Method::Get, path, &[][..]);
+        let (status, summary) = get("/accounts/1");
+        assert_eq!(status, 200);
+        assert!(summary.contains(r#""available":"2.0000""#));
+        assert_eq!(
+            get("/accounts/1/transactions"),
+            (
+                200,
+                r#"[{"tx":1,"type":"deposit","amount":"2.0000"},{"tx":3,"type":"deposit","amount":"1.0000"}]"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            get("/accounts/1/disputes"),
+            (200, r#"[{"tx":3,"stage":"open"}]"#.to_owned())
+        );
+        assert_eq!(get("/accounts/2/disputes").0, 404);
+        let (status, totals) = get("/totals");
+        assert_eq!(status, 200);
+        assert!(totals.starts_with(r#"{"applied":3,"rejected":0,"deposits":"3.0000""#));
+    }
+}
+
+#[cfg(loom)]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn read_whole_bodies_only() {
+        loom::model(|| {
+            let service = Arc::new(Service::default());
+            let writer = {
+                let service = service.clone();
+                loom::thread::spawn(move || {
+                    let body = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.0"}
+{"type": "deposit", "client": 2, "tx": 2, "amount": "1.0"}
+"#;
+                    assert_eq!(
+                        service
+                            .respond(&Method::Post, "/actions", body.as_bytes())
+                            .0,
+                        200
+                    );
+                })
+            };
+            let accounts = service.view().summaries().count();
+            assert!(accounts == 0 || accounts == 2, "{accounts} accounts");
+            let (status, totals) = service.respond(&Method::Get, "/totals", &[][..]);
+            assert_eq!(status, 200);
+            assert!(
+                totals.starts_with(r#"{"applied":0,"#) || totals.starts_with(r#"{"applied":2,"#)
+            );
+            writer.join().unwrap();
+            assert_eq!(service.view().summaries().count(), 2);
+        });
+    }
+}
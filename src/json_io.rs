@@ -0,0 +1,152 @@
+//! JSON output of account summaries and JSON snapshots of states,
+//! only available with the `std` feature
+
+use std::{
+    borrow::Borrow,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use serde::{Serialize, Serializer};
+use serde_json::value::RawValue;
+
+use crate::{snapshot::Snapshot, AccountStates, AccountSummary, Balance, ClientId};
+
+/// How balances are written in JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonBalances {
+    /// As strings like `"1.5000"`, which every JSON parser reads exactly
+    #[default]
+    String,
+    /// As numbers like `1.5000`, which parsers reading numbers as doubles may round
+    Number,
+}
+
+impl FromStr for JsonBalances {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "string" => Ok(Self::String),
+            "number" => Ok(Self::Number),
+            _ => bail!("unknown balance representation {s:?}, expected string or number"),
+        }
+    }
+}
+
+struct Amount<'a>(&'a Balance, JsonBalances);
+
+impl Serialize for Amount<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.1 {
+            JsonBalances::String => serializer.collect_str(self.0),
+            // Written verbatim, so that large balances keep every digit
+            JsonBalances::Number => RawValue::from_string(self.0.to_string())
+                .map_err(serde::ser::Error::custom)?
+                .serialize(serializer),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSummary<'a> {
+    client: ClientId,
+    locked: bool,
+    available: Amount<'a>,
+    held: Amount<'a>,
+    total: Amount<'a>,
+}
+
+impl<'a> JsonSummary<'a> {
+    fn new(summary: &'a AccountSummary, balances: JsonBalances) -> Self {
+        Self {
+            client: summary.client(),
+            locked: summary.locked(),
+            available: Amount(summary.available(), balances),
+            held: Amount(summary.held(), balances),
+            total: Amount(summary.total(), balances),
+        }
+    }
+}
+
+/// Write summaries as one JSON array of objects with the same fields as the CSV columns
+pub fn write_summary_json(
+    summaries: impl IntoIterator<Item = impl Borrow<AccountSummary>>,
+    balances: JsonBalances,
+    mut writer: impl Write,
+) -> Result<()> {
+    writer.write_all(b"[")?;
+    for (index, summary) in summaries.into_iter().enumerate() {
+        if index > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, &JsonSummary::new(summary.borrow(), balances))?;
+    }
+    writer.write_all(b"]\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write summaries as JSON Lines, one object per account
+pub fn write_summary_jsonl(
+    summaries: impl IntoIterator<Item = impl Borrow<AccountSummary>>,
+    balances: JsonBalances,
+    mut writer: impl Write,
+) -> Result<()> {
+    for summary in summaries {
+        serde_json::to_writer(&mut writer, &JsonSummary::new(summary.borrow(), balances))?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the [`Snapshot`] of a state as one JSON object
+pub fn write_snapshot_json(states: &AccountStates, mut writer: impl Write) -> Result<()> {
+    serde_json::to_writer(&mut writer, &states.snapshot())?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Restore a state from a snapshot written by [`write_snapshot_json`],
+/// under the default configuration
+pub fn read_snapshot_json(reader: impl Read) -> Result<AccountStates> {
+    let snapshot: Snapshot = serde_json::from_reader(reader)?;
+    Ok(AccountStates::from_snapshot(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccountStates, Action, TransactionId};
+
+    #[test]
+    fn write_strings_or_numbers() {
+        let mut states = AccountStates::default();
+        for (client, amount) in [(2, "1.5"), (1, "12345678901234567890.1")] {
+            states.process(Action::Deposit {
+                client: ClientId::from(client),
+                transaction: TransactionId::from(u32::from(client)),
+                amount: amount.parse().unwrap(),
+            });
+        }
+
+        let mut output = vec![];
+        write_summary_json(states.summary(), JsonBalances::Number, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            r#"[{"client":1,"locked":false,"available":12345678901234567890.1000,"held":0.0000,"#
+        ));
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+
+        let mut output = vec![];
+        write_summary_jsonl(&states.summary()[1..], JsonBalances::default(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":2,\"locked\":false,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\"}\n"
+        );
+    }
+}
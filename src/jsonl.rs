@@ -0,0 +1,105 @@
+//! JSON Lines input of actions, only available with the `std` feature
+//!
+//! Every non-blank line is one JSON object with the same fields as a CSV row,
+//! for example `{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}`.
+//! As with CSV, field names are trimmed, and ids and amounts may be padded strings.
+//! Amounts must be strings, since JSON numbers cannot carry exact decimals.
+
+use std::io::{BufRead, BufReader, Read};
+
+use anyhow::{Context, Result};
+use serde::{de::value::MapDeserializer, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::{AccountStates, AccountSummary, Action, Outcome};
+
+/// Compute account summary from a JSON Lines source
+pub fn summaries_from_jsonl(reader: impl Read) -> Result<Vec<AccountSummary>> {
+    let mut states = AccountStates::default();
+    states.process_jsonl(BufReader::new(reader))?;
+    Ok(states.summary())
+}
+
+/// JSON Lines input for [`AccountStates`]
+pub trait ProcessJsonl {
+    /// Apply all actions from a JSON Lines reader
+    ///
+    /// Fails at the first malformed line, reporting its line number,
+    /// or at the first action that would exceed a configured limit.
+    fn process_jsonl(&mut self, reader: impl BufRead) -> Result<()>;
+}
+
+impl ProcessJsonl for AccountStates {
+    fn process_jsonl(&mut self, reader: impl BufRead) -> Result<()> {
+        for_each_jsonl_action(reader, |action| match self.process(action) {
+            Outcome::Rejected(rejection) if rejection.is_limit() => Err(rejection.into()),
+            Outcome::Failed(error) => Err(error.into()),
+            _ => Ok(()),
+        })
+    }
+}
+
+/// Decode every action from a JSON Lines reader in order, skipping blank lines
+pub fn for_each_jsonl_action(
+    reader: impl BufRead,
+    mut f: impl FnMut(Action) -> Result<()>,
+) -> Result<()> {
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let action = parse_action(&line).with_context(|| format!("line {}", index + 1))?;
+        f(action)?
+    }
+    Ok(())
+}
+
+fn parse_action(line: &str) -> Result<Action> {
+    let fields: Map<String, Value> = serde_json::from_str(line)?;
+    Ok(Action::deserialize(
+        MapDeserializer::<_, serde_json::Error>::new(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.trim().to_owned(), value)),
+        ),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summaries_from_io_csv;
+
+    const TRANSACTION_JSONL: &str = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}
+{" type ": "deposit", "client": " 2 ", "tx": 2, "amount": " 2.0 "}
+
+{"type": "withdrawal", "client": 1, "tx": 3, "amount": "0.5"}
+{"type": "dispute", "client": 2, "tx": 2}
+"#;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+withdrawal, 1, 3, 0.5
+dispute, 2, 2,
+"#;
+
+    #[test]
+    fn match_csv() {
+        assert_eq!(
+            summaries_from_jsonl(TRANSACTION_JSONL.as_bytes()).unwrap(),
+            summaries_from_io_csv(TRANSACTION_CSV.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_line_of_malformed_action() {
+        let error = summaries_from_jsonl(
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1\"}\n{\"type\": \"refund\"}\n"
+                .as_bytes(),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "line 2");
+    }
+}
@@ -0,0 +1,491 @@
+//! Double-entry postings of processed actions, only available with the `ledger` feature
+//!
+//! [`Ledger::process`] applies an action and records how it moved the available and
+//! held funds of the accounts involved as balanced journal entries. Client funds are
+//! liabilities, so an increase is a credit to `client:{id}:available` or
+//! `client:{id}:held`, and the net change is balanced against the contra account of
+//! the action type:
+//!
+//! - `cash` for deposits, withdrawals and returns
+//! - `suspense` for disputes and resolutions, such as the hold of a disputed withdrawal
+//! - `chargebacks` for chargebacks and their reversals
+//! - `adjustments` for unlocks and balance adjustments
+//!
+//! Fees move between the client and the fee account within the same entry.
+//! A shortfall absorbed by the house account of
+//! [`ChargebackPolicy::BookToHouse`] is debited to `receivable`, and released from it
+//! by a chargeback reversal. The interest of [`AccountStates::end_of_day`], posted with
+//! [`Ledger::end_of_day`], is balanced against `interest_expense` and negative interest
+//! against `interest_income`.
+//!
+//! Besides CSV, the journal can be written as a Beancount or ledger-cli file with
+//! [`Ledger::write_plain_text`], where client funds are `Liabilities:Clients:{id}`,
+//! cash, suspense, chargebacks and the receivable are assets, adjustments are equity
+//! and interest is an expense or income.
+
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    fmt::Display,
+    io::{BufWriter, Write},
+};
+
+use anyhow::Result;
+use csv::WriterBuilder;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    period::{LedgerEntry, LedgerKind},
+    policy::ChargebackPolicy,
+    AccountStates, AccountStore, Action, Balance, ClientId, Outcome, TransactionId,
+};
+
+/// An account of the general ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LedgerAccount {
+    Available(ClientId),
+    Held(ClientId),
+    Cash,
+    Suspense,
+    Chargebacks,
+    Adjustments,
+    /// Shortfalls absorbed by the house account
+    Receivable,
+    /// Interest paid to clients
+    InterestExpense,
+    /// Negative interest taken from clients
+    InterestIncome,
+}
+
+impl LedgerAccount {
+    /// The contra account balancing the client postings of an action
+    fn contra(action: &Action) -> Self {
+        match action {
+            Action::Deposit { .. } | Action::Withdrawal { .. } | Action::Return { .. } => {
+                LedgerAccount::Cash
+            }
+            Action::Dispute { .. } | Action::Resolve { .. } | Action::Representment { .. } => {
+                LedgerAccount::Suspense
+            }
+            Action::Chargeback { .. } | Action::ChargebackReversal { .. } => {
+                LedgerAccount::Chargebacks
+            }
+            Action::Unlock { .. }
+            | Action::CreditAdjustment { .. }
+            | Action::DebitAdjustment { .. } => LedgerAccount::Adjustments,
+        }
+    }
+}
+
+impl LedgerAccount {
+    /// The name of the account in Beancount and ledger-cli files
+    fn plain_text_name(&self) -> String {
+        match self {
+            LedgerAccount::Available(client) => {
+                format!("Liabilities:Clients:{}:Available", u16::from(*client))
+            }
+            LedgerAccount::Held(client) => {
+                format!("Liabilities:Clients:{}:Held", u16::from(*client))
+            }
+            LedgerAccount::Cash => "Assets:Cash".into(),
+            LedgerAccount::Suspense => "Assets:Suspense".into(),
+            LedgerAccount::Chargebacks => "Assets:Chargebacks".into(),
+            LedgerAccount::Adjustments => "Equity:Adjustments".into(),
+            LedgerAccount::Receivable => "Assets:Receivable".into(),
+            LedgerAccount::InterestExpense => "Expenses:Interest".into(),
+            LedgerAccount::InterestIncome => "Income:Interest".into(),
+        }
+    }
+}
+
+impl Display for LedgerAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerAccount::Available(client) => {
+                write!(f, "client:{}:available", u16::from(*client))
+            }
+            LedgerAccount::Held(client) => write!(f, "client:{}:held", u16::from(*client)),
+            LedgerAccount::Cash => f.write_str("cash"),
+            LedgerAccount::Suspense => f.write_str("suspense"),
+            LedgerAccount::Chargebacks => f.write_str("chargebacks"),
+            LedgerAccount::Adjustments => f.write_str("adjustments"),
+            LedgerAccount::Receivable => f.write_str("receivable"),
+            LedgerAccount::InterestExpense => f.write_str("interest_expense"),
+            LedgerAccount::InterestIncome => f.write_str("interest_income"),
+        }
+    }
+}
+
+impl Serialize for LedgerAccount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// One side of a journal entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Posting {
+    /// Number of the journal entry, shared by the postings of one action
+    pub entry: u64,
+    /// The transaction of the action, `None` for interest
+    pub tx: Option<TransactionId>,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub account: LedgerAccount,
+    pub debit: Option<Balance>,
+    pub credit: Option<Balance>,
+}
+
+/// Plain-text accounting formats of [`Ledger::write_plain_text`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlainTextFormat {
+    /// Beancount, with an `open` directive for every account used
+    Beancount,
+    /// ledger-cli, also read by hledger
+    LedgerCli,
+}
+
+/// Journal of the postings of every applied action, in the order they were applied
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    postings: Vec<Posting>,
+    entries: u64,
+}
+
+impl Ledger {
+    /// Apply `action` to `states`, recording its postings if it is applied
+    pub fn process<S: AccountStore<Error = Infallible>>(
+        &mut self,
+        states: &mut AccountStates<S>,
+        action: Action,
+    ) -> Outcome {
+        let mut clients = vec![action.client()];
+        if let Some(fees) = states.fee_schedule() {
+            if fees.account != action.client() {
+                clients.push(fees.account);
+            }
+        }
+        let balances = |states: &AccountStates<S>| -> Vec<_> {
+            clients
+                .iter()
+                .map(|&client| {
+                    states.account(client).map_or_else(<_>::default, |account| {
+                        (account.available().clone(), account.held().clone())
+                    })
+                })
+                .collect()
+        };
+        let house = match states.chargeback_policy() {
+            ChargebackPolicy::BookToHouse(house) => Some(house),
+            _ => None,
+        };
+        let shortfall = |states: &AccountStates<S>| {
+            house
+                .and_then(|house| states.account(house))
+                .map_or_else(<_>::default, |account| account.shortfall().clone())
+        };
+        let (before, shortfall_before) = (balances(states), shortfall(states));
+        let (tx, kind, contra) = (
+            Some(action.transaction()),
+            action.type_name(),
+            LedgerAccount::contra(&action),
+        );
+        let outcome = states.process(action);
+        if outcome != Outcome::Applied {
+            return outcome;
+        }
+        self.entries += 1;
+        let (mut debits, mut credits) = (Balance::default(), Balance::default());
+        let changes = clients.iter().zip(before).zip(balances(states)).flat_map(
+            |((&client, before), after)| {
+                [
+                    (LedgerAccount::Available(client), before.0, after.0),
+                    (LedgerAccount::Held(client), before.1, after.1),
+                ]
+            },
+        );
+        for (account, before, after) in changes {
+            // Client funds are liabilities, which grow with credits
+            let (debit, credit) = match after.clone() - before.clone() {
+                Some(increase) if increase.is_zero() => continue,
+                Some(increase) => {
+                    credits += &increase;
+                    (None, Some(increase))
+                }
+                None => {
+                    let decrease = (before - after).unwrap_or_default();
+                    debits += &decrease;
+                    (Some(decrease), None)
+                }
+            };
+            self.post(tx, kind, account, debit, credit);
+        }
+        // Shortfalls absorbed by the house are owed to it, an asset growing with debits
+        match shortfall(states) - shortfall_before.clone() {
+            Some(increase) if increase.is_zero() => {}
+            Some(increase) => {
+                debits += &increase;
+                self.post(tx, kind, LedgerAccount::Receivable, Some(increase), None);
+            }
+            None => {
+                let decrease = (shortfall_before - shortfall(states)).unwrap_or_default();
+                credits += &decrease;
+                self.post(tx, kind, LedgerAccount::Receivable, None, Some(decrease));
+            }
+        }
+        match credits.clone() - debits.clone() {
+            Some(net) if net.is_zero() => {}
+            Some(net) => self.post(tx, kind, contra, Some(net), None),
+            None => self.post(tx, kind, contra, None, debits - credits),
+        }
+        outcome
+    }
+
+    /// Close the day on `states` like [`AccountStates::end_of_day`], posting its interest
+    ///
+    /// Every interest posting is an entry of its own, without a transaction. Fees are not
+    /// posted again, as they were with the actions they were charged for.
+    pub fn end_of_day<S: AccountStore<Error = Infallible>>(
+        &mut self,
+        states: &mut AccountStates<S>,
+        date: impl Into<String>,
+    ) -> Vec<LedgerEntry> {
+        let ledger = states.end_of_day(date);
+        for entry in ledger.iter().filter(|entry| entry.kind != LedgerKind::Fee) {
+            self.entries += 1;
+            let (available, amount) = (
+                LedgerAccount::Available(entry.client),
+                Some(entry.amount.clone()),
+            );
+            if entry.kind == LedgerKind::Interest {
+                self.post(None, "interest", available, None, amount.clone());
+                self.post(
+                    None,
+                    "interest",
+                    LedgerAccount::InterestExpense,
+                    amount,
+                    None,
+                );
+            } else {
+                let kind = "negative_interest";
+                self.post(None, kind, available, amount.clone(), None);
+                self.post(None, kind, LedgerAccount::InterestIncome, None, amount);
+            }
+        }
+        ledger
+    }
+
+    fn post(
+        &mut self,
+        tx: Option<TransactionId>,
+        kind: &'static str,
+        account: LedgerAccount,
+        debit: Option<Balance>,
+        credit: Option<Balance>,
+    ) {
+        self.postings.push(Posting {
+            entry: self.entries,
+            tx,
+            kind,
+            account,
+            debit,
+            credit,
+        });
+    }
+
+    /// All postings so far, entry by entry
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// Write the postings as CSV with the columns `entry`, `tx`, `type`, `account`,
+    /// `debit` and `credit`, one of the last two being empty
+    pub fn write_csv(&self, writer: impl Write) -> Result<()> {
+        let mut writer = WriterBuilder::new().from_writer(writer);
+        for posting in &self.postings {
+            writer.serialize(posting)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the postings as a Beancount or ledger-cli journal, one transaction per entry,
+    /// all dated `date` as `YYYY-MM-DD` and amounts in `commodity`
+    ///
+    /// Debits are positive and credits negative, so every transaction balances.
+    pub fn write_plain_text(
+        &self,
+        format: PlainTextFormat,
+        date: &str,
+        commodity: &str,
+        writer: impl Write,
+    ) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        if format == PlainTextFormat::Beancount {
+            let accounts: BTreeSet<_> = self
+                .postings
+                .iter()
+                .map(|posting| posting.account)
+                .collect();
+            for account in &accounts {
+                writeln!(writer, "{date} open {}", account.plain_text_name())?;
+            }
+            if !accounts.is_empty() {
+                writeln!(writer)?;
+            }
+        }
+        for entry in self.postings.chunk_by(|a, b| a.entry == b.entry) {
+            let description = match entry[0].tx {
+                Some(tx) => format!("{} {}", entry[0].kind, u32::from(tx)),
+                None => entry[0].kind.to_owned(),
+            };
+            match format {
+                PlainTextFormat::Beancount => writeln!(writer, "{date} * \"{description}\"")?,
+                PlainTextFormat::LedgerCli => writeln!(writer, "{date} * {description}")?,
+            }
+            for posting in entry {
+                let (sign, amount) = match (&posting.debit, &posting.credit) {
+                    (Some(debit), _) => ("", debit),
+                    (None, Some(credit)) => ("-", credit),
+                    (None, None) => continue,
+                };
+                writeln!(
+                    writer,
+                    "  {}  {sign}{amount} {commodity}",
+                    posting.account.plain_text_name()
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_balanced_entries() {
+        let (mut states, mut ledger): (AccountStates, _) = (<_>::default(), Ledger::default());
+        let client = ClientId::from(1);
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: TransactionId::from(1),
+                amount: "5".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId::from(2),
+                amount: "9".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId::from(1),
+            },
+            Action::Chargeback {
+                client,
+                transaction: TransactionId::from(1),
+            },
+        ] {
+            ledger.process(&mut states, action);
+        }
+        let mut output = vec![];
+        ledger
+            .write_plain_text(PlainTextFormat::Beancount, "2024-01-31", "USD", &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"2024-01-31 open Liabilities:Clients:1:Available
+2024-01-31 open Liabilities:Clients:1:Held
+2024-01-31 open Assets:Cash
+2024-01-31 open Assets:Chargebacks
+
+2024-01-31 * "deposit 1"
+  Liabilities:Clients:1:Available  -5.0000 USD
+  Assets:Cash  5.0000 USD
+
+2024-01-31 * "dispute 1"
+  Liabilities:Clients:1:Available  5.0000 USD
+  Liabilities:Clients:1:Held  -5.0000 USD
+
+2024-01-31 * "chargeback 1"
+  Liabilities:Clients:1:Held  5.0000 USD
+  Assets:Chargebacks  -5.0000 USD
+
+"#
+        );
+
+        let mut output = vec![];
+        ledger.write_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "entry,tx,type,account,debit,credit
+1,1,deposit,client:1:available,,5.0000
+1,1,deposit,cash,5.0000,
+2,1,dispute,client:1:available,5.0000,
+2,1,dispute,client:1:held,,5.0000
+3,1,chargeback,client:1:held,5.0000,
+3,1,chargeback,chargebacks,,5.0000
+"
+        );
+    }
+
+    #[test]
+    fn post_interest_and_house_shortfalls() {
+        let house = ClientId::from(0);
+        let mut states = AccountStates::builder()
+            .chargeback_policy(ChargebackPolicy::BookToHouse(house))
+            .interest(3650)
+            .build();
+        let mut ledger = Ledger::default();
+        let (client, other) = (ClientId::from(1), ClientId::from(2));
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: TransactionId::from(1),
+                amount: "10".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId::from(2),
+                amount: "4".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId::from(1),
+            },
+            Action::Chargeback {
+                client,
+                transaction: TransactionId::from(1),
+            },
+            Action::Deposit {
+                client: other,
+                transaction: TransactionId::from(3),
+                amount: "100".parse().unwrap(),
+            },
+        ] {
+            assert_eq!(ledger.process(&mut states, action), Outcome::Applied);
+        }
+        ledger.end_of_day(&mut states, "2026-10-16");
+        assert_eq!(
+            states.account(house).unwrap().shortfall(),
+            &"4".parse().unwrap()
+        );
+
+        let mut output = vec![];
+        ledger.write_csv(&mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().ends_with(
+            "4,1,chargeback,client:1:held,6.0000,
+4,1,chargeback,receivable,4.0000,
+4,1,chargeback,chargebacks,,10.0000
+5,3,deposit,client:2:available,,100.0000
+5,3,deposit,cash,100.0000,
+6,,interest,client:2:available,,0.1000
+6,,interest,interest_expense,0.1000,
+"
+        ));
+    }
+}
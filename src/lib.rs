@@ -1,333 +1,104 @@
-use std::{
-    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
-    io::{Read, Write},
-};
+//! Transaction processor with CSV, file and command line support
+//!
+//! The engine lives in the `transaction-processor-core` crate and is re-exported here,
+//! so paths like `transaction_processor::AccountStates` keep working.
+//! Input methods that are not part of the core engine are extension traits
+//! of [`AccountStates`], all of which are available from [`prelude`].
 
-use anyhow::Result;
-use csv::{Reader, ReaderBuilder, Writer, WriterBuilder};
-use serde::{
-    de::{self, value::MapDeserializer},
-    Deserialize, Serialize,
-};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-mod decimal;
-mod op_impls;
-mod serde_impls;
-pub use decimal::Balance;
+pub use transaction_processor_core::*;
 
-#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Hash)]
-#[serde(transparent)]
-pub struct ClientId(u16);
+#[cfg(feature = "std")]
+pub mod anonymize;
+#[cfg(feature = "std")]
+pub mod categories;
+#[cfg(feature = "std")]
+pub mod cdc;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "std")]
+pub mod columnar;
+#[cfg(feature = "std")]
+pub mod compare;
+#[cfg(feature = "std")]
+pub mod corpus;
+#[cfg(feature = "std")]
+mod csv_io;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+#[cfg(feature = "std")]
+pub mod disputes;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
+mod json_io;
+#[cfg(feature = "std")]
+pub mod jsonl;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod parallel;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+#[cfg(feature = "std")]
+pub mod policies;
+#[cfg(feature = "std")]
+pub mod producer;
+#[cfg(feature = "pyo3")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "std")]
+pub mod selection;
+#[cfg(feature = "std")]
+pub mod statement;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod trend;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+#[cfg(feature = "std")]
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
+#[cfg(feature = "std")]
+pub use csv_io::*;
+#[cfg(feature = "std")]
+pub use json_io::*;
 
-#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
-#[serde(transparent)]
-pub struct TransactionId(u32);
-
-#[derive(Deserialize)]
-#[serde(tag = "type", rename_all = "lowercase")]
-pub enum Action {
-    Deposit {
-        client: ClientId,
-        #[serde(rename = "tx")]
-        transaction: TransactionId,
-        amount: Balance,
-    },
-    Withdrawal {
-        client: ClientId,
-        #[serde(rename = "tx")]
-        transaction: TransactionId,
-        amount: Balance,
-    },
-    Dispute {
-        client: ClientId,
-        #[serde(rename = "tx")]
-        transaction: TransactionId,
-    },
-    Resolve {
-        client: ClientId,
-        #[serde(rename = "tx")]
-        transaction: TransactionId,
-    },
-    Chargeback {
-        client: ClientId,
-        #[serde(rename = "tx")]
-        transaction: TransactionId,
-    },
-}
-
-pub enum Transaction {
-    Deposit {
-        client: ClientId,
-        transaction: TransactionId,
-        amount: Balance,
-    },
-    Withdrawal {
-        client: ClientId,
-        transaction: TransactionId,
-        amount: Balance,
-    },
-}
-
-#[derive(Serialize)]
-pub struct AccountSummary {
-    client: ClientId,
-    locked: bool,
-    available: Balance,
-    held: Balance,
-    total: Balance,
-}
-
-pub enum TransactionKind {
-    Deposit(Balance),
-    Withdrawal(Balance),
-}
-
-#[derive(Default)]
-struct AccountState {
-    transaction_amounts: BTreeMap<TransactionId, TransactionKind>,
-    disputes: HashSet<TransactionId>,
-    locked: bool,
-    available: Balance,
-    held: Balance,
-}
-
-impl AccountStates {
-    pub fn summary(&self) -> Vec<AccountSummary> {
-        self.accounts
-            .iter()
-            .map(
-                |(
-                    &client,
-                    &AccountState {
-                        locked,
-                        ref available,
-                        ref held,
-                        ..
-                    },
-                )| {
-                    AccountSummary {
-                        client,
-                        locked,
-                        available: available.clone(),
-                        held: held.clone(),
-                        total: available + held,
-                    }
-                },
-            )
-            .collect()
-    }
-
-    /// Apply an action against the client
-    ///
-    /// *Details*:
-    /// When a dispute is resolved, subsequent dispute filed will be ignored.
-    /// When a dispute is filed against a `Withdrawal` transaction,
-    /// some funds will be allocated to the `held` state,
-    /// and the reversal will move this portion of funds from `held` to `available`.
-    pub fn process(&mut self, action: Action) {
-        match action {
-            Action::Deposit {
-                client,
-                transaction,
-                amount,
-            } => {
-                let client = self.accounts.entry(client).or_default();
-                if client.locked {
-                    return;
-                }
-                if let Entry::Vacant(e) = client.transaction_amounts.entry(transaction) {
-                    e.insert(TransactionKind::Deposit(amount.clone()));
-                    client.available += amount;
-                }
-            }
-            Action::Withdrawal {
-                client,
-                transaction,
-                amount,
-            } => {
-                let client = self.accounts.entry(client).or_default();
-                if client.locked {
-                    return;
-                }
-                if let Entry::Vacant(e) = client.transaction_amounts.entry(transaction) {
-                    if let Some(available) = client.available.clone() - amount.clone() {
-                        client.available = available;
-                        e.insert(TransactionKind::Withdrawal(amount));
-                    }
-                }
-            }
-            Action::Dispute {
-                client,
-                transaction,
-            } => {
-                let client = self.accounts.entry(client).or_default();
-                if client.locked {
-                    return;
-                }
-                if client.disputes.contains(&transaction) {
-                    return;
-                }
-                match client.transaction_amounts.get(&transaction) {
-                    Some(TransactionKind::Deposit(amount)) => {
-                        if let Some(available) = client.available.clone() - amount.clone() {
-                            client.available = available;
-                            client.held += amount.clone();
-                            client.disputes.insert(transaction);
-                        }
-                    }
-                    Some(TransactionKind::Withdrawal(amount)) => {
-                        client.held += amount;
-                        client.disputes.insert(transaction);
-                    }
-                    None => {}
-                }
-            }
-            Action::Resolve {
-                client,
-                transaction,
-            } => {
-                let client = self.accounts.entry(client).or_default();
-                if client.locked {
-                    return;
-                }
-                if !client.disputes.contains(&transaction) {
-                    return;
-                }
-                match client.transaction_amounts.get(&transaction) {
-                    Some(TransactionKind::Deposit(amount)) => {
-                        if let Some(held) = client.held.clone() - amount.clone() {
-                            client.held = held;
-                            client.available += amount.clone();
-                            client.transaction_amounts.remove(&transaction);
-                            client.disputes.remove(&transaction);
-                        } else {
-                            unreachable!(
-                                "the held amount should always be sufficient for dispute resolution"
-                            )
-                        }
-                    }
-                    Some(TransactionKind::Withdrawal(amount)) => {
-                        if let Some(held) = client.held.clone() - amount.clone() {
-                            client.held = held;
-                            client.transaction_amounts.remove(&transaction);
-                            client.disputes.remove(&transaction);
-                        } else {
-                            unreachable!(
-                                "the held amount should always be sufficient for dispute resolution"
-                            )
-                        }
-                    }
-                    None => {}
-                }
-            }
-            Action::Chargeback {
-                client,
-                transaction,
-            } => {
-                let client = self.accounts.entry(client).or_default();
-                if client.locked {
-                    return;
-                }
-                if !client.disputes.contains(&transaction) {
-                    return;
-                }
-                match client.transaction_amounts.get(&transaction) {
-                    Some(TransactionKind::Deposit(amount)) => {
-                        if let Some(held) = client.held.clone() - amount.clone() {
-                            client.held = held;
-                            client.disputes.remove(&transaction);
-                            client.locked = true;
-                        } else {
-                            unreachable!(
-                                "the held amount should always be sufficient for dispute resolution"
-                            )
-                        }
-                    }
-                    Some(TransactionKind::Withdrawal(amount)) => {
-                        if let Some(held) = client.held.clone() - amount.clone() {
-                            client.held = held;
-                            client.available += amount.clone();
-                            client.disputes.remove(&transaction);
-                            client.locked = true;
-                        } else {
-                            unreachable!(
-                                "the held amount should always be sufficient for dispute resolution"
-                            )
-                        }
-                    }
-                    None => {}
-                }
-            }
-        }
-    }
-}
-
-#[derive(Default)]
-pub struct AccountStates {
-    accounts: BTreeMap<ClientId, AccountState>,
-}
-
-pub fn aggregate(stream: impl IntoIterator<Item = Action>) -> Vec<AccountSummary> {
-    let mut states = AccountStates::default();
-    for action in stream {
-        states.process(action)
-    }
-    states.summary()
-}
-
-pub fn summaries_from_csv<R: Read>(mut reader: Reader<R>) -> Result<Vec<AccountSummary>> {
-    let mut states = AccountStates::default();
-    for record in reader.deserialize() {
-        let record: HashMap<String, String> = record?;
-        states.process(<_>::deserialize(
-            MapDeserializer::<_, de::value::Error>::new(
-                record.into_iter().map(|(k, v)| (k.trim().to_owned(), v)),
-            ),
-        )?)
-    }
-    Ok(states.summary())
-}
-
-/// Compute account summary from IO CSV source
-pub fn summaries_from_io_csv(reader: impl Read) -> Result<Vec<AccountSummary>> {
-    summaries_from_csv(ReaderBuilder::new().from_reader(reader))
-}
-
-pub fn write_summary_csv<'a, W: Write>(
-    summaries: impl IntoIterator<Item = &'a AccountSummary>,
-    mut writer: Writer<W>,
-) -> Result<()> {
-    for record in summaries {
-        writer.serialize(record)?
-    }
-    Ok(())
-}
-
-pub fn write_summary_io_csv<'a>(
-    summaries: impl IntoIterator<Item = &'a AccountSummary>,
-    writer: impl Write,
-) -> Result<()> {
-    write_summary_csv(summaries, WriterBuilder::new().from_writer(writer))
+/// Extension traits adding I/O to [`AccountStates`]
+pub mod prelude {
+    #[cfg(feature = "scripting")]
+    pub use crate::scripting::ProcessScripted;
+    #[cfg(feature = "std")]
+    pub use crate::{
+        cdc::ProcessCsvWithChanges, columnar::Replay, jsonl::ProcessJsonl, ProcessCsv,
+    };
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_static_lifetimes)]
 mod tests {
     use std::collections::HashMap;
 
-    use super::*;
+    use csv::ReaderBuilder;
+    use serde::{de::value::MapDeserializer, Deserialize};
 
-    #[test]
-    fn serde_works() {
-        serde_json::from_str::<Action>(
-            r#"{
-            "type": "deposit",
-            "client": 1,
-            "tx": 1,
-            "amount": "1.0"
-        }"#,
-        )
-        .unwrap();
-    }
+    use super::*;
 
     const TRANSACTION_CSV: &'static str = r#"type, client, tx, amount
 deposit, 1,   1, 1.0
@@ -368,6 +139,89 @@ withdrawal, 2, 5, 3.0
         )
     }
 
+    #[test]
+    fn skip_malformed_rows_when_lenient() {
+        let (summaries, errors) = summaries_from_csv_lenient(
+            ReaderBuilder::new().from_reader(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0\ntransfer, 1, 2, 1.0\ndeposit, 1, 3, abc\ndeposit, 1\ndeposit, 1, 4, 2.0\n".as_bytes(),
+            ),
+        )
+        .unwrap();
+        assert_eq!(summaries[0].total().to_string(), "3.0000");
+        assert_eq!(
+            errors.iter().map(|error| error.line).collect::<Vec<_>>(),
+            [3, 4, 5]
+        );
+        assert_eq!(errors[2].record, ["deposit", " 1"]);
+        assert!(summaries_from_csv(
+            ReaderBuilder::new().from_reader("type, client, tx, amount\ndeposit, 1\n".as_bytes())
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_admin_actions() {
+        let summaries = summaries_from_csv(
+            ReaderBuilder::new().from_reader(
+                r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+dispute, 1, 1,
+chargeback, 1, 1,
+credit_adjustment, 1, 2, 2.5
+unlock, 1, 3,
+debit_adjustment, 1, 4, 0.5
+"#
+                .as_bytes(),
+            ),
+        )
+        .unwrap();
+        let mut output = vec![];
+        write_summary_io_csv(&summaries, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,locked,available,held,total\n1,false,2.0000,0.0000,2.0000\n"
+        );
+    }
+
+    #[test]
+    fn apply_timestamps_in_order() {
+        const TIMED_CSV: &str = r#"type, client, tx, amount, timestamp
+deposit, 1, 1, 1.0, 100
+deposit, 1, 2, 2.0, 300
+withdrawal, 1, 3, 1.5, 200
+deposit, 1, 4, 1.0,
+"#;
+        let (summaries, errors) = summaries_from_csv_chronological(
+            ReaderBuilder::new().from_reader(TIMED_CSV.as_bytes()),
+        )
+        .unwrap();
+        assert_eq!(summaries[0].total().to_string(), "4.0000");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 4);
+        assert!(errors[0].error.contains("the latest is 300"));
+
+        let mut states = AccountStates::default();
+        states
+            .process_csv(ReaderBuilder::new().from_reader(TIMED_CSV.as_bytes()))
+            .unwrap();
+        assert_eq!(
+            states
+                .account_summary(1.into())
+                .unwrap()
+                .total()
+                .to_string(),
+            "2.5000"
+        );
+        assert_eq!(
+            states.transaction_timestamp(1.into(), 2.into()),
+            Some(300.into())
+        );
+        assert!(summaries_from_csv(ReaderBuilder::new().from_reader(
+            "type, client, tx, amount, timestamp\ndeposit, 1, 1, 1.0, noon\n".as_bytes()
+        ))
+        .is_err());
+    }
+
     const TRANSACTION_DISPUTE_CSV: &'static str = r#"type, client, tx, amount
 deposit, 1, 1, 1.0
 dispute, 1, 1,
@@ -399,4 +253,163 @@ withdrawal, 2, 6, 2
             .as_bytes()
         )
     }
+
+    #[test]
+    fn process_batch_matches_process() {
+        let mut rdr = ReaderBuilder::new().from_reader(TRANSACTION_DISPUTE_CSV.as_bytes());
+        let mut actions = vec![];
+        for record in rdr.deserialize() {
+            let record: HashMap<String, String> = record.unwrap();
+            actions.push(
+                Action::deserialize(MapDeserializer::<_, serde::de::value::Error>::new(
+                    record.into_iter().map(|(k, v)| (k.trim().to_owned(), v)),
+                ))
+                .unwrap(),
+            );
+        }
+        actions.sort_by_key(Action::client);
+        let mut states = AccountStates::default();
+        states.process_batch(&actions).unwrap();
+        let mut batched = vec![];
+        write_summary_io_csv(states.summary(), &mut batched).unwrap();
+        let mut sequential = vec![];
+        write_summary_io_csv(aggregate(actions), &mut sequential).unwrap();
+        assert_eq!(batched, sequential);
+    }
+
+    #[test]
+    fn fail_on_limit() {
+        let mut states = AccountStates::builder().max_clients(1).build();
+        let error = states
+            .process_csv(ReaderBuilder::new().from_reader(TRANSACTION_CSV.as_bytes()))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "limit of distinct clients reached");
+    }
+
+    #[test]
+    fn reuse_headers_across_inputs() {
+        let mut states = AccountStates::default();
+        let mut cache = HeaderCache::default();
+        for input in [
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\n",
+            "type, client, tx, amount\nwithdrawal, 1, 3, 0.5\ndispute, 2, 2,\n",
+        ] {
+            cache
+                .process_csv(
+                    &mut states,
+                    ReaderBuilder::new().from_reader(input.as_bytes()),
+                )
+                .unwrap();
+        }
+        let mut output = vec![];
+        write_summary_io_csv(states.summary(), &mut output).unwrap();
+        assert_eq!(
+            output,
+            r#"client,locked,available,held,total
+1,false,0.5000,0.0000,0.5000
+2,false,0.0000,2.0000,2.0000
+"#
+            .as_bytes()
+        );
+        let error = cache
+            .process_csv(
+                &mut states,
+                ReaderBuilder::new().from_reader("type,client,tx,amount\n".as_bytes()),
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("differ from the first input"));
+    }
+
+    #[test]
+    fn seed_from_summary_csv() {
+        let summaries = summaries_from_csv(
+            ReaderBuilder::new().from_reader(TRANSACTION_DISPUTE_CSV.as_bytes()),
+        )
+        .unwrap();
+        let mut written = vec![];
+        write_summary_io_csv(&summaries, &mut written).unwrap();
+        let read = read_summary_io_csv(&written[..]).unwrap();
+        assert_eq!(read, summaries);
+        assert_eq!(AccountStates::from_summaries(&read).summary(), summaries);
+    }
+
+    #[test]
+    fn continue_from_summary_csv() {
+        let mut states = AccountStates::from_summary_csv(
+            "client,locked,available,held,total\n1,false,1.0000,2.5000,3.5000\n2,true,1.0000,0.0000,1.0000\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        states
+            .process_csv(ReaderBuilder::new().from_reader(
+                "type, client, tx, amount\nwithdrawal, 1, 1, 0.5\ndeposit, 2, 2, 1.0\n".as_bytes(),
+            ))
+            .unwrap();
+        let mut output = vec![];
+        write_summary_io_csv(states.summary(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,locked,available,held,total\n1,false,0.5000,2.5000,3.0000\n2,true,1.0000,0.0000,1.0000\n"
+        );
+        let error = AccountStates::from_summary_csv(
+            "client,locked,available,held,total\n1,false,1.0000,2.5000,1.0000\n".as_bytes(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("total of client 1"));
+    }
+
+    #[test]
+    fn seed_open_disputes_from_csv() {
+        let mut states = AccountStates::from_summaries(
+            &read_summary_io_csv(
+                "client,locked,available,held,total\n1,false,1.0000,2.5000,3.5000\n".as_bytes(),
+            )
+            .unwrap(),
+        );
+        let disputes =
+            read_open_disputes_io_csv("tx, client, amount, kind\n7, 1, 2.5, deposit\n".as_bytes())
+                .unwrap();
+        states.seed_disputes(disputes).unwrap();
+        assert_eq!(states.reconcile(), []);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_rejected_actions() {
+        use std::sync::{Arc, Mutex};
+
+        let logs = Arc::new(Mutex::new(vec![]));
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || Log(writer.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            summaries_from_csv(ReaderBuilder::new().from_reader(
+                "type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 1, 2, 5.0\n".as_bytes(),
+            ))
+            .unwrap();
+        });
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        assert_eq!(logs.lines().count(), 1, "{logs}");
+        assert!(logs.contains(
+            "process_csv: transaction_processor_core::observer: rejected action \
+             client=1 tx=2 type=\"withdrawal\" reason=insufficient available funds"
+        ));
+    }
+
+    #[cfg(feature = "tracing")]
+    struct Log(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "tracing")]
+    impl std::io::Write for Log {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -1,31 +1,1315 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use clap::Parser;
-use transaction_processor::{self, write_summary_io_csv};
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use csv::ReaderBuilder;
+#[cfg(feature = "metrics")]
+use transaction_processor::metrics::Metrics;
+use transaction_processor::{
+    self,
+    anonymize::{write_anonymized_summary_io_csv, Anonymizer},
+    categories::process_csv_with_categories,
+    cdc::{JsonlChangeSink, ProcessCsvWithChanges},
+    columnar::ColumnarActions,
+    compare, corpus, disputes,
+    parallel::{self, Placement},
+    policies::{read_client_policies_io_csv, PolicySet},
+    prelude::ProcessCsv,
+    producer::TransactionWriter,
+    read_summary_io_csv,
+    schedule::{self, Order, Schedule},
+    selection::{SummaryOptions, SummaryOrder},
+    statement,
+    synthetic::{self, WorkloadConfig},
+    trace, trend, write_extended_summary_io_csv, write_summary_io_csv, write_summary_json,
+    write_summary_jsonl, AccountStates, AccountSummary, ClientId, CsvOptions, JsonBalances, Schema,
+};
+
+/// System allocator that counts allocations for the `bench` report,
+/// only installed with the `bench` feature so that other commands allocate at full speed
+#[cfg(feature = "bench")]
+mod counting_alloc {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    struct CountingAlloc;
+
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    /// Number of allocations and reallocations since the start of the process
+    pub fn allocations() -> u64 {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+}
+
+/// Number of allocations so far, only counted with the `bench` feature
+fn allocations() -> Option<u64> {
+    #[cfg(feature = "bench")]
+    return Some(counting_alloc::allocations());
+    #[cfg(not(feature = "bench"))]
+    None
+}
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    input: PathBuf,
+    /// CSV file of actions to summarize
+    #[clap(required = true)]
+    input: Option<PathBuf>,
+    /// Also write every balance change as JSON Lines to this file
+    #[clap(long)]
+    changes: Option<PathBuf>,
+    /// Also write per-client totals by the optional `category` column as CSV to this file
+    #[clap(long, conflicts_with = "changes")]
+    categories: Option<PathBuf>,
+    /// Replace client ids in the summary and change stream by hashes salted with this file
+    #[clap(long, conflicts_with = "categories")]
+    anonymize_salt_file: Option<PathBuf>,
+    /// Also write the disputes still open at the end as CSV to this file
+    #[clap(long, conflicts_with_all = &["changes", "categories"])]
+    open_disputes: Option<PathBuf>,
+    /// Skip rows that fail to decode and report them with their line numbers on standard error,
+    /// followed by a count of the skipped rows by reason and column
+    #[clap(long, conflicts_with_all = &["changes", "categories", "open-disputes"])]
+    lenient: bool,
+    /// Process the accounts on this many threads, sharded by client
+    #[clap(long, conflicts_with_all = &["changes", "categories", "open-disputes", "lenient"])]
+    shards: Option<usize>,
+    /// Pin every shard thread to a core of its own
+    #[clap(long, requires = "shards")]
+    pin_cores: bool,
+    /// Give each NUMA node a contiguous range of client ids, processed by shards pinned to its cores
+    #[clap(long, requires = "shards")]
+    numa: bool,
+    /// Skip rows whose `timestamp` is before that of an earlier row and report them on standard error
+    #[clap(
+        long,
+        conflicts_with_all = &["changes", "categories", "open-disputes", "lenient", "shards"]
+    )]
+    chronological: bool,
+    /// Start from the balances of this summary CSV of an earlier run instead of empty accounts
+    #[clap(long, conflicts_with_all = &["shards", "chronological"])]
+    initial_state: Option<PathBuf>,
+    /// Apply the dispute, chargeback, limit, lock and withdrawal rules of this TOML policy pack
+    #[clap(long, conflicts_with_all = &["shards", "chronological", "initial-state"])]
+    policies: Option<PathBuf>,
+    /// Suspend single clients or give them withdrawal limits of their own, as listed in this CSV
+    #[clap(long, conflicts_with_all = &["shards", "chronological", "initial-state"])]
+    client_policies: Option<PathBuf>,
+    /// Field delimiter of the input, a single character or `tab`
+    #[clap(long, value_parser, default_value = ",")]
+    delimiter: CsvByte,
+    /// Quote character of the input
+    #[clap(long, value_parser)]
+    quote: Option<CsvByte>,
+    /// Read quotes in the input as part of the fields
+    #[clap(long, conflicts_with = "quote")]
+    no_quoting: bool,
+    /// Read the input without a header row, as the columns `type`, `client`, `tx` and `amount`
+    #[clap(long)]
+    no_headers: bool,
+    /// Read an input header as another column, as in `--map txn_type=type`
+    #[clap(long = "map", value_parser, value_name = "HEADER=COLUMN")]
+    aliases: Vec<ColumnAlias>,
+    /// Fail on unknown columns and on misplaced or missing amounts with `strict`,
+    /// or ignore unknown columns and misplaced amounts with `lenient`
+    #[clap(
+        long,
+        value_parser,
+        default_value = "lenient",
+        conflicts_with_all = &["changes", "categories", "open-disputes", "lenient", "shards", "chronological"]
+    )]
+    schema: Schema,
+    /// Write the summary to this file instead of standard output, replacing it only once complete
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// Format of the summary, `csv`, `json` for one array or `jsonl` for one object per line
+    #[clap(long, value_parser, default_value = "csv")]
+    format: Format,
+    /// Write balances in JSON as `string` or, exact but not safe for every parser, `number`
+    #[clap(long, value_parser, default_value = "string")]
+    json_balances: JsonBalances,
+    /// Add the fees, shortfall, deposit and withdrawal counts, open disputes
+    /// and charged back amount of every client to the CSV summary
+    #[clap(
+        long,
+        conflicts_with_all = &["changes", "categories", "anonymize-salt-file", "open-disputes", "lenient", "shards", "chronological"]
+    )]
+    extended: bool,
+    /// Order of the summary, by `client` id, by `total` funds descending or `locked` first
+    #[clap(long, value_parser, default_value = "client")]
+    sort: SummaryOrder,
+    /// Only write locked accounts
+    #[clap(long)]
+    locked_only: bool,
+    /// Only write these clients, as in `--clients 1,2,3`
+    #[clap(long, value_delimiter = ',')]
+    clients: Vec<u16>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compile a CSV input into the columnar format for repeated replays
+    Compile { input: PathBuf, output: PathBuf },
+    /// Compute account summary from a compiled columnar file
+    Replay { input: PathBuf },
+    /// Summarize every CSV file of a directory as one run, continuing past failed files
+    Batch {
+        dir: PathBuf,
+        /// Processing order of the files, `name` or `mtime`
+        #[clap(long, value_parser, default_value = "name")]
+        order: Order,
+        /// Give up on a file, without applying any of it, after reading it for this many seconds
+        #[clap(long)]
+        timeout_secs: Option<u64>,
+        /// Write the status of every file as CSV to this file instead of standard error
+        #[clap(long)]
+        report: Option<PathBuf>,
+        /// Write the final metrics in the Prometheus text format to this file
+        #[cfg(feature = "metrics")]
+        #[clap(long)]
+        metrics: Option<PathBuf>,
+    },
+    /// Print every action against one client with its outcome and balances before and after
+    Trace { client: u16, input: PathBuf },
+    /// Write the statement of every client as CSV, with the balances after each action
+    Statement {
+        input: PathBuf,
+        /// Only write the statement of this client
+        #[clap(long)]
+        client: Option<u16>,
+        /// Write to this file instead of standard output, replacing it only once complete
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Process a CSV input while showing throughput, rejects, held funds and locks live
+    #[cfg(feature = "tui")]
+    Tui { input: PathBuf },
+    /// Process a CSV input into the accounts stored in a sled database,
+    /// continuing from those of earlier runs, and write their summary
+    #[cfg(feature = "persistence")]
+    Persist {
+        input: PathBuf,
+        /// Directory of the database, created if needed
+        #[clap(long)]
+        store: PathBuf,
+    },
+    /// Serve `POST /actions` and queries of accounts, transactions, disputes and totals over HTTP
+    #[cfg(feature = "http")]
+    Serve {
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// Serve the accounts of this JSON snapshot, as written by `snapshot`, instead of empty ones
+        #[clap(long)]
+        state: Option<PathBuf>,
+        /// Only serve the `GET` requests, answering `POST /actions` with 405
+        #[clap(long, requires = "state")]
+        read_only: bool,
+        /// Raise an alert when actions wait this many seconds without any being processed
+        #[cfg(feature = "watchdog")]
+        #[clap(long, conflicts_with = "read-only")]
+        stall_secs: Option<u64>,
+        /// Post the alerts of `--stall-secs` as JSON to this URL
+        #[cfg(feature = "watchdog")]
+        #[clap(long, requires = "stall-secs")]
+        alert_webhook: Option<String>,
+    },
+    /// Print shell completions
+    Completions {
+        #[clap(value_parser)]
+        shell: Shell,
+    },
+    /// Print the man page
+    Man,
+    /// Report the clients whose balances or lock flags differ between two summary files
+    ///
+    /// Exits with status 1 if any client differs, and 2 if a file cannot be read.
+    Reconcile { expected: PathBuf, actual: PathBuf },
+    /// Process a CSV input, then a batch of further actions, and report per client
+    /// what the batch changed, including the disputes it opened and closed
+    Diff { input: PathBuf, batch: PathBuf },
+    /// Report per-client changes across summary files, given in chronological order
+    Trend {
+        #[clap(required = true, min_values = 2)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Process a CSV input and save its accounts with their transactions and disputes,
+    /// and the period totals, as a JSON snapshot for `serve --state`
+    Snapshot { input: PathBuf, output: PathBuf },
+    /// Summarize a CSV input and append the anonymized shape and timing of the run to a corpus
+    Record { input: PathBuf, corpus: PathBuf },
+    /// Process a synthetic in-memory workload and report throughput and memory usage,
+    /// with the number of allocations when built with the `bench` feature
+    Bench {
+        /// Replay the shape of every run recorded in this corpus instead
+        #[clap(long)]
+        corpus: Option<PathBuf>,
+        #[clap(flatten)]
+        workload: Workload,
+    },
+    /// Write a synthetic workload as CSV input, for load testing
+    Generate {
+        /// Write to this file instead of standard output, replacing it only once complete
+        #[clap(long)]
+        output: Option<PathBuf>,
+        #[clap(flatten)]
+        workload: Workload,
+    },
+}
+
+/// Shape of a synthetic workload, see [`WorkloadConfig`]
+#[derive(clap::Args, Debug)]
+struct Workload {
+    /// Number of rows
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: usize,
+    /// Number of distinct clients
+    #[clap(long, default_value_t = 1000)]
+    clients: u16,
+    /// Probability that a row disputes an earlier deposit of the client
+    #[clap(long, default_value_t = 0.01)]
+    dispute_rate: f64,
+    /// Probability that a closed dispute ends in a chargeback rather than a resolution
+    #[clap(long, default_value_t = 0.2)]
+    chargeback_rate: f64,
+    /// Share of deposits among the remaining rows, which are withdrawals
+    #[clap(long, default_value_t = 0.6)]
+    deposit_share: f64,
+    /// Seed of the generator, the same seed always gives the same rows
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl From<Workload> for WorkloadConfig {
+    fn from(workload: Workload) -> Self {
+        let Workload {
+            rows,
+            clients,
+            dispute_rate,
+            chargeback_rate,
+            deposit_share,
+            seed,
+        } = workload;
+        WorkloadConfig {
+            rows,
+            clients,
+            dispute_rate,
+            chargeback_rate,
+            deposit_share,
+            seed,
+        }
+    }
 }
 
 fn main() {
-    let Args { input } = Args::parse();
-    let reader = match std::fs::File::open(input) {
+    // Spans and rejected actions go to standard error, filtered by `RUST_LOG`
+    // as in `RUST_LOG=transaction_processor_core=info`
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+    let Args {
+        input,
+        changes,
+        categories,
+        anonymize_salt_file,
+        open_disputes,
+        lenient,
+        shards,
+        pin_cores,
+        numa,
+        chronological,
+        initial_state,
+        policies,
+        client_policies,
+        delimiter,
+        quote,
+        no_quoting,
+        no_headers,
+        aliases,
+        schema,
+        output,
+        format,
+        json_balances,
+        extended,
+        sort,
+        locked_only,
+        clients,
+        command,
+    } = Args::parse();
+    match command {
+        None => {
+            let anonymizer = match anonymize_salt_file.map(Anonymizer::from_file).transpose() {
+                Ok(anonymizer) => anonymizer,
+                Err(e) => {
+                    eprintln!("error while reading anonymization salt: {e:?}");
+                    return;
+                }
+            };
+            if anonymizer.is_some() && format != Format::Csv {
+                eprintln!("anonymized summaries can only be written as csv");
+                return;
+            }
+            if extended && format != Format::Csv {
+                eprintln!("extended summaries can only be written as csv");
+                return;
+            }
+            let mode = match (lenient, shards, chronological) {
+                (true, _, _) => Mode::Lenient,
+                (_, Some(shards), _) => Mode::Sharded(shards, Placement { pin_cores, numa }),
+                (_, _, true) => Mode::Chronological,
+                _ => Mode::Strict,
+            };
+            let csv = CsvOptions {
+                delimiter: delimiter.0,
+                quote: match (no_quoting, quote) {
+                    (true, _) => None,
+                    (false, quote) => Some(quote.map_or(b'"', |quote| quote.0)),
+                },
+                headers: !no_headers,
+                aliases: aliases
+                    .into_iter()
+                    .map(|alias| (alias.header, alias.column))
+                    .collect(),
+                schema,
+            };
+            summarize(
+                Input {
+                    path: input.expect("input is required without a subcommand"),
+                    csv,
+                },
+                changes,
+                categories,
+                open_disputes,
+                mode,
+                Start {
+                    initial_state,
+                    policies,
+                    client_policies,
+                },
+                Output {
+                    path: output,
+                    format,
+                    balances: json_balances,
+                    anonymizer,
+                    extended,
+                    selection: SummaryOptions {
+                        order: sort,
+                        locked_only,
+                        clients: (!clients.is_empty())
+                            .then(|| clients.into_iter().map(ClientId::from).collect()),
+                    },
+                },
+            )
+        }
+        Some(Command::Compile { input, output }) => compile(input, output),
+        Some(Command::Replay { input }) => replay(input),
+        Some(Command::Batch {
+            dir,
+            order,
+            timeout_secs,
+            report,
+            #[cfg(feature = "metrics")]
+            metrics,
+        }) => batch(
+            dir,
+            Schedule {
+                order,
+                timeout: timeout_secs.map(Duration::from_secs),
+            },
+            report,
+            #[cfg(feature = "metrics")]
+            metrics,
+        ),
+        Some(Command::Trace { client, input }) => trace(client, input),
+        Some(Command::Statement {
+            input,
+            client,
+            output,
+        }) => statement(input, client.map(ClientId::from), output),
+        Some(Command::Reconcile { expected, actual }) => reconcile(expected, actual),
+        Some(Command::Diff { input, batch }) => diff(input, batch),
+        Some(Command::Trend { inputs }) => trend(inputs),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui { input }) => tui(input),
+        #[cfg(feature = "persistence")]
+        Some(Command::Persist { input, store }) => persist(input, store),
+        #[cfg(feature = "http")]
+        Some(Command::Serve {
+            addr,
+            state,
+            read_only,
+            #[cfg(feature = "watchdog")]
+            stall_secs,
+            #[cfg(feature = "watchdog")]
+            alert_webhook,
+        }) => serve(
+            addr,
+            state,
+            read_only,
+            #[cfg(feature = "watchdog")]
+            stall_secs.map(|secs| transaction_processor::watchdog::Watchdog {
+                stall: Duration::from_secs(secs),
+                webhook: alert_webhook,
+            }),
+        ),
+        Some(Command::Completions { shell }) => {
+            let mut command = Args::command();
+            let name = command.get_name().to_owned();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout().lock())
+        }
+        Some(Command::Man) => {
+            let mut command = Args::command();
+            command.build();
+            print!("{}", man_page(&command))
+        }
+        Some(Command::Snapshot { input, output }) => snapshot(input, output),
+        Some(Command::Record { input, corpus }) => record(input, corpus),
+        Some(Command::Bench {
+            corpus: Some(corpus),
+            workload,
+        }) => bench_corpus(corpus, workload.seed),
+        Some(Command::Bench {
+            corpus: None,
+            workload,
+        }) => bench(workload.into()),
+        Some(Command::Generate { output, workload }) => generate(output, workload.into()),
+    }
+}
+
+/// How a plain summary reads its input, set by mutually exclusive flags
+enum Mode {
+    Strict,
+    Lenient,
+    Sharded(usize, Placement),
+    Chronological,
+}
+
+/// How a plain summary finds its input
+struct Input {
+    path: PathBuf,
+    csv: CsvOptions,
+}
+
+/// What a plain summary starts from, empty accounts under the default rules if both are unset
+struct Start {
+    /// Summary CSV of an earlier run
+    initial_state: Option<PathBuf>,
+    /// TOML policy pack, see [`PolicySet`]
+    policies: Option<PathBuf>,
+    /// CSV of client policies on top of the pack
+    client_policies: Option<PathBuf>,
+}
+
+/// A state under a policy pack and client policies, the defaults where unset
+fn configured_states(
+    policies: Option<&Path>,
+    client_policies: Option<&Path>,
+) -> Result<AccountStates> {
+    let policies = policies
+        .map(PolicySet::load)
+        .transpose()?
+        .unwrap_or_default();
+    let overrides = match client_policies {
+        Some(path) => File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| read_client_policies_io_csv(BufReader::new(file)))
+            .with_context(|| format!("cannot read client policies {}", path.display()))?,
+        None => vec![],
+    };
+    Ok(policies.build_with_overrides(overrides))
+}
+
+/// A single-byte character of a CSV dialect, like `;` or `tab`
+#[derive(Debug, Clone, Copy)]
+struct CsvByte(u8);
+
+impl FromStr for CsvByte {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tab" | "\\t" | "\t" => Ok(Self(b'\t')),
+            _ if s.len() == 1 && s.is_ascii() => Ok(Self(s.as_bytes()[0])),
+            _ => bail!("expected a single ASCII character or tab, not {s:?}"),
+        }
+    }
+}
+
+/// An input header read as another column
+#[derive(Debug, Clone)]
+struct ColumnAlias {
+    header: String,
+    column: String,
+}
+
+impl FromStr for ColumnAlias {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((header, column)) if !header.is_empty() && !column.is_empty() => Ok(Self {
+                header: header.to_owned(),
+                column: column.to_owned(),
+            }),
+            _ => bail!("expected HEADER=COLUMN, not {s:?}"),
+        }
+    }
+}
+
+/// Format of the written summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => bail!("unknown format {s:?}, expected csv, json or jsonl"),
+        }
+    }
+}
+
+/// How a plain summary is written
+struct Output {
+    /// Standard output if `None`
+    path: Option<PathBuf>,
+    format: Format,
+    balances: JsonBalances,
+    anonymizer: Option<Anonymizer>,
+    /// Write [`AccountStates::extended_summary`] instead, as CSV
+    extended: bool,
+    selection: SummaryOptions,
+}
+
+impl Output {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    fn write(&self, summaries: &[AccountSummary]) -> Result<()> {
+        let summaries = self.selection.select(summaries);
+        match &self.path {
+            Some(path) => write_atomically(path, |writer| self.write_to(&summaries, writer)),
+            None => self.write_to(&summaries, std::io::stdout().lock()),
+        }
+    }
+
+    fn write_extended(&self, states: &AccountStates) -> Result<()> {
+        let mut extended: BTreeMap<_, _> = states
+            .extended_summary()
+            .map(|summary| (summary.client, summary))
+            .collect();
+        let summaries = self
+            .selection
+            .select(states.summary())
+            .into_iter()
+            .filter_map(|summary| extended.remove(&summary.client()));
+        match &self.path {
+            Some(path) => write_atomically(path, |writer| {
+                write_extended_summary_io_csv(summaries, writer)
+            }),
+            None => write_extended_summary_io_csv(summaries, std::io::stdout().lock()),
+        }
+    }
+
+    fn write_to(&self, summaries: &[&AccountSummary], writer: impl Write) -> Result<()> {
+        let summaries = summaries.iter().copied();
+        match (self.format, &self.anonymizer) {
+            (Format::Csv, None) => write_summary_io_csv(summaries, writer),
+            (Format::Csv, Some(anonymizer)) => {
+                write_anonymized_summary_io_csv(summaries, anonymizer, writer)
+            }
+            (Format::Json, _) => write_summary_json(summaries, self.balances, writer),
+            (Format::Jsonl, _) => write_summary_jsonl(summaries, self.balances, writer),
+        }
+    }
+}
+
+/// Write a file through a temporary file next to it, renamed over `path` once written and synced
+///
+/// Readers of `path` see either the previous file or the complete new one.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", std::process::id()));
+    let temporary = PathBuf::from(temporary);
+    let written = (|| -> Result<()> {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        write(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written.with_context(|| format!("cannot write {}", path.display()))
+}
+
+fn summarize(
+    input: Input,
+    changes: Option<PathBuf>,
+    categories: Option<PathBuf>,
+    open_disputes: Option<PathBuf>,
+    mode: Mode,
+    start: Start,
+    output: Output,
+) {
+    let configured = start.policies.is_some() || start.client_policies.is_some();
+    let states = match start.initial_state {
+        Some(path) => match File::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| AccountStates::from_summary_csv(BufReader::new(file)))
+        {
+            Ok(states) => Some(states),
+            Err(e) => {
+                eprintln!(
+                    "error while reading initial state {}: {e:?}",
+                    path.display()
+                );
+                return;
+            }
+        },
+        None if configured => {
+            match configured_states(start.policies.as_deref(), start.client_policies.as_deref()) {
+                Ok(states) => Some(states),
+                Err(e) => {
+                    eprintln!("error while reading policies: {e:?}");
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+    let reader = match File::open(input.path) {
         Ok(reader) => reader,
         Err(e) => {
             eprintln!("i/o error: {e:?}");
             return;
         }
     };
-    let summaries = match transaction_processor::summaries_from_io_csv(reader) {
+    let csv = |reader| input.csv.reader(BufReader::new(reader));
+    if output.extended {
+        let mut states = states.unwrap_or_default();
+        if let Err(e) =
+            csv(reader).and_then(|reader| states.process_csv_with_schema(reader, input.csv.schema))
+        {
+            eprintln!("error while parsing csv: {e:?}");
+        } else if let Err(e) = output.write_extended(&states) {
+            eprintln!("i/o error: {e:?}")
+        }
+        return;
+    }
+    let summaries = match (changes, categories, open_disputes) {
+        (None, None, None) => {
+            let report = |(summaries, errors): (_, Vec<_>), prefix| {
+                for error in &errors {
+                    eprintln!("{prefix} {error}");
+                }
+                eprint!(
+                    "{}",
+                    transaction_processor::format_row_error_counts(&errors)
+                );
+                summaries
+            };
+            match mode {
+                Mode::Strict => match states {
+                    Some(mut states) => csv(reader)
+                        .and_then(|reader| states.process_csv_with_schema(reader, input.csv.schema))
+                        .map(|()| states.summary()),
+                    None => {
+                        transaction_processor::summaries_from_file_with_options(reader, &input.csv)
+                    }
+                },
+                Mode::Lenient => {
+                    let mut states = states.unwrap_or_default();
+                    csv(reader)
+                        .and_then(|reader| states.process_csv_lenient(reader))
+                        .map(|errors| report((states.summary(), errors), "skipped"))
+                }
+                Mode::Sharded(shards, placement) => csv(reader).and_then(|reader| {
+                    parallel::summaries_from_csv_placed(reader, shards, placement)
+                }),
+                Mode::Chronological => csv(reader)
+                    .and_then(transaction_processor::summaries_from_csv_chronological)
+                    .map(|result| report(result, "out of order")),
+            }
+        }
+        (None, None, Some(open_disputes)) => match File::create(open_disputes) {
+            Ok(writer) => {
+                let mut states = states.unwrap_or_default();
+                csv(reader)
+                    .and_then(|reader| disputes::process_csv_tracking_disputes(&mut states, reader))
+                    .and_then(|rows| {
+                        disputes::write_open_disputes_io_csv(
+                            &states,
+                            &rows,
+                            BufWriter::new(writer),
+                        )?;
+                        Ok(states.summary())
+                    })
+            }
+            Err(e) => {
+                eprintln!("i/o error: {e:?}");
+                return;
+            }
+        },
+        (None, Some(categories), _) => match File::create(categories) {
+            Ok(writer) => {
+                let mut states = states.unwrap_or_default();
+                csv(reader)
+                    .and_then(|reader| process_csv_with_categories(&mut states, reader))
+                    .and_then(|rollup| {
+                        rollup.write_io_csv(BufWriter::new(writer))?;
+                        Ok(states.summary())
+                    })
+            }
+            Err(e) => {
+                eprintln!("i/o error: {e:?}");
+                return;
+            }
+        },
+        (Some(changes), _, _) => match File::create(changes) {
+            Ok(writer) => {
+                let mut sink = JsonlChangeSink::new(BufWriter::new(writer));
+                if let Some(anonymizer) = &output.anonymizer {
+                    sink = sink.anonymized(anonymizer.clone());
+                }
+                let mut states = states.unwrap_or_default();
+                csv(reader)
+                    .and_then(|reader| states.process_csv_with_changes(reader, &mut sink))
+                    .and_then(|()| {
+                        sink.into_inner().flush()?;
+                        Ok(states.summary())
+                    })
+            }
+            Err(e) => {
+                eprintln!("i/o error: {e:?}");
+                return;
+            }
+        },
+    };
+    let summaries = match summaries {
         Ok(summaries) => summaries,
         Err(e) => {
             eprintln!("error while parsing csv: {e:?}");
             return;
         }
     };
+    if let Err(e) = output.write(&summaries) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn compile(input: PathBuf, output: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let columns = match ColumnarActions::from_csv(ReaderBuilder::new().from_reader(reader)) {
+        Ok(columns) => columns,
+        Err(e) => {
+            eprintln!("error while parsing csv: {e:?}");
+            return;
+        }
+    };
+    let writer = match File::create(output) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = columns.write_to(BufWriter::new(writer)) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn replay(input: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let summaries =
+        match transaction_processor::columnar::summaries_from_columnar(BufReader::new(reader)) {
+            Ok(summaries) => summaries,
+            Err(e) => {
+                eprintln!("error while replaying columnar file: {e:?}");
+                return;
+            }
+        };
+    if let Err(e) = write_summary_io_csv(&summaries, std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn batch(
+    dir: PathBuf,
+    schedule: Schedule,
+    report: Option<PathBuf>,
+    #[cfg(feature = "metrics")] metrics: Option<PathBuf>,
+) {
+    let mut states = AccountStates::default();
+    #[cfg(feature = "metrics")]
+    let observer = std::sync::Arc::new(Metrics::default());
+    #[cfg(feature = "metrics")]
+    states.add_observer(observer.clone());
+    let reports = match schedule.run(&dir, &mut states) {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("error while processing {}: {e:?}", dir.display());
+            return;
+        }
+    };
+    let summaries = states.summary();
+    #[cfg(feature = "metrics")]
+    if let Some(path) = metrics {
+        if let Err(e) = write_atomically(&path, |writer| {
+            observer.write_prometheus(&summaries, writer)
+        }) {
+            eprintln!("i/o error: {e:?}")
+        }
+    }
+    let written = match report {
+        Some(report) => File::create(report)
+            .map_err(Into::into)
+            .and_then(|writer| schedule::write_report_io_csv(&reports, BufWriter::new(writer))),
+        None => schedule::write_report_io_csv(&reports, std::io::stderr().lock()),
+    };
+    if let Err(e) = written {
+        eprintln!("i/o error: {e:?}")
+    }
+    if let Err(e) = write_summary_io_csv(&summaries, std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn trace(client: u16, input: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = trace::trace_client(
+        ReaderBuilder::new().from_reader(BufReader::new(reader)),
+        ClientId::from(client),
+        std::io::stdout().lock(),
+    ) {
+        eprintln!("error while tracing client {client}: {e:?}")
+    }
+}
+
+fn statement(input: PathBuf, client: Option<ClientId>, output: Option<PathBuf>) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let reader = ReaderBuilder::new().from_reader(BufReader::new(reader));
+    let written = match output {
+        Some(output) => write_atomically(&output, |writer| {
+            statement::write_statement_io_csv(reader, client, writer)
+        }),
+        None => statement::write_statement_io_csv(reader, client, std::io::stdout().lock()),
+    };
+    if let Err(e) = written {
+        eprintln!("error while writing statements: {e:?}")
+    }
+}
+
+#[cfg(feature = "tui")]
+fn tui(input: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let mut terminal = ratatui::init();
+    let summaries = transaction_processor::dashboard::run(
+        &mut terminal,
+        ReaderBuilder::new().from_reader(BufReader::new(reader)),
+    );
+    ratatui::restore();
+    let summaries = match summaries {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            eprintln!("error while processing csv: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = write_summary_io_csv(&summaries, std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+#[cfg(feature = "persistence")]
+fn persist(input: PathBuf, store: PathBuf) {
+    use transaction_processor::persistence::SledStore;
+
+    let store = match SledStore::open(&store) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("error while opening store {}: {e:?}", store.display());
+            return;
+        }
+    };
+    let mut states = AccountStates::builder().build_with(store);
+    let processed = File::open(input)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            let reader = ReaderBuilder::new().from_reader(BufReader::new(file));
+            transaction_processor::for_each_csv_action(reader, |action| {
+                states.try_process(action)?;
+                Ok(())
+            })
+        })
+        .and_then(|()| Ok(states.store().flush()?));
+    if let Err(e) = processed {
+        eprintln!("error while processing csv: {e:?}");
+        return;
+    }
+    let summaries = match states.try_summary() {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            eprintln!("error while reading accounts: {:?}", anyhow::Error::from(e));
+            return;
+        }
+    };
+    if let Err(e) = write_summary_io_csv(summaries, std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+#[cfg(feature = "http")]
+fn serve(
+    addr: String,
+    state: Option<PathBuf>,
+    read_only: bool,
+    #[cfg(feature = "watchdog")] watchdog: Option<transaction_processor::watchdog::Watchdog>,
+) {
+    use transaction_processor::http::{self, Service};
+
+    let states = match state {
+        Some(path) => match File::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| transaction_processor::read_snapshot_json(BufReader::new(file)))
+        {
+            Ok(states) => states,
+            Err(e) => {
+                eprintln!("error while reading state {}: {e:?}", path.display());
+                return;
+            }
+        },
+        None => AccountStates::default(),
+    };
+    let service = if read_only {
+        Service::read_only(states)
+    } else {
+        Service::new(states)
+    };
+    #[cfg(feature = "watchdog")]
+    if let Some(watchdog) = watchdog {
+        let report = |e: anyhow::Error| eprintln!("error while alerting: {e:?}");
+        if let Err(e) = http::serve_watched(&addr, service, watchdog, report) {
+            eprintln!("error while serving: {e:?}")
+        }
+        return;
+    }
+    if let Err(e) = http::serve(&addr, service) {
+        eprintln!("error while serving: {e:?}")
+    }
+}
+
+fn reconcile(expected: PathBuf, actual: PathBuf) {
+    let read = |path: &Path| -> Result<Vec<AccountSummary>> {
+        let reader = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        read_summary_io_csv(BufReader::new(reader))
+            .with_context(|| format!("cannot parse summary {}", path.display()))
+    };
+    let (expected, actual) = match (read(&expected), read(&actual)) {
+        (Ok(expected), Ok(actual)) => (expected, actual),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("error while reading summaries: {e:?}");
+            std::process::exit(2);
+        }
+    };
+    let differences = compare::compare(&expected, &actual);
+    if let Err(e) = compare::write_differences_io_csv(&differences, std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}");
+        std::process::exit(2);
+    }
+    if !differences.is_empty() {
+        eprintln!("{} clients differ", differences.len());
+        std::process::exit(1);
+    }
+}
+
+fn diff(input: PathBuf, batch: PathBuf) {
+    let process = |states: &mut AccountStates, path: &Path| -> Result<()> {
+        let reader = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        states
+            .process_csv(ReaderBuilder::new().from_reader(BufReader::new(reader)))
+            .with_context(|| format!("cannot process {}", path.display()))
+    };
+    let mut before = AccountStates::default();
+    if let Err(e) = process(&mut before, &input) {
+        eprintln!("error while processing input: {e:?}");
+        return;
+    }
+    let mut after = before.clone();
+    if let Err(e) = process(&mut after, &batch) {
+        eprintln!("error while processing batch: {e:?}");
+        return;
+    }
+    if let Err(e) = compare::write_state_diff_io_csv(&before.diff(&after), std::io::stdout().lock())
+    {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn trend(inputs: Vec<PathBuf>) {
+    let mut snapshots = vec![];
+    for input in inputs {
+        let reader = match File::open(&input) {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("i/o error: {e:?}");
+                return;
+            }
+        };
+        match read_summary_io_csv(BufReader::new(reader)) {
+            Ok(summaries) => snapshots.push((input.display().to_string(), summaries)),
+            Err(e) => {
+                eprintln!("error while parsing summary {}: {e:?}", input.display());
+                return;
+            }
+        }
+    }
+    if let Err(e) = trend::write_trend_io_csv(&trend::trend(&snapshots), std::io::stdout().lock()) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn snapshot(input: PathBuf, output: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let mut states = AccountStates::default();
+    if let Err(e) = states.process_csv(ReaderBuilder::new().from_reader(BufReader::new(reader))) {
+        eprintln!("error while processing csv: {e:?}");
+        return;
+    }
+    if let Err(e) = write_atomically(&output, |writer| {
+        transaction_processor::write_snapshot_json(&states, writer)
+    }) {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn record(input: PathBuf, corpus: PathBuf) {
+    let reader = match File::open(input) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("i/o error: {e:?}");
+            return;
+        }
+    };
+    let (summaries, entry) =
+        match corpus::record_csv(ReaderBuilder::new().from_reader(BufReader::new(reader))) {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                eprintln!("error while parsing csv: {e:?}");
+                return;
+            }
+        };
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(corpus)
+        .map_err(Into::into)
+        .and_then(|writer| corpus::append_corpus_entry(&entry, writer));
+    if let Err(e) = appended {
+        eprintln!("error while recording corpus: {e:?}")
+    }
     if let Err(e) = write_summary_io_csv(&summaries, std::io::stdout().lock()) {
         eprintln!("i/o error: {e:?}")
     }
 }
+
+fn bench_corpus(corpus: PathBuf, seed: u64) {
+    let entries = match File::open(corpus)
+        .map_err(Into::into)
+        .and_then(|reader| corpus::read_corpus(BufReader::new(reader)))
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("error while reading corpus: {e:?}");
+            return;
+        }
+    };
+    for (index, entry) in entries.iter().enumerate() {
+        println!("run: {index} (recorded {} ms)", entry.elapsed_ms);
+        bench(entry.workload(seed));
+    }
+}
+
+fn generate(output: Option<PathBuf>, config: WorkloadConfig) {
+    let write = |writer: &mut dyn Write| -> Result<()> {
+        let mut writer = TransactionWriter::csv(writer)?;
+        for action in synthetic::generate(&config) {
+            writer.write(&action)?;
+        }
+        writer.into_inner()?.flush()?;
+        Ok(())
+    };
+    let written = match output {
+        Some(output) => write_atomically(&output, |writer| write(writer)),
+        None => write(&mut std::io::stdout().lock()),
+    };
+    if let Err(e) = written {
+        eprintln!("i/o error: {e:?}")
+    }
+}
+
+fn bench(config: WorkloadConfig) {
+    let actions: Vec<_> = synthetic::generate(&config).collect();
+    let allocations_before = allocations();
+    let start = Instant::now();
+    let mut states = AccountStates::default();
+    for action in actions {
+        states.process(action);
+    }
+    let summaries = states.summary();
+    let elapsed = start.elapsed();
+    let allocations = allocations()
+        .zip(allocations_before)
+        .map(|(after, before)| after - before);
+    println!("rows: {}", config.rows);
+    println!("clients: {}", summaries.len());
+    println!("elapsed: {:.3}s", elapsed.as_secs_f64());
+    println!(
+        "rows/sec: {:.0}",
+        config.rows as f64 / elapsed.as_secs_f64()
+    );
+    match allocations {
+        Some(allocations) => println!("allocations: {allocations}"),
+        None => println!("allocations: unavailable, build with `--features bench` to count them"),
+    }
+    match peak_rss_kib() {
+        Some(rss) => println!("peak RSS: {rss} KiB"),
+        None => println!("peak RSS: unavailable"),
+    }
+}
+
+/// Render a man page in roff from the command line definition
+fn man_page(command: &clap::Command) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('-', "\\-");
+    let name = command.get_name();
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{name} {}\"\n.SH NAME\n{} \\- {}\n.SH SYNOPSIS\n\\fB{}\\fR [OPTIONS] <INPUT>\n.br\n\\fB{}\\fR <SUBCOMMAND>\n",
+        name.to_uppercase(),
+        command.get_version().unwrap_or_default(),
+        escape(name),
+        escape(command.get_about().unwrap_or_default()),
+        escape(name),
+        escape(name),
+    );
+    page.push_str(".SH OPTIONS\n");
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let value = match arg.get_value_names().and_then(|names| names.first()) {
+            _ if !arg.is_takes_value_set() => String::new(),
+            Some(name) => format!(" <{name}>"),
+            None => format!(" <{}>", arg.get_id().to_uppercase()),
+        };
+        let flag = match (arg.get_short(), arg.get_long()) {
+            (Some(short), Some(long)) => {
+                format!("\\fB\\-{short}\\fR, \\fB\\-\\-{}\\fR{value}", escape(long))
+            }
+            (None, Some(long)) => format!("\\fB\\-\\-{}\\fR{value}", escape(long)),
+            (Some(short), None) => format!("\\fB\\-{short}\\fR{value}"),
+            (None, None) => value.trim_start().to_owned(),
+        };
+        page.push_str(&format!(
+            ".TP\n{flag}\n{}\n",
+            escape(arg.get_help().unwrap_or_default())
+        ));
+    }
+    page.push_str(".SH SUBCOMMANDS\n");
+    for subcommand in command.get_subcommands() {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            escape(subcommand.get_name()),
+            escape(subcommand.get_about().unwrap_or_default())
+        ));
+    }
+    page
+}
+
+/// Peak resident set size of this process, as reported by Linux procfs
+fn peak_rss_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,220 @@
+//! Prometheus metrics of processed actions, only available with the `metrics` feature
+//!
+//! [`Metrics`] is an [`EventObserver`] counting actions by type, rejections by reason
+//! and locked accounts. Gauges of the held funds and the number of accounts are
+//! computed from the summaries when the metrics are written, in the Prometheus
+//! text exposition format.
+//!
+//! With the `watchdog` feature, a gauge and a counter also tell whether the ingestion
+//! of the HTTP service is stalled, as set by the [`crate::watchdog`], and how often it was.
+
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Result;
+
+use crate::{policy::AutoLock, AccountSummary, Action, Balance, ClientId, EventObserver, Outcome};
+
+/// Counters of processed actions, to be registered with
+/// [`AccountStates::add_observer`](crate::AccountStates::add_observer)
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Processed actions by type name
+    actions: Mutex<BTreeMap<&'static str, u64>>,
+    /// Rejected actions by reason
+    rejections: Mutex<BTreeMap<String, u64>>,
+    locked: AtomicU64,
+    #[cfg(feature = "watchdog")]
+    stalled: AtomicBool,
+    #[cfg(feature = "watchdog")]
+    stalls: AtomicU64,
+}
+
+impl EventObserver for Metrics {
+    fn on_action(&self, action: &Action, outcome: Outcome) {
+        *lock(&self.actions).entry(action.type_name()).or_default() += 1;
+        if let Outcome::Rejected(rejection) = outcome {
+            *lock(&self.rejections)
+                .entry(snake_case(&format!("{rejection:?}")))
+                .or_default() += 1;
+        }
+    }
+
+    fn on_account_locked(&self, _client: ClientId, _rule: Option<AutoLock>) {
+        self.locked.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Set whether the ingestion is stalled, counting a stall each time it becomes so
+    #[cfg(feature = "watchdog")]
+    pub fn set_ingestion_stalled(&self, stalled: bool) {
+        if !self.stalled.swap(stalled, Ordering::Relaxed) && stalled {
+            self.stalls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Write the counters, and the gauges over `summaries`, in the Prometheus text format
+    pub fn write_prometheus(
+        &self,
+        summaries: impl IntoIterator<Item = impl Borrow<AccountSummary>>,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        let (mut held, mut accounts) = (Balance::default(), 0u64);
+        for summary in summaries {
+            held += summary.borrow().held();
+            accounts += 1;
+        }
+        family(
+            &mut writer,
+            "transactions_actions_total",
+            "counter",
+            "Processed actions, by type",
+        )?;
+        for (kind, count) in lock(&self.actions).iter() {
+            writeln!(
+                writer,
+                "transactions_actions_total{{type=\"{kind}\"}} {count}"
+            )?;
+        }
+        family(
+            &mut writer,
+            "transactions_rejections_total",
+            "counter",
+            "Rejected actions, by reason",
+        )?;
+        for (reason, count) in lock(&self.rejections).iter() {
+            writeln!(
+                writer,
+                "transactions_rejections_total{{reason=\"{reason}\"}} {count}"
+            )?;
+        }
+        family(
+            &mut writer,
+            "transactions_accounts_locked_total",
+            "counter",
+            "Accounts locked by a chargeback or a lock policy",
+        )?;
+        writeln!(
+            writer,
+            "transactions_accounts_locked_total {}",
+            self.locked.load(Ordering::Relaxed)
+        )?;
+        family(
+            &mut writer,
+            "transactions_held_funds",
+            "gauge",
+            "Held funds of all accounts",
+        )?;
+        writeln!(writer, "transactions_held_funds {held}")?;
+        family(
+            &mut writer,
+            "transactions_accounts",
+            "gauge",
+            "Accounts, archived ones included",
+        )?;
+        writeln!(writer, "transactions_accounts {accounts}")?;
+        #[cfg(feature = "watchdog")]
+        {
+            family(
+                &mut writer,
+                "transactions_ingestion_stalled",
+                "gauge",
+                "Whether actions wait without any being processed, as seen by the watchdog",
+            )?;
+            let stalled = self.stalled.load(Ordering::Relaxed);
+            writeln!(
+                writer,
+                "transactions_ingestion_stalled {}",
+                u8::from(stalled)
+            )?;
+            family(
+                &mut writer,
+                "transactions_ingestion_stalls_total",
+                "counter",
+                "Stalls of the ingestion raised by the watchdog",
+            )?;
+            let stalls = self.stalls.load(Ordering::Relaxed);
+            writeln!(writer, "transactions_ingestion_stalls_total {stalls}")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn family(writer: &mut impl Write, name: &str, kind: &str, help: &str) -> std::io::Result<()> {
+    writeln!(writer, "# HELP {name} {help}\n# TYPE {name} {kind}")
+}
+
+/// Counters stay usable after a panic in another thread, they are only ever incremented
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `InsufficientFunds` as `insufficient_funds`, the usual form of Prometheus label values
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (index, c) in name.char_indices() {
+        if c.is_ascii_uppercase() && index > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{AccountStates, TransactionId};
+
+    #[test]
+    fn count_actions() {
+        let metrics = Arc::new(Metrics::default());
+        let mut states = AccountStates::default();
+        states.add_observer(metrics.clone());
+        let client = ClientId::from(1);
+        for action in [
+            Action::Deposit {
+                client,
+                transaction: TransactionId::from(1),
+                amount: "2.5".parse().unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId::from(2),
+                amount: "5".parse().unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId::from(1),
+            },
+        ] {
+            states.process(action);
+        }
+        let mut output = vec![];
+        metrics
+            .write_prometheus(states.summary(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        for line in [
+            "transactions_actions_total{type=\"deposit\"} 1",
+            "transactions_actions_total{type=\"withdrawal\"} 1",
+            "transactions_rejections_total{reason=\"insufficient_funds\"} 1",
+            "transactions_accounts_locked_total 0",
+            "transactions_held_funds 2.5000",
+            "transactions_accounts 1",
+        ] {
+            assert!(output.lines().any(|l| l == line), "{line} in {output}");
+        }
+    }
+}
@@ -0,0 +1,547 @@
+//! Processing on several threads, with the accounts sharded by client
+//!
+//! Every action only touches the account of its client,
+//! so shards of clients can be processed independently and their summaries concatenated.
+//! Each shard starts from a default [`AccountStates`], without limits or policies.
+//! A [`Placement`] can pin the shards to cores and keep the clients of each NUMA node together.
+
+use std::{collections::VecDeque, fs, io::Read, mem, panic, thread};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use core_affinity::CoreId;
+use csv::Reader;
+
+use crate::{
+    for_each_csv_action,
+    sync::{Condvar, Mutex, MutexGuard},
+    AccountStates, AccountSummary, Action,
+};
+
+/// Number of actions sent to a shard at once
+const BATCH: usize = 1024;
+/// Number of batches waiting for each shard before the reader blocks
+const QUEUED_BATCHES: usize = 16;
+/// Number of distinct client ids
+const CLIENTS: usize = 1 << u16::BITS;
+/// Where Linux lists the NUMA nodes and their cores
+const NODES_DIR: &str = "/sys/devices/system/node";
+
+/// How the shard threads are laid out on the machine, all off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Placement {
+    /// Pin every shard thread to a core of its own, wrapping around if there are more shards than cores
+    pub pin_cores: bool,
+    /// Split the client ids into one contiguous range per NUMA node,
+    /// shard each range over threads pinned to the cores of its node
+    ///
+    /// Nodes are only known on Linux; elsewhere all cores count as one node.
+    pub numa: bool,
+}
+
+/// Compute account summary with the actions of client `c` processed by shard `c % num_shards`
+///
+/// Gives the same summary as processing all actions on one thread.
+/// `num_shards` of 0 is treated as 1.
+pub fn aggregate_parallel(
+    actions: impl IntoIterator<Item = Action>,
+    num_shards: usize,
+) -> Vec<AccountSummary> {
+    run_sharded(Layout::unpinned(num_shards), |send| {
+        actions.into_iter().try_for_each(send)
+    })
+    .expect("shards only stop early by panicking")
+}
+
+/// Compute account summary from a CSV reader like [`aggregate_parallel`]
+///
+/// Fails at the first action that failed to decode.
+pub fn summaries_from_csv_parallel<R: Read>(
+    reader: Reader<R>,
+    num_shards: usize,
+) -> Result<Vec<AccountSummary>> {
+    summaries_from_csv_placed(reader, num_shards, Placement::default())
+}
+
+/// Compute account summary from a CSV reader like [`summaries_from_csv_parallel`],
+/// with the shard threads laid out by `placement`
+///
+/// Also fails if the cores to pin to cannot be listed or a thread cannot be pinned.
+pub fn summaries_from_csv_placed<R: Read>(
+    reader: Reader<R>,
+    num_shards: usize,
+    placement: Placement,
+) -> Result<Vec<AccountSummary>> {
+    let layout = Layout::detect(num_shards, placement)?;
+    run_sharded(layout, |send| for_each_csv_action(reader, send))
+}
+
+/// Shards of every node and core of every shard
+#[derive(Debug, PartialEq, Eq)]
+struct Layout {
+    /// Shards of each node in turn, every node gets at least one
+    nodes: Vec<Vec<usize>>,
+    /// Core to pin each shard to, if any
+    cores: Vec<Option<CoreId>>,
+}
+
+impl Layout {
+    /// Lay out `num_shards` shards without pinning them
+    fn unpinned(num_shards: usize) -> Self {
+        let num_shards = num_shards.max(1);
+        Self {
+            nodes: vec![(0..num_shards).collect()],
+            cores: vec![None; num_shards],
+        }
+    }
+
+    /// Lay out `num_shards` shards for `placement` on the cores of this machine
+    fn detect(num_shards: usize, placement: Placement) -> Result<Self> {
+        if !placement.pin_cores && !placement.numa {
+            return Ok(Self::unpinned(num_shards));
+        }
+        let Some(allowed) = core_affinity::get_core_ids().filter(|cores| !cores.is_empty()) else {
+            bail!("cannot list the cores to pin the shards to");
+        };
+        let nodes = if placement.numa {
+            numa_nodes(&allowed)
+        } else {
+            vec![]
+        };
+        Ok(Self::pinned(
+            num_shards,
+            if nodes.is_empty() {
+                vec![allowed]
+            } else {
+                nodes
+            },
+        ))
+    }
+
+    /// Lay out `num_shards` shards over `nodes`, given by their cores, in contiguous blocks
+    ///
+    /// Only the first `num_shards` nodes are used if there are fewer shards than nodes.
+    fn pinned(num_shards: usize, mut nodes: Vec<Vec<CoreId>>) -> Self {
+        let num_shards = num_shards.max(1);
+        nodes.truncate(num_shards);
+        let mut layout = Self {
+            nodes: vec![vec![]; nodes.len()],
+            cores: vec![],
+        };
+        for shard in 0..num_shards {
+            let node = shard * nodes.len() / num_shards;
+            let cores = &nodes[node];
+            layout
+                .cores
+                .push(Some(cores[layout.nodes[node].len() % cores.len()]));
+            layout.nodes[node].push(shard);
+        }
+        layout
+    }
+
+    /// Shard processing the actions of `client`
+    fn shard(&self, client: u16) -> usize {
+        let client = usize::from(client);
+        let shards = &self.nodes[client * self.nodes.len() / CLIENTS];
+        shards[client % shards.len()]
+    }
+}
+
+/// Cores in `allowed` of every NUMA node having any, empty if the nodes are unknown
+fn numa_nodes(allowed: &[CoreId]) -> Vec<Vec<CoreId>> {
+    let Ok(entries) = fs::read_dir(NODES_DIR) else {
+        return vec![];
+    };
+    let mut nodes: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let node: usize = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()?;
+            let cpus = parse_cpu_list(&fs::read_to_string(entry.path().join("cpulist")).ok()?)?;
+            Some((node, cpus))
+        })
+        .collect();
+    nodes.sort_unstable_by_key(|(node, _)| *node);
+    nodes
+        .into_iter()
+        .map(|(_, cpus)| {
+            allowed
+                .iter()
+                .copied()
+                .filter(|core| cpus.contains(&core.id))
+                .collect()
+        })
+        .filter(|cores: &Vec<_>| !cores.is_empty())
+        .collect()
+}
+
+/// Parse a Linux CPU list like `0-3,8,10-11`
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?);
+    }
+    Some(cpus)
+}
+
+/// Run one thread per shard of `layout`, fed with the actions passed to `send` by `feed`
+fn run_sharded(
+    layout: Layout,
+    feed: impl FnOnce(&mut dyn FnMut(Action) -> Result<()>) -> Result<()>,
+) -> Result<Vec<AccountSummary>> {
+    let queues: Vec<_> = layout
+        .cores
+        .iter()
+        .map(|_| Queue::new(QUEUED_BATCHES))
+        .collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (queues.iter().zip(&layout.cores).enumerate())
+            .map(|(shard, (queue, &core))| scope.spawn(move || run_shard(shard, core, queue)))
+            .collect();
+
+        let mut router = Router::new(&layout, &queues, BATCH);
+        let fed = feed(&mut |action| router.route(action));
+        if fed.is_ok() {
+            router.flush();
+        }
+        drop(router);
+
+        let mut shards = vec![];
+        for worker in workers {
+            shards.push(worker.join().unwrap_or_else(|e| panic::resume_unwind(e))?);
+        }
+        fed?;
+        Ok(merge(shards))
+    })
+}
+
+/// Process the batches of `queue` on the current thread, pinned to `core` if any
+fn run_shard(
+    shard: usize,
+    core: Option<CoreId>,
+    queue: &Queue<Vec<Action>>,
+) -> Result<Vec<AccountSummary>> {
+    let _closing = Closing(queue);
+    if let Some(core) = core {
+        ensure!(
+            core_affinity::set_for_current(core),
+            "cannot pin shard {shard} to core {}",
+            core.id
+        );
+    }
+    let mut states = AccountStates::default();
+    while let Some(batch) = queue.pop() {
+        for action in batch {
+            states.process(action);
+        }
+    }
+    Ok(states.summary())
+}
+
+/// Summaries of all shards, ordered by client
+fn merge(shards: Vec<Vec<AccountSummary>>) -> Vec<AccountSummary> {
+    let mut summaries: Vec<_> = shards.into_iter().flatten().collect();
+    summaries.sort_unstable_by_key(|summary| summary.client());
+    summaries
+}
+
+/// Batches of actions on their way to the queues of their shards
+///
+/// Closes all queues when dropped, so that the shards stop even if feeding them failed.
+struct Router<'a> {
+    layout: &'a Layout,
+    queues: &'a [Queue<Vec<Action>>],
+    batches: Vec<Vec<Action>>,
+    batch_size: usize,
+}
+
+impl<'a> Router<'a> {
+    fn new(layout: &'a Layout, queues: &'a [Queue<Vec<Action>>], batch_size: usize) -> Self {
+        let batches = queues
+            .iter()
+            .map(|_| Vec::with_capacity(batch_size))
+            .collect();
+        Self {
+            layout,
+            queues,
+            batches,
+            batch_size,
+        }
+    }
+
+    /// Add `action` to the batch of its shard, queueing the batch once full
+    fn route(&mut self, action: Action) -> Result<()> {
+        let shard = self.layout.shard(action.client().into());
+        let batch = &mut self.batches[shard];
+        batch.push(action);
+        if batch.len() == self.batch_size {
+            let batch = mem::replace(batch, Vec::with_capacity(self.batch_size));
+            self.queues[shard]
+                .push(batch)
+                .map_err(|_| anyhow!("shard {shard} stopped"))?;
+        }
+        Ok(())
+    }
+
+    /// Queue the partly filled batches
+    fn flush(&mut self) {
+        for (queue, batch) in self.queues.iter().zip(self.batches.drain(..)) {
+            // A stopped shard reports its panic or pinning failure when joined
+            let _ = queue.push(batch);
+        }
+    }
+}
+
+impl Drop for Router<'_> {
+    fn drop(&mut self) {
+        self.queues.iter().for_each(Queue::close);
+    }
+}
+
+/// Bounded queue from the reader to one shard
+///
+/// Either side closes it when done: the shard then takes what is left and the reader stops.
+struct Queue<T> {
+    state: Mutex<QueueState<T>>,
+    changed: Condvar,
+    capacity: usize,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T> Queue<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add `item` once there is room, or give it back if the queue is closed
+    fn push(&self, item: T) -> Result<(), T> {
+        let mut state = self.lock();
+        while state.items.len() >= self.capacity && !state.closed {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.closed {
+            return Err(item);
+        }
+        state.items.push_back(item);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Take the oldest item once there is one, or `None` if the queue is closed and empty
+    fn pop(&self) -> Option<T> {
+        let mut state = self.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.changed.notify_all();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// Closes a queue when dropped, also when unwinding
+struct Closing<'a, T>(&'a Queue<T>);
+
+impl<T> Drop for Closing<'_, T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::ReaderBuilder;
+
+    use super::*;
+    use crate::synthetic::{self, WorkloadConfig};
+
+    #[test]
+    fn match_sequential_processing() {
+        let config = WorkloadConfig {
+            rows: 20_000,
+            clients: 100,
+            ..Default::default()
+        };
+        let mut states = AccountStates::default();
+        for action in synthetic::generate(&config) {
+            states.process(action);
+        }
+        let expected = states.summary();
+        for num_shards in [0, 1, 3, 8] {
+            let summaries = aggregate_parallel(synthetic::generate(&config), num_shards);
+            assert_eq!(summaries, expected, "{num_shards} shards");
+        }
+    }
+
+    #[test]
+    fn match_sequential_processing_when_placed() {
+        let config = WorkloadConfig {
+            rows: 20_000,
+            clients: 100,
+            ..Default::default()
+        };
+        let expected = aggregate_parallel(synthetic::generate(&config), 1);
+        for numa in [false, true] {
+            let placement = Placement {
+                pin_cores: !numa,
+                numa,
+            };
+            let layout = Layout::detect(4, placement).unwrap();
+            let summaries = run_sharded(layout, |send| {
+                synthetic::generate(&config).try_for_each(send)
+            });
+            assert_eq!(summaries.unwrap(), expected, "{placement:?}");
+        }
+    }
+
+    #[test]
+    fn keep_client_ranges_on_their_node() {
+        let cores = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let layout = Layout::pinned(5, vec![cores(&[0, 1]), cores(&[2, 3])]);
+        assert_eq!(layout.nodes, [vec![0, 1, 2], vec![3, 4]]);
+        let pinned: Vec<_> = layout.cores.iter().map(|core| core.unwrap().id).collect();
+        assert_eq!(pinned, [0, 1, 0, 2, 3]);
+        assert!((0..=u16::MAX / 2).all(|client| layout.shard(client) < 3));
+        assert!((u16::MAX / 2 + 1..=u16::MAX).all(|client| layout.shard(client) >= 3));
+
+        let layout = Layout::pinned(1, vec![cores(&[0]), cores(&[1])]);
+        assert_eq!(layout.nodes, [vec![0]]);
+        assert_eq!(layout.shard(u16::MAX), 0);
+    }
+
+    #[test]
+    fn parse_linux_cpu_lists() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("\n"), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn fail_on_bad_csv_row() {
+        let input = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nbogus, 2, 2, 1.0\n";
+        let reader = ReaderBuilder::new().from_reader(input.as_bytes());
+        assert!(summaries_from_csv_parallel(reader, 2).is_err());
+    }
+}
+
+#[cfg(loom)]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn deposit(client: u16, transaction: u32, amount: &str) -> Action {
+        Action::Deposit {
+            client: client.into(),
+            transaction: transaction.into(),
+            amount: amount.parse().unwrap(),
+        }
+    }
+
+    /// Spawn `num_shards` shards, each with a queue of a single batch
+    fn spawn_shards(
+        num_shards: usize,
+    ) -> (
+        Arc<Vec<Queue<Vec<Action>>>>,
+        Vec<loom::thread::JoinHandle<Vec<AccountSummary>>>,
+    ) {
+        let queues = Arc::new((0..num_shards).map(|_| Queue::new(1)).collect::<Vec<_>>());
+        let workers = (0..num_shards)
+            .map(|shard| {
+                let queues = queues.clone();
+                loom::thread::spawn(move || run_shard(shard, None, &queues[shard]).unwrap())
+            })
+            .collect();
+        (queues, workers)
+    }
+
+    #[test]
+    fn deliver_every_action_once_and_in_order() {
+        let actions = vec![
+            deposit(1, 1, "2.0"),
+            deposit(2, 2, "1.0"),
+            Action::Withdrawal {
+                client: 1.into(),
+                transaction: 3.into(),
+                amount: "1.5".parse().unwrap(),
+            },
+        ];
+        let mut states = AccountStates::default();
+        for action in actions.clone() {
+            states.process(action);
+        }
+        let expected = states.summary();
+
+        // Three preemptions already take seconds, unbounded runs take hours
+        let mut model = loom::model::Builder::new();
+        model.preemption_bound.get_or_insert(3);
+        model.check(move || {
+            let layout = Layout::unpinned(2);
+            let (queues, workers) = spawn_shards(2);
+            let mut router = Router::new(&layout, &queues, 1);
+            for action in actions.clone() {
+                router.route(action).unwrap();
+            }
+            router.flush();
+            drop(router);
+            let shards = workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect();
+            assert_eq!(merge(shards), expected);
+        });
+    }
+
+    #[test]
+    fn stop_the_reader_when_a_shard_stops() {
+        loom::model(|| {
+            let layout = Layout::unpinned(1);
+            let queues = Arc::new(vec![Queue::new(1)]);
+            let shard = {
+                let queues = queues.clone();
+                loom::thread::spawn(move || drop(Closing(&queues[0])))
+            };
+            let mut router = Router::new(&layout, &queues, 1);
+            let routed: Vec<_> = (1..=3)
+                .map(|tx| router.route(deposit(1, tx, "1.0")))
+                .collect();
+            drop(router);
+            shard.join().unwrap();
+            assert!(routed
+                .iter()
+                .skip_while(|routed| routed.is_ok())
+                .all(Result::is_err));
+        });
+    }
+}
@@ -0,0 +1,407 @@
+//! Accounts stored on disk in a sled database, only available with the `persistence` feature
+//!
+//! A [`SledStore`] keeps every account in a sled tree keyed by client, and next to it
+//! the [`TransactionEntry`] of each of its transactions, keyed by client and transaction.
+//! An action only loads the account of its client with the entry of its own transaction,
+//! and writes back the keys it changed in one atomic batch, so its cost does not grow
+//! with the history of the client and only sled's cache is held in memory. The accounts
+//! stay in the database for later runs. The store plugs into
+//! [`AccountStatesBuilder::build_with`](crate::AccountStatesBuilder::build_with)
+//! like any other [`AccountStore`]. The configuration, period totals and archived
+//! accounts of the states are not stored.
+//!
+//! Reading all accounts, for example for [`AccountStates::try_summary`](crate::AccountStates::try_summary),
+//! loads whole accounts, and so does every action under a retention policy, which evicts
+//! transactions the action does not refer to.
+//!
+//! Failures of sled and stored values that do not decode are reported as [`StoreError`],
+//! and the actions they hit fail with [`EngineError::Store`](crate::EngineError::Store).
+
+use std::{borrow::Cow, collections::HashMap, fmt::Display, iter::Peekable, path::Path};
+
+use serde::de::DeserializeOwned;
+use sled::{Batch, IVec};
+
+use crate::{store::TransactionEntry, AccountState, AccountStore, ClientId, TransactionId};
+
+/// Name of the tree of accounts in the database
+const ACCOUNTS_TREE: &str = "accounts";
+
+/// Length of the key of an account, the big-endian client id
+const ACCOUNT_KEY_LEN: usize = 2;
+
+/// Length of the key of a transaction entry, the account key and the big-endian transaction id
+const TRANSACTION_KEY_LEN: usize = 6;
+
+/// Accounts and their transaction entries in a sled tree
+///
+/// Every account key sorts right before the keys of its transactions.
+pub struct SledStore {
+    tree: sled::Tree,
+    /// Number of accounts, counted once on opening since sled counts by iterating
+    len: usize,
+}
+
+/// Failure of a [`SledStore`] to read or write its database, or to decode what it read
+#[derive(Debug)]
+pub enum StoreError {
+    Database(sled::Error),
+    Encoding(serde_json::Error),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Database(_) => f.write_str("cannot access the account database"),
+            StoreError::Encoding(_) => f.write_str("cannot encode or decode a stored account"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Database(error) => Some(error),
+            StoreError::Encoding(error) => Some(error),
+        }
+    }
+}
+
+impl From<sled::Error> for StoreError {
+    fn from(error: sled::Error) -> Self {
+        StoreError::Database(error)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError::Encoding(error)
+    }
+}
+
+/// Entries loaded with an account, to tell which ones it changed
+type Loaded = HashMap<TransactionId, TransactionEntry>;
+
+impl SledStore {
+    /// Open the database at `path`, creating it if needed, with the accounts of earlier runs
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::from_tree(sled::open(path)?.open_tree(ACCOUNTS_TREE)?)
+    }
+
+    /// Store the accounts in a tree of a database opened by the caller
+    pub fn from_tree(tree: sled::Tree) -> Result<Self, StoreError> {
+        let mut len = 0;
+        for key in tree.iter().keys() {
+            len += usize::from(key?.len() == ACCOUNT_KEY_LEN);
+        }
+        Ok(Self { tree, len })
+    }
+
+    /// Write all updated accounts to disk, returning once they are durable
+    pub fn flush(&self) -> Result<(), StoreError> {
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// The account of `client` without its transaction entries, and its stored encoding
+    fn load(&self, client: ClientId) -> Result<(AccountState, Option<IVec>), StoreError> {
+        match self.tree.get(account_key(client))? {
+            Some(value) => Ok((decode(&value)?, Some(value))),
+            None => Ok((AccountState::default(), None)),
+        }
+    }
+
+    /// Load the stored entries of `transactions` into `account`
+    fn load_entries(
+        &self,
+        client: ClientId,
+        transactions: &[TransactionId],
+        account: &mut AccountState,
+    ) -> Result<Loaded, StoreError> {
+        let mut loaded = Loaded::with_capacity(transactions.len());
+        for &transaction in transactions {
+            if let Some(value) = self.tree.get(transaction_key(client, transaction))? {
+                let entry: TransactionEntry = decode(&value)?;
+                account.set_transaction_entry(transaction, entry.clone());
+                loaded.insert(transaction, entry);
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Load the entries of all transactions of `client` into `account`
+    fn load_all_entries(
+        &self,
+        client: ClientId,
+        account: &mut AccountState,
+    ) -> Result<Loaded, StoreError> {
+        let mut loaded = Loaded::new();
+        for record in self.tree.scan_prefix(account_key(client)) {
+            let (key, value) = record?;
+            if key.len() == TRANSACTION_KEY_LEN {
+                let entry: TransactionEntry = decode(&value)?;
+                account.set_transaction_entry(transaction_of(&key), entry.clone());
+                loaded.insert(transaction_of(&key), entry);
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Write back the keys of `account` that differ from the `stored` encoding
+    /// and the `loaded` entries
+    fn write(
+        &mut self,
+        client: ClientId,
+        stored: Option<IVec>,
+        mut loaded: Loaded,
+        mut account: AccountState,
+    ) -> Result<(), StoreError> {
+        let mut batch = Batch::default();
+        for (transaction, entry) in account.take_transaction_entries() {
+            if loaded.remove(&transaction).as_ref() != Some(&entry) {
+                let value = serde_json::to_vec(&entry)?;
+                batch.insert(&transaction_key(client, transaction)[..], value);
+            }
+        }
+        // Loaded entries that are gone were dropped from the account
+        for transaction in loaded.into_keys() {
+            batch.remove(&transaction_key(client, transaction)[..]);
+        }
+        let value = serde_json::to_vec(&account)?;
+        if stored.as_deref() != Some(&value[..]) {
+            batch.insert(&account_key(client)[..], value);
+        }
+        self.tree.apply_batch(batch)?;
+        self.len += usize::from(stored.is_none());
+        Ok(())
+    }
+}
+
+fn account_key(client: ClientId) -> [u8; ACCOUNT_KEY_LEN] {
+    u16::from(client).to_be_bytes()
+}
+
+fn transaction_key(client: ClientId, transaction: TransactionId) -> [u8; TRANSACTION_KEY_LEN] {
+    let mut key = [0; TRANSACTION_KEY_LEN];
+    key[..ACCOUNT_KEY_LEN].copy_from_slice(&account_key(client));
+    key[ACCOUNT_KEY_LEN..].copy_from_slice(&u32::from(transaction).to_be_bytes());
+    key
+}
+
+fn client_of(key: &[u8]) -> ClientId {
+    u16::from_be_bytes([key[0], key[1]]).into()
+}
+
+fn transaction_of(key: &[u8]) -> TransactionId {
+    u32::from_be_bytes([key[2], key[3], key[4], key[5]]).into()
+}
+
+fn decode<T: DeserializeOwned>(value: &[u8]) -> Result<T, StoreError> {
+    Ok(serde_json::from_slice(value)?)
+}
+
+/// The account stored as `value`, with the entries of its transactions that follow it in `records`
+fn read_account(
+    value: &[u8],
+    records: &mut Peekable<sled::Iter>,
+) -> Result<AccountState, StoreError> {
+    let mut account: AccountState = decode(value)?;
+    while let Some(record) = records.next_if(|record| {
+        record
+            .as_ref()
+            .map_or(true, |(key, _)| key.len() == TRANSACTION_KEY_LEN)
+    }) {
+        let (key, value) = record?;
+        account.set_transaction_entry(transaction_of(&key), decode(&value)?);
+    }
+    Ok(account)
+}
+
+impl AccountStore for SledStore {
+    type Error = StoreError;
+
+    fn get(&self, client: ClientId) -> Result<Option<Cow<'_, AccountState>>, StoreError> {
+        let (mut account, stored) = self.load(client)?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+        self.load_all_entries(client, &mut account)?;
+        Ok(Some(Cow::Owned(account)))
+    }
+
+    fn upsert(&mut self, client: ClientId, account: AccountState) -> Result<(), StoreError> {
+        let stored = self.tree.get(account_key(client))?;
+        let loaded = self.load_all_entries(client, &mut AccountState::default())?;
+        self.write(client, stored, loaded, account)
+    }
+
+    fn remove(&mut self, client: ClientId) -> Result<Option<AccountState>, StoreError> {
+        let (mut account, stored) = self.load(client)?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+        let loaded = self.load_all_entries(client, &mut account)?;
+        let mut batch = Batch::default();
+        batch.remove(&account_key(client)[..]);
+        for transaction in loaded.into_keys() {
+            batch.remove(&transaction_key(client, transaction)[..]);
+        }
+        self.tree.apply_batch(batch)?;
+        self.len -= 1;
+        Ok(Some(account))
+    }
+
+    fn iter(
+        &self,
+    ) -> impl Iterator<Item = Result<(ClientId, Cow<'_, AccountState>), StoreError>> + '_ {
+        let mut records = self.tree.iter().peekable();
+        std::iter::from_fn(move || {
+            let (key, value) = match records.next()? {
+                Ok(record) => record,
+                Err(error) => return Some(Err(error.into())),
+            };
+            let account = read_account(&value, &mut records);
+            Some(account.map(|account| (client_of(&key), Cow::Owned(account))))
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains(&self, client: ClientId) -> Result<bool, StoreError> {
+        Ok(self.tree.contains_key(account_key(client))?)
+    }
+
+    fn clients(&self) -> impl Iterator<Item = Result<ClientId, StoreError>> + '_ {
+        self.tree.iter().keys().filter_map(|key| match key {
+            Ok(key) if key.len() == ACCOUNT_KEY_LEN => Some(Ok(client_of(&key))),
+            Ok(_) => None,
+            Err(error) => Some(Err(error.into())),
+        })
+    }
+
+    fn update<R>(
+        &mut self,
+        client: ClientId,
+        f: impl FnOnce(&mut AccountState) -> R,
+    ) -> Result<R, StoreError> {
+        let (mut account, stored) = self.load(client)?;
+        let loaded = self.load_all_entries(client, &mut account)?;
+        let result = f(&mut account);
+        self.write(client, stored, loaded, account)?;
+        Ok(result)
+    }
+
+    fn get_partial(
+        &self,
+        client: ClientId,
+        transactions: &[TransactionId],
+    ) -> Result<Option<Cow<'_, AccountState>>, StoreError> {
+        let (mut account, stored) = self.load(client)?;
+        if stored.is_none() {
+            return Ok(None);
+        }
+        self.load_entries(client, transactions, &mut account)?;
+        Ok(Some(Cow::Owned(account)))
+    }
+
+    fn update_partial<R>(
+        &mut self,
+        client: ClientId,
+        transactions: &[TransactionId],
+        f: impl FnOnce(&mut AccountState) -> R,
+    ) -> Result<R, StoreError> {
+        let (mut account, stored) = self.load(client)?;
+        let loaded = self.load_entries(client, transactions, &mut account)?;
+        let result = f(&mut account);
+        self.write(client, stored, loaded, account)?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fees::FeeSchedule,
+        policy::{ChargebackPolicy, DisputeWindow, Retention},
+        synthetic, AccountStates, AccountStatesBuilder,
+    };
+
+    #[test]
+    fn match_memory_store() {
+        let config = synthetic::WorkloadConfig {
+            rows: 2_000,
+            clients: 20,
+            dispute_rate: 0.1,
+            chargeback_rate: 0.3,
+            ..<_>::default()
+        };
+        let builders: [fn() -> AccountStatesBuilder; 3] = [
+            AccountStates::builder,
+            || {
+                AccountStates::builder()
+                    .retention(Retention::PerClient(20))
+                    .dispute_window(DisputeWindow::Transactions(50))
+            },
+            || {
+                AccountStates::builder()
+                    .fees(FeeSchedule {
+                        account: ClientId::from(0),
+                        withdrawal: "0.1".parse().unwrap(),
+                        withdrawal_basis_points: 10,
+                        chargeback: "1".parse().unwrap(),
+                    })
+                    .chargeback_policy(ChargebackPolicy::BookToHouse(ClientId::from(0)))
+                    .transaction_index()
+            },
+        ];
+        for builder in builders {
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            let store = SledStore::from_tree(db.open_tree(ACCOUNTS_TREE).unwrap()).unwrap();
+            let mut memory = builder().build();
+            let mut persisted = builder().build_with(store);
+            for action in synthetic::generate(&config) {
+                let expected = memory.process(action.clone());
+                assert_eq!(persisted.try_process(action).unwrap(), expected);
+            }
+            assert_eq!(persisted.try_summary().unwrap(), memory.summary());
+
+            let reopened = SledStore::from_tree(db.open_tree(ACCOUNTS_TREE).unwrap()).unwrap();
+            assert_eq!(reopened.len(), memory.store().len());
+            let mut accounts: Vec<_> = reopened
+                .iter()
+                .map(|entry| entry.map(|(client, account)| (client, account.into_owned())))
+                .collect::<Result<_, _>>()
+                .unwrap();
+            accounts.sort_unstable_by_key(|&(client, _)| client);
+            let mut expected: Vec<_> = memory
+                .store()
+                .iter()
+                .map(|entry| entry.map(|(client, account)| (client, account.into_owned())))
+                .collect::<Result<_, _>>()
+                .unwrap();
+            expected.sort_unstable_by_key(|&(client, _)| client);
+            assert_eq!(accounts, expected);
+        }
+    }
+
+    #[test]
+    fn process_batches() {
+        let config = synthetic::WorkloadConfig {
+            rows: 2_000,
+            clients: 5,
+            dispute_rate: 0.1,
+            ..<_>::default()
+        };
+        let actions: Vec<_> = synthetic::generate(&config).collect();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SledStore::from_tree(db.open_tree(ACCOUNTS_TREE).unwrap()).unwrap();
+        let mut memory = AccountStates::default();
+        let mut persisted = AccountStates::builder().build_with(store);
+        memory.process_batch(&actions).unwrap();
+        persisted.process_batch(&actions).unwrap();
+        assert_eq!(persisted.try_summary().unwrap(), memory.summary());
+    }
+}
@@ -0,0 +1,352 @@
+//! Policy packs, the rules of [`AccountStatesBuilder`] gathered in one TOML file
+//!
+//! Every key is optional, and a missing one keeps the default rule:
+//!
+//! ```toml
+//! dispute = "reverse_withdrawals"
+//! chargeback = { book_to_house = 0 }
+//! dispute_window = { seconds = 7776000 }
+//! retention = { per_client = 1000 }
+//! chronological = true
+//! transaction_index = true
+//!
+//! [limits]
+//! max_clients = 10000
+//! max_transactions = 1000000
+//!
+//! [lock]
+//! held_exceeds = 2
+//! chargebacks = 3
+//!
+//! [withdrawals]
+//! max_single = "1000"
+//! max_daily = "5000"
+//! min_balance = "10"
+//! ```
+//!
+//! Duplicate transaction ids are always rejected. With `transaction_index`,
+//! references to the transactions of another client are rejected as such
+//! rather than as unknown transactions.
+//!
+//! Single clients can be given rules of their own on top of the pack, read from a CSV
+//! file by [`read_client_policies_io_csv`]:
+//!
+//! ```csv
+//! client,suspended,max_withdrawal,max_daily_withdrawals,min_balance
+//! 7,,100000,250000,
+//! 9,true,,,
+//! ```
+
+use std::{collections::BTreeSet, io::Read, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    policy::{ChargebackPolicy, ClientPolicy, DisputePolicy, DisputeWindow, Retention},
+    AccountStates, AccountStatesBuilder, Balance, ClientId,
+};
+
+/// Every configurable rule of the engine, all of them the defaults by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicySet {
+    /// See [`AccountStatesBuilder::dispute_policy`]
+    pub dispute: DisputePolicy,
+    /// See [`AccountStatesBuilder::chargeback_policy`]
+    pub chargeback: ChargebackPolicy,
+    /// See [`AccountStatesBuilder::dispute_window`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dispute_window: Option<DisputeWindow>,
+    /// See [`AccountStatesBuilder::retention`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Retention>,
+    /// See [`AccountStatesBuilder::chronological`]
+    pub chronological: bool,
+    /// See [`AccountStatesBuilder::transaction_index`]
+    pub transaction_index: bool,
+    pub limits: PolicyLimits,
+    pub lock: LockRules,
+    pub withdrawals: WithdrawalRules,
+}
+
+/// Caps on the size of the state, see [`AccountStatesBuilder::max_clients`]
+/// and [`AccountStatesBuilder::max_transactions`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clients: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_transactions: Option<usize>,
+}
+
+/// Thresholds of automatic locks, see [`AccountStatesBuilder::lock_when_held_exceeds`]
+/// and [`AccountStatesBuilder::lock_after_chargebacks`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockRules {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held_exceeds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chargebacks: Option<usize>,
+}
+
+/// Caps on withdrawals, see [`AccountStatesBuilder::max_withdrawal`],
+/// [`AccountStatesBuilder::max_daily_withdrawals`] and [`AccountStatesBuilder::min_balance`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WithdrawalRules {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_single: Option<Balance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_daily: Option<Balance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_balance: Option<Balance>,
+}
+
+impl PolicySet {
+    /// Parse a policy pack, failing on unknown keys
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Read a policy pack from the TOML file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|toml| Self::from_toml(&toml))
+            .with_context(|| format!("cannot read policies {}", path.display()))
+    }
+
+    /// The policy pack as TOML, leaving out unset rules
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Apply every rule of the pack to `builder`
+    pub fn configure(&self, mut builder: AccountStatesBuilder) -> AccountStatesBuilder {
+        builder = builder
+            .dispute_policy(self.dispute)
+            .chargeback_policy(self.chargeback);
+        if let Some(window) = self.dispute_window {
+            builder = builder.dispute_window(window);
+        }
+        if let Some(retention) = self.retention {
+            builder = builder.retention(retention);
+        }
+        if self.chronological {
+            builder = builder.chronological();
+        }
+        if self.transaction_index {
+            builder = builder.transaction_index();
+        }
+        if let Some(max) = self.limits.max_clients {
+            builder = builder.max_clients(max);
+        }
+        if let Some(max) = self.limits.max_transactions {
+            builder = builder.max_transactions(max);
+        }
+        if let Some(multiple) = self.lock.held_exceeds {
+            builder = builder.lock_when_held_exceeds(multiple);
+        }
+        if let Some(count) = self.lock.chargebacks {
+            builder = builder.lock_after_chargebacks(count);
+        }
+        if let Some(max) = &self.withdrawals.max_single {
+            builder = builder.max_withdrawal(max.clone());
+        }
+        if let Some(max) = &self.withdrawals.max_daily {
+            builder = builder.max_daily_withdrawals(max.clone());
+        }
+        if let Some(min) = &self.withdrawals.min_balance {
+            builder = builder.min_balance(min.clone());
+        }
+        builder
+    }
+
+    /// A new state following the pack
+    pub fn build(&self) -> AccountStates {
+        self.configure(AccountStates::builder()).build()
+    }
+
+    /// A new state following the pack, with the rules of `overrides` for their clients
+    pub fn build_with_overrides(
+        &self,
+        overrides: impl IntoIterator<Item = (ClientId, ClientPolicy)>,
+    ) -> AccountStates {
+        overrides
+            .into_iter()
+            .fold(
+                self.configure(AccountStates::builder()),
+                |builder, (client, policy)| builder.client_policy(client, policy),
+            )
+            .build()
+    }
+}
+
+/// A row of a client policy file, where empty fields keep the rules of the pack
+#[derive(Deserialize)]
+struct ClientPolicyRow {
+    client: ClientId,
+    #[serde(default)]
+    suspended: Option<bool>,
+    #[serde(default)]
+    max_withdrawal: Option<Balance>,
+    #[serde(default)]
+    max_daily_withdrawals: Option<Balance>,
+    #[serde(default)]
+    min_balance: Option<Balance>,
+}
+
+/// Read client policies with the columns `client`, `suspended`, `max_withdrawal`,
+/// `max_daily_withdrawals` and `min_balance`, all but `client` optional
+///
+/// Fails on a client listed twice.
+pub fn read_client_policies_io_csv(reader: impl Read) -> Result<Vec<(ClientId, ClientPolicy)>> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    let mut clients = BTreeSet::new();
+    let mut policies = vec![];
+    for row in reader.deserialize() {
+        let row: ClientPolicyRow = row?;
+        ensure!(
+            clients.insert(row.client),
+            "client {} is listed twice",
+            u16::from(row.client)
+        );
+        policies.push((
+            row.client,
+            ClientPolicy {
+                suspended: row.suspended.unwrap_or_default(),
+                max_withdrawal: row.max_withdrawal,
+                max_daily_withdrawals: row.max_daily_withdrawals,
+                min_balance: row.min_balance,
+            },
+        ));
+    }
+    Ok(policies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, ClientId, Outcome, Rejection, TransactionId};
+
+    #[test]
+    fn load_policy_pack() {
+        let policies = PolicySet::from_toml(
+            r#"
+dispute = "reverse_withdrawals"
+chargeback = { book_to_house = 0 }
+dispute_window = { transactions = 10 }
+
+[limits]
+max_clients = 2
+
+[lock]
+chargebacks = 2
+
+[withdrawals]
+max_single = "5"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policies,
+            PolicySet {
+                dispute: DisputePolicy::ReverseWithdrawals,
+                chargeback: ChargebackPolicy::BookToHouse(ClientId::from(0)),
+                dispute_window: Some(DisputeWindow::Transactions(10)),
+                limits: PolicyLimits {
+                    max_clients: Some(2),
+                    ..<_>::default()
+                },
+                lock: LockRules {
+                    chargebacks: Some(2),
+                    ..<_>::default()
+                },
+                withdrawals: WithdrawalRules {
+                    max_single: Some("5".parse().unwrap()),
+                    ..<_>::default()
+                },
+                ..<_>::default()
+            }
+        );
+        assert_eq!(
+            PolicySet::from_toml(&policies.to_toml().unwrap()).unwrap(),
+            policies
+        );
+        assert!(PolicySet::from_toml("max_clients = 2").is_err());
+
+        let mut states = policies.build();
+        let client = ClientId::from(1);
+        states.process(Action::Deposit {
+            client,
+            transaction: TransactionId::from(1),
+            amount: "10".parse().unwrap(),
+        });
+        assert_eq!(
+            states.process(Action::Withdrawal {
+                client,
+                transaction: TransactionId::from(2),
+                amount: "6".parse().unwrap(),
+            }),
+            Outcome::Rejected(Rejection::OverWithdrawalLimit)
+        );
+        assert_eq!(states.chargeback_policy(), policies.chargeback);
+    }
+
+    #[test]
+    fn override_single_clients() {
+        let overrides = read_client_policies_io_csv(
+            "client, suspended, max_withdrawal\n1, , 50\n2, true,\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            overrides,
+            [
+                (
+                    ClientId::from(1),
+                    ClientPolicy {
+                        max_withdrawal: Some("50".parse().unwrap()),
+                        ..<_>::default()
+                    }
+                ),
+                (
+                    ClientId::from(2),
+                    ClientPolicy {
+                        suspended: true,
+                        ..<_>::default()
+                    }
+                ),
+            ]
+        );
+        assert!(read_client_policies_io_csv("client\n1\n1\n".as_bytes()).is_err());
+
+        let policies = PolicySet::from_toml("[withdrawals]\nmax_single = \"5\"").unwrap();
+        let mut states = policies.build_with_overrides(overrides);
+        let withdrawal = |client: u16, transaction: u32| Action::Withdrawal {
+            client: ClientId::from(client),
+            transaction: TransactionId::from(transaction),
+            amount: "6".parse().unwrap(),
+        };
+        for client in [1, 2, 3] {
+            states.process(Action::Deposit {
+                client: ClientId::from(client),
+                transaction: TransactionId::from(u32::from(client)),
+                amount: "10".parse().unwrap(),
+            });
+        }
+        assert_eq!(states.process(withdrawal(1, 4)), Outcome::Applied);
+        assert_eq!(
+            states.process(withdrawal(2, 5)),
+            Outcome::Rejected(Rejection::Suspended)
+        );
+        assert_eq!(
+            states.process(withdrawal(3, 6)),
+            Outcome::Rejected(Rejection::OverWithdrawalLimit)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Writing input files that the processor accepts as intended
+//!
+//! [`TransactionWriter`] refuses actions the processor would reject because of
+//! the file itself: reused transaction ids and disputes, resolutions or chargebacks
+//! of transactions the client never made. [`parse_amount`] refuses amounts with
+//! more decimal places than the processor keeps, instead of silently truncating them.
+
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::{Action, Balance, ClientId, TransactionId};
+
+/// Decimal places kept by [`Balance`]
+const PRECISION: usize = 4;
+
+/// Parse an amount, rejecting any precision beyond what the processor keeps
+pub fn parse_amount(s: &str) -> Result<Balance> {
+    let digits = s
+        .trim()
+        .split_once('.')
+        .map_or("", |(_, fractional)| fractional);
+    ensure!(
+        digits.len() <= PRECISION,
+        "amount {s} has more than {PRECISION} decimal places"
+    );
+    s.parse().map_err(|_| anyhow!("invalid amount {s}"))
+}
+
+enum Output<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Jsonl(W),
+}
+
+/// Validating writer of actions as CSV or JSON Lines
+pub struct TransactionWriter<W: Write> {
+    output: Output<W>,
+    /// Owner of every deposit and withdrawal written so far
+    transactions: HashMap<TransactionId, ClientId>,
+}
+
+impl<W: Write> TransactionWriter<W> {
+    /// Write CSV with the header expected by the processor
+    pub fn csv(writer: W) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["type", "client", "tx", "amount"])?;
+        Ok(Self::new(Output::Csv(Box::new(writer))))
+    }
+
+    /// Write one JSON object per line
+    pub fn jsonl(writer: W) -> Self {
+        Self::new(Output::Jsonl(writer))
+    }
+
+    fn new(output: Output<W>) -> Self {
+        Self {
+            output,
+            transactions: HashMap::new(),
+        }
+    }
+
+    /// Validate and write an action
+    pub fn write(&mut self, action: &Action) -> Result<()> {
+        let client = action.client();
+        let transaction = action.transaction();
+        let tx = u32::from(transaction);
+        match action {
+            Action::Deposit { .. } | Action::Withdrawal { .. } => {
+                if self.transactions.contains_key(&transaction) {
+                    bail!("transaction {tx} is already used");
+                }
+            }
+            Action::Dispute { .. }
+            | Action::Resolve { .. }
+            | Action::Chargeback { .. }
+            | Action::Return { .. }
+            | Action::Representment { .. }
+            | Action::ChargebackReversal { .. } => match self.transactions.get(&transaction) {
+                None => bail!("{} of unknown transaction {tx}", action.type_name()),
+                Some(&owner) if owner != client => bail!(
+                    "{} of transaction {tx} by client {} which belongs to client {}",
+                    action.type_name(),
+                    u16::from(client),
+                    u16::from(owner)
+                ),
+                Some(_) => {}
+            },
+            Action::Unlock { .. }
+            | Action::CreditAdjustment { .. }
+            | Action::DebitAdjustment { .. } => {}
+        }
+        match &mut self.output {
+            Output::Csv(writer) => writer.write_record([
+                action.type_name().to_owned(),
+                u16::from(client).to_string(),
+                tx.to_string(),
+                action.amount().map_or_else(String::new, Balance::to_string),
+            ])?,
+            Output::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, action)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        if matches!(action, Action::Deposit { .. } | Action::Withdrawal { .. }) {
+            self.transactions.insert(transaction, client);
+        }
+        Ok(())
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(self) -> Result<W> {
+        Ok(match self.output {
+            Output::Csv(writer) => writer.into_inner().map_err(|e| e.into_error())?,
+            Output::Jsonl(mut writer) => {
+                writer.flush()?;
+                writer
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{summaries_from_io_csv, write_summary_io_csv};
+
+    fn actions() -> Vec<Action> {
+        let client = ClientId::from(1);
+        vec![
+            Action::Deposit {
+                client,
+                transaction: TransactionId::from(1),
+                amount: parse_amount("2.5").unwrap(),
+            },
+            Action::Withdrawal {
+                client,
+                transaction: TransactionId::from(2),
+                amount: parse_amount("1").unwrap(),
+            },
+            Action::Dispute {
+                client,
+                transaction: TransactionId::from(1),
+            },
+        ]
+    }
+
+    #[test]
+    fn write_readable_csv() {
+        let mut writer = TransactionWriter::csv(vec![]).unwrap();
+        for action in actions() {
+            writer.write(&action).unwrap();
+        }
+        let written = writer.into_inner().unwrap();
+        assert_eq!(
+            String::from_utf8(written.clone()).unwrap(),
+            "type,client,tx,amount\ndeposit,1,1,2.5000\nwithdrawal,1,2,1.0000\ndispute,1,1,\n"
+        );
+        let mut summary = vec![];
+        write_summary_io_csv(summaries_from_io_csv(&written[..]).unwrap(), &mut summary).unwrap();
+        assert_eq!(
+            String::from_utf8(summary).unwrap(),
+            "client,locked,available,held,total\n1,false,1.5000,0.0000,1.5000\n"
+        );
+    }
+
+    #[test]
+    fn write_jsonl() {
+        let mut writer = TransactionWriter::jsonl(vec![]);
+        for action in actions() {
+            writer.write(&action).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            r#"{"type":"deposit","client":1,"tx":1,"amount":"2.5000"}
+{"type":"withdrawal","client":1,"tx":2,"amount":"1.0000"}
+{"type":"dispute","client":1,"tx":1}
+"#
+        );
+    }
+
+    #[test]
+    fn reject_invalid_actions() {
+        let mut writer = TransactionWriter::jsonl(vec![]);
+        let [deposit, _, dispute] = &actions()[..] else {
+            unreachable!()
+        };
+        assert!(writer.write(dispute).is_err());
+        writer.write(deposit).unwrap();
+        assert!(writer.write(deposit).is_err());
+        assert!(writer
+            .write(&Action::Chargeback {
+                client: ClientId::from(2),
+                transaction: TransactionId::from(1),
+            })
+            .is_err());
+        writer.write(dispute).unwrap();
+        assert!(parse_amount("1.00001").is_err());
+        assert!(parse_amount("abc").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Python bindings, only available with the `pyo3` feature
+//!
+//! Built as an extension module, for example by maturin with the features
+//! `pyo3` and `pyo3/extension-module`, the `transaction_processor` module replays
+//! transactions with the same account logic as the command line:
+//!
+//! ```python
+//! from transaction_processor import AccountStates, Action
+//!
+//! states = AccountStates()
+//! states.process(Action("deposit", client=1, tx=1, amount="1.5"))
+//! states.process(Action("dispute", client=1, tx=1))
+//! states.summary()  # [{'client': 1, 'locked': False, 'available': Decimal('0.0000'), ...}]
+//! ```
+//!
+//! Amounts are taken as strings and balances given back as `decimal.Decimal`,
+//! so that none are rounded through floats.
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+use crate::{AccountStates, Action, Balance, ClientId, TransactionId};
+
+/// An action to process, as in a row of a CSV input
+#[pyclass(name = "Action", frozen)]
+#[derive(Clone)]
+pub struct PyAction(Action);
+
+#[pymethods]
+impl PyAction {
+    /// `kind` is the `type` column of CSV inputs, `amount` is required by deposits,
+    /// withdrawals and adjustments and refused by the other actions
+    #[new]
+    #[pyo3(signature = (kind, client, tx, amount = None))]
+    fn new(kind: &str, client: u16, tx: u32, amount: Option<&str>) -> PyResult<Self> {
+        let (client, transaction) = (ClientId::from(client), TransactionId::from(tx));
+        let amount = amount
+            .map(|amount| {
+                amount
+                    .parse::<Balance>()
+                    .map_err(|_| PyValueError::new_err(format!("invalid amount {amount:?}")))
+            })
+            .transpose()?;
+        let action = match (kind, amount) {
+            ("deposit", Some(amount)) => Action::Deposit {
+                client,
+                transaction,
+                amount,
+            },
+            ("withdrawal", Some(amount)) => Action::Withdrawal {
+                client,
+                transaction,
+                amount,
+            },
+            ("credit_adjustment", Some(amount)) => Action::CreditAdjustment {
+                client,
+                transaction,
+                amount,
+            },
+            ("debit_adjustment", Some(amount)) => Action::DebitAdjustment {
+                client,
+                transaction,
+                amount,
+            },
+            ("dispute", None) => Action::Dispute {
+                client,
+                transaction,
+            },
+            ("resolve", None) => Action::Resolve {
+                client,
+                transaction,
+            },
+            ("chargeback", None) => Action::Chargeback {
+                client,
+                transaction,
+            },
+            ("return", None) => Action::Return {
+                client,
+                transaction,
+            },
+            ("representment", None) => Action::Representment {
+                client,
+                transaction,
+            },
+            ("chargeback_reversal", None) => Action::ChargebackReversal {
+                client,
+                transaction,
+            },
+            ("unlock", None) => Action::Unlock {
+                client,
+                transaction,
+            },
+            (kind, amount) => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown action {kind:?} {} an amount",
+                    if amount.is_some() { "with" } else { "without" }
+                )))
+            }
+        };
+        Ok(Self(action))
+    }
+
+    fn __repr__(&self) -> String {
+        let (client, tx) = (u16::from(self.0.client()), u32::from(self.0.transaction()));
+        match self.0.amount() {
+            Some(amount) => format!(
+                "Action({:?}, client={client}, tx={tx}, amount=\"{amount}\")",
+                self.0.type_name()
+            ),
+            None => format!("Action({:?}, client={client}, tx={tx})", self.0.type_name()),
+        }
+    }
+}
+
+/// Account states of one replay
+#[pyclass(name = "AccountStates")]
+#[derive(Default)]
+pub struct PyAccountStates(AccountStates);
+
+#[pymethods]
+impl PyAccountStates {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an action, answering with the outcome as in `applied`
+    /// or `rejected: insufficient available funds`
+    fn process(&mut self, action: &PyAction) -> String {
+        self.0.process(action.0.clone()).to_string()
+    }
+
+    /// The summaries of all accounts, as dictionaries with the same keys as the CSV columns
+    fn summary<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let decimal = py.import("decimal")?.getattr("Decimal")?;
+        let summaries = PyList::empty(py);
+        for summary in self.0.summary() {
+            let dict = PyDict::new(py);
+            dict.set_item("client", u16::from(summary.client()))?;
+            dict.set_item("locked", summary.locked())?;
+            for (key, balance) in [
+                ("available", summary.available()),
+                ("held", summary.held()),
+                ("total", summary.total()),
+            ] {
+                dict.set_item(key, decimal.call1((balance.to_string(),))?)?;
+            }
+            summaries.append(dict)?;
+        }
+        Ok(summaries)
+    }
+}
+
+#[pymodule]
+fn transaction_processor(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyAction>()?;
+    module.add_class::<PyAccountStates>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "transaction_processor").unwrap();
+            transaction_processor(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("tp", module).unwrap();
+            py.run(
+                c"
+states = tp.AccountStates()
+assert states.process(tp.Action('deposit', client=1, tx=1, amount='1.5')) == 'applied'
+assert states.process(tp.Action('withdrawal', 1, 2, '2')).startswith('rejected')
+summary, = states.summary()
+assert summary['available'] == summary['total'] == __import__('decimal').Decimal('1.5')
+try:
+    tp.Action('dispute', client=1, tx=1, amount='1')
+    assert False
+except ValueError as e:
+    assert 'with an amount' in str(e)
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
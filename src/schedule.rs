@@ -0,0 +1,281 @@
+//! Processing of a directory of input files as one run, with a status per file
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::Serialize;
+
+use crate::{AccountStates, AccountSummary, Action, HeaderCache, Outcome};
+
+/// Number of rows decoded between two checks of the clock
+const ROWS_PER_CHECK: usize = 4096;
+
+/// Order in which the files of a directory are processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// By file name
+    #[default]
+    Name,
+    /// By modification time, oldest first, then by file name
+    Mtime,
+}
+
+impl FromStr for Order {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Self::Name),
+            "mtime" => Ok(Self::Mtime),
+            _ => bail!("unknown order {s:?}, expected name or mtime"),
+        }
+    }
+}
+
+/// What happened to one input file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /// All actions of the file were applied
+    Processed { rows: usize },
+    /// The file could not be read or decoded, and none of its actions were applied
+    Failed(String),
+    /// Decoding the file took longer than the timeout, and none of its actions were applied
+    TimedOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub status: FileStatus,
+    pub elapsed: Duration,
+}
+
+/// Options of a directory run
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    pub order: Order,
+    /// Time allowed to read and decode each file
+    pub timeout: Option<Duration>,
+}
+
+impl Schedule {
+    /// The `.csv` files of `dir` in processing order
+    pub fn files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_file() && path.extension().is_some_and(|ext| ext == "csv") {
+                files.push((entry.metadata()?.modified()?, path));
+            }
+        }
+        match self.order {
+            Order::Name => files.sort_by(|a, b| a.1.cmp(&b.1)),
+            Order::Mtime => files.sort(),
+        }
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Process every `.csv` file of `dir` in order into `states`
+    ///
+    /// Each file is fully decoded before any of its actions is applied,
+    /// so a file that fails or times out leaves `states` untouched and the run continues
+    /// with the next file. Reaching a configured limit still fails the whole run.
+    pub fn run(&self, dir: &Path, states: &mut AccountStates) -> Result<Vec<FileReport>> {
+        let mut cache = HeaderCache::default();
+        let mut reports = vec![];
+        for path in self.files(dir)? {
+            let start = Instant::now();
+            let status = match self.decode(&path, &mut cache, start) {
+                Ok(Some(actions)) => {
+                    let rows = actions.len();
+                    for action in actions {
+                        match states.process(action) {
+                            Outcome::Rejected(rejection) if rejection.is_limit() => {
+                                bail!("{}: {rejection}", path.display())
+                            }
+                            Outcome::Failed(error) => bail!("{}: {error}", path.display()),
+                            _ => {}
+                        }
+                    }
+                    FileStatus::Processed { rows }
+                }
+                Ok(None) => FileStatus::TimedOut,
+                Err(e) => FileStatus::Failed(format!("{e:#}")),
+            };
+            reports.push(FileReport {
+                path,
+                status,
+                elapsed: start.elapsed(),
+            });
+        }
+        Ok(reports)
+    }
+
+    /// All actions of a file, or `None` if decoding exceeded the timeout
+    fn decode(
+        &self,
+        path: &Path,
+        cache: &mut HeaderCache,
+        start: Instant,
+    ) -> Result<Option<Vec<Action>>> {
+        let reader = ReaderBuilder::new().from_reader(BufReader::new(File::open(path)?));
+        let mut actions = vec![];
+        let decoded = cache.for_each_csv_action(reader, |action| {
+            actions.push(action);
+            match self.timeout {
+                Some(timeout)
+                    if actions.len() % ROWS_PER_CHECK == 0 && start.elapsed() > timeout =>
+                {
+                    Err(anyhow!(TimedOut))
+                }
+                _ => Ok(()),
+            }
+        });
+        match decoded {
+            Ok(())
+                if self
+                    .timeout
+                    .is_some_and(|timeout| start.elapsed() > timeout) =>
+            {
+                Ok(None)
+            }
+            Ok(()) => Ok(Some(actions)),
+            Err(e) if e.is::<TimedOut>() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TimedOut;
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Compute one account summary over every `.csv` file of `dir`, with a report per file
+pub fn summaries_from_dir(
+    dir: &Path,
+    schedule: &Schedule,
+) -> Result<(Vec<AccountSummary>, Vec<FileReport>)> {
+    let mut states = AccountStates::default();
+    let reports = schedule.run(dir, &mut states)?;
+    Ok((states.summary(), reports))
+}
+
+#[derive(Serialize)]
+struct ReportRecord<'a> {
+    path: &'a str,
+    status: &'a str,
+    rows: Option<usize>,
+    elapsed_ms: u128,
+    error: Option<&'a str>,
+}
+
+/// Write the per-file status as CSV with the columns `path`, `status`, `rows`, `elapsed_ms` and `error`
+pub fn write_report_io_csv(reports: &[FileReport], writer: impl Write) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for report in reports {
+        let path = report.path.display().to_string();
+        let (status, rows, error) = match &report.status {
+            FileStatus::Processed { rows } => ("processed", Some(*rows), None),
+            FileStatus::Failed(error) => ("failed", None, Some(error.as_str())),
+            FileStatus::TimedOut => ("timed out", None, None),
+        };
+        writer.serialize(ReportRecord {
+            path: &path,
+            status,
+            rows,
+            elapsed_ms: report.elapsed.as_millis(),
+            error,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continue_past_failed_files() {
+        let dir = std::env::temp_dir().join(format!("schedule-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("1.csv"),
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("2.csv"),
+            "type, client, tx, amount\ndeposit, 1, 2, 5.0\nbogus, 1, 3, 1.0\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("3.csv"),
+            "type, client, tx, amount\nwithdrawal, 1, 4, 0.5\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not an input").unwrap();
+
+        let (summaries, reports) = summaries_from_dir(&dir, &Schedule::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].total().to_string(), "0.5000");
+        let statuses: Vec<_> = reports
+            .iter()
+            .map(|report| {
+                (
+                    report.path.file_name().unwrap().to_str().unwrap(),
+                    &report.status,
+                )
+            })
+            .collect();
+        assert!(matches!(
+            statuses[..],
+            [
+                ("1.csv", FileStatus::Processed { rows: 1 }),
+                ("2.csv", FileStatus::Failed(_)),
+                ("3.csv", FileStatus::Processed { rows: 1 }),
+            ]
+        ));
+
+        let mut output = vec![];
+        write_report_io_csv(&reports[..1], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("path,status,rows,elapsed_ms,error\n"));
+        assert!(output.contains("1.csv,processed,1,"));
+    }
+
+    #[test]
+    fn time_out_without_applying() {
+        let dir = std::env::temp_dir().join(format!("schedule-timeout-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("1.csv"),
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\n",
+        )
+        .unwrap();
+        let schedule = Schedule {
+            order: Order::Mtime,
+            timeout: Some(Duration::ZERO),
+        };
+        let (summaries, reports) = summaries_from_dir(&dir, &schedule).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(summaries.is_empty());
+        assert_eq!(reports[0].status, FileStatus::TimedOut);
+    }
+}
@@ -0,0 +1,172 @@
+//! Embedded rhai scripting hook for custom guardrails
+//!
+//! A script defines `fn check(action, account)`, which is called before each action
+//! is applied. `action` is a map with `type`, `client`, `tx` and, for deposits,
+//! withdrawals and adjustments, `amount`; `account` is a map with `client`, `available`, `held`,
+//! `total` and `locked` as of before the action. Amounts are rhai decimals.
+//!
+//! Returning `false` rejects the action, returning a string applies it with that
+//! annotation, and returning anything else applies it as usual.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use num::ToPrimitive;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use rust_decimal::Decimal;
+
+use crate::{AccountStates, Action, Balance};
+
+pub struct ScriptHook {
+    engine: Engine,
+    ast: AST,
+}
+
+/// What the script decided about an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptDecision {
+    Accepted,
+    Annotated(String),
+    Rejected,
+}
+
+fn to_decimal(balance: &Balance) -> Result<Decimal> {
+    let units = balance
+        .to_biguint()
+        .to_i128()
+        .ok_or_else(|| anyhow!("balance {balance} is too large for a script decimal"))?;
+    Decimal::try_from_i128_with_scale(units, 4)
+        .map_err(|_| anyhow!("balance {balance} is too large for a script decimal"))
+}
+
+impl ScriptHook {
+    pub fn compile(source: &str) -> Result<Self> {
+        let engine = Engine::new();
+        let ast = engine.compile(source)?;
+        Ok(Self { engine, ast })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::compile(&std::fs::read_to_string(path)?)
+    }
+
+    fn check(&self, states: &AccountStates, action: &Action) -> Result<ScriptDecision> {
+        let (kind, amount) = (action.type_name(), action.amount());
+        let client = action.client();
+        let mut action_map = Map::new();
+        action_map.insert("type".into(), kind.into());
+        action_map.insert("client".into(), i64::from(u16::from(client)).into());
+        action_map.insert(
+            "tx".into(),
+            i64::from(u32::from(action.transaction())).into(),
+        );
+        if let Some(amount) = amount {
+            action_map.insert("amount".into(), Dynamic::from_decimal(to_decimal(amount)?));
+        }
+
+        let mut account_map = Map::new();
+        let (available, held, locked) = match states.account_summary(client) {
+            Some(summary) => (
+                summary.available().clone(),
+                summary.held().clone(),
+                summary.locked(),
+            ),
+            None => (Balance::default(), Balance::default(), false),
+        };
+        account_map.insert("client".into(), i64::from(u16::from(client)).into());
+        account_map.insert(
+            "total".into(),
+            Dynamic::from_decimal(to_decimal(&(&available + &held))?),
+        );
+        account_map.insert(
+            "available".into(),
+            Dynamic::from_decimal(to_decimal(&available)?),
+        );
+        account_map.insert("held".into(), Dynamic::from_decimal(to_decimal(&held)?));
+        account_map.insert("locked".into(), locked.into());
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                "check",
+                (action_map, account_map),
+            )
+            .map_err(|e| anyhow!("script error: {e}"))?;
+        Ok(match result.as_bool() {
+            Ok(false) => ScriptDecision::Rejected,
+            _ => match result.into_immutable_string() {
+                Ok(annotation) => ScriptDecision::Annotated(annotation.into()),
+                Err(_) => ScriptDecision::Accepted,
+            },
+        })
+    }
+}
+
+/// Script-guarded processing for [`AccountStates`]
+pub trait ProcessScripted {
+    /// Apply an action unless the script hook rejects it
+    fn process_scripted(&mut self, action: Action, hook: &ScriptHook) -> Result<ScriptDecision>;
+}
+
+impl ProcessScripted for AccountStates {
+    fn process_scripted(&mut self, action: Action, hook: &ScriptHook) -> Result<ScriptDecision> {
+        let decision = hook.check(self, &action)?;
+        if decision != ScriptDecision::Rejected {
+            self.process(action);
+        }
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, TransactionId};
+
+    #[test]
+    fn reject_and_annotate() {
+        let hook = ScriptHook::compile(
+            r#"
+            fn check(action, account) {
+                if action.type == "withdrawal" && action.amount > account.available / 2 {
+                    return false;
+                }
+                if action.type == "deposit" && action.amount >= 100 {
+                    return "large deposit";
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        let mut states = AccountStates::default();
+        let deposit = |transaction, amount: &str| Action::Deposit {
+            client: ClientId::from(1),
+            transaction: TransactionId::from(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        let withdrawal = |transaction, amount: &str| Action::Withdrawal {
+            client: ClientId::from(1),
+            transaction: TransactionId::from(transaction),
+            amount: amount.parse().unwrap(),
+        };
+        assert_eq!(
+            states.process_scripted(deposit(1, "150"), &hook).unwrap(),
+            ScriptDecision::Annotated("large deposit".into())
+        );
+        assert_eq!(
+            states
+                .process_scripted(withdrawal(2, "100"), &hook)
+                .unwrap(),
+            ScriptDecision::Rejected
+        );
+        assert_eq!(
+            states
+                .process_scripted(withdrawal(3, "50.5"), &hook)
+                .unwrap(),
+            ScriptDecision::Accepted
+        );
+        assert_eq!(states.summary()[0].available().to_string(), "99.5000");
+    }
+}
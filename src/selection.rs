@@ -0,0 +1,140 @@
+//! Ordering and filtering of account summaries before they are written
+//!
+//! Summaries come ordered by client id. [`SummaryOptions::select`] reorders and filters
+//! them for any of the `write_summary_*` functions, as the command line does with
+//! `--sort`, `--locked-only` and `--clients`.
+
+use std::{borrow::Borrow, cmp::Reverse, collections::BTreeSet, str::FromStr};
+
+use anyhow::{bail, Result};
+
+use crate::{AccountSummary, ClientId};
+
+/// Order of written summaries, ties always broken by client id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryOrder {
+    /// By client id
+    #[default]
+    Client,
+    /// By total funds, largest first
+    TotalDescending,
+    /// Locked accounts first
+    LockedFirst,
+}
+
+impl FromStr for SummaryOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "client" => Ok(Self::Client),
+            "total" => Ok(Self::TotalDescending),
+            "locked" => Ok(Self::LockedFirst),
+            _ => bail!("unknown order {s:?}, expected client, total or locked"),
+        }
+    }
+}
+
+/// Which summaries are written and in which order, all of them by client id by default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SummaryOptions {
+    pub order: SummaryOrder,
+    /// Only write locked accounts
+    pub locked_only: bool,
+    /// Only write these clients, if set
+    pub clients: Option<BTreeSet<ClientId>>,
+}
+
+impl SummaryOptions {
+    /// Whether the summary of this account is written
+    pub fn selects(&self, summary: &AccountSummary) -> bool {
+        (!self.locked_only || summary.locked())
+            && self
+                .clients
+                .as_ref()
+                .is_none_or(|clients| clients.contains(&summary.client()))
+    }
+
+    /// The selected summaries in order, either borrowed or owned
+    pub fn select<T: Borrow<AccountSummary>>(
+        &self,
+        summaries: impl IntoIterator<Item = T>,
+    ) -> Vec<T> {
+        let mut selected: Vec<_> = summaries
+            .into_iter()
+            .filter(|summary| self.selects(summary.borrow()))
+            .collect();
+        match self.order {
+            SummaryOrder::Client => selected.sort_by_key(|summary| summary.borrow().client()),
+            SummaryOrder::TotalDescending => selected.sort_by(|a, b| {
+                let (a, b) = (a.borrow(), b.borrow());
+                b.total().cmp(a.total()).then(a.client().cmp(&b.client()))
+            }),
+            SummaryOrder::LockedFirst => selected.sort_by_key(|summary| {
+                let summary = summary.borrow();
+                (Reverse(summary.locked()), summary.client())
+            }),
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccountStates, Action, TransactionId};
+
+    #[test]
+    fn order_and_filter() {
+        let mut states = AccountStates::default();
+        for (client, amount) in [(1, "2"), (2, "7"), (3, "7"), (4, "1")] {
+            states.process(Action::Deposit {
+                client: ClientId::from(client),
+                transaction: TransactionId::from(u32::from(client)),
+                amount: amount.parse().unwrap(),
+            });
+        }
+        for action in [
+            Action::Dispute {
+                client: ClientId::from(4),
+                transaction: TransactionId::from(4),
+            },
+            Action::Chargeback {
+                client: ClientId::from(4),
+                transaction: TransactionId::from(4),
+            },
+        ] {
+            states.process(action);
+        }
+        let summaries = states.summary();
+        let clients = |options: SummaryOptions| -> Vec<u16> {
+            options
+                .select(&summaries)
+                .into_iter()
+                .map(|summary| summary.client().into())
+                .collect()
+        };
+        let order = |order: &str| SummaryOptions {
+            order: order.parse().unwrap(),
+            ..<_>::default()
+        };
+        assert_eq!(clients(order("client")), [1, 2, 3, 4]);
+        assert_eq!(clients(order("total")), [2, 3, 1, 4]);
+        assert_eq!(clients(order("locked")), [4, 1, 2, 3]);
+        assert_eq!(
+            clients(SummaryOptions {
+                locked_only: true,
+                ..<_>::default()
+            }),
+            [4]
+        );
+        assert_eq!(
+            clients(SummaryOptions {
+                order: SummaryOrder::TotalDescending,
+                clients: Some([3, 1].map(ClientId::from).into()),
+                ..<_>::default()
+            }),
+            [3, 1]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Per-client account statements for customer support and audits
+//!
+//! A statement lists every action against a client in input order, with its outcome
+//! and the balances it left behind, so that the summary of a run can be explained
+//! line by line. The fees and interest posted by [`AccountStates::end_of_day`]
+//! are written as a ledger next to it.
+
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use anyhow::Result;
+use csv::{Reader, WriterBuilder};
+use serde::Serialize;
+
+use crate::{
+    for_each_csv_action, period::LedgerEntry, AccountStates, Balance, ClientId, TransactionId,
+};
+
+#[derive(Serialize)]
+struct StatementRecord {
+    client: ClientId,
+    tx: TransactionId,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    amount: Option<Balance>,
+    outcome: String,
+    available: Balance,
+    held: Balance,
+    locked: bool,
+}
+
+/// Replay a CSV input and write the statement of `client`, or of every client if `None`,
+/// as CSV with the columns `client`, `tx`, `type`, `amount`, `outcome`, `available`,
+/// `held` and `locked`
+///
+/// Statements are ordered by client, and the lines of each client in input order.
+/// Balances and the lock flag are those right after the action, rejected actions included.
+pub fn write_statement_io_csv<R: Read>(
+    reader: Reader<R>,
+    client: Option<ClientId>,
+    writer: impl Write,
+) -> Result<()> {
+    let mut states = AccountStates::default();
+    let mut statements: BTreeMap<ClientId, Vec<StatementRecord>> = BTreeMap::new();
+    for_each_csv_action(reader, |action| {
+        let id = action.client();
+        if client.is_some_and(|client| client != id) {
+            states.process(action);
+            return Ok(());
+        }
+        let (tx, kind, amount) = (
+            action.transaction(),
+            action.type_name(),
+            action.amount().cloned(),
+        );
+        let outcome = states.process(action).to_string();
+        let account = states
+            .account(id)
+            .expect("an account exists once an action was processed");
+        statements.entry(id).or_default().push(StatementRecord {
+            client: id,
+            tx,
+            kind,
+            amount,
+            outcome,
+            available: account.available().clone(),
+            held: account.held().clone(),
+            locked: account.locked(),
+        });
+        Ok(())
+    })?;
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for record in statements.values().flatten() {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write ledger entries as CSV with the columns `date`, `client`, `tx`, `kind` and `amount`,
+/// `tx` being empty for interest
+pub fn write_ledger_io_csv(
+    entries: impl IntoIterator<Item = impl Borrow<LedgerEntry>>,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for entry in entries {
+        writer.serialize(entry.borrow())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 2, 1, 3.0
+deposit, 1, 2, 1.0
+withdrawal, 1, 3, 5.0
+dispute, 2, 1,
+dispute, 1, 2,
+chargeback, 1, 2,
+"#;
+
+    #[test]
+    fn write_statements() {
+        let mut output = vec![];
+        write_statement_io_csv(
+            Reader::from_reader(TRANSACTION_CSV.as_bytes()),
+            None,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,type,amount,outcome,available,held,locked
+1,2,deposit,1.0000,applied,1.0000,0.0000,false
+1,3,withdrawal,5.0000,rejected: insufficient available funds,1.0000,0.0000,false
+1,2,dispute,,applied,0.0000,1.0000,false
+1,2,chargeback,,applied,0.0000,0.0000,true
+2,1,deposit,3.0000,applied,3.0000,0.0000,false
+2,1,dispute,,applied,0.0000,3.0000,false
+"
+        );
+
+        let mut output = vec![];
+        write_statement_io_csv(
+            Reader::from_reader(TRANSACTION_CSV.as_bytes()),
+            Some(ClientId::from(2)),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn write_ledger() {
+        let mut states = AccountStates::builder().interest(365).build();
+        states.process(crate::Action::Deposit {
+            client: ClientId::from(1),
+            transaction: TransactionId::from(1),
+            amount: "100".parse().unwrap(),
+        });
+        let mut output = vec![];
+        write_ledger_io_csv(states.end_of_day("2026-10-16"), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "date,client,tx,kind,amount\n2026-10-16,1,,interest,0.0100\n"
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! Synchronization primitives of the state shared between threads, from loom under `--cfg loom`
+//!
+//! Built that way, the tests named `loom` check the shard queues of [`crate::parallel`]
+//! and the locking of `http::Service` under every schedule loom tells apart:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --features http --lib loom
+//! ```
+//!
+//! Loom primitives panic outside of `loom::model`, so such a build is only good for those tests.
+
+#[cfg(all(loom, feature = "http"))]
+pub(crate) use loom::sync::RwLock;
+#[cfg(loom)]
+pub(crate) use loom::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(all(not(loom), feature = "http"))]
+pub(crate) use std::sync::RwLock;
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Condvar, Mutex, MutexGuard};
@@ -0,0 +1,6 @@
+//! Helpers for pinning the behaviour of the engine in downstream test suites
+
+pub mod golden;
+pub mod invariants;
+#[cfg(feature = "proptest")]
+pub mod strategies;
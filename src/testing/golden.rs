@@ -0,0 +1,154 @@
+//! Golden-file snapshots of the whole pipeline
+//!
+//! [`check`] runs a CSV fixture through the engine and compares the summary CSV
+//! and the JSON Lines change stream, which doubles as an audit trail of every
+//! balance change, against checked-in files. On a mismatch the error shows a
+//! line diff of the expected and actual output.
+//!
+//! Setting the `UPDATE_GOLDEN` environment variable to anything but `0`
+//! rewrites the golden files from the actual output instead of comparing.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use csv::ReaderBuilder;
+
+use crate::{
+    cdc::{JsonlChangeSink, ProcessCsvWithChanges},
+    write_summary_io_csv, AccountStates,
+};
+
+/// Output of the pipeline for one input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Summary CSV, as written by the command line tool
+    pub summary: String,
+    /// Every balance change as JSON Lines
+    pub changes: String,
+}
+
+/// Run a CSV input through the engine, capturing its summary and change stream
+pub fn render(input: &[u8]) -> Result<Snapshot> {
+    let mut states = AccountStates::default();
+    let mut sink = JsonlChangeSink::new(vec![]);
+    states.process_csv_with_changes(ReaderBuilder::new().from_reader(input), &mut sink)?;
+    let mut summary = vec![];
+    write_summary_io_csv(states.summary_iter(), &mut summary)?;
+    Ok(Snapshot {
+        summary: String::from_utf8(summary)?,
+        changes: String::from_utf8(sink.into_inner())?,
+    })
+}
+
+/// Compare the pipeline output for the `input` fixture with the `summary` and `changes` golden files
+pub fn check(
+    input: impl AsRef<Path>,
+    summary: impl AsRef<Path>,
+    changes: impl AsRef<Path>,
+) -> Result<()> {
+    let input = input.as_ref();
+    let actual = render(
+        &fs::read(input).with_context(|| format!("cannot read fixture {}", input.display()))?,
+    )?;
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some_and(|v| v != "0");
+    let mut report = String::new();
+    for (path, actual) in [
+        (summary.as_ref(), &actual.summary),
+        (changes.as_ref(), &actual.changes),
+    ] {
+        if update {
+            fs::write(path, actual)
+                .with_context(|| format!("cannot write golden file {}", path.display()))?;
+            continue;
+        }
+        let expected = fs::read_to_string(path)
+            .with_context(|| format!("cannot read golden file {}", path.display()))?;
+        if expected != *actual {
+            writeln!(report, "--- {}\n+++ actual", path.display())?;
+            report.push_str(&diff(&expected, actual));
+        }
+    }
+    if !report.is_empty() {
+        bail!(
+            "output of {} differs from the golden files, rerun with UPDATE_GOLDEN=1 to accept it\n{report}",
+            input.display()
+        );
+    }
+    Ok(())
+}
+
+/// Line diff of two texts, with removed lines prefixed by `-` and added lines by `+`
+///
+/// Based on the longest common subsequence of lines, which is plenty for fixture-sized files.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of expected[i..] and actual[j..]
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            out.push_str("  ");
+            out.push_str(expected[i]);
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            out.push_str("- ");
+            out.push_str(expected[i]);
+            i += 1;
+        } else {
+            out.push_str("+ ");
+            out.push_str(actual[j]);
+            j += 1;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+dispute, 1, 1,
+"#;
+
+    #[test]
+    fn pass_and_report_mismatch() {
+        let dir = std::env::temp_dir().join(format!("golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+        let summary = dir.join("summary.csv");
+        let changes = dir.join("changes.jsonl");
+        fs::write(&input, TRANSACTION_CSV).unwrap();
+        let snapshot = render(TRANSACTION_CSV.as_bytes()).unwrap();
+        fs::write(&summary, &snapshot.summary).unwrap();
+        fs::write(&changes, &snapshot.changes).unwrap();
+        check(&input, &summary, &changes).unwrap();
+
+        fs::write(&summary, snapshot.summary.replace("2.0000", "3.0000")).unwrap();
+        let report = check(&input, &summary, &changes).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(report.contains("- 2,false,3.0000,0.0000,3.0000\n+ 2,false,2.0000,0.0000,2.0000\n"));
+        assert!(!report.contains("changes.jsonl"));
+    }
+
+    #[test]
+    fn diff_lines() {
+        assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+    }
+}
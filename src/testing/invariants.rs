@@ -0,0 +1,32 @@
+//! Invariants every state built by the engine upholds, for use in property tests
+
+use anyhow::{ensure, Result};
+
+use crate::AccountStates;
+
+/// Check that every account balances and that the engine invariants hold
+///
+/// - the total of every summary is its available plus its held funds
+/// - the held funds cover the open disputes, so they never go negative on a resolution,
+///   see [`AccountStates::verify`]
+pub fn check(states: &AccountStates) -> Result<()> {
+    for summary in states.summary_iter() {
+        ensure!(
+            *summary.total() == summary.available() + summary.held(),
+            "total of client {} is {} but available and held add up to {}",
+            u16::from(summary.client()),
+            summary.total(),
+            summary.available() + summary.held()
+        );
+    }
+    states.verify()?;
+    Ok(())
+}
+
+/// Like [`check`], but panics with the broken invariant
+#[track_caller]
+pub fn assert_holds(states: &AccountStates) {
+    if let Err(e) = check(states) {
+        panic!("invariant broken: {e}")
+    }
+}
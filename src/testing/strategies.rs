@@ -0,0 +1,151 @@
+//! [`proptest`] strategies for realistic action streams, only available with the `proptest` feature
+//!
+//! Streams are generated as abstract steps that refer to earlier transactions by index,
+//! then resolved against the transactions generated so far. Disputes, resolutions and
+//! chargebacks therefore mostly hit real deposits and withdrawals of the same client,
+//! some deposits reuse an earlier id, and the few clients keep receiving actions
+//! after a chargeback locked them. Shrinking removes steps, keeping the rest meaningful.
+//!
+//! ```
+//! use proptest::prelude::*;
+//! use transaction_processor::{testing::{invariants, strategies}, AccountStates};
+//!
+//! proptest!(|(actions in strategies::actions(4, 0..100))| {
+//!     let mut states = AccountStates::default();
+//!     for action in actions {
+//!         states.process(action);
+//!         invariants::assert_holds(&states);
+//!     }
+//! });
+//! ```
+
+use proptest::{collection::SizeRange, prelude::*};
+
+use crate::{Action, Balance, ClientId, TransactionId};
+
+/// Amounts from 0.0001 up to 1000, with four decimal places
+pub fn amount() -> impl Strategy<Value = Balance> {
+    (1..=1000_0000u64).prop_map(|units| {
+        format!("{}.{:04}", units / 1_0000, units % 1_0000)
+            .parse()
+            .expect("four decimal places always parse")
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Deposit(Balance),
+    Withdrawal(Balance),
+    /// A deposit reusing the id of an earlier transaction of any client
+    DuplicateDeposit(prop::sample::Index, Balance),
+    /// An action against an earlier transaction of the client, if it has any
+    Dispute(prop::sample::Index),
+    Resolve(prop::sample::Index),
+    Chargeback(prop::sample::Index),
+    Return(prop::sample::Index),
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    let index = any::<prop::sample::Index>;
+    prop_oneof![
+        4 => amount().prop_map(Step::Deposit),
+        3 => amount().prop_map(Step::Withdrawal),
+        1 => (index(), amount()).prop_map(|(index, amount)| Step::DuplicateDeposit(index, amount)),
+        2 => index().prop_map(Step::Dispute),
+        1 => index().prop_map(Step::Resolve),
+        1 => index().prop_map(Step::Chargeback),
+        1 => index().prop_map(Step::Return),
+    ]
+}
+
+/// Streams of `len` actions against clients `1..=clients`
+///
+/// Transaction ids count up from 1. A dispute, resolution, chargeback or return
+/// of a client without transactions refers to an unknown id.
+pub fn actions(clients: u16, len: impl Into<SizeRange>) -> impl Strategy<Value = Vec<Action>> {
+    let clients = clients.max(1);
+    prop::collection::vec((1..=clients, step()), len).prop_map(|steps| {
+        let mut transactions: Vec<(ClientId, TransactionId)> = vec![];
+        let mut next = 0u32;
+        let mut fresh = || {
+            next += 1;
+            TransactionId::from(next)
+        };
+        let mut actions = Vec::with_capacity(steps.len());
+        for (client, step) in steps {
+            let client = ClientId::from(client);
+            let own: Vec<_> = transactions
+                .iter()
+                .filter(|(owner, _)| *owner == client)
+                .map(|&(_, transaction)| transaction)
+                .collect();
+            let earlier = |index: prop::sample::Index| match &own[..] {
+                [] => TransactionId::from(u32::MAX),
+                own => *index.get(own),
+            };
+            actions.push(match step {
+                Step::Deposit(amount) => {
+                    let transaction = fresh();
+                    transactions.push((client, transaction));
+                    Action::Deposit {
+                        client,
+                        transaction,
+                        amount,
+                    }
+                }
+                Step::Withdrawal(amount) => {
+                    let transaction = fresh();
+                    transactions.push((client, transaction));
+                    Action::Withdrawal {
+                        client,
+                        transaction,
+                        amount,
+                    }
+                }
+                Step::DuplicateDeposit(index, amount) => Action::Deposit {
+                    client,
+                    transaction: match &transactions[..] {
+                        [] => fresh(),
+                        transactions => index.get(transactions).1,
+                    },
+                    amount,
+                },
+                Step::Dispute(index) => Action::Dispute {
+                    client,
+                    transaction: earlier(index),
+                },
+                Step::Resolve(index) => Action::Resolve {
+                    client,
+                    transaction: earlier(index),
+                },
+                Step::Chargeback(index) => Action::Chargeback {
+                    client,
+                    transaction: earlier(index),
+                },
+                Step::Return(index) => Action::Return {
+                    client,
+                    transaction: earlier(index),
+                },
+            });
+        }
+        actions
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::invariants, AccountStates};
+
+    proptest! {
+        #[test]
+        fn invariants_hold(actions in actions(3, 0..200)) {
+            let mut states = AccountStates::default();
+            for action in actions {
+                states.process(action);
+                let checked = invariants::check(&states);
+                prop_assert!(checked.is_ok(), "{:?}", checked);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Step-by-step history of a single client for support investigations
+
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use csv::Reader;
+
+use crate::{for_each_csv_action, AccountStates, AccountSummary, Balance, ClientId};
+
+/// Replay a CSV input and describe every action against `client`
+///
+/// Each line shows the action, the decision of the engine,
+/// and the available and held funds and the lock flag before and after the action.
+pub fn trace_client<R: Read>(
+    reader: Reader<R>,
+    client: ClientId,
+    mut writer: impl Write,
+) -> Result<()> {
+    let mut states = AccountStates::default();
+    for_each_csv_action(reader, |action| {
+        if action.client() != client {
+            states.process(action);
+            return Ok(());
+        }
+        let before = balances(states.account_summary(client));
+        let line = match action.amount() {
+            Some(amount) => format!(
+                "{} tx {} amount {amount}",
+                action.type_name(),
+                u32::from(action.transaction())
+            ),
+            None => format!(
+                "{} tx {}",
+                action.type_name(),
+                u32::from(action.transaction())
+            ),
+        };
+        let outcome = states.process(action);
+        let after = balances(states.account_summary(client));
+        writeln!(
+            writer,
+            "{line}: {outcome}; available {} -> {}, held {} -> {}, locked {} -> {}",
+            before.0, after.0, before.1, after.1, before.2, after.2
+        )?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn balances(summary: Option<AccountSummary>) -> (Balance, Balance, bool) {
+    match summary {
+        Some(summary) => (
+            summary.available().clone(),
+            summary.held().clone(),
+            summary.locked(),
+        ),
+        None => (Balance::default(), Balance::default(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTION_CSV: &str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+withdrawal, 1, 3, 5.0
+dispute, 1, 1,
+chargeback, 1, 1,
+"#;
+
+    #[test]
+    fn trace_one_client() {
+        let mut output = vec![];
+        trace_client(
+            Reader::from_reader(TRANSACTION_CSV.as_bytes()),
+            ClientId::from(1),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "deposit tx 1 amount 1.0000: applied; available 0.0000 -> 1.0000, held 0.0000 -> 0.0000, locked false -> false
+withdrawal tx 3 amount 5.0000: rejected: insufficient available funds; available 1.0000 -> 1.0000, held 0.0000 -> 0.0000, locked false -> false
+dispute tx 1: applied; available 1.0000 -> 0.0000, held 0.0000 -> 1.0000, locked false -> false
+chargeback tx 1: applied; available 0.0000 -> 0.0000, held 1.0000 -> 0.0000, locked false -> true
+"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-client changes across a series of summary files
+//!
+//! Summaries only carry balances and lock flags, so the trend covers the change of
+//! the total and held funds and newly locked accounts between consecutive summaries.
+
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use crate::{AccountSummary, Balance, ClientId};
+
+/// Change of one client between two consecutive summaries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrendRow {
+    pub from: String,
+    pub to: String,
+    pub client: ClientId,
+    pub total: Balance,
+    /// Change of the total funds, with an explicit sign
+    pub total_change: String,
+    /// Change of the held funds, with an explicit sign
+    pub held_change: String,
+    pub newly_locked: bool,
+}
+
+/// Changes of every client between each pair of consecutive labeled summaries,
+/// ordered by pair and then by client
+///
+/// A client missing from the earlier summary of a pair starts from zero balances.
+pub fn trend(snapshots: &[(String, Vec<AccountSummary>)]) -> Vec<TrendRow> {
+    let mut rows = vec![];
+    for pair in snapshots.windows(2) {
+        let [(from, before), (to, after)] = pair else {
+            unreachable!("windows of two")
+        };
+        let before: BTreeMap<_, _> = before.iter().map(|s| (s.client(), s)).collect();
+        for summary in after {
+            let zero = Balance::default();
+            let (total, held, locked) = match before.get(&summary.client()) {
+                Some(before) => (before.total(), before.held(), before.locked()),
+                None => (&zero, &zero, false),
+            };
+            rows.push(TrendRow {
+                from: from.clone(),
+                to: to.clone(),
+                client: summary.client(),
+                total: summary.total().clone(),
+                total_change: signed_change(total, summary.total()),
+                held_change: signed_change(held, summary.held()),
+                newly_locked: summary.locked() && !locked,
+            });
+        }
+    }
+    rows
+}
+
+pub(crate) fn signed_change(before: &Balance, after: &Balance) -> String {
+    let sign = if after < before { '-' } else { '+' };
+    format!("{sign}{}", after.abs_diff(before))
+}
+
+pub fn write_trend_io_csv(rows: &[TrendRow], writer: impl Write) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_summary_io_csv;
+
+    #[test]
+    fn report_changes() {
+        let day = |label: &str, csv: &str| {
+            (
+                label.to_owned(),
+                read_summary_io_csv(csv.as_bytes()).unwrap(),
+            )
+        };
+        let snapshots = [
+            day(
+                "mon",
+                "client,locked,available,held,total\n1,false,5,0,5\n2,false,1,1,2\n",
+            ),
+            day(
+                "tue",
+                "client,locked,available,held,total\n1,false,7.5,0,7.5\n2,true,0,0,0\n3,false,1,0,1\n",
+            ),
+        ];
+        let mut output = vec![];
+        write_trend_io_csv(&trend(&snapshots), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "from,to,client,total,total_change,held_change,newly_locked
+mon,tue,1,7.5000,+2.5000,+0.0000,false
+mon,tue,2,0.0000,-2.0000,-1.0000,true
+mon,tue,3,1.0000,+1.0000,+0.0000,false
+"
+        );
+    }
+}
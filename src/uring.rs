@@ -0,0 +1,145 @@
+//! Double-buffered file reader on top of io_uring
+//!
+//! While the CSV parser consumes one buffer, the kernel is already filling the other,
+//! so parsing and disk reads overlap.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::unix::io::AsRawFd,
+};
+
+use io_uring::{opcode, types, IoUring};
+
+const BUFFER_SIZE: usize = 1 << 18;
+
+pub struct UringReader {
+    ring: IoUring,
+    file: File,
+    buffers: [Vec<u8>; 2],
+    /// Index of the buffer being consumed; the other one is the target of the read in flight
+    current: usize,
+    pos: usize,
+    filled: usize,
+    /// File offset of the next read to submit
+    offset: u64,
+    in_flight: bool,
+    eof: bool,
+}
+
+impl UringReader {
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut reader = Self {
+            ring: IoUring::new(2)?,
+            file,
+            buffers: [vec![0; BUFFER_SIZE], vec![0; BUFFER_SIZE]],
+            current: 0,
+            pos: 0,
+            filled: 0,
+            offset: 0,
+            in_flight: false,
+            eof: false,
+        };
+        reader.submit()?;
+        Ok(reader)
+    }
+
+    /// Start reading the next chunk of the file into the buffer not being consumed
+    fn submit(&mut self) -> io::Result<()> {
+        let buffer = &mut self.buffers[1 - self.current];
+        let read = opcode::Read::new(
+            types::Fd(self.file.as_raw_fd()),
+            buffer.as_mut_ptr(),
+            buffer.len() as u32,
+        )
+        .offset(self.offset)
+        .build();
+        // SAFETY: the buffer is neither touched nor freed until the read completes,
+        // since `wait` is the only way to clear `in_flight` and `Drop` waits for it.
+        unsafe {
+            self.ring
+                .submission()
+                .push(&read)
+                .map_err(|_| io::Error::other("submission queue is full"))?;
+        }
+        self.ring.submit()?;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Wait for the read in flight and return the number of bytes read
+    fn wait(&mut self) -> io::Result<usize> {
+        loop {
+            if let Some(completion) = self.ring.completion().next() {
+                self.in_flight = false;
+                let result = completion.result();
+                return if result < 0 {
+                    Err(io::Error::from_raw_os_error(-result))
+                } else {
+                    Ok(result as usize)
+                };
+            }
+            match self.ring.submit_and_wait(1) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => {
+                    result?;
+                }
+            }
+        }
+    }
+}
+
+impl Read for UringReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.filled {
+            if self.eof {
+                return Ok(0);
+            }
+            let read = self.wait()?;
+            if read == 0 {
+                self.eof = true;
+                return Ok(0);
+            }
+            self.current = 1 - self.current;
+            self.pos = 0;
+            self.filled = read;
+            self.offset += read as u64;
+            self.submit()?;
+        }
+        let available = &self.buffers[self.current][self.pos..self.filled];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Drop for UringReader {
+    fn drop(&mut self) {
+        if self.in_flight {
+            let _ = self.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn read_across_buffers() {
+        let path = std::env::temp_dir().join(format!("uring-reader-{}", std::process::id()));
+        let content: Vec<u8> = (0..BUFFER_SIZE * 5 / 2).map(|i| i as u8).collect();
+        File::create(&path).unwrap().write_all(&content).unwrap();
+
+        let mut read = vec![];
+        UringReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, content);
+    }
+}
@@ -0,0 +1,394 @@
+//! Write-ahead log of actions, for services that must survive a crash
+//!
+//! Every action is appended to the log, and flushed, before it is applied,
+//! so replaying the log from the start into a state configured like the original,
+//! with the same limits, policies, fees and interest, rebuilds the same state,
+//! including rejections and period counters. Entries are JSON Lines in the format of
+//! [`crate::jsonl`], with an extra `timestamp` field for actions processed with one.
+//!
+//! Changes that are not actions, namely the end of day, archival, period closes and
+//! seeded disputes, are logged as entries with an `operation` field when they are made
+//! through [`ProcessLogged`]. Made directly on the state, they are lost on recovery.
+//!
+//! With the `chaos` feature, a log given [`Faults`](crate::chaos::Faults) can be slowed down
+//! and made to fail an action, see [`WriteAheadLog::with_faults`].
+//!
+//! A crash in the middle of an append leaves an incomplete last line,
+//! which recovery ignores and [`WriteAheadLog::open`] cuts off. An append that fails
+//! without a crash cuts off its own partial line.
+
+#[cfg(feature = "chaos")]
+use std::sync::Arc;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[cfg(feature = "chaos")]
+use crate::chaos::Faults;
+use crate::{
+    period::{LedgerEntry, PeriodReport},
+    seed::OpenDispute,
+    AccountStates, Action, Outcome, Timestamp,
+};
+
+/// Size of the blocks read from the end of the log to find its last complete entry
+const TAIL_BLOCK: usize = 4096;
+
+/// Append-only log of the actions of one [`AccountStates`]
+pub struct WriteAheadLog {
+    file: File,
+    /// Length of the log up to the end of its last complete entry
+    len: u64,
+    /// Whether every append also waits for the disk, see [`WriteAheadLog::synced`]
+    sync: bool,
+    #[cfg(feature = "chaos")]
+    faults: Option<Arc<Faults>>,
+}
+
+impl WriteAheadLog {
+    /// Open or create the log at `path` for appending, dropping an incomplete last entry
+    ///
+    /// Entries are flushed to the operating system as they are appended,
+    /// which survives a crash of the process but not of the machine.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("cannot open write-ahead log {}", path.display()))?;
+        let len = file.seek(SeekFrom::End(0))?;
+        // Only the tail after the last newline is read, block by block from the end
+        let (mut complete, mut end) = (0, len);
+        let mut block = [0; TAIL_BLOCK];
+        while end > 0 {
+            let start = end.saturating_sub(TAIL_BLOCK as u64);
+            let block = &mut block[..(end - start) as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(block)?;
+            if let Some(newline) = block.iter().rposition(|&byte| byte == b'\n') {
+                complete = start + newline as u64 + 1;
+                break;
+            }
+            end = start;
+        }
+        if complete < len {
+            file.set_len(complete)?;
+        }
+        Ok(Self {
+            file,
+            len: complete,
+            sync: false,
+            #[cfg(feature = "chaos")]
+            faults: None,
+        })
+    }
+
+    /// Also wait for every appended entry to reach the disk, which survives power loss
+    /// at the cost of a sync per action
+    pub fn synced(mut self) -> Self {
+        self.sync = true;
+        self
+    }
+
+    /// Delay every append and fail an action as set by `faults`
+    #[cfg(feature = "chaos")]
+    pub fn with_faults(mut self, faults: Arc<Faults>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Append an action that is about to be applied
+    pub fn append(&mut self, action: &Action, timestamp: Option<Timestamp>) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        if let Some(faults) = &self.faults {
+            faults.count_action()?;
+        }
+        let mut entry = serde_json::to_value(action)?;
+        if let (Some(timestamp), Value::Object(fields)) = (timestamp, &mut entry) {
+            fields.insert("timestamp".to_owned(), u64::from(timestamp).into());
+        }
+        self.append_entry(&entry)
+    }
+
+    fn append_entry(&mut self, entry: &impl Serialize) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        if let Some(faults) = &self.faults {
+            faults.delay_sink();
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if let Err(e) = self.write_line(&line) {
+            // Cut off whatever part of the entry was written, so the next one starts a line
+            self.file
+                .set_len(self.len)
+                .context("cannot drop a partially appended entry")?;
+            return Err(e.into());
+        }
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(line)?;
+        if self.sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// A change of state that is not an action, logged before it is made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+enum Operation {
+    EndOfDay { date: String },
+    ArchiveLocked,
+    ClosePeriod { label: String },
+    SeedDispute(OpenDispute),
+}
+
+/// An entry of the log
+enum Entry {
+    Action(Action, Option<Timestamp>),
+    Operation(Operation),
+}
+
+/// Logged processing and crash recovery for [`AccountStates`]
+pub trait ProcessLogged: Sized {
+    /// Append an action to `log`, then apply it
+    ///
+    /// Fails without applying the action if it could not be logged.
+    fn process_logged(
+        &mut self,
+        action: Action,
+        timestamp: Option<Timestamp>,
+        log: &mut WriteAheadLog,
+    ) -> Result<Outcome>;
+
+    /// [`AccountStates::end_of_day`], logged first
+    fn end_of_day_logged(
+        &mut self,
+        date: &str,
+        log: &mut WriteAheadLog,
+    ) -> Result<Vec<LedgerEntry>>;
+
+    /// [`AccountStates::archive_locked`], logged first
+    fn archive_locked_logged(&mut self, log: &mut WriteAheadLog) -> Result<usize>;
+
+    /// [`AccountStates::close_period`], logged first
+    fn close_period_logged(&mut self, label: &str, log: &mut WriteAheadLog)
+        -> Result<PeriodReport>;
+
+    /// [`AccountStates::seed_disputes`], logging every dispute before it is seeded
+    ///
+    /// A dispute that cannot be seeded stays in the log, and is skipped again on replay.
+    fn seed_disputes_logged(
+        &mut self,
+        disputes: impl IntoIterator<Item = OpenDispute>,
+        log: &mut WriteAheadLog,
+    ) -> Result<()>;
+
+    /// Apply every complete entry of the log at `path`, returning how many were applied
+    ///
+    /// A missing log is empty. Fails at the first malformed entry, reporting its line number.
+    fn replay_log(&mut self, path: impl AsRef<Path>) -> Result<usize>;
+
+    /// Rebuild a state from the log at `path`, starting from `states`
+    ///
+    /// `states` should be freshly built with the configuration of the logged state,
+    /// such as from the same [`AccountStatesBuilder`](crate::AccountStatesBuilder),
+    /// as the log only holds what was processed.
+    fn recover(states: Self, path: impl AsRef<Path>) -> Result<Self>;
+}
+
+impl ProcessLogged for AccountStates {
+    fn process_logged(
+        &mut self,
+        action: Action,
+        timestamp: Option<Timestamp>,
+        log: &mut WriteAheadLog,
+    ) -> Result<Outcome> {
+        log.append(&action, timestamp)?;
+        Ok(self.process_at(action, timestamp))
+    }
+
+    fn end_of_day_logged(
+        &mut self,
+        date: &str,
+        log: &mut WriteAheadLog,
+    ) -> Result<Vec<LedgerEntry>> {
+        log.append_entry(&Operation::EndOfDay { date: date.into() })?;
+        Ok(self.end_of_day(date))
+    }
+
+    fn archive_locked_logged(&mut self, log: &mut WriteAheadLog) -> Result<usize> {
+        log.append_entry(&Operation::ArchiveLocked)?;
+        Ok(self.archive_locked())
+    }
+
+    fn close_period_logged(
+        &mut self,
+        label: &str,
+        log: &mut WriteAheadLog,
+    ) -> Result<PeriodReport> {
+        log.append_entry(&Operation::ClosePeriod {
+            label: label.into(),
+        })?;
+        Ok(self.close_period(label))
+    }
+
+    fn seed_disputes_logged(
+        &mut self,
+        disputes: impl IntoIterator<Item = OpenDispute>,
+        log: &mut WriteAheadLog,
+    ) -> Result<()> {
+        for dispute in disputes {
+            log.append_entry(&Operation::SeedDispute(dispute.clone()))?;
+            self.seed_disputes([dispute])?;
+        }
+        Ok(())
+    }
+
+    fn replay_log(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("cannot open write-ahead log {}", path.display()))
+            }
+        };
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let mut replayed = 0;
+        while reader.read_line(&mut line)? > 0 {
+            // An entry without its newline was cut short by a crash before it was applied
+            if !line.ends_with('\n') {
+                break;
+            }
+            match parse_entry(&line).with_context(|| format!("line {}", replayed + 1))? {
+                Entry::Action(action, timestamp) => {
+                    self.process_at(action, timestamp);
+                }
+                Entry::Operation(Operation::EndOfDay { date }) => {
+                    self.end_of_day(date);
+                }
+                Entry::Operation(Operation::ArchiveLocked) => {
+                    self.archive_locked();
+                }
+                Entry::Operation(Operation::ClosePeriod { label }) => {
+                    self.close_period(label);
+                }
+                // A dispute that failed to seed failed before it changed anything
+                Entry::Operation(Operation::SeedDispute(dispute)) => {
+                    let _ = self.seed_disputes([dispute]);
+                }
+            }
+            replayed += 1;
+            line.clear();
+        }
+        Ok(replayed)
+    }
+
+    fn recover(mut states: Self, path: impl AsRef<Path>) -> Result<Self> {
+        states.replay_log(path)?;
+        Ok(states)
+    }
+}
+
+fn parse_entry(line: &str) -> Result<Entry> {
+    let mut fields: Map<String, Value> = serde_json::from_str(line)?;
+    if fields.contains_key("operation") {
+        return Ok(Entry::Operation(Operation::deserialize(Value::Object(
+            fields,
+        ))?));
+    }
+    let timestamp = fields
+        .remove("timestamp")
+        .map(serde_json::from_value::<u64>)
+        .transpose()?;
+    let action = Action::deserialize(Value::Object(fields))?;
+    Ok(Entry::Action(action, timestamp.map(Timestamp::from)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, TransactionId};
+
+    #[test]
+    fn recover_after_crash() {
+        let path = std::env::temp_dir().join(format!("wal-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let client = ClientId::from(1);
+        let deposit = |transaction: u32, amount: &str| Action::Deposit {
+            client,
+            transaction: TransactionId::from(transaction),
+            amount: amount.parse().unwrap(),
+        };
+
+        // Interest and a transaction limit are not in the log, only in the configuration
+        let configured = || {
+            AccountStates::builder()
+                .interest(3650)
+                .max_transactions(10)
+                .build()
+        };
+        let mut states = configured();
+        let mut log = WriteAheadLog::open(&path).unwrap();
+        for (action, timestamp) in [
+            (deposit(1, "2.5"), Some(Timestamp::from(10))),
+            (deposit(1, "1"), None),
+            (
+                Action::Dispute {
+                    client,
+                    transaction: TransactionId::from(1),
+                },
+                None,
+            ),
+            (deposit(3, "365"), None),
+        ] {
+            states.process_logged(action, timestamp, &mut log).unwrap();
+        }
+        states.end_of_day_logged("2026-10-16", &mut log).unwrap();
+        states.close_period_logged("october", &mut log).unwrap();
+        // Rejected live, and skipped again on replay
+        let unknown = OpenDispute {
+            client: ClientId::from(9),
+            transaction: TransactionId::from(9),
+            amount: "1".parse().unwrap(),
+            kind: crate::seed::DisputedKind::Deposit,
+        };
+        assert!(states.seed_disputes_logged([unknown], &mut log).is_err());
+        drop(log);
+        assert_eq!(
+            states.account(client).unwrap().available().to_string(),
+            "365.3650"
+        );
+        // A torn append, as left by a crash
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"type":"deposit","cli"#).unwrap();
+        drop(file);
+
+        let recovered = AccountStates::recover(configured(), &path).unwrap();
+        assert_eq!(recovered, states);
+        assert_eq!(recovered.latest_timestamp(), Some(Timestamp::from(10)));
+
+        let mut log = WriteAheadLog::open(&path).unwrap();
+        states
+            .process_logged(deposit(2, "1"), None, &mut log)
+            .unwrap();
+        drop(log);
+        assert_eq!(AccountStates::recover(configured(), &path).unwrap(), states);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
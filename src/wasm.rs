@@ -0,0 +1,80 @@
+//! JavaScript bindings for browsers and edge runtimes, only available with the `wasm` feature
+//!
+//! The library is only an `rlib` by default, since the `no_std` build cannot be a `cdylib`.
+//! Build the module with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib`
+//! and generate its JavaScript glue with `wasm-bindgen`. It exports an `Engine` running
+//! the same account logic as the command line:
+//!
+//! ```js
+//! const engine = new Engine();
+//! engine.process({ type: "deposit", client: 1, tx: 1, amount: "1.5" });
+//! engine.processCsv("type,client,tx,amount\nwithdrawal,1,2,0.5\n");
+//! const summaries = JSON.parse(engine.summary());
+//! ```
+//!
+//! Amounts are decimal strings, like balances in the summaries, so that none are rounded.
+
+use csv::ReaderBuilder;
+use wasm_bindgen::prelude::*;
+
+use crate::{write_summary_json, AccountStates, Action, JsonBalances, ProcessCsv};
+
+/// Account states of one session
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Engine {
+    states: AccountStates,
+}
+
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an action object with the fields of a CSV row,
+    /// answering with the outcome as in `applied` or `rejected: insufficient available funds`
+    pub fn process(&mut self, action: JsValue) -> Result<String, JsError> {
+        let action: Action = serde_wasm_bindgen::from_value(action)?;
+        Ok(self.states.process(action).to_string())
+    }
+
+    /// Apply all actions of a CSV text with a header row, stopping at the first invalid row
+    #[wasm_bindgen(js_name = processCsv)]
+    pub fn process_csv(&mut self, text: &str) -> Result<(), JsError> {
+        self.states
+            .process_csv(ReaderBuilder::new().from_reader(text.as_bytes()))
+            .map_err(js_error)
+    }
+
+    /// The summaries of all accounts as a JSON array, with balances as strings
+    pub fn summary(&self) -> Result<String, JsError> {
+        let mut output = vec![];
+        write_summary_json(self.states.summary(), JsonBalances::String, &mut output)
+            .map_err(js_error)?;
+        Ok(String::from_utf8(output)?)
+    }
+}
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&format!("{error:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_csv() {
+        let mut engine = Engine::new();
+        engine
+            .process_csv("type, client, tx, amount\ndeposit, 1, 1, 1.5\nwithdrawal, 1, 2, 0.5\n")
+            .unwrap();
+        assert_eq!(
+            engine.summary().unwrap(),
+            "[{\"client\":1,\"locked\":false,\"available\":\"1.0000\",\"held\":\"0.0000\",\"total\":\"1.0000\"}]\n"
+        );
+    }
+}
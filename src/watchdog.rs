@@ -0,0 +1,167 @@
+//! Watchdog of the ingestion of the HTTP service, only available with the `watchdog` feature
+//!
+//! A writer stuck in a slow store or observer keeps `POST /actions` waiting without failing.
+//! The [`Watchdog`] raises an alert once the [`Ingestion`] of the service has had a backlog
+//! without processing any action for [`Watchdog::stall`]: the `transactions_ingestion_stalled`
+//! gauge of [`crate::metrics`] turns 1 and the webhook, if any, receives the [`Alert`] as a JSON
+//! `POST`. Both are cleared, with another post, as soon as an action is processed again.
+
+use std::{
+    mem,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{http::Ingestion, metrics::Metrics};
+
+/// Longest time between two looks at the ingestion
+const MAX_TICK: Duration = Duration::from_secs(1);
+/// Shortest time between two looks at the ingestion
+const MIN_TICK: Duration = Duration::from_millis(10);
+/// Longest wait for the webhook to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Alerting on an ingestion with a backlog that makes no progress
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// Time with a backlog but without any processed action before the alert is raised
+    pub stall: Duration,
+    /// URL receiving every [`Alert`] as a JSON `POST`
+    pub webhook: Option<String>,
+}
+
+/// Change of the ingestion, as posted to the webhook
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Alert {
+    pub state: AlertState,
+    /// Actions waiting for the writer
+    pub backlog: u64,
+    /// Whole seconds since an action was last processed, or the backlog was last empty
+    pub stalled_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Stalled,
+    Recovered,
+}
+
+impl Watchdog {
+    /// Look at `ingestion` until `stop` receives or is dropped, setting the stall gauge
+    /// of `metrics` and posting the alerts to the webhook, whose failures are passed to `report`
+    pub fn watch(
+        &self,
+        ingestion: &Ingestion,
+        metrics: &Metrics,
+        stop: Receiver<()>,
+        mut report: impl FnMut(anyhow::Error),
+    ) {
+        let tick = (self.stall / 4).clamp(MIN_TICK, MAX_TICK);
+        let mut progress = Progress::new(ingestion.processed(), Instant::now());
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(tick) {
+            let (processed, backlog) = (ingestion.processed(), ingestion.backlog());
+            let Some(alert) = progress.check(processed, backlog, Instant::now(), self.stall) else {
+                continue;
+            };
+            metrics.set_ingestion_stalled(alert.state == AlertState::Stalled);
+            if let Some(url) = &self.webhook {
+                if let Err(e) = post(url, &alert) {
+                    report(e);
+                }
+            }
+        }
+    }
+}
+
+/// What the watchdog saw of the ingestion so far
+#[derive(Debug)]
+struct Progress {
+    processed: u64,
+    /// Last time an action was processed or the backlog was empty
+    since: Instant,
+    stalled: bool,
+}
+
+impl Progress {
+    fn new(processed: u64, now: Instant) -> Self {
+        Self {
+            processed,
+            since: now,
+            stalled: false,
+        }
+    }
+
+    /// Take in the counts of the ingestion at `now`, returning the alert if the state changed
+    fn check(
+        &mut self,
+        processed: u64,
+        backlog: u64,
+        now: Instant,
+        stall: Duration,
+    ) -> Option<Alert> {
+        let alert = |state| Alert {
+            state,
+            backlog,
+            stalled_secs: now.duration_since(self.since).as_secs(),
+        };
+        if processed != self.processed || backlog == 0 {
+            let recovered = mem::take(&mut self.stalled).then(|| alert(AlertState::Recovered));
+            (self.processed, self.since) = (processed, now);
+            return recovered;
+        }
+        if self.stalled || now.duration_since(self.since) < stall {
+            return None;
+        }
+        self.stalled = true;
+        Some(alert(AlertState::Stalled))
+    }
+}
+
+fn post(url: &str, alert: &Alert) -> Result<()> {
+    ureq::post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&serde_json::to_string(alert)?)
+        .with_context(|| format!("cannot post alert to {url}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alert_on_backlog_without_progress() {
+        let (start, stall) = (Instant::now(), Duration::from_secs(5));
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut progress = Progress::new(0, start);
+        // Idle without a backlog is no stall
+        assert_eq!(progress.check(0, 0, at(10), stall), None);
+        assert_eq!(progress.check(0, 3, at(14), stall), None);
+        assert_eq!(
+            progress.check(0, 3, at(15), stall),
+            Some(Alert {
+                state: AlertState::Stalled,
+                backlog: 3,
+                stalled_secs: 5
+            })
+        );
+        assert_eq!(progress.check(0, 3, at(30), stall), None);
+        assert_eq!(
+            progress.check(1, 2, at(31), stall),
+            Some(Alert {
+                state: AlertState::Recovered,
+                backlog: 2,
+                stalled_secs: 21
+            })
+        );
+        // Slow but moving ingestion is no stall either
+        for secs in 32..50 {
+            assert_eq!(progress.check(secs, 2, at(secs), stall), None);
+        }
+    }
+}